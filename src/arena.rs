@@ -0,0 +1,368 @@
+use crate::{
+    syntax::{self, BadExpressionError, Expression, SyntaxTree},
+    token::{locate_tokens, Span, Token},
+};
+use std::mem;
+
+/// Identifies a node stored in an [`ArenaTree`]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    /// Position of the node in [`ArenaTree::nodes`]
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// A node of an [`ArenaTree`]
+///
+/// The body of a loop or a procedure is made of the nodes following it,
+/// up to the node it ends before.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum ArenaNode {
+    /// Any expression without a body
+    Expression(Expression),
+    /// A loop whose body ends before the given node
+    Loop(NodeId),
+    /// A procedure whose body ends before the given node
+    Procedure(NodeId),
+}
+
+/// A syntax tree stored in a single list of nodes
+///
+/// Nodes are kept in the order of the source, each loop or procedure
+/// followed by its body, so parsing makes no allocation per block and
+/// running the tree walks through memory in order. Like [`SyntaxTree`],
+/// it remembers where each node came from when parsed from source code.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{ArenaNode, ArenaTree, SyntaxTree};
+/// let tree: ArenaTree = "+[->+<]>.".parse().unwrap();
+/// let top_level: Vec<_> = tree.top_level().collect();
+///
+/// assert_eq!(top_level.len(), 4);
+/// assert!(matches!(tree.node(top_level[1]), ArenaNode::Loop(_)));
+/// assert_eq!(tree.children(top_level[1]).count(), 4);
+/// assert_eq!(tree.to_syntax_tree().to_code(), "+[->+<]>.");
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ArenaTree {
+    nodes: Vec<ArenaNode>,
+    spans: Vec<Span>,
+}
+
+impl ArenaTree {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Parse a collection of tokens into a valid [`ArenaTree`]
+    ///
+    /// Each token is assumed to come from one byte of the source, so the
+    /// tokens are located as if they were that source.
+    pub fn parse_tokens<T>(tokens: T) -> Result<Self, BadExpressionError>
+    where
+        T: IntoIterator<Item = Token>,
+    {
+        Self::parse_spanned_tokens(locate_tokens(tokens))
+    }
+
+    /// Parse a collection of tokens, along with their location in the
+    /// source, into a valid [`ArenaTree`]
+    ///
+    /// Errors are the same [`SyntaxTree::parse_spanned_tokens`] gives.
+    pub fn parse_spanned_tokens<T>(tokens: T) -> Result<Self, BadExpressionError>
+    where
+        T: IntoIterator<Item = (Token, Span)>,
+    {
+        let mut tree = ArenaTree::new();
+        // Blocks still open, with the token and node opening them
+        let mut open: Vec<(Token, usize)> = Vec::new();
+
+        for (token, span) in tokens {
+            match token {
+                Token::LoopStart | Token::ProcedureStart => {
+                    open.push((token, tree.nodes.len()));
+                    tree.nodes.push(ArenaNode::Loop(NodeId(0)));
+                    tree.spans.push(span);
+                }
+                Token::LoopEnd | Token::ProcedureEnd => {
+                    let (opening, start) = match open.pop() {
+                        Some(block) if syntax::closing(block.0) == token => block,
+                        _ => return Err(syntax::not_opened(token, span)),
+                    };
+                    let end = NodeId(tree.nodes.len());
+                    tree.nodes[start] = match opening {
+                        Token::ProcedureStart => ArenaNode::Procedure(end),
+                        _ => ArenaNode::Loop(end),
+                    };
+                    tree.spans[start].end = span.end;
+                }
+                token => {
+                    if let Some(expr) = syntax::simple_expression(token) {
+                        tree.nodes.push(ArenaNode::Expression(expr));
+                        tree.spans.push(span);
+                    }
+                }
+            }
+        }
+
+        match open.pop() {
+            Some((opening, start)) => Err(syntax::not_closed(opening, tree.spans[start])),
+            None => Ok(tree),
+        }
+    }
+
+    /// Every node of the tree, bodies following the node owning them
+    pub fn nodes(&self) -> &[ArenaNode] {
+        &self.nodes
+    }
+
+    /// Amount of nodes, at every nesting level
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// # Panics
+    /// If `id` comes from another tree.
+    pub fn node(&self, id: NodeId) -> &ArenaNode {
+        &self.nodes[id.0]
+    }
+
+    /// Get the location in the source of the node `id`
+    ///
+    /// Returns [`None`] if the tree was not parsed from source.
+    pub fn span(&self, id: NodeId) -> Option<Span> {
+        match self.spans.len() == self.nodes.len() {
+            true => self.spans.get(id.0).copied(),
+            false => None,
+        }
+    }
+
+    /// Nodes of the top level of the tree
+    pub fn top_level(&self) -> ArenaChildren<'_> {
+        ArenaChildren {
+            tree: self,
+            next: 0,
+            end: self.nodes.len(),
+        }
+    }
+
+    /// Nodes of the body of `id`, which is empty unless it is a loop or
+    /// a procedure
+    ///
+    /// # Panics
+    /// If `id` comes from another tree.
+    pub fn children(&self, id: NodeId) -> ArenaChildren<'_> {
+        ArenaChildren {
+            tree: self,
+            next: id.0 + 1,
+            end: self.block_end(id.0).max(id.0 + 1),
+        }
+    }
+
+    /// Index of the node following the one at `index`, skipping its body
+    pub(crate) fn block_end(&self, index: usize) -> usize {
+        match self.nodes[index] {
+            ArenaNode::Loop(end) | ArenaNode::Procedure(end) => end.0,
+            ArenaNode::Expression(_) => index + 1,
+        }
+    }
+
+    /// Rebuilds this tree as a [`SyntaxTree`], keeping the locations
+    pub fn to_syntax_tree(&self) -> SyntaxTree {
+        // Blocks being rebuilt, with the node opening them and the tree they
+        // are part of
+        let mut open: Vec<(usize, SyntaxTree)> = Vec::new();
+        let mut tree = SyntaxTree::new();
+
+        for index in 0..=self.nodes.len() {
+            while open
+                .last()
+                .is_some_and(|&(start, _)| self.block_end(start) == index)
+            {
+                let (start, parent) = open.pop().expect("a block is open");
+                let body = mem::replace(&mut tree, parent);
+                let expr = match self.nodes[start] {
+                    ArenaNode::Procedure(_) => Expression::Procedure(body),
+                    _ => Expression::Loop(body),
+                };
+                tree.push_located(expr, self.span(NodeId(start)));
+            }
+
+            match self.nodes.get(index) {
+                Some(ArenaNode::Expression(expr)) => {
+                    tree.push_located(expr.clone(), self.span(NodeId(index)))
+                }
+                Some(_) => open.push((index, mem::take(&mut tree))),
+                None => (),
+            }
+        }
+
+        tree
+    }
+}
+
+/// Iterator over the nodes of one nesting level of an [`ArenaTree`]
+///
+/// See [`ArenaTree::top_level`] and [`ArenaTree::children`].
+#[derive(Debug, Clone)]
+pub struct ArenaChildren<'a> {
+    tree: &'a ArenaTree,
+    next: usize,
+    end: usize,
+}
+
+impl Iterator for ArenaChildren<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.end {
+            return None;
+        }
+
+        let id = NodeId(self.next);
+        self.next = self.tree.block_end(self.next);
+        Some(id)
+    }
+}
+
+impl From<&SyntaxTree> for ArenaTree {
+    fn from(tree: &SyntaxTree) -> Self {
+        let mut arena = ArenaTree::new();
+        let mut located = true;
+        // Blocks being copied, with the index of their next expression and
+        // the node opening them
+        let mut blocks = vec![(tree, 0, None)];
+
+        while let Some((block, index, start)) = blocks.last_mut() {
+            let Some(expr) = block.get(*index) else {
+                if let Some(start) = *start {
+                    let end = NodeId(arena.nodes.len());
+                    if let ArenaNode::Loop(body_end) | ArenaNode::Procedure(body_end) =
+                        &mut arena.nodes[start]
+                    {
+                        *body_end = end;
+                    }
+                }
+                blocks.pop();
+                continue;
+            };
+
+            match block.span(*index) {
+                Some(span) => arena.spans.push(span),
+                None => located = false,
+            }
+            *index += 1;
+
+            let node = match expr {
+                Expression::Loop(body) => {
+                    blocks.push((body, 0, Some(arena.nodes.len())));
+                    ArenaNode::Loop(NodeId(0))
+                }
+                Expression::Procedure(body) => {
+                    blocks.push((body, 0, Some(arena.nodes.len())));
+                    ArenaNode::Procedure(NodeId(0))
+                }
+                expr => ArenaNode::Expression(expr.clone()),
+            };
+            arena.nodes.push(node);
+        }
+
+        if !located {
+            arena.spans.clear();
+        }
+
+        arena
+    }
+}
+
+impl std::str::FromStr for ArenaTree {
+    type Err = BadExpressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_tokens(s.bytes().map(Token::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dialect::Dialect, token::Lexer};
+
+    #[test]
+    fn arena_parses_nested_blocks() {
+        let tokens = Lexer::new("+[>(-)]<".bytes()).dialect(Dialect::pbrain());
+        let tree = ArenaTree::parse_spanned_tokens(tokens).unwrap();
+
+        assert_eq!(
+            tree.nodes(),
+            [
+                ArenaNode::Expression(Expression::Increment),
+                ArenaNode::Loop(NodeId(5)),
+                ArenaNode::Expression(Expression::Forward),
+                ArenaNode::Procedure(NodeId(5)),
+                ArenaNode::Expression(Expression::Decrement),
+                ArenaNode::Expression(Expression::Backward),
+            ]
+        );
+        assert_eq!(
+            tree.top_level().collect::<Vec<_>>(),
+            [NodeId(0), NodeId(1), NodeId(5)]
+        );
+        assert_eq!(
+            tree.children(NodeId(1)).collect::<Vec<_>>(),
+            [NodeId(2), NodeId(3)]
+        );
+        assert_eq!(tree.children(NodeId(0)).count(), 0);
+        assert_eq!(
+            tree.span(NodeId(1)).map(|span| span.start..span.end),
+            Some(1..7)
+        );
+    }
+
+    #[test]
+    fn arena_round_trips_syntax_trees() {
+        let tokens = || Lexer::new("a,[>[-]<(b[->+<])]:".bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(tokens()).unwrap();
+        let arena = ArenaTree::parse_spanned_tokens(tokens()).unwrap();
+
+        assert_eq!(ArenaTree::from(&tree), arena);
+        assert_eq!(arena.to_syntax_tree(), tree);
+        assert_eq!(
+            arena.to_syntax_tree().span_at(&[1, 3]),
+            tree.span_at(&[1, 3])
+        );
+
+        let unlocated = SyntaxTree::from(tree.to_vec());
+        assert_eq!(ArenaTree::from(&unlocated).span(NodeId(0)), None);
+    }
+
+    #[test]
+    fn arena_reports_syntax_errors() {
+        for code in ["[[]", "[)", "(]]", "+(", ")"] {
+            assert_eq!(
+                code.parse::<ArenaTree>(),
+                code.parse::<SyntaxTree>()
+                    .map(|tree| ArenaTree::from(&tree))
+            );
+        }
+    }
+
+    #[test]
+    fn arena_deep_nesting() {
+        let depth = 100_000;
+        let code = "[".repeat(depth) + "+" + &"]".repeat(depth);
+        let arena: ArenaTree = code.parse().unwrap();
+
+        assert_eq!(arena.len(), depth + 1);
+        assert_eq!(arena.top_level().count(), 1);
+        assert_eq!(ArenaTree::from(&arena.to_syntax_tree()), arena);
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{
+    bytecode::{Op, Program},
+    execution::DEFAULT_BRAINFUCK_STACK_SIZE,
+    optimizer::{ClearLoops, MultiplyLoops, PassManager, RunLength},
+    syntax::{Expression, SyntaxTree},
+};
+use std::fmt::Write;
+
+/// Cells printed by `#`, like the interpreter does
+const DUMP_CELLS: usize = 16;
+
+/// Longest code shown in the comment annotating a block
+const MAX_ANNOTATION: usize = 40;
+
+impl SyntaxTree {
+    /// Writes this tree as an annotated x86-64 assembly listing
+    ///
+    /// The listing uses the AT&T syntax of the GNU assembler and the
+    /// System V calling convention, reading and writing through the C
+    /// library, so it builds into an executable with `cc listing.s`. Every
+    /// block of code starts with a comment telling the line and column it
+    /// comes from, followed by its code. Runs of instructions, clear loops
+    /// and multiplication loops are optimized.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::SyntaxTree;
+    /// let tree: SyntaxTree = "+++\n[->++<]".parse().unwrap();
+    /// let listing = tree.to_x86_64();
+    ///
+    /// assert!(listing.contains("    # 1:1  +++\n    addb $3, (%rbx,%r12)\n"));
+    /// assert!(listing.contains("    # 2:1  [->++<]\n"));
+    /// ```
+    pub fn to_x86_64(&self) -> String {
+        let mut listing = Listing {
+            memory: DEFAULT_BRAINFUCK_STACK_SIZE,
+            labels: 0,
+            procedures: Vec::new(),
+            dumps: false,
+        };
+
+        let mut main = String::new();
+        listing.block(self, &mut main);
+        listing.source(&main)
+    }
+}
+
+/// Writes the instructions of a listing
+struct Listing {
+    memory: usize,
+    /// Amount of local labels used so far
+    labels: usize,
+    /// Instructions of the procedures found so far
+    procedures: Vec<String>,
+    /// Whether `#` is used, needing the routine dumping the memory
+    dumps: bool,
+}
+
+impl Listing {
+    fn block(&mut self, tree: &SyntaxTree, out: &mut String) {
+        let mut index = 0;
+
+        while index < tree.len() {
+            match &tree[index] {
+                Expression::Loop(body) => {
+                    let program = optimized(&tree[index..=index]);
+
+                    let is_loop = program
+                        .ops()
+                        .iter()
+                        .any(|op| matches!(op, Op::JumpIfZero(_)));
+                    if is_loop {
+                        let label = self.label();
+                        annotate(out, tree, index, "[");
+                        let _ = writeln!(out, "    cmpb $0, (%rbx,%r12)");
+                        let _ = writeln!(out, "    je .Lend{label}");
+                        let _ = writeln!(out, ".Lloop{label}:");
+                        self.block(body, out);
+                        let _ = writeln!(out, "    # ]");
+                        let _ = writeln!(out, "    cmpb $0, (%rbx,%r12)");
+                        let _ = writeln!(out, "    jne .Lloop{label}");
+                        let _ = writeln!(out, ".Lend{label}:");
+                    } else {
+                        annotate(out, tree, index, &tree[index].to_code());
+                        self.ops(program.ops(), out);
+                    }
+
+                    index += 1;
+                }
+                Expression::Procedure(body) => {
+                    let id = self.procedures.len();
+                    self.procedures.push(String::new());
+
+                    let mut instructions = String::new();
+                    self.block(body, &mut instructions);
+                    self.procedures[id] = instructions;
+
+                    annotate(out, tree, index, "(");
+                    let _ = writeln!(out, "    movzbl (%rbx,%r12), %ecx");
+                    let _ = writeln!(out, "    leaq procedures(%rip), %rdx");
+                    let _ = writeln!(out, "    leaq procedure_{id}(%rip), %rax");
+                    let _ = writeln!(out, "    movq %rax, (%rdx,%rcx,8)");
+
+                    index += 1;
+                }
+                _ => {
+                    let end = tree[index..]
+                        .iter()
+                        .position(|expr| {
+                            matches!(expr, Expression::Loop(_) | Expression::Procedure(_))
+                        })
+                        .map_or(tree.len(), |length| index + length);
+                    let code: String = tree[index..end].iter().map(Expression::to_code).collect();
+
+                    annotate(out, tree, index, &code);
+                    self.ops(optimized(&tree[index..end]).ops(), out);
+
+                    index = end;
+                }
+            }
+        }
+    }
+
+    /// Writes instructions without jumps nor procedures
+    fn ops(&mut self, ops: &[Op], out: &mut String) {
+        for &op in ops {
+            let cell = "(%rbx,%r12)";
+
+            match op {
+                Op::Add(amount) => {
+                    let _ = writeln!(out, "    addb ${amount}, {cell}");
+                }
+                Op::Move(offset) => {
+                    self.index(offset, out);
+                    let _ = writeln!(out, "    movq %rax, %r12");
+                }
+                Op::Set(value) => {
+                    let _ = writeln!(out, "    movb ${value}, {cell}");
+                }
+                Op::Multiply { offset, factor } => {
+                    let _ = writeln!(out, "    movzbl {cell}, %edx");
+                    let _ = writeln!(out, "    imull ${factor}, %edx, %edx");
+                    self.index(offset, out);
+                    let _ = writeln!(out, "    addb %dl, (%rbx,%rax)");
+                }
+                Op::Input => {
+                    let _ = writeln!(out, "    call getchar@PLT");
+                    let _ = writeln!(out, "    xorl %ecx, %ecx");
+                    let _ = writeln!(out, "    cmpl $-1, %eax");
+                    let _ = writeln!(out, "    cmove %ecx, %eax");
+                    let _ = writeln!(out, "    movb %al, {cell}");
+                }
+                Op::Output => {
+                    let _ = writeln!(out, "    movzbl {cell}, %edi");
+                    let _ = writeln!(out, "    call putchar@PLT");
+                }
+                Op::DebugDump => {
+                    self.dumps = true;
+                    let _ = writeln!(out, "    call dump");
+                }
+                Op::Call => {
+                    let label = self.label();
+                    let _ = writeln!(out, "    movzbl {cell}, %ecx");
+                    let _ = writeln!(out, "    leaq procedures(%rip), %rdx");
+                    let _ = writeln!(out, "    movq (%rdx,%rcx,8), %rax");
+                    let _ = writeln!(out, "    testq %rax, %rax");
+                    let _ = writeln!(out, "    je .Lskip{label}");
+                    let _ = writeln!(out, "    call *%rax");
+                    let _ = writeln!(out, ".Lskip{label}:");
+                }
+                Op::End => {
+                    let _ = writeln!(out, "    xorl %edi, %edi");
+                    let _ = writeln!(out, "    call exit@PLT");
+                }
+                Op::Store => {
+                    let _ = writeln!(out, "    movb {cell}, %r13b");
+                }
+                Op::Retrieve => {
+                    let _ = writeln!(out, "    movb %r13b, {cell}");
+                }
+                Op::ShiftLeft => {
+                    let _ = writeln!(out, "    shlb {cell}");
+                }
+                Op::ShiftRight => {
+                    let _ = writeln!(out, "    shrb {cell}");
+                }
+                Op::Not => {
+                    let _ = writeln!(out, "    notb {cell}");
+                }
+                Op::Xor => {
+                    let _ = writeln!(out, "    xorb %r13b, {cell}");
+                }
+                Op::And => {
+                    let _ = writeln!(out, "    andb %r13b, {cell}");
+                }
+                Op::Or => {
+                    let _ = writeln!(out, "    orb %r13b, {cell}");
+                }
+                Op::JumpIfZero(_) | Op::JumpIfNotZero(_) | Op::Procedure(_) | Op::Return => {
+                    unreachable!("loops and procedures are written from the tree")
+                }
+            }
+        }
+    }
+
+    /// Puts in `%rax` the index of the cell `offset` cells away from the
+    /// pointer, wrapping around the memory
+    fn index(&self, offset: isize, out: &mut String) {
+        let offset = offset.rem_euclid(self.memory as isize);
+        let _ = writeln!(out, "    leaq {offset}(%r12), %rax");
+        let _ = writeln!(out, "    leaq {offset}-MEMORY(%r12), %rcx");
+        let _ = writeln!(out, "    cmpq $MEMORY, %rax");
+        let _ = writeln!(out, "    cmovae %rcx, %rax");
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    /// Whole listing running `main`
+    fn source(&self, main: &str) -> String {
+        let mut source = format!(
+            "    .set MEMORY, {memory}\n\
+             \n    \
+                 .text\n    \
+                 .globl main\n\
+             main:\n    \
+                 pushq %rbx\n    \
+                 pushq %r12\n    \
+                 pushq %r13\n    \
+                 leaq tape(%rip), %rbx\n    \
+                 movq $MEMORY/2, %r12\n    \
+                 xorl %r13d, %r13d\n\
+             {main}    \
+                 popq %r13\n    \
+                 popq %r12\n    \
+                 popq %rbx\n    \
+                 xorl %eax, %eax\n    \
+                 ret\n",
+            memory = self.memory,
+        );
+
+        for (id, instructions) in self.procedures.iter().enumerate() {
+            let _ = write!(
+                source,
+                "\nprocedure_{id}:\n    \
+                     subq $8, %rsp\n\
+                 {instructions}    \
+                     addq $8, %rsp\n    \
+                     ret\n"
+            );
+        }
+
+        if self.dumps {
+            let start = self.memory / 2;
+            let end = (start + DUMP_CELLS).min(self.memory);
+            let _ = write!(
+                source,
+                "\ndump:\n    \
+                     pushq %r14\n    \
+                     subq $16, %rsp\n    \
+                     movq stdout@GOTPCREL(%rip), %rax\n    \
+                     movq (%rax), %rdi\n    \
+                     call fflush@PLT\n    \
+                     movq stderr@GOTPCREL(%rip), %rax\n    \
+                     movq (%rax), %rdi\n    \
+                     leaq dump_head(%rip), %rsi\n    \
+                     movq %r12, %rdx\n    \
+                     movq ${start}, %rcx\n    \
+                     movq ${end}, %r8\n    \
+                     movzbl {start}(%rbx), %r9d\n    \
+                     xorl %eax, %eax\n    \
+                     call fprintf@PLT\n    \
+                     movq ${next}, %r14\n\
+                 .Ldump:\n    \
+                     cmpq ${end}, %r14\n    \
+                     jae .Ldumped\n    \
+                     movq stderr@GOTPCREL(%rip), %rax\n    \
+                     movq (%rax), %rdi\n    \
+                     leaq dump_cell(%rip), %rsi\n    \
+                     movzbl (%rbx,%r14), %edx\n    \
+                     xorl %eax, %eax\n    \
+                     call fprintf@PLT\n    \
+                     incq %r14\n    \
+                     jmp .Ldump\n\
+                 .Ldumped:\n    \
+                     leaq dump_tail(%rip), %rdi\n    \
+                     movq stderr@GOTPCREL(%rip), %rax\n    \
+                     movq (%rax), %rsi\n    \
+                     call fputs@PLT\n    \
+                     addq $16, %rsp\n    \
+                     popq %r14\n    \
+                     ret\n\
+                 \n    \
+                     .section .rodata\n\
+                 dump_head:\n    \
+                     .string \"pointer: %zu, cells %zu..%zu: [%d\"\n\
+                 dump_cell:\n    \
+                     .string \", %d\"\n\
+                 dump_tail:\n    \
+                     .string \"]\\n\"\n",
+                next = start + 1,
+            );
+        }
+
+        source.push_str("\n    .bss\ntape:\n    .zero MEMORY\n");
+        if !self.procedures.is_empty() {
+            source.push_str("procedures:\n    .zero 256*8\n");
+        }
+        source.push_str("\n    .section .note.GNU-stack,\"\",@progbits\n");
+
+        source
+    }
+}
+
+/// `block` compiled with the optimizations that don't assume anything
+/// about the memory
+fn optimized(block: &[Expression]) -> Program {
+    let mut program = Program::from_block(block);
+    let mut passes = PassManager::new();
+    passes.push(ClearLoops);
+    passes.push(MultiplyLoops);
+    passes.push(RunLength);
+    passes.run(&mut program);
+
+    program
+}
+
+/// Writes a comment with the location of the expression at `index` and
+/// the code of its block
+fn annotate(out: &mut String, tree: &SyntaxTree, index: usize, code: &str) {
+    let code: String = match code.chars().count() > MAX_ANNOTATION {
+        true => code
+            .chars()
+            .take(MAX_ANNOTATION)
+            .chain("...".chars())
+            .collect(),
+        false => code.to_string(),
+    };
+
+    let _ = match tree.span(index) {
+        Some(span) => writeln!(out, "    # {}:{}  {code}", span.line, span.column),
+        None => writeln!(out, "    # {code}"),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dialect::Dialect, token::Lexer};
+
+    #[test]
+    fn x86_64_annotates_blocks() {
+        let tree: SyntaxTree = ",\n[>[-]<-]".parse().unwrap();
+        let listing = tree.to_x86_64();
+
+        assert!(listing.contains(
+            "    # 2:1  [\n    \
+                 cmpb $0, (%rbx,%r12)\n    \
+                 je .Lend0\n\
+             .Lloop0:\n    \
+                 # 2:2  >\n    \
+                 leaq 1(%r12), %rax\n    \
+                 leaq 1-MEMORY(%r12), %rcx\n    \
+                 cmpq $MEMORY, %rax\n    \
+                 cmovae %rcx, %rax\n    \
+                 movq %rax, %r12\n    \
+                 # 2:3  [-]\n    \
+                 movb $0, (%rbx,%r12)\n    \
+                 # 2:6  <-\n"
+        ));
+        assert!(listing.contains("    # ]\n    cmpb $0, (%rbx,%r12)\n    jne .Lloop0\n.Lend0:\n"));
+        assert!(!listing.contains("procedures:"));
+    }
+
+    #[test]
+    fn x86_64_writes_procedures() {
+        let lexer = Lexer::new("(.):".bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(lexer).unwrap();
+        let listing = tree.to_x86_64();
+
+        assert!(listing.contains("    leaq procedure_0(%rip), %rax\n"));
+        assert!(listing.contains(
+            "procedure_0:\n    \
+                 subq $8, %rsp\n    \
+                 # 1:2  .\n    \
+                 movzbl (%rbx,%r12), %edi\n    \
+                 call putchar@PLT\n    \
+                 addq $8, %rsp\n    \
+                 ret\n"
+        ));
+        assert!(listing.contains("procedures:\n    .zero 256*8\n"));
+    }
+}
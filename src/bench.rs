@@ -0,0 +1,247 @@
+//! Measuring how fast programs run with different settings
+//!
+//! [`run`] compiles a tree, runs it a few times to warm up, then times
+//! every run after those. Output is kept rather than written, so it can be
+//! compared between settings.
+
+use crate::{
+    bytecode::Program,
+    execution::{ExecutionState, MemoryContext, DEFAULT_BRAINFUCK_STACK_SIZE},
+    input::Input,
+    optimizer::{OptLevel, PassManager, PassReport},
+    profile::Profile,
+    syntax::SyntaxTree,
+};
+use std::time::{Duration, Instant};
+
+/// How a benchmarked program runs
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Engine {
+    /// Walking the syntax tree, without compiling it
+    TreeWalker,
+    /// Running the compiled instructions one by one
+    #[default]
+    Bytecode,
+    /// Compiling the instructions to native code first, or running them
+    /// one by one for programs with procedures
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+/// Settings of a benchmark
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct BenchConfig {
+    /// Runs made before timing any, left out of the report
+    pub warmup: usize,
+    /// Runs timed
+    pub iterations: usize,
+    pub opt_level: OptLevel,
+    pub engine: Engine,
+    /// Cells of the memory every run starts with
+    pub memory: usize,
+}
+
+impl std::default::Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            warmup: 1,
+            iterations: 10,
+            opt_level: OptLevel::default(),
+            engine: Engine::default(),
+            memory: DEFAULT_BRAINFUCK_STACK_SIZE,
+        }
+    }
+}
+
+/// Times measured by [`run`]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct BenchReport {
+    /// Wall time of every timed run, in order
+    pub runs: Vec<Duration>,
+    /// Instructions of the source a run executes, counting the checks of
+    /// the condition of loops like the interpreter counts steps
+    pub instructions: u64,
+    /// What every optimization pass did, and how long it took
+    pub passes: Vec<PassReport>,
+    /// Bytes written by the last run
+    pub output: Vec<u8>,
+}
+
+impl BenchReport {
+    /// Average time of the timed runs
+    pub fn mean(&self) -> Duration {
+        match self.runs.len() {
+            0 => Duration::ZERO,
+            runs => self.runs.iter().sum::<Duration>() / runs as u32,
+        }
+    }
+
+    /// Time of the timed run in the middle, or halfway between the two in
+    /// the middle
+    pub fn median(&self) -> Duration {
+        let mut runs = self.runs.clone();
+        runs.sort();
+        let middle = runs.len() / 2;
+        match runs.len() {
+            0 => Duration::ZERO,
+            len if len % 2 == 0 => (runs[middle - 1] + runs[middle]) / 2,
+            _ => runs[middle],
+        }
+    }
+
+    /// Time of the fastest timed run
+    pub fn fastest(&self) -> Duration {
+        self.runs.iter().min().copied().unwrap_or_default()
+    }
+
+    /// Time of the slowest timed run
+    pub fn slowest(&self) -> Duration {
+        self.runs.iter().max().copied().unwrap_or_default()
+    }
+
+    /// Instructions of the source executed every second, on average
+    pub fn instructions_per_second(&self) -> f64 {
+        let seconds = self.mean().as_secs_f64();
+        match seconds > 0.0 {
+            true => self.instructions as f64 / seconds,
+            false => 0.0,
+        }
+    }
+
+    /// Time spent compiling, adding up every pass
+    pub fn compile_time(&self) -> Duration {
+        self.passes.iter().map(|pass| pass.time).sum()
+    }
+}
+
+/// Runs `tree` with `input` as `config` says, timing every run after the
+/// warmup
+///
+/// Every run starts with a fresh memory and the input from its start. The
+/// instructions executed are counted by one more run walking the tree,
+/// which is not timed. Programs that never finish never return.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{bench::{self, BenchConfig}, SyntaxTree};
+/// let tree: SyntaxTree = ",[.-]".parse().unwrap();
+/// let config = BenchConfig {
+///     iterations: 3,
+///     ..Default::default()
+/// };
+/// let report = bench::run(&tree, b"\x03", &config);
+///
+/// assert_eq!(report.runs.len(), 3);
+/// assert_eq!(report.instructions, 11);
+/// assert_eq!(report.output, b"\x03\x02\x01");
+/// ```
+pub fn run(tree: &SyntaxTree, input: &[u8], config: &BenchConfig) -> BenchReport {
+    let mut program = Program::new(tree);
+    let passes = match config.engine {
+        Engine::TreeWalker => Vec::new(),
+        _ => PassManager::for_level(config.opt_level, config.memory).run(&mut program),
+    };
+
+    let mut output = Vec::new();
+    let mut runs = Vec::with_capacity(config.iterations);
+    for iteration in 0..config.warmup + config.iterations {
+        let mut memory = fresh_memory(input, config.memory);
+
+        let start = Instant::now();
+        run_once(tree, &program, config.engine, &mut memory);
+        let time = start.elapsed();
+
+        if iteration >= config.warmup {
+            runs.push(time);
+        }
+        output = memory.take_output();
+    }
+
+    let mut memory = fresh_memory(input, config.memory);
+    let mut state = ExecutionState {
+        profile: Some(Profile::new()),
+        ..Default::default()
+    };
+    memory.run(tree, &mut state, None);
+    let instructions = state.profile.map_or(0, |profile| profile.total());
+
+    BenchReport {
+        runs,
+        instructions,
+        passes,
+        output,
+    }
+}
+
+fn fresh_memory(input: &[u8], size: usize) -> MemoryContext {
+    let mut memory = MemoryContext::with_capacity(size);
+    memory.set_input(Input::replay(input.to_vec()));
+    memory.capture_output();
+
+    memory
+}
+
+fn run_once(tree: &SyntaxTree, program: &Program, engine: Engine, memory: &mut MemoryContext) {
+    match engine {
+        Engine::TreeWalker => {
+            memory.run(tree, &mut ExecutionState::default(), None);
+        }
+        Engine::Bytecode => {
+            memory.run_program(program);
+        }
+        #[cfg(feature = "jit")]
+        Engine::Jit => match program.jit(memory.cells().len(), memory.pointer()) {
+            Ok(compiled) => {
+                compiled.run(memory);
+            }
+            Err(_) => {
+                memory.run_program(program);
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bench_engines_agree() {
+        let tree: SyntaxTree = ",[>+++<-]>[<++>-]<.".parse().unwrap();
+        let engines = [Engine::TreeWalker, Engine::Bytecode].into_iter();
+        #[cfg(feature = "jit")]
+        let engines = engines.chain([Engine::Jit]);
+
+        for engine in engines {
+            let config = BenchConfig {
+                warmup: 2,
+                iterations: 4,
+                engine,
+                ..Default::default()
+            };
+            let report = run(&tree, b"\x05", &config);
+
+            assert_eq!(report.runs.len(), 4, "{engine:?}");
+            assert_eq!(report.output, [30], "{engine:?}");
+            // Every loop is reached once, then checked again after each iteration
+            let loops = (1 + 6 * 5 + 5) + (1 + 5 * 15 + 15);
+            assert_eq!(report.instructions, loops + 4);
+            assert!(report.fastest() <= report.mean() && report.mean() <= report.slowest());
+            assert_eq!(report.passes.is_empty(), engine == Engine::TreeWalker);
+        }
+    }
+
+    #[test]
+    fn bench_report_median() {
+        let report = |runs: &[u64]| BenchReport {
+            runs: runs.iter().copied().map(Duration::from_millis).collect(),
+            instructions: 0,
+            passes: Vec::new(),
+            output: Vec::new(),
+        };
+
+        assert_eq!(report(&[]).median(), Duration::ZERO);
+        assert_eq!(report(&[9, 1, 4]).median(), Duration::from_millis(4));
+        assert_eq!(report(&[9, 1, 4, 2]).median(), Duration::from_millis(3));
+    }
+}
@@ -0,0 +1,107 @@
+use brainfuck::bench::BenchReport;
+use std::{fmt::Write, time::Duration};
+
+/// Writes the times of `report` as a table, with how much they changed from
+/// `baseline` if given
+pub fn report(report: &BenchReport, baseline: Option<&Baseline>) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "runs:          {}", report.runs.len());
+    let _ = writeln!(text, "instructions:  {}", report.instructions);
+    let _ = writeln!(text, "compile:       {:>12.3?}", report.compile_time());
+    let figures = [
+        (
+            "min",
+            report.fastest(),
+            baseline.map(|baseline| baseline.min),
+        ),
+        (
+            "median",
+            report.median(),
+            baseline.map(|baseline| baseline.median),
+        ),
+        (
+            "max",
+            report.slowest(),
+            baseline.map(|baseline| baseline.max),
+        ),
+    ];
+    for (name, time, before) in figures {
+        let _ = write!(text, "{:<15}{time:>12.3?}", format!("{name}:"));
+        if let Some(before) = before {
+            let _ = write!(text, "  {}", change(before, time));
+        }
+        text.push('\n');
+    }
+    let rate = report.instructions as f64 / report.median().as_secs_f64().max(f64::EPSILON);
+    let _ = write!(text, "{:<15}{:>12}/s", "speed:", rate as u64);
+    if let Some(baseline) = baseline {
+        let speedup =
+            baseline.median.as_secs_f64() / report.median().as_secs_f64().max(f64::EPSILON);
+        let _ = write!(text, "  {speedup:.2}x the baseline");
+    }
+    text.push('\n');
+
+    text
+}
+
+/// Times saved by `bf bench --save`, to compare later runs against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Baseline {
+    min: Duration,
+    median: Duration,
+    max: Duration,
+}
+
+impl Baseline {
+    /// Takes the times of `report`
+    pub fn new(report: &BenchReport) -> Self {
+        Self {
+            min: report.fastest(),
+            median: report.median(),
+            max: report.slowest(),
+        }
+    }
+
+    /// Writes the times in nanoseconds, as read by [`Baseline::from_text`]
+    pub fn to_text(&self) -> String {
+        format!(
+            "min {}\nmedian {}\nmax {}\n",
+            self.min.as_nanos(),
+            self.median.as_nanos(),
+            self.max.as_nanos()
+        )
+    }
+
+    /// Reads times written by [`Baseline::to_text`]
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let (mut min, mut median, mut max) = (None, None, None);
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (name, nanos) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("expected a name and a time in `{line}`"))?;
+            let nanos: u64 = nanos
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid time in `{line}`"))?;
+            let time = Some(Duration::from_nanos(nanos));
+            match name {
+                "min" => min = time,
+                "median" => median = time,
+                "max" => max = time,
+                _ => return Err(format!("unknown time `{name}`")),
+            }
+        }
+
+        match (min, median, max) {
+            (Some(min), Some(median), Some(max)) => Ok(Self { min, median, max }),
+            _ => Err("expected a `min`, `median` and `max` time".into()),
+        }
+    }
+}
+
+/// How much slower or faster `after` is than `before`, in percent
+fn change(before: Duration, after: Duration) -> String {
+    let percent = (after.as_secs_f64() - before.as_secs_f64()) * 100.0
+        / before.as_secs_f64().max(f64::EPSILON);
+    format!("{percent:+.1}%")
+}
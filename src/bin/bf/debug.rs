@@ -0,0 +1,396 @@
+use brainfuck::{BrainFuckInterpreter, Lexer, Span, Stopped, SyntaxTree, Token};
+use nix::sys::termios::{self, SetArg, SpecialCharacterIndices, Termios};
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs::File,
+    io::{self, Read, Write},
+    thread,
+    time::Duration,
+};
+
+/// Steps run by `c` between looks at the keyboard, to stop on any key
+const CHUNK: usize = 100_000;
+
+/// Cells shown on every row of the tape
+const ROW: usize = 16;
+
+/// Rows of the tape shown
+const TAPE_ROWS: usize = 4;
+
+/// Lines of the script shown
+const SOURCE_LINES: usize = 12;
+
+/// Last lines of the output shown
+const OUTPUT_LINES: usize = 5;
+
+const KEYS: &str = "s step  c continue  b breakpoint  arrows select  \
+                    </> scroll tape  r restart  q quit";
+
+/// Runs `tree`, parsed from `code`, one step at a time on a full screen
+/// view of the script, its tape and its output
+///
+/// The script reads `input` and has a memory of `memory` cells. Keys are
+/// read from the terminal, see [`KEYS`].
+pub fn run(code: &[u8], tree: SyntaxTree, input: Vec<u8>, memory: usize) -> io::Result<()> {
+    let mut terminal = Terminal::open()?;
+    let mut debugger = Debugger::new(code, tree, input, memory);
+
+    loop {
+        terminal.draw(&debugger.screen())?;
+        match terminal.wait_key()? {
+            Key::Char(b's' | b' ') => debugger.step(),
+            Key::Char(b'c') => debugger.resume(&mut terminal)?,
+            Key::Char(b'b') => debugger.toggle_breakpoint(),
+            Key::Char(b'r') => debugger.restart(),
+            Key::Char(b'<') => debugger.tape_row -= 1,
+            Key::Char(b'>') => debugger.tape_row += 1,
+            Key::Left | Key::Char(b'h') => debugger.select(-1),
+            Key::Right | Key::Char(b'l') => debugger.select(1),
+            Key::Up | Key::Char(b'k') => debugger.select_line(-1),
+            Key::Down | Key::Char(b'j') => debugger.select_line(1),
+            // Ctrl-C, since the terminal does not send signals in raw mode
+            Key::Char(b'q' | 3) => return Ok(()),
+            Key::Char(_) => (),
+        }
+    }
+}
+
+/// Key read from the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(u8),
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Terminal in raw mode, showing the alternate screen until dropped
+struct Terminal {
+    tty: File,
+    original: Termios,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Self> {
+        let tty = File::options().read(true).write(true).open("/dev/tty")?;
+        let original = termios::tcgetattr(&tty)?;
+
+        // Reads return right away, with no bytes if no key was pressed
+        let mut raw = original.clone();
+        termios::cfmakeraw(&mut raw);
+        raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 0;
+        raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+        termios::tcsetattr(&tty, SetArg::TCSANOW, &raw)?;
+
+        let mut terminal = Terminal { tty, original };
+        terminal.tty.write_all(b"\x1b[?1049h\x1b[?25l")?;
+        Ok(terminal)
+    }
+
+    /// Replaces the screen with `screen`
+    fn draw(&mut self, screen: &str) -> io::Result<()> {
+        let screen = screen.replace('\n', "\r\n");
+        write!(self.tty, "\x1b[H\x1b[2J{screen}")?;
+        self.tty.flush()
+    }
+
+    /// Key pressed since the last look, if any
+    fn key(&mut self) -> io::Result<Option<Key>> {
+        let mut bytes = [0; 8];
+        let read = self.tty.read(&mut bytes)?;
+        let key = match &bytes[..read] {
+            [] => return Ok(None),
+            b"\x1b[A" => Key::Up,
+            b"\x1b[B" => Key::Down,
+            b"\x1b[C" => Key::Right,
+            b"\x1b[D" => Key::Left,
+            [byte, ..] => Key::Char(*byte),
+        };
+
+        Ok(Some(key))
+    }
+
+    fn wait_key(&mut self) -> io::Result<Key> {
+        loop {
+            if let Some(key) = self.key()? {
+                return Ok(key);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.tty.write_all(b"\x1b[?25h\x1b[?1049l");
+        let _ = termios::tcsetattr(&self.tty, SetArg::TCSANOW, &self.original);
+    }
+}
+
+struct Debugger {
+    code: Vec<u8>,
+    tree: SyntaxTree,
+    input: Vec<u8>,
+    memory: usize,
+    interpreter: BrainFuckInterpreter,
+    /// Where every instruction of the script is, in order
+    instructions: Vec<Span>,
+    breakpoints: BTreeSet<usize>,
+    /// Instruction selected to toggle its breakpoint
+    selected: usize,
+    /// Row of the tape shown first, relative to the row above the pointer
+    tape_row: isize,
+    output: Vec<u8>,
+    /// Why the script stopped last, or what happened
+    status: String,
+    finished: bool,
+}
+
+impl Debugger {
+    fn new(code: &[u8], tree: SyntaxTree, input: Vec<u8>, memory: usize) -> Self {
+        let instructions = Lexer::new(code.iter().copied())
+            .filter(|(token, _)| !matches!(token, Token::Comment(_)))
+            .map(|(_, span)| span)
+            .collect();
+
+        let mut debugger = Debugger {
+            code: code.to_vec(),
+            interpreter: BrainFuckInterpreter::with_memory_size(memory),
+            tree,
+            input,
+            memory,
+            instructions,
+            breakpoints: BTreeSet::new(),
+            selected: 0,
+            tape_row: 0,
+            output: Vec::new(),
+            status: String::new(),
+            finished: false,
+        };
+        debugger.restart();
+        debugger.status = "ready".into();
+
+        debugger
+    }
+
+    /// Starts the script over with a fresh memory, keeping the breakpoints
+    fn restart(&mut self) {
+        self.interpreter = BrainFuckInterpreter::with_memory_size(self.memory);
+        self.interpreter.capture_output();
+        self.interpreter.replay_input(self.input.clone());
+        self.interpreter.feed_tree(self.tree.clone());
+        for &offset in &self.breakpoints {
+            self.interpreter.add_breakpoint(offset);
+        }
+
+        self.output.clear();
+        self.finished = false;
+        self.status = "restarted".into();
+        self.follow();
+    }
+
+    fn step(&mut self) {
+        if !self.finished {
+            let stopped = self.interpreter.step();
+            self.stopped(stopped);
+        }
+    }
+
+    /// Runs until a breakpoint, the end of the script or any key
+    fn resume(&mut self, terminal: &mut Terminal) -> io::Result<()> {
+        while !self.finished {
+            let stopped = self.interpreter.step_by(CHUNK);
+            self.stopped(stopped);
+            if stopped != Stopped::StepLimit {
+                break;
+            }
+
+            terminal.draw(&self.screen())?;
+            if terminal.key()?.is_some() {
+                self.status = "interrupted".into();
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes what the script wrote, and shows why it stopped
+    fn stopped(&mut self, stopped: Stopped) {
+        self.output.extend(self.interpreter.take_output());
+        self.status = match stopped {
+            Stopped::Finished => "finished, press r to restart".into(),
+            Stopped::Breakpoint(offset) => format!("breakpoint at {}", self.position(offset)),
+            Stopped::StepLimit => "paused".into(),
+            Stopped::EndOfInput => "read past the end of the input".into(),
+        };
+        self.finished = stopped == Stopped::Finished;
+        self.follow();
+    }
+
+    /// Selects the instruction that runs next, showing the pointer's row
+    fn follow(&mut self) {
+        let next = self.interpreter.next_offset();
+        if let Some(index) = self.instructions.iter().position(|s| Some(s.start) == next) {
+            self.selected = index;
+        }
+        self.tape_row = 0;
+    }
+
+    fn toggle_breakpoint(&mut self) {
+        let Some(span) = self.instructions.get(self.selected) else {
+            return;
+        };
+
+        let position = self.position(span.start);
+        if self.breakpoints.remove(&span.start) {
+            self.interpreter.remove_breakpoint(span.start);
+            self.status = format!("removed breakpoint at {position}");
+        } else {
+            self.breakpoints.insert(span.start);
+            self.interpreter.add_breakpoint(span.start);
+            self.status = format!("added breakpoint at {position}");
+        }
+    }
+
+    /// Selects the instruction `delta` instructions away from the selected
+    /// one
+    fn select(&mut self, delta: isize) {
+        let last = self.instructions.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// Selects the instruction closest to the selected column on the
+    /// nearest line above, for a negative `direction`, or below
+    fn select_line(&mut self, direction: isize) {
+        let Some(&current) = self.instructions.get(self.selected) else {
+            return;
+        };
+
+        let lines = self.instructions.iter().map(|span| span.line);
+        let line = match direction < 0 {
+            true => lines.filter(|&line| line < current.line).max(),
+            false => lines.filter(|&line| line > current.line).min(),
+        };
+
+        let closest = self
+            .instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, span)| Some(span.line) == line)
+            .min_by_key(|(_, span)| span.column.abs_diff(current.column));
+        if let Some((index, _)) = closest {
+            self.selected = index;
+        }
+    }
+
+    /// `line:column` of the instruction at `offset`
+    fn position(&self, offset: usize) -> String {
+        match self.instructions.iter().find(|span| span.start == offset) {
+            Some(span) => format!("{}:{}", span.line, span.column),
+            None => format!("offset {offset}"),
+        }
+    }
+
+    fn screen(&self) -> String {
+        let mut screen = String::new();
+        self.draw_source(&mut screen);
+        screen.push('\n');
+        self.draw_tape(&mut screen);
+        screen.push('\n');
+        self.draw_output(&mut screen);
+
+        let pointer = self.interpreter.pointer();
+        let cell = self.interpreter.memory()[pointer];
+        let _ = writeln!(
+            screen,
+            "\n pointer {pointer}, holding {cell}: {}",
+            self.status
+        );
+        screen.push_str(KEYS);
+
+        screen
+    }
+
+    /// Lines of the script around the selected instruction, highlighting
+    /// the one running next, the selected one and the breakpoints
+    fn draw_source(&self, screen: &mut String) {
+        let next = self.interpreter.next_offset();
+        let selected = self.instructions.get(self.selected).map(|span| span.start);
+        let selected_line = self.instructions.get(self.selected).map_or(1, |s| s.line);
+        let first = selected_line.saturating_sub(SOURCE_LINES / 2).max(1);
+
+        let mut offset = 0;
+        for (number, line) in self.code.split(|&byte| byte == b'\n').enumerate() {
+            let number = number + 1;
+            if (first..first + SOURCE_LINES).contains(&number) {
+                let _ = write!(screen, "{number:>5} | ");
+                for (column, &byte) in line.iter().enumerate() {
+                    let at = offset + column;
+                    let mut style = String::new();
+                    if Some(at) == next {
+                        style.push_str("\x1b[7m");
+                    }
+                    if Some(at) == selected {
+                        style.push_str("\x1b[4m");
+                    }
+                    if self.breakpoints.contains(&at) {
+                        style.push_str("\x1b[31m");
+                    }
+
+                    let shown = match byte {
+                        b'\t' => ' ',
+                        byte if byte.is_ascii_graphic() || byte == b' ' => byte as char,
+                        _ => '?',
+                    };
+                    match style.is_empty() {
+                        true => screen.push(shown),
+                        false => {
+                            let _ = write!(screen, "{style}{shown}\x1b[0m");
+                        }
+                    }
+                }
+                screen.push('\n');
+            }
+            offset += line.len() + 1;
+        }
+    }
+
+    /// Rows of the tape from [`tape_row`](Self::tape_row), highlighting
+    /// the cell of the pointer
+    fn draw_tape(&self, screen: &mut String) {
+        let memory = self.interpreter.memory();
+        let pointer = self.interpreter.pointer();
+        let rows = memory.len().div_ceil(ROW);
+        let first = (pointer / ROW) as isize - 1 + self.tape_row;
+        let first = first.clamp(0, rows.saturating_sub(TAPE_ROWS) as isize) as usize;
+
+        for row in first..(first + TAPE_ROWS).min(rows) {
+            let start = row * ROW;
+            let _ = write!(screen, "{start:>6}:");
+            let end = (start + ROW).min(memory.len());
+            for (cell, value) in memory[start..end].iter().enumerate() {
+                let _ = match start + cell == pointer {
+                    true => write!(screen, " \x1b[7m{value:>3}\x1b[0m"),
+                    false => write!(screen, " {value:>3}"),
+                };
+            }
+            screen.push('\n');
+        }
+    }
+
+    /// Last lines written by the script
+    fn draw_output(&self, screen: &mut String) {
+        let output = String::from_utf8_lossy(&self.output);
+        let lines: Vec<_> = output.split('\n').collect();
+        let shown = &lines[lines.len().saturating_sub(OUTPUT_LINES)..];
+
+        screen.push_str(" output:\n");
+        for line in shown {
+            let line: String = line.chars().filter(|c| !c.is_control()).collect();
+            let _ = writeln!(screen, "  {line}");
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use brainfuck::{
+    visit::{self, ExpressionVisitor},
+    ExecutionStats, Expression, Profile, Program, SourceOffset, Span, SyntaxTree, Token,
+};
+use std::{collections::HashMap, fmt::Write, time::Duration};
+
+/// Cells on every row of [`memory`]
+const ROW: usize = 16;
+
+/// Writes `tree` as an indented list of its expressions, one per line,
+/// with runs of the same instruction on a single line
+pub fn pretty(tree: &SyntaxTree) -> String {
+    let mut pretty = Pretty::default();
+    pretty.visit_block(tree);
+
+    pretty.text
+}
+
+/// Writes the instructions of `program`, one per line after its index
+pub fn ops(program: &Program) -> String {
+    let mut text = String::new();
+    for (index, op) in program.ops().iter().enumerate() {
+        let _ = writeln!(text, "{index:>6}  {op:?}");
+    }
+
+    text
+}
+
+/// Writes `stats` of a run that took `time`, one figure per line
+pub fn stats(stats: &ExecutionStats, time: Duration) -> String {
+    let total: u64 = stats.instructions.iter().map(|(_, count)| count).sum();
+    let mut text = String::new();
+
+    let _ = writeln!(text, "instructions run:  {total}");
+    for (token, count) in &stats.instructions {
+        let _ = writeln!(text, "  {token}  {count:>14}");
+    }
+    let _ = writeln!(text, "loop iterations:   {}", stats.loop_iterations);
+    let _ = writeln!(text, "input reads:       {}", stats.reads);
+    let _ = writeln!(text, "bytes written:     {}", stats.writes);
+    let _ = match &stats.touched {
+        Some(cells) => writeln!(
+            text,
+            "cells touched:     {}, from {} to {}",
+            cells.end() - cells.start() + 1,
+            cells.start(),
+            cells.end()
+        ),
+        None => writeln!(text, "cells touched:     none"),
+    };
+    let _ = writeln!(text, "time:              {time:?}");
+
+    text
+}
+
+/// Writes the cells of `memory` for which `shown` is true, in rows of
+/// [`ROW`] bytes in hexadecimal and as text, then where the `pointer` is
+///
+/// Rows without any cell shown are left out, marked with `*`.
+pub fn memory<F>(memory: &[u8], pointer: usize, shown: F) -> String
+where
+    F: Fn(usize) -> bool,
+{
+    let mut text = String::new();
+    let mut skipped = false;
+
+    for start in (0..memory.len()).step_by(ROW) {
+        let cells = start..(start + ROW).min(memory.len());
+        if !cells.clone().any(&shown) {
+            skipped = true;
+            continue;
+        }
+        if std::mem::take(&mut skipped) {
+            text.push_str("*\n");
+        }
+
+        let _ = write!(text, "{start:>8} ");
+        let mut printable = String::new();
+        for cell in cells {
+            let byte = memory[cell];
+            let (hex, char) = match shown(cell) {
+                true if byte.is_ascii_graphic() || byte == b' ' => {
+                    (format!("{byte:02x}"), byte as char)
+                }
+                true => (format!("{byte:02x}"), '.'),
+                false => ("  ".into(), ' '),
+            };
+            let _ = match cell == pointer {
+                true => write!(text, "[{hex}]"),
+                false => write!(text, " {hex} "),
+            };
+            printable.push(char);
+        }
+        let _ = writeln!(text, " |{printable}|");
+    }
+    if skipped {
+        text.push_str("*\n");
+    }
+    let _ = writeln!(text, "pointer: {pointer}, holding {}", memory[pointer]);
+
+    text
+}
+
+/// Writes the instructions run most by `profile` as a table of their
+/// position, found in the `tokens` of the code, their count and their share
+/// of every step, only the first `limit` if given
+pub fn hot_spots<I>(profile: &Profile, tokens: I, limit: Option<usize>) -> String
+where
+    I: IntoIterator<Item = (Token, Span)>,
+{
+    let total = profile.total();
+    let hot_spots = profile.hot_spots();
+    let shown = limit.unwrap_or(hot_spots.len()).min(hot_spots.len());
+    let tokens = positions(tokens);
+
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "{:<12} {:<4} {:>14} {:>8}",
+        "position", "", "count", "share"
+    );
+    for &(offset, count) in &hot_spots[..shown] {
+        let (position, token) = match tokens.get(&offset) {
+            Some((span, token)) => (format!("{}:{}", span.line, span.column), token.to_string()),
+            None => (format!("offset {offset}"), "?".into()),
+        };
+        let share = count as f64 * 100.0 / total.max(1) as f64;
+        let _ = writeln!(text, "{position:<12} {token:<4} {count:>14} {share:>7.2}%");
+    }
+    if shown < hot_spots.len() {
+        let _ = writeln!(text, "... and {} more", hot_spots.len() - shown);
+    }
+    let _ = writeln!(text, "{:<17} {total:>14}", "total");
+
+    text
+}
+
+/// Writes every instruction run by `profile` like [`hot_spots`] does, as
+/// JSON
+pub fn hot_spots_json<I>(profile: &Profile, tokens: I) -> String
+where
+    I: IntoIterator<Item = (Token, Span)>,
+{
+    let total = profile.total();
+    let tokens = positions(tokens);
+
+    let mut text = format!("{{\n  \"total\": {total},\n  \"hot_spots\": [");
+    for (index, (offset, count)) in profile.hot_spots().into_iter().enumerate() {
+        let (line, column, token) = match tokens.get(&offset) {
+            Some((span, token)) => (
+                span.line.to_string(),
+                span.column.to_string(),
+                format!("{:?}", token.to_string()),
+            ),
+            None => ("null".into(), "null".into(), "null".into()),
+        };
+        let share = count as f64 * 100.0 / total.max(1) as f64;
+        let separator = if index == 0 { "" } else { "," };
+        let _ = write!(
+            text,
+            "{separator}\n    {{\"offset\": {offset}, \"line\": {line}, \"column\": {column}, \
+             \"instruction\": {token}, \"count\": {count}, \"share\": {share:.2}}}"
+        );
+    }
+    text.push_str("\n  ]\n}\n");
+
+    text
+}
+
+/// Span and token of every instruction of `tokens`, by source offset
+fn positions<I>(tokens: I) -> HashMap<SourceOffset, (Span, Token)>
+where
+    I: IntoIterator<Item = (Token, Span)>,
+{
+    tokens
+        .into_iter()
+        .map(|(token, span)| (span.start, (span, token)))
+        .collect()
+}
+
+#[derive(Default)]
+struct Pretty {
+    text: String,
+    depth: usize,
+}
+
+impl Pretty {
+    fn line(&mut self, line: std::fmt::Arguments) {
+        let _ = writeln!(self.text, "{:indent$}{line}", "", indent = self.depth * 4);
+    }
+}
+
+impl ExpressionVisitor for Pretty {
+    fn visit_block(&mut self, block: &[Expression]) {
+        let runs = block.chunk_by(|a, b| {
+            a == b && !matches!(a, Expression::Loop(_) | Expression::Procedure(_))
+        });
+
+        for run in runs {
+            match (&run[0], run.len()) {
+                (Expression::Loop(_), _) => self.line(format_args!("Loop")),
+                (Expression::Procedure(_), _) => self.line(format_args!("Procedure")),
+                (expr, 1) => self.line(format_args!("{expr:?}")),
+                (expr, times) => self.line(format_args!("{expr:?} x{times}")),
+            }
+
+            self.depth += 1;
+            visit::walk_expression(self, &run[0]);
+            self.depth -= 1;
+        }
+    }
+}
@@ -0,0 +1,1799 @@
+use brainfuck::{
+    bench::BenchConfig,
+    split_input,
+    transpile::{CTranspiler, CellType, RustTranspiler},
+    BadExpressionError, BrainFuckInterpreter, ConcreteSyntaxTree, Diagnostic, Dialect,
+    DialectError, EofBehavior, ExecutionStats, FlushPolicy, Formatter, Lexer, Lint, NativeError,
+    OptLevel, OutputMode, PassManager, Profile, ProfileError, Program, ProgramCache, Severity,
+    SourceOffset, Span, Stopped, SyntaxTree, Token,
+};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use derive_more::{Display, From};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+mod bench;
+#[cfg(all(feature = "tui-debugger", unix))]
+mod debug;
+mod dump;
+mod progress;
+#[cfg(feature = "repl")]
+mod repl;
+mod stepper;
+mod trace;
+#[cfg(all(feature = "raw-tty", unix))]
+mod tty;
+
+/// Terminal controlling the process, read by scripts given through the
+/// standard input
+#[cfg(not(windows))]
+const TERMINAL: &str = "/dev/tty";
+#[cfg(windows)]
+const TERMINAL: &str = "CONIN$";
+
+/// Hot spots printed by `--profile` to the standard error
+const HOT_SPOTS: usize = 20;
+
+/// Exit status of wrong arguments, like clap gives
+const USAGE_STATUS: u8 = 2;
+
+/// Exit status of scripts stopped by `--max-steps`
+const STEP_LIMIT_STATUS: u8 = 3;
+
+/// Exit status of scripts stopped by `--timeout`
+const TIMEOUT_STATUS: u8 = 4;
+
+/// Exit status of scripts with syntax errors
+const SYNTAX_STATUS: u8 = 5;
+
+/// Exit status of files not found
+const NOT_FOUND_STATUS: u8 = 6;
+
+/// Exit status of scripts failing while running
+const RUNTIME_STATUS: u8 = 7;
+
+/// Steps run between looks at the clock for `--timeout` and `--progress`
+const STEP_CHUNK: usize = 1 << 16;
+
+/// Whether errors and warnings are colored, as set by `--color`
+static COLORED: AtomicBool = AtomicBool::new(false);
+
+/// Time between looks at the files watched by `--watch`
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// BrainFuck Interpreter
+///
+/// This is an implementation made in rust. Expected to be performant enough.
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    long_about,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
+struct Arguments {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    run: RunArguments,
+
+    /// when to color errors and warnings: `auto` does on terminals, unless
+    /// `NO_COLOR` is set
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = Color::Auto, global = true)]
+    color: Color,
+}
+
+#[derive(Args, Debug)]
+struct RunArguments {
+    /// scripts to read from, joined one after the other in the order given,
+    /// or a Brainloller PNG image with the `image` feature. With `-` or
+    /// none, the script is read from the standard input, and the script
+    /// reads from the terminal
+    files: Vec<PathBuf>,
+
+    /// run CODE instead of a script
+    #[arg(
+        short,
+        long,
+        value_name = "CODE",
+        conflicts_with = "files",
+        allow_hyphen_values = true
+    )]
+    eval: Option<String>,
+
+    /// read everything after the first `!` of the script as its input
+    #[arg(long)]
+    bang_input: bool,
+
+    /// file the script reads its input from, instead of the standard input
+    #[arg(long, value_name = "FILE", conflicts_with = "bang_input")]
+    input: Option<PathBuf>,
+
+    /// text the script reads as its input, instead of the standard input
+    #[arg(
+        long,
+        value_name = "TEXT",
+        conflicts_with_all = ["bang_input", "input"]
+    )]
+    input_str: Option<String>,
+
+    /// save every byte the script reads to FILE once it stops, even if it
+    /// fails, to feed the same bytes back later with `--replay`
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["compile", "dump_ast"])]
+    record: Option<PathBuf>,
+
+    /// feed the script the bytes saved by `--record` to FILE, instead of
+    /// the standard input
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["bang_input", "input", "input_str", "compile", "dump_ast"]
+    )]
+    replay: Option<PathBuf>,
+
+    /// treat `#` as an instruction dumping memory to the standard error
+    #[arg(long)]
+    debug_dump: bool,
+
+    /// language the script is written in: `pbrain`, `extended`, `ook`,
+    /// `blub`, `pikalang`, or a file defining it with lines like
+    /// `increment = "pattern"`
+    #[arg(long, value_name = "NAME|FILE")]
+    dialect: Option<String>,
+
+    /// file the script writes its output to, byte by byte, instead of the
+    /// standard output
+    #[arg(short, long, value_name = "FILE", conflicts_with = "compile")]
+    output: Option<PathBuf>,
+
+    /// build a native executable at OUTPUT instead of running the script,
+    /// using the C compiler named by `CC` or `cc`
+    #[arg(long, value_name = "OUTPUT")]
+    compile: Option<PathBuf>,
+
+    /// cells of the memory, like `30000`, `64K` or `1M`
+    #[arg(long, value_name = "SIZE", default_value = "32K", value_parser = parse_size)]
+    memory_size: usize,
+
+    /// what `,` does once the input has no more bytes
+    #[arg(long, value_enum, default_value_t = Eof::Zero)]
+    eof: Eof,
+
+    /// print the script as parsed instead of running it: as an indented
+    /// `pretty` list, as `json` with the `serde` feature, as a `dot` graph,
+    /// or as the optimized instructions it runs as with `ops`
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "pretty",
+        conflicts_with_all = ["compile", "pgo_record"]
+    )]
+    dump_ast: Option<AstFormat>,
+
+    /// log every instruction run with its position, the pointer and the
+    /// cell under it, to FILE or the standard error. Instructions running
+    /// over 100 times are summed up at the end instead
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with_all = ["compile", "dump_ast", "pgo", "pgo_record"]
+    )]
+    trace: Option<Option<PathBuf>>,
+
+    /// print to the standard error how many instructions of every kind
+    /// ran, loop iterations, input read, bytes written, the cells touched
+    /// and the time taken, once the script finishes
+    #[arg(long, conflicts_with_all = ["compile", "dump_ast", "trace", "pgo"])]
+    stats: bool,
+
+    /// count how many times every instruction runs, printing the hottest
+    /// with their share of every step to the standard error, or all of them
+    /// to FILE, as JSON if it ends in `.json`
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with_all = ["compile", "dump_ast", "trace", "pgo"]
+    )]
+    profile: Option<Option<PathBuf>>,
+
+    /// stop the script once it runs STEPS instructions, failing with exit
+    /// status 3. Checking the condition of a loop is a step too
+    #[arg(
+        long,
+        value_name = "STEPS",
+        conflicts_with_all = ["compile", "dump_ast", "pgo"]
+    )]
+    max_steps: Option<usize>,
+
+    /// stop the script once it runs for DURATION, like `5s`, `250ms` or
+    /// `2m`, failing with exit status 4
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        conflicts_with_all = ["compile", "dump_ast", "trace", "pgo"]
+    )]
+    timeout: Option<Duration>,
+
+    /// show how many instructions ran, how fast and for how long on a line
+    /// of the standard error while the script runs, if it is a terminal
+    #[arg(long, conflicts_with_all = ["compile", "dump_ast", "trace", "pgo"])]
+    progress: bool,
+
+    /// print the memory to the standard error once the script stops, as
+    /// rows of bytes in hexadecimal, with the pointer: N cells from the one
+    /// the pointer starts at, or `all` the cells that are not zero
+    #[arg(
+        long,
+        value_name = "N|all",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "all",
+        value_parser = parse_memory_dump,
+        conflicts_with_all = ["compile", "dump_ast"]
+    )]
+    dump_memory: Option<MemoryDump>,
+
+    /// when the output of the script is flushed
+    #[arg(long, value_enum, default_value_t = Flush::Line)]
+    flush: Flush,
+
+    /// how `.` prints cells: as `chars`, or as `decimal` or `hex` numbers
+    /// separated by spaces, and by line breaks after line feeds
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = Output::Chars)]
+    output_mode: Output,
+
+    /// print the bytes of `.` as UTF-8 text, once they make up a character
+    #[arg(long, conflicts_with = "output_mode")]
+    unicode: bool,
+
+    /// send every key pressed to the script right away, without echoing
+    /// it, instead of whole lines once Enter is pressed. For games and
+    /// other interactive scripts, on Unix terminals with the `raw-tty`
+    /// feature
+    #[arg(long, conflicts_with_all = ["compile", "dump_ast"])]
+    raw_tty: bool,
+
+    /// reuse the script compiled by previous runs, kept in
+    /// `$XDG_CACHE_HOME/brainfuck` or `~/.cache/brainfuck`
+    #[arg(long)]
+    cache: bool,
+
+    /// count how many times each instruction runs, writing the counts to
+    /// PROFILE for `--pgo`
+    #[arg(long, value_name = "PROFILE", conflicts_with_all = ["compile", "cache"])]
+    pgo_record: Option<PathBuf>,
+
+    /// optimize harder the loops found hot in PROFILE, written by
+    /// `--pgo-record`
+    #[arg(
+        long,
+        value_name = "PROFILE",
+        conflicts_with_all = ["compile", "cache", "pgo_record"]
+    )]
+    pgo: Option<PathBuf>,
+
+    /// run the script again every time it or its `--input` file change,
+    /// clearing the screen first
+    #[arg(long, requires = "files", conflicts_with = "eval")]
+    watch: bool,
+
+    /// stop before running the script, and every STEPS steps after, taking
+    /// commands from the standard input to step, continue, print cells and
+    /// toggle breakpoints. The script reads nothing unless given `--input`,
+    /// `--input-str` or `--replay`
+    #[arg(
+        long,
+        value_name = "STEPS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "1",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        conflicts_with_all = [
+            "compile", "dump_ast", "trace", "watch", "raw_tty", "pgo", "pgo_record",
+            "stats", "profile", "max_steps", "timeout", "progress",
+        ]
+    )]
+    interactive: Option<usize>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// run a script, as done without any command
+    Run(Box<RunArguments>),
+
+    /// read code line by line and run it as it comes, keeping the memory
+    /// between lines
+    #[cfg(feature = "repl")]
+    Repl,
+
+    /// indent the code of scripts, putting every loop and comment on its
+    /// own lines
+    Fmt(FmtArguments),
+
+    /// remove the comments of a script, telling how much smaller it got
+    Minify(MinifyArguments),
+
+    /// parse scripts without running them, showing every error and
+    /// warning found. Fails if any script has errors
+    Check(CheckArguments),
+
+    /// translate a script into C, Rust or WebAssembly, or build it into a
+    /// native executable
+    Compile(CompileArguments),
+
+    /// run a script step by step on a full screen view of its code, its
+    /// memory and its output, stopping at breakpoints
+    #[cfg(all(feature = "tui-debugger", unix))]
+    Debug(DebugArguments),
+
+    /// run a script many times, discarding its output, and tell how long
+    /// the runs took and how fast they went
+    Bench(BenchArguments),
+
+    /// rewrite a script in another dialect, like from `ook` to `brainfuck`
+    Translate(TranslateArguments),
+
+    /// write a short script printing some text
+    Generate(GenerateArguments),
+
+    /// run scripts side by side, each reading what the one before writes,
+    /// the first reading the standard input and the last writing the
+    /// standard output
+    Pipe(PipeArguments),
+}
+
+#[derive(Args, Debug)]
+struct FmtArguments {
+    /// scripts to format, or `-` for the standard input, which is also
+    /// read without any
+    files: Vec<PathBuf>,
+
+    /// only tell which scripts are not formatted, failing if any
+    #[arg(long, conflicts_with = "write")]
+    check: bool,
+
+    /// format the scripts in place instead of printing them
+    #[arg(long)]
+    write: bool,
+
+    /// spaces added on every nesting level
+    #[arg(long, default_value_t = 4)]
+    indent: usize,
+
+    /// column lines are wrapped at, between runs of the same instruction
+    #[arg(long, value_name = "COLUMNS")]
+    max_width: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+struct MinifyArguments {
+    /// script to minify, or `-` for the standard input, which is also
+    /// read without one
+    file: Option<PathBuf>,
+
+    /// file to write the minified script to, instead of the standard output
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// also remove instructions undoing each other, like `+-` or `><`,
+    /// which needs the script to be valid
+    #[arg(long)]
+    shorten: bool,
+}
+
+#[derive(Args, Debug)]
+struct CheckArguments {
+    /// scripts to check, or `-` for the standard input
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// language the scripts are written in, like for running them
+    #[arg(long, value_name = "NAME|FILE")]
+    dialect: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct CompileArguments {
+    /// script to compile, or `-` for the standard input, which is also
+    /// read without one
+    file: Option<PathBuf>,
+
+    /// what to compile the script into
+    #[arg(long, value_enum, default_value_t = Target::Native)]
+    target: Target,
+
+    /// file to write to, instead of the standard output for `c` and `rust`.
+    /// Needed by `wasm` and `native`
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// language the script is written in, like for running it
+    #[arg(long, value_name = "NAME|FILE")]
+    dialect: Option<String>,
+
+    /// cells of the memory, like `30000`, `64K` or `1M`
+    #[arg(long, value_name = "SIZE", default_value = "32K", value_parser = parse_size)]
+    memory_size: usize,
+
+    /// what `,` does once the input has no more bytes. WebAssembly modules
+    /// leave it to the `read` function they import
+    #[arg(long, value_enum, default_value_t = Eof::Zero)]
+    eof: Eof,
+
+    /// bits of every cell. Cells wider than 8 bits need `c` or `native`
+    #[arg(long, value_enum, value_name = "BITS", default_value_t = CellSize::Bits8)]
+    cell_size: CellSize,
+
+    /// with cells wider than 8 bits, make them hold code points written and
+    /// read as UTF-8 characters
+    #[arg(long)]
+    unicode: bool,
+}
+
+#[cfg(all(feature = "tui-debugger", unix))]
+#[derive(Args, Debug)]
+struct DebugArguments {
+    /// script to debug
+    file: PathBuf,
+
+    /// file the script reads its input from. Without it or `--input-str`,
+    /// the script reads nothing
+    #[arg(long, value_name = "FILE")]
+    input: Option<PathBuf>,
+
+    /// text the script reads as its input
+    #[arg(long, value_name = "TEXT", conflicts_with = "input")]
+    input_str: Option<String>,
+
+    /// cells of the memory, like `30000`, `64K` or `1M`
+    #[arg(long, value_name = "SIZE", default_value = "32K", value_parser = parse_size)]
+    memory_size: usize,
+}
+
+#[derive(Args, Debug)]
+struct BenchArguments {
+    /// script to measure
+    file: PathBuf,
+
+    /// runs measured
+    #[arg(
+        long,
+        value_name = "RUNS",
+        default_value_t = 10,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    iterations: usize,
+
+    /// runs before the measured ones, left out of the times
+    #[arg(long, value_name = "RUNS", default_value_t = 1)]
+    warmup: usize,
+
+    /// file every run reads its input from. Without it or `--input-str`,
+    /// the script reads nothing
+    #[arg(long, value_name = "FILE")]
+    input: Option<PathBuf>,
+
+    /// text every run reads as its input
+    #[arg(long, value_name = "TEXT", conflicts_with = "input")]
+    input_str: Option<String>,
+
+    /// language the script is written in, like for running it
+    #[arg(long, value_name = "NAME|FILE")]
+    dialect: Option<String>,
+
+    /// cells of the memory, like `30000`, `64K` or `1M`
+    #[arg(long, value_name = "SIZE", default_value = "32K", value_parser = parse_size)]
+    memory_size: usize,
+
+    /// compare the times against those saved to FILE by `--save`
+    #[arg(long, value_name = "FILE")]
+    baseline: Option<PathBuf>,
+
+    /// save the times to FILE, to compare later runs against with
+    /// `--baseline`
+    #[arg(long, value_name = "FILE")]
+    save: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct TranslateArguments {
+    /// script to translate, or `-` for the standard input, which is also
+    /// read without one
+    file: Option<PathBuf>,
+
+    /// dialect the script is written in, a name like for running it or a
+    /// file defining it
+    #[arg(long, value_name = "NAME|FILE", default_value = "brainfuck")]
+    from: String,
+
+    /// dialect to write the script in
+    #[arg(long, value_name = "NAME|FILE", default_value = "brainfuck")]
+    to: String,
+
+    /// file to write the translated script to, instead of the standard
+    /// output
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct GenerateArguments {
+    /// text the script prints
+    #[arg(allow_hyphen_values = true)]
+    text: String,
+
+    /// file to write the script to, instead of the standard output
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct PipeArguments {
+    /// scripts to run, in the order their output goes through
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// language the scripts are written in, like for running them
+    #[arg(long, value_name = "NAME|FILE")]
+    dialect: Option<String>,
+
+    /// cells of the memory of every script, like `30000`, `64K` or `1M`
+    #[arg(long, value_name = "SIZE", default_value = "32K", value_parser = parse_size)]
+    memory_size: usize,
+
+    /// what `,` does once the input has no more bytes
+    #[arg(long, value_enum, default_value_t = Eof::Zero)]
+    eof: Eof,
+
+    /// when the output of every script is flushed, to the next one or to
+    /// the standard output
+    #[arg(long, value_enum, default_value_t = Flush::Line)]
+    flush: Flush,
+}
+
+/// When errors and warnings are colored
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Color {
+    /// when the standard error is a terminal and `NO_COLOR` is not set
+    Auto,
+    /// always
+    Always,
+    /// never
+    Never,
+}
+
+impl Color {
+    /// Whether errors and warnings are colored
+    fn enabled(self) -> bool {
+        match self {
+            Color::Auto => {
+                io::stderr().is_terminal()
+                    && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+}
+
+/// What `bf compile` compiles scripts into
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    /// a C99 program
+    C,
+    /// a self-contained `main.rs`
+    Rust,
+    /// a WebAssembly module
+    Wasm,
+    /// an executable, built with the C compiler named by `CC` or `cc`
+    Native,
+}
+
+/// What `,` does once the input has no more bytes
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Eof {
+    /// set the cell to zero
+    Zero,
+    /// set the cell to 255
+    MinusOne,
+    /// leave the cell as it is
+    Unchanged,
+    /// stop the script with an error
+    Error,
+}
+
+impl From<Eof> for EofBehavior {
+    fn from(eof: Eof) -> Self {
+        match eof {
+            Eof::Zero => EofBehavior::Zero,
+            Eof::MinusOne => EofBehavior::MinusOne,
+            Eof::Unchanged => EofBehavior::Unchanged,
+            Eof::Error => EofBehavior::Error,
+        }
+    }
+}
+
+/// Bits of every cell
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum CellSize {
+    #[value(name = "8")]
+    Bits8,
+    #[value(name = "16")]
+    Bits16,
+    #[value(name = "32")]
+    Bits32,
+}
+
+impl From<CellSize> for CellType {
+    fn from(size: CellSize) -> Self {
+        match size {
+            CellSize::Bits8 => CellType::U8,
+            CellSize::Bits16 => CellType::U16,
+            CellSize::Bits32 => CellType::U32,
+        }
+    }
+}
+
+/// Cells printed by `--dump-memory`
+#[derive(Debug, Clone, Copy)]
+enum MemoryDump {
+    /// This many cells from the one the pointer starts at
+    Cells(usize),
+    /// Every cell that is not zero
+    NonZero,
+}
+
+/// How `--dump-ast` prints the script
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum AstFormat {
+    Pretty,
+    Json,
+    Dot,
+    Ops,
+}
+
+/// When the output of the script is flushed
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Flush {
+    /// after every byte, for interactive scripts
+    Byte,
+    /// after every line break
+    Line,
+    /// once the script finishes
+    End,
+}
+
+impl From<Flush> for FlushPolicy {
+    fn from(flush: Flush) -> Self {
+        match flush {
+            Flush::Byte => FlushPolicy::Byte,
+            Flush::Line => FlushPolicy::Line,
+            Flush::End => FlushPolicy::End,
+        }
+    }
+}
+
+/// How the script prints cells
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Output {
+    /// as the characters they encode
+    Chars,
+    /// as decimal numbers
+    Decimal,
+    /// as hexadecimal numbers
+    Hex,
+}
+
+impl From<Output> for OutputMode {
+    fn from(output: Output) -> Self {
+        match output {
+            Output::Chars => OutputMode::Chars,
+            Output::Decimal => OutputMode::Decimal,
+            Output::Hex => OutputMode::Hex,
+        }
+    }
+}
+
+#[derive(From, Display)]
+enum CliError {
+    #[display(fmt = "error: {}", _0)]
+    IO(io::Error),
+
+    /// Diagnostics already rendered with their source
+    #[from(ignore)]
+    Syntax(String),
+
+    /// Options that can't go together, or that this build doesn't support
+    #[display(fmt = "error: {}", _0)]
+    #[from(ignore)]
+    Usage(String),
+
+    #[display(fmt = "error: invalid dialect: {}", _0)]
+    Dialect(DialectError),
+
+    #[display(fmt = "error: can't build executable: {}", _0)]
+    Native(NativeError),
+
+    #[display(fmt = "error: invalid profile: {}", _0)]
+    Profile(ProfileError),
+
+    #[display(fmt = "error: the script read past the end of its input")]
+    EndOfInput,
+
+    #[display(fmt = "error: the script was stopped after running {} steps", _0)]
+    #[from(ignore)]
+    StepLimit(usize),
+
+    #[display(
+        fmt = "error: the script timed out after {:?}, having run {} steps",
+        timeout,
+        steps
+    )]
+    Timeout { timeout: Duration, steps: usize },
+
+    #[display(fmt = "error: invalid baseline: {}", _0)]
+    #[from(ignore)]
+    Baseline(String),
+
+    #[display(fmt = "error: {} of the scripts are not formatted", _0)]
+    #[from(ignore)]
+    Unformatted(usize),
+
+    #[cfg(feature = "image")]
+    #[display(fmt = "error: invalid image: {}", _0)]
+    Image(brainfuck::ImageError),
+
+    #[cfg(feature = "repl")]
+    #[display(fmt = "error: {}", _0)]
+    Repl(rustyline::error::ReadlineError),
+}
+
+fn read_file<T: AsRef<Path>>(path: &T) -> io::Result<String> {
+    let mut f = File::open(path)?;
+    let mut output = String::new();
+    f.read_to_string(&mut output)?;
+
+    Ok(output)
+}
+
+/// Parses an amount of cells, in units of 1024 with a `K` suffix or of
+/// 1024² with `M`
+fn parse_size(size: &str) -> Result<usize, String> {
+    let (digits, unit) = match size.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&size[..index], 1 << 10),
+        Some((index, 'm' | 'M')) => (&size[..index], 1 << 20),
+        _ => (size, 1),
+    };
+
+    match digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+    {
+        Some(0) => Err("the memory needs at least one cell".into()),
+        Some(cells) => Ok(cells),
+        None => Err("expected a number of cells, like `30000`, `64K` or `1M`".into()),
+    }
+}
+
+/// Parses an amount of time, in seconds or with a `ms`, `s`, `m` or `h`
+/// suffix
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let invalid = || "expected an amount of time, like `5s`, `250ms` or `2m`".to_string();
+    let (number, unit) = match duration.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => duration.split_at(index),
+        None => (duration, "s"),
+    };
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(invalid()),
+    };
+
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+/// Parses the cells printed by `--dump-memory`, as a number or `all`
+fn parse_memory_dump(cells: &str) -> Result<MemoryDump, String> {
+    match cells {
+        "all" => Ok(MemoryDump::NonZero),
+        cells => cells
+            .parse()
+            .map(MemoryDump::Cells)
+            .map_err(|_| "expected a number of cells or `all`".into()),
+    }
+}
+
+/// Finds a built-in dialect by name, or loads it from a file
+fn load_dialect(name: &str) -> Result<Dialect, CliError> {
+    match Dialect::named(name) {
+        Some(dialect) => Ok(dialect),
+        None => Ok(Dialect::from_definition(&read_file(&name)?)?),
+    }
+}
+
+/// Whether `path` names the standard input, as `-`
+fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+fn is_png(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+/// Parses a Brainloller image
+#[cfg(feature = "image")]
+fn read_image(path: &Path) -> Result<SyntaxTree, CliError> {
+    let reader = io::BufReader::new(File::open(path)?);
+    Ok(SyntaxTree::parse_brainloller(reader)?)
+}
+
+#[cfg(not(feature = "image"))]
+fn read_image(_: &Path) -> Result<SyntaxTree, CliError> {
+    let message = "reading images needs the `image` feature";
+    Err(CliError::Usage(message.into()))
+}
+
+#[cfg(feature = "repl")]
+fn repl() -> Result<(), CliError> {
+    Ok(repl::run()?)
+}
+
+/// Runs the script of `args`, read from the standard input if none is given
+/// and it is not a terminal, or watches it with `--watch`
+fn start(mut args: RunArguments) -> Result<(), CliError> {
+    if args.files.is_empty() && args.eval.is_none() {
+        if io::stdin().is_terminal() {
+            let message = "a script is required, or `-` to read it from the standard input";
+            Arguments::command()
+                .bin_name("bf")
+                .error(ErrorKind::MissingRequiredArgument, message)
+                .exit();
+        }
+        args.files.push(PathBuf::from("-"));
+    }
+
+    let _raw_tty = match args.raw_tty {
+        true => Some(raw_tty()?),
+        false => None,
+    };
+    match args.watch {
+        true => watch(&args),
+        false => run(&args),
+    }
+}
+
+/// Puts the terminal in raw mode until the returned guard is dropped
+#[cfg(all(feature = "raw-tty", unix))]
+fn raw_tty() -> Result<tty::RawTty, CliError> {
+    Ok(tty::RawTty::enable()?)
+}
+
+#[cfg(not(all(feature = "raw-tty", unix)))]
+fn raw_tty() -> Result<(), CliError> {
+    let message = "`--raw-tty` needs the `raw-tty` feature, on a Unix terminal";
+    Err(CliError::Usage(message.into()))
+}
+
+/// Runs the script of `args` every time it or its input file change, until
+/// interrupted
+fn watch(args: &RunArguments) -> Result<(), CliError> {
+    let paths: Vec<&Path> = args
+        .files
+        .iter()
+        .chain(&args.input)
+        .map(PathBuf::as_path)
+        .collect();
+    if paths.contains(&Path::new("-")) {
+        let message = "`--watch` needs a script file, not the standard input";
+        return Err(CliError::Usage(message.into()));
+    }
+    let modified = || {
+        paths
+            .iter()
+            .map(|path| {
+                std::fs::metadata(path)
+                    .and_then(|meta| meta.modified())
+                    .ok()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    loop {
+        let seen = modified();
+        if io::stdout().is_terminal() {
+            print!("\x1b[2J\x1b[H");
+            io::stdout().flush()?;
+        }
+        if let Err(error) = run(args) {
+            report(&error);
+        }
+        eprintln!("\nwaiting for changes, Ctrl-C to stop");
+
+        while modified() == seen {
+            std::thread::sleep(WATCH_INTERVAL);
+        }
+    }
+}
+
+fn run(args: &RunArguments) -> Result<(), CliError> {
+    let sources = match (&args.eval, &args.files[..]) {
+        (Some(code), _) => Sources::eval(code),
+        (None, [file]) if is_png(file) => {
+            let tree = read_image(file)?;
+            return match (args.trace.is_some(), args.interactive.is_some()) {
+                (true, _) => traced(args, tree, [], None),
+                (_, true) => stepped(args, tree, [], None),
+                _ => execute(args, tree, [], None),
+            };
+        }
+        (None, files) if files.iter().any(|file| is_png(file)) => {
+            let message = "images can only be run on their own";
+            return Err(CliError::Usage(message.into()));
+        }
+        (None, files) => Sources::read(files)?,
+    };
+    let dialect = args.dialect.as_deref().map(load_dialect).transpose()?;
+    let (code, input) = match args.bang_input {
+        true => split_input(&sources.code),
+        false => (&sources.code[..], None),
+    };
+
+    // Everything changing how the script is compiled
+    let settings = format!(
+        "{dialect:?} {} {:?} {}",
+        args.debug_dump,
+        OptLevel::Aggressive,
+        args.memory_size
+    );
+    let cache = match args.cache
+        && args.compile.is_none()
+        && args.dump_ast.is_none()
+        && args.trace.is_none()
+        && args.interactive.is_none()
+        && !args.stats
+        && args.profile.is_none()
+        && args.max_steps.is_none()
+        && args.timeout.is_none()
+        && !args.progress
+    {
+        true => ProgramCache::in_user_cache(),
+        false => None,
+    };
+    if let Some(program) = cache.as_ref().and_then(|cache| cache.get(code, &settings)) {
+        return execute_program(args, input, &program);
+    }
+
+    let lexer = || {
+        let lexer = Lexer::new(code.iter().copied()).debug_dump(args.debug_dump);
+        match &dialect {
+            Some(dialect) => lexer.dialect(dialect.clone()),
+            None => lexer,
+        }
+    };
+
+    let tree =
+        SyntaxTree::parse_all_errors(lexer()).map_err(|errors| sources.syntax_error(errors))?;
+
+    if args.trace.is_some() {
+        return traced(args, tree, lexer(), input);
+    }
+    if args.interactive.is_some() {
+        return stepped(args, tree, lexer(), input);
+    }
+
+    if let Some(cache) = cache {
+        let program = optimize(args, Program::new(&tree));
+        // The cache only saves time, so the script still runs without it
+        let _ = cache.insert(code, &settings, &program);
+        return execute_program(args, input, &program);
+    }
+
+    execute(args, tree, lexer(), input)
+}
+
+/// Reads the script at `path`, or the standard input for `-`
+fn read_source(path: &Path) -> io::Result<Vec<u8>> {
+    if !is_stdin(path) {
+        return std::fs::read(path);
+    }
+
+    let mut source = Vec::new();
+    io::stdin().read_to_end(&mut source)?;
+    Ok(source)
+}
+
+/// Name of the script at `path` in diagnostics
+fn origin(path: &Path) -> Cow<'_, str> {
+    match is_stdin(path) {
+        true => "<stdin>".into(),
+        false => path.to_string_lossy(),
+    }
+}
+
+/// Renders every diagnostic with an excerpt of `code`, the script at
+/// `origin`
+fn render<I>(diagnostics: I, code: &[u8], origin: &str) -> Vec<String>
+where
+    I: IntoIterator<Item = Diagnostic>,
+{
+    diagnostics
+        .into_iter()
+        .map(|diagnostic| {
+            diagnostic
+                .render(code)
+                .origin(origin)
+                .colored(COLORED.load(Ordering::Relaxed))
+                .to_string()
+        })
+        .collect()
+}
+
+/// Error showing every error found parsing `code`, the script at `origin`
+fn syntax_error(errors: Vec<BadExpressionError>, code: &[u8], origin: &str) -> CliError {
+    let rendered = render(errors.into_iter().map(Diagnostic::from), code, origin);
+    CliError::Syntax(rendered.join("\n\n"))
+}
+
+/// Scripts joined one after the other, each starting on a new line
+struct Sources {
+    code: Vec<u8>,
+    /// Name, offset and line in `code` of the start of every script
+    starts: Vec<(String, SourceOffset, usize)>,
+}
+
+impl Sources {
+    /// Code given on the command line
+    fn eval(code: &str) -> Self {
+        Sources {
+            code: code.as_bytes().to_vec(),
+            starts: vec![("<eval>".into(), 0, 1)],
+        }
+    }
+
+    /// Reads every script of `files`, in order
+    fn read(files: &[PathBuf]) -> io::Result<Self> {
+        let mut code = Vec::new();
+        let mut starts = Vec::new();
+        let mut line = 1;
+
+        for file in files {
+            if code.last().is_some_and(|&byte| byte != b'\n') {
+                code.push(b'\n');
+                line += 1;
+            }
+            starts.push((origin(file).into_owned(), code.len(), line));
+
+            let source = read_source(file)?;
+            line += source.iter().filter(|&&byte| byte == b'\n').count();
+            code.extend(source);
+        }
+
+        Ok(Sources { code, starts })
+    }
+
+    /// Error showing every error found parsing the code, each pointing at
+    /// the script it was found in
+    fn syntax_error(&self, errors: Vec<BadExpressionError>) -> CliError {
+        let rendered: Vec<String> = errors
+            .into_iter()
+            .map(|error| self.render(error.into()))
+            .collect();
+        CliError::Syntax(rendered.join("\n\n"))
+    }
+
+    /// Renders `diagnostic`, found in the joined code, with an excerpt of
+    /// the script it points into
+    fn render(&self, mut diagnostic: Diagnostic) -> String {
+        let span = &mut diagnostic.span;
+        let index = self
+            .starts
+            .partition_point(|&(_, start, _)| start <= span.start)
+            .saturating_sub(1);
+        let (origin, start, line) = &self.starts[index];
+        let end = self
+            .starts
+            .get(index + 1)
+            .map_or(self.code.len(), |next| next.1);
+
+        span.start -= start;
+        span.end = span.end.saturating_sub(*start);
+        span.line -= line - 1;
+
+        let code = &self.code[*start..end];
+        diagnostic
+            .render(code)
+            .origin(origin)
+            .colored(COLORED.load(Ordering::Relaxed))
+            .to_string()
+    }
+}
+
+/// Formats every script of `args` as `bf fmt` does
+fn format(args: &FmtArguments) -> Result<(), CliError> {
+    let mut formatter = Formatter::new().indent(args.indent);
+    if let Some(max_width) = args.max_width {
+        formatter = formatter.max_width(max_width);
+    }
+
+    let stdin = [PathBuf::from("-")];
+    let files = match args.files.is_empty() {
+        true => &stdin[..],
+        false => &args.files[..],
+    };
+    let mut unformatted = 0;
+
+    for file in files {
+        let code = read_source(file)?;
+        let cst = ConcreteSyntaxTree::parse(Lexer::new(code.iter().copied())).map_err(|_| {
+            let lexer = Lexer::new(code.iter().copied());
+            let errors = SyntaxTree::parse_all_errors(lexer)
+                .err()
+                .unwrap_or_default();
+            syntax_error(errors, &code, &origin(file))
+        })?;
+        let formatted = formatter.format_source(&cst);
+        let changed = formatted.as_bytes() != code;
+
+        if args.check {
+            if changed {
+                eprintln!("{} is not formatted", origin(file));
+                unformatted += 1;
+            }
+        } else if args.write && !is_stdin(file) {
+            if changed {
+                std::fs::write(file, formatted)?;
+            }
+        } else {
+            print!("{formatted}");
+        }
+    }
+
+    match unformatted {
+        0 => Ok(()),
+        unformatted => Err(CliError::Unformatted(unformatted)),
+    }
+}
+
+/// Minifies the script of `args` as `bf minify` does
+fn minify(args: &MinifyArguments) -> Result<(), CliError> {
+    let file = args.file.as_deref().unwrap_or(Path::new("-"));
+    let code = read_source(file)?;
+
+    let minified = match args.shorten {
+        true => SyntaxTree::parse_all_errors(Lexer::new(code.iter().copied()))
+            .map_err(|errors| syntax_error(errors, &code, &origin(file)))?
+            .minify(),
+        false => brainfuck::minify(&String::from_utf8_lossy(&code)),
+    };
+
+    match &args.output {
+        Some(path) => std::fs::write(path, &minified)?,
+        None => io::stdout().write_all(minified.as_bytes())?,
+    }
+
+    let saved = code.len() - minified.len();
+    let percent = match code.len() {
+        0 => 0.0,
+        len => saved as f64 * 100.0 / len as f64,
+    };
+    eprintln!(
+        "{} bytes down to {} bytes, {percent:.1}% smaller",
+        code.len(),
+        minified.len()
+    );
+
+    Ok(())
+}
+
+/// Rewrites the script of `args` in another dialect as `bf translate` does
+fn translate(args: &TranslateArguments) -> Result<(), CliError> {
+    let from = load_dialect(&args.from)?;
+    let to = load_dialect(&args.to)?;
+    let file = args.file.as_deref().unwrap_or(Path::new("-"));
+    let code = read_source(file)?;
+
+    let tokens = Lexer::new(code.iter().copied()).dialect(from);
+    let translated = to.encode(tokens.map(|(token, _)| token))?;
+
+    match &args.output {
+        Some(path) => std::fs::write(path, translated)?,
+        None => io::stdout().write_all(&translated)?,
+    }
+
+    Ok(())
+}
+
+/// Writes a script printing the text of `args` as `bf generate` does
+fn generate(args: &GenerateArguments) -> Result<(), CliError> {
+    let code = brainfuck::generate(args.text.as_bytes()).to_code();
+
+    match &args.output {
+        Some(path) => std::fs::write(path, code)?,
+        None => println!("{code}"),
+    }
+
+    Ok(())
+}
+
+/// Runs the scripts of `args` as `bf pipe` does, every one on its own
+/// thread so that they run as their input comes
+fn pipe(args: &PipeArguments) -> Result<(), CliError> {
+    let dialect = args.dialect.as_deref().map(load_dialect).transpose()?;
+    let mut trees = Vec::new();
+    let mut errors = Vec::new();
+    for file in &args.files {
+        let code = read_source(file)?;
+        let lexer = Lexer::new(code.iter().copied());
+        let lexer = match &dialect {
+            Some(dialect) => lexer.dialect(dialect.clone()),
+            None => lexer,
+        };
+        // Every script is checked before any runs
+        match SyntaxTree::parse_all_errors(lexer) {
+            Ok(tree) => trees.push(tree),
+            Err(found) => errors.push(syntax_error(found, &code, &origin(file)).to_string()),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(CliError::Syntax(errors.join("\n\n")));
+    }
+
+    let last = trees.len() - 1;
+    let mut reader = None;
+    let stages = std::thread::scope(|scope| {
+        let mut stages = Vec::new();
+        for (index, tree) in trees.into_iter().enumerate() {
+            let input = reader.take();
+            let output = match index == last {
+                true => None,
+                false => {
+                    let (next, writer) = io::pipe()?;
+                    reader = Some(next);
+                    Some(writer)
+                }
+            };
+
+            stages.push(scope.spawn(move || {
+                let mut interpreter = BrainFuckInterpreter::with_memory_size(args.memory_size);
+                interpreter.set_opt_level(OptLevel::Aggressive);
+                interpreter.set_eof_behavior(args.eof.into());
+                if let Some(input) = input {
+                    interpreter.read_input_from(input);
+                }
+                if let Some(output) = output {
+                    interpreter.write_output_to(output);
+                }
+                interpreter.set_flush_policy(args.flush.into());
+                interpreter.feed_tree(tree);
+                // Dropping the interpreter closes its end of the pipe, ending
+                // the input of the next script
+                interpreter.execute()
+            }));
+        }
+
+        io::Result::Ok(
+            stages
+                .into_iter()
+                .map(|stage| stage.join().expect("scripts don't panic"))
+                .collect::<Vec<_>>(),
+        )
+    })?;
+
+    match stages.contains(&Stopped::EndOfInput) {
+        true => Err(CliError::EndOfInput),
+        false => Ok(()),
+    }
+}
+
+/// Parses every script of `args` as `bf check` does, failing with the
+/// diagnostics if any has errors
+fn check(args: &CheckArguments) -> Result<(), CliError> {
+    let dialect = args.dialect.as_deref().map(load_dialect).transpose()?;
+    let mut diagnostics = Vec::new();
+    let mut failed = false;
+
+    for file in &args.files {
+        let code = read_source(file)?;
+        let mut lexer = Lexer::new(code.iter().copied());
+        if let Some(dialect) = &dialect {
+            lexer = lexer.dialect(dialect.clone());
+        }
+
+        let found: Vec<_> = match SyntaxTree::parse_all_errors(lexer) {
+            Ok(tree) => tree.lint().iter().filter_map(Lint::to_diagnostic).collect(),
+            Err(errors) => errors.into_iter().map(Diagnostic::from).collect(),
+        };
+        failed |= found.iter().any(|d| d.severity == Severity::Error);
+        diagnostics.extend(render(found, &code, &origin(file)));
+    }
+
+    match failed {
+        true => Err(CliError::Syntax(diagnostics.join("\n\n"))),
+        false => {
+            if !diagnostics.is_empty() {
+                eprintln!("{}", diagnostics.join("\n\n"));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Runs the script of `args` in the debugger of `bf debug`
+#[cfg(all(feature = "tui-debugger", unix))]
+fn debug(args: &DebugArguments) -> Result<(), CliError> {
+    let code = read_source(&args.file)?;
+    let tree = SyntaxTree::parse_all_errors(Lexer::new(code.iter().copied()))
+        .map_err(|errors| syntax_error(errors, &code, &origin(&args.file)))?;
+    let input = match (&args.input, &args.input_str) {
+        (Some(path), _) => std::fs::read(path)?,
+        (None, Some(text)) => text.clone().into_bytes(),
+        (None, None) => Vec::new(),
+    };
+
+    Ok(debug::run(&code, tree, input, args.memory_size)?)
+}
+
+/// Compiles the script of `args` as `bf compile` does
+fn compile(args: &CompileArguments) -> Result<(), CliError> {
+    if args.cell_size != CellSize::Bits8 && !matches!(args.target, Target::C | Target::Native) {
+        let message = "cells wider than 8 bits are only supported by `c` and `native`";
+        return Err(CliError::Usage(message.into()));
+    }
+    if args.eof != Eof::Zero && args.target == Target::Wasm {
+        let message = "WebAssembly modules leave `--eof` to the `read` function they import";
+        return Err(CliError::Usage(message.into()));
+    }
+    if args.output.is_none() && matches!(args.target, Target::Wasm | Target::Native) {
+        let message = "`--output` is required by `wasm` and `native`";
+        let mut command = Arguments::command().bin_name("bf");
+        command.build();
+        command
+            .find_subcommand_mut("compile")
+            .expect("`compile` is a subcommand")
+            .error(ErrorKind::MissingRequiredArgument, message)
+            .exit();
+    }
+
+    let file = args.file.as_deref().unwrap_or(Path::new("-"));
+    let code = read_source(file)?;
+    let mut lexer = Lexer::new(code.iter().copied());
+    if let Some(dialect) = args.dialect.as_deref() {
+        lexer = lexer.dialect(load_dialect(dialect)?);
+    }
+    let tree = SyntaxTree::parse_all_errors(lexer)
+        .map_err(|errors| syntax_error(errors, &code, &origin(file)))?;
+
+    let transpiler = CTranspiler::new()
+        .cell_type(args.cell_size.into())
+        .memory(args.memory_size)
+        .eof(args.eof.into())
+        .unicode(args.unicode);
+    let compiled = match args.target {
+        Target::C => transpiler.transpile(&tree).into_bytes(),
+        Target::Rust => RustTranspiler::new()
+            .memory(args.memory_size)
+            .eof(args.eof.into())
+            .transpile(&tree)
+            .into_bytes(),
+        Target::Wasm => {
+            let mut program = Program::new(&tree);
+            PassManager::for_level(OptLevel::Aggressive, args.memory_size).run(&mut program);
+            program.to_wasm(args.memory_size)
+        }
+        Target::Native => {
+            let output = args.output.as_deref().expect("checked above");
+            return Ok(transpiler.build_native(&tree, output)?);
+        }
+    };
+
+    match &args.output {
+        Some(path) => std::fs::write(path, compiled)?,
+        None => io::stdout().write_all(&compiled)?,
+    }
+
+    Ok(())
+}
+
+/// Runs the script of `args` again and again as `bf bench` does
+fn bench(args: &BenchArguments) -> Result<(), CliError> {
+    let baseline = match &args.baseline {
+        Some(path) => {
+            Some(bench::Baseline::from_text(&read_file(path)?).map_err(CliError::Baseline)?)
+        }
+        None => None,
+    };
+    let code = read_source(&args.file)?;
+    let mut lexer = Lexer::new(code.iter().copied());
+    if let Some(dialect) = args.dialect.as_deref() {
+        lexer = lexer.dialect(load_dialect(dialect)?);
+    }
+    let tree = SyntaxTree::parse_all_errors(lexer)
+        .map_err(|errors| syntax_error(errors, &code, &origin(&args.file)))?;
+    let input = match (&args.input, &args.input_str) {
+        (Some(path), _) => std::fs::read(path)?,
+        (None, Some(text)) => text.clone().into_bytes(),
+        (None, None) => Vec::new(),
+    };
+
+    let config = BenchConfig {
+        warmup: args.warmup,
+        iterations: args.iterations,
+        opt_level: OptLevel::Aggressive,
+        memory: args.memory_size,
+        ..Default::default()
+    };
+    let report = brainfuck::bench::run(&tree, &input, &config);
+
+    print!("{}", bench::report(&report, baseline.as_ref()));
+    if let Some(path) = &args.save {
+        std::fs::write(path, bench::Baseline::new(&report).to_text())?;
+    }
+
+    Ok(())
+}
+
+/// Interpreter running scripts as set by `args`, reading `input` if given
+fn interpreter(
+    args: &RunArguments,
+    input: Option<&[u8]>,
+) -> Result<BrainFuckInterpreter, CliError> {
+    let mut interpreter = BrainFuckInterpreter::with_memory_size(args.memory_size);
+    interpreter.set_opt_level(OptLevel::Aggressive);
+    interpreter.set_flush_policy(args.flush.into());
+    interpreter.set_output_mode(match args.unicode {
+        true => OutputMode::Utf8,
+        false => args.output_mode.into(),
+    });
+    interpreter.set_eof_behavior(args.eof.into());
+    if let Some(input) = input.or(args.input_str.as_deref().map(str::as_bytes)) {
+        interpreter.replay_input(input.to_vec());
+    } else if let Some(path) = &args.input {
+        interpreter.read_input_from(io::BufReader::new(File::open(path)?));
+    } else if let Some(path) = &args.replay {
+        interpreter.replay_input(std::fs::read(path)?);
+    } else if args.interactive.is_some() {
+        // The standard input has the commands of `--interactive`
+        interpreter.replay_input(Vec::new());
+    } else if args.files.iter().any(|file| is_stdin(file)) {
+        // The standard input was the script, so only the terminal is left
+        match File::open(TERMINAL) {
+            Ok(terminal) => interpreter.read_input_from(terminal),
+            Err(_) => interpreter.replay_input(Vec::new()),
+        }
+    }
+
+    if let Some(path) = &args.output {
+        interpreter.write_output_to(File::create(path)?);
+    }
+    if args.record.is_some() {
+        interpreter.record_input();
+    }
+
+    Ok(interpreter)
+}
+
+/// Optimizes `program` as much as possible, for a fresh memory of the size
+/// set by `args`
+fn optimize(args: &RunArguments, mut program: Program) -> Program {
+    PassManager::for_level(OptLevel::Aggressive, args.memory_size).run(&mut program);
+    program
+}
+
+fn execute<I>(
+    args: &RunArguments,
+    tree: SyntaxTree,
+    tokens: I,
+    input: Option<&[u8]>,
+) -> Result<(), CliError>
+where
+    I: IntoIterator<Item = (Token, Span)>,
+{
+    if let Some(format) = args.dump_ast {
+        print!("{}", dump_ast(args, &tree, format)?);
+        return Ok(());
+    }
+
+    if let Some(output) = &args.compile {
+        let transpiler = CTranspiler::new()
+            .memory(args.memory_size)
+            .eof(args.eof.into());
+        return Ok(transpiler.build_native(&tree, output)?);
+    }
+
+    if let Some(path) = &args.pgo {
+        let profile = Profile::from_text(&read_file(path)?)?;
+        let program = tree.compile_with_profile(OptLevel::Aggressive, &profile);
+        return execute_program(args, input, &program);
+    }
+
+    let mut interpreter = interpreter(args, input)?;
+    interpreter.feed_tree(tree);
+    if args.pgo_record.is_some() || args.stats || args.profile.is_some() {
+        interpreter.start_profiling();
+    }
+    if args.stats {
+        interpreter.start_memory_heatmap();
+    }
+    let start = Instant::now();
+    let stopped = bounded(args, &mut interpreter);
+    let time = start.elapsed();
+
+    if let (true, Some(profile)) = (args.stats, interpreter.profile()) {
+        let tree = interpreter.syntax_tree();
+        let stats = ExecutionStats::new(tree, profile, interpreter.memory_heatmap());
+        eprint!("{}", dump::stats(&stats, time));
+    }
+
+    if let (Some(path), Some(profile)) = (&args.profile, interpreter.profile()) {
+        match path {
+            Some(path)
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "json") =>
+            {
+                std::fs::write(path, dump::hot_spots_json(profile, tokens))?
+            }
+            Some(path) => std::fs::write(path, dump::hot_spots(profile, tokens, None))?,
+            None => eprint!("{}", dump::hot_spots(profile, tokens, Some(HOT_SPOTS))),
+        }
+    }
+
+    if let Some(path) = &args.pgo_record {
+        let profile = interpreter.stop_profiling().unwrap_or_default();
+        std::fs::write(path, profile.to_text())?;
+    }
+
+    dump_memory(args, &interpreter);
+    save_recording(args, &mut interpreter)?;
+    finished(args, stopped?)
+}
+
+/// Runs `program`, already optimized, as set by `args`
+fn execute_program(
+    args: &RunArguments,
+    input: Option<&[u8]>,
+    program: &Program,
+) -> Result<(), CliError> {
+    let mut interpreter = interpreter(args, input)?;
+    let stopped = interpreter.execute_program(program);
+
+    dump_memory(args, &interpreter);
+    save_recording(args, &mut interpreter)?;
+    finished(args, stopped)
+}
+
+/// Prints the memory of `interpreter` if `--dump-memory` asks for it
+fn dump_memory(args: &RunArguments, interpreter: &BrainFuckInterpreter) {
+    let memory = interpreter.memory();
+    let start = memory.len() / 2;
+    let dump = match args.dump_memory {
+        Some(MemoryDump::Cells(cells)) => dump::memory(memory, interpreter.pointer(), |cell| {
+            (start..start.saturating_add(cells)).contains(&cell)
+        }),
+        Some(MemoryDump::NonZero) => {
+            dump::memory(memory, interpreter.pointer(), |cell| memory[cell] != 0)
+        }
+        None => return,
+    };
+
+    eprint!("{dump}");
+}
+
+/// Saves the bytes read by the script of `interpreter` if `--record` asks
+/// for it
+fn save_recording(
+    args: &RunArguments,
+    interpreter: &mut BrainFuckInterpreter,
+) -> Result<(), CliError> {
+    if let Some(path) = &args.record {
+        std::fs::write(path, interpreter.take_recorded_input())?;
+    }
+
+    Ok(())
+}
+
+/// Runs the code fed to `interpreter` until it stops, or until it runs
+/// out of the steps or the time given by `args`, showing its progress if
+/// asked to
+fn bounded(
+    args: &RunArguments,
+    interpreter: &mut BrainFuckInterpreter,
+) -> Result<Stopped, CliError> {
+    let mut progress = match args.progress && io::stderr().is_terminal() {
+        true => Some(progress::Progress::new()),
+        false => None,
+    };
+    if args.timeout.is_none() && progress.is_none() {
+        return Ok(match args.max_steps {
+            Some(steps) => interpreter.step_by(steps),
+            None => interpreter.execute(),
+        });
+    }
+
+    let start = Instant::now();
+    let mut steps = 0;
+    loop {
+        let chunk = match args.max_steps {
+            Some(max_steps) => STEP_CHUNK.min(max_steps - steps),
+            None => STEP_CHUNK,
+        };
+        let stopped = interpreter.step_by(chunk);
+        if stopped != Stopped::StepLimit || Some(steps + chunk) == args.max_steps {
+            return Ok(stopped);
+        }
+        steps += chunk;
+
+        if let Some(progress) = &mut progress {
+            progress.update(steps);
+        }
+        if let Some(timeout) = args.timeout.filter(|&timeout| start.elapsed() >= timeout) {
+            return Err(CliError::Timeout { timeout, steps });
+        }
+    }
+}
+
+/// Runs `tree` as `--trace` does, with the `tokens` it was parsed from
+fn traced<I>(
+    args: &RunArguments,
+    tree: SyntaxTree,
+    tokens: I,
+    input: Option<&[u8]>,
+) -> Result<(), CliError>
+where
+    I: IntoIterator<Item = (Token, Span)>,
+{
+    let mut log: Box<dyn Write> = match args.trace.as_ref().and_then(Option::as_ref) {
+        Some(path) => Box::new(io::BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stderr()),
+    };
+
+    let mut interpreter = interpreter(args, input)?;
+    interpreter.feed_tree(tree);
+    let stopped = trace::run(&mut interpreter, tokens, &mut log, args.max_steps)?;
+
+    dump_memory(args, &interpreter);
+    save_recording(args, &mut interpreter)?;
+    finished(args, stopped)
+}
+
+/// Runs `tree` as `--interactive` does, with the `tokens` it was parsed
+/// from
+fn stepped<I>(
+    args: &RunArguments,
+    tree: SyntaxTree,
+    tokens: I,
+    input: Option<&[u8]>,
+) -> Result<(), CliError>
+where
+    I: IntoIterator<Item = (Token, Span)>,
+{
+    if args.files.iter().any(|file| is_stdin(file)) {
+        let message = "`--interactive` takes commands from the standard input, not the script";
+        return Err(CliError::Usage(message.into()));
+    }
+    let every = args.interactive.unwrap_or(1);
+
+    let mut interpreter = interpreter(args, input)?;
+    interpreter.feed_tree(tree);
+    let stopped = stepper::run(
+        &mut interpreter,
+        tokens,
+        every,
+        &mut io::stdin().lock(),
+        &mut io::stderr(),
+    )?;
+
+    dump_memory(args, &interpreter);
+    save_recording(args, &mut interpreter)?;
+    finished(args, stopped)
+}
+
+/// Writes `tree` in `format`, optimized like it would run for
+/// [`AstFormat::Ops`]
+fn dump_ast(args: &RunArguments, tree: &SyntaxTree, format: AstFormat) -> Result<String, CliError> {
+    Ok(match format {
+        AstFormat::Pretty => dump::pretty(tree),
+        AstFormat::Json => json(tree)? + "\n",
+        AstFormat::Dot => tree.to_dot(),
+        AstFormat::Ops => dump::ops(&optimize(args, Program::new(tree))),
+    })
+}
+
+#[cfg(feature = "serde")]
+fn json(tree: &SyntaxTree) -> Result<String, CliError> {
+    Ok(tree.to_json())
+}
+
+#[cfg(not(feature = "serde"))]
+fn json(_: &SyntaxTree) -> Result<String, CliError> {
+    let message = "JSON needs the `serde` feature";
+    Err(CliError::Usage(message.into()))
+}
+
+/// Whether the script ran as it should, given why it stopped
+fn finished(args: &RunArguments, stopped: Stopped) -> Result<(), CliError> {
+    match (stopped, args.max_steps) {
+        (Stopped::EndOfInput, _) => Err(CliError::EndOfInput),
+        (Stopped::StepLimit, Some(steps)) => Err(CliError::StepLimit(steps)),
+        _ => Ok(()),
+    }
+}
+
+impl CliError {
+    /// Exit status telling what kind of error this is, or 1 for any other
+    fn status(&self) -> u8 {
+        match self {
+            CliError::IO(error) if error.kind() == io::ErrorKind::NotFound => NOT_FOUND_STATUS,
+            CliError::Usage(_) => USAGE_STATUS,
+            CliError::Syntax(_) => SYNTAX_STATUS,
+            CliError::EndOfInput => RUNTIME_STATUS,
+            CliError::StepLimit(_) => STEP_LIMIT_STATUS,
+            CliError::Timeout { .. } => TIMEOUT_STATUS,
+            _ => 1,
+        }
+    }
+}
+
+/// Prints `error` to the standard error, with its `error:` colored if
+/// errors are
+fn report(error: &CliError) {
+    let message = error.to_string();
+    match message.strip_prefix("error:") {
+        Some(rest) if COLORED.load(Ordering::Relaxed) => {
+            eprintln!("\x1b[1;31merror:\x1b[0m{rest}")
+        }
+        _ => eprintln!("{message}"),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Arguments::parse();
+    COLORED.store(args.color.enabled(), Ordering::Relaxed);
+
+    let result = match args.command {
+        Some(Command::Run(run_args)) => start(*run_args),
+        #[cfg(feature = "repl")]
+        Some(Command::Repl) => repl(),
+        Some(Command::Fmt(fmt_args)) => format(&fmt_args),
+        Some(Command::Minify(minify_args)) => minify(&minify_args),
+        Some(Command::Check(check_args)) => check(&check_args),
+        Some(Command::Compile(compile_args)) => compile(&compile_args),
+        #[cfg(all(feature = "tui-debugger", unix))]
+        Some(Command::Debug(debug_args)) => debug(&debug_args),
+        Some(Command::Bench(bench_args)) => bench(&bench_args),
+        Some(Command::Translate(translate_args)) => translate(&translate_args),
+        Some(Command::Generate(generate_args)) => generate(&generate_args),
+        Some(Command::Pipe(pipe_args)) => pipe(&pipe_args),
+        None => start(args.run),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            report(&error);
+            ExitCode::from(error.status())
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+/// Time between redraws of the status line
+const REDRAW: Duration = Duration::from_millis(100);
+
+/// Status line of `--progress`, telling on the standard error how many
+/// instructions ran, how fast, and for how long
+///
+/// The line is drawn over itself and cleared once dropped.
+pub struct Progress {
+    start: Instant,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    /// Starts counting the time from now
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            drawn: None,
+        }
+    }
+
+    /// Redraws the line with the `steps` run so far, if it was not drawn
+    /// lately
+    pub fn update(&mut self, steps: usize) {
+        let now = Instant::now();
+        if self.drawn.is_some_and(|drawn| now - drawn < REDRAW) {
+            return;
+        }
+        self.drawn = Some(now);
+
+        let elapsed = now - self.start;
+        let rate = steps as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        // The output of the script goes out first, so that it is not drawn over
+        let _ = io::stdout().flush();
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r\x1b[K{steps} instructions  {}/s  {:.1}s",
+            rate as u64,
+            elapsed.as_secs_f64()
+        );
+        let _ = stderr.flush();
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.drawn.is_some() {
+            let _ = write!(io::stderr(), "\r\x1b[K");
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use brainfuck::{BrainFuckInterpreter, Fed, ParserSession, ProgramCache};
+use rustyline::{error::ReadlineError, DefaultEditor};
+use std::{
+    fmt::Write,
+    ops::Range,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Cells shown on every line of `:mem`
+const ROW: usize = 16;
+
+const HELP: &str = "\
+:mem [RANGE]  show the cells in RANGE, like `0..32` or `5`, or the ones around the pointer
+:ptr          show where the pointer is
+:reset        start over with a fresh memory
+:load FILE    run the code in FILE
+:save FILE    write the memory and the pointer to FILE as JSON
+:stats        show what has run so far
+:help         show this help";
+
+/// Reads code line by line, running it once every loop is closed
+///
+/// The memory is kept between lines, and what every line writes is shown
+/// right after it. Lines starting with `:` are commands, see [`HELP`].
+/// Lines are remembered between sessions in the user cache.
+pub fn run() -> Result<(), ReadlineError> {
+    let mut editor = DefaultEditor::new()?;
+    let history = ProgramCache::in_user_cache().map(|cache| cache.directory().join("history"));
+    if let Some(history) = &history {
+        let _ = editor.load_history(history);
+    }
+
+    let mut repl = Repl::new();
+    loop {
+        let prompt = match repl.session.is_incomplete() {
+            true => "... ",
+            false => "bf> ",
+        };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C drops the code waiting for its loops to be closed
+            Err(ReadlineError::Interrupted) => {
+                repl.session.reset();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(error),
+        };
+        let _ = editor.add_history_entry(&line);
+
+        match line.trim().strip_prefix(':') {
+            Some(command) if !repl.session.is_incomplete() => {
+                if let Err(error) = repl.command(command) {
+                    eprintln!("error: {error}");
+                }
+            }
+            _ => repl.feed(&line),
+        }
+    }
+
+    if let Some(history) = history {
+        // History only helps, so the REPL ends fine without it
+        let _ = history.parent().map(std::fs::create_dir_all);
+        let _ = editor.save_history(&history);
+    }
+
+    Ok(())
+}
+
+/// What has run since the REPL started, or since `:reset`
+#[derive(Debug, Default)]
+struct Stats {
+    /// Pieces of code run, as lines or files
+    runs: usize,
+    /// Commands of the code run
+    commands: usize,
+    /// Bytes written by the code run
+    written: usize,
+    /// Time spent running code
+    time: Duration,
+}
+
+struct Repl {
+    session: ParserSession,
+    interpreter: BrainFuckInterpreter,
+    stats: Stats,
+}
+
+impl Repl {
+    fn new() -> Self {
+        let mut interpreter = BrainFuckInterpreter::new();
+        interpreter.capture_output();
+
+        Repl {
+            session: ParserSession::new(),
+            interpreter,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Feeds a line of code, running it if every loop is closed
+    fn feed(&mut self, line: &str) {
+        match self.session.feed(line.bytes().chain([b'\n'])) {
+            Ok(Fed::Complete(tree)) => {
+                self.interpreter.clear();
+                self.interpreter.feed_tree(tree);
+                self.execute();
+            }
+            Ok(Fed::Incomplete { .. }) => (),
+            Err(error) => eprintln!("error: {error}"),
+        }
+    }
+
+    /// Runs the code of the interpreter, showing what it writes
+    fn execute(&mut self) {
+        let start = Instant::now();
+        self.interpreter.execute();
+        self.stats.time += start.elapsed();
+
+        let output = self.interpreter.take_output();
+        self.stats.runs += 1;
+        self.stats.commands += self.interpreter.syntax_tree().metrics().commands();
+        self.stats.written += output.len();
+        show(&output);
+    }
+
+    /// Runs a command, given without its `:`
+    fn command(&mut self, command: &str) -> Result<(), String> {
+        let (name, argument) = match command.trim().split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim())),
+            None => (command.trim(), None),
+        };
+
+        match (name, argument) {
+            ("mem", argument) => {
+                let range = self.range(argument)?;
+                print!("{}", self.cells(range));
+            }
+            ("ptr", None) => {
+                let pointer = self.interpreter.pointer();
+                let cell = self.interpreter.memory()[pointer];
+                println!("pointer at {pointer}, holding {cell}");
+            }
+            ("reset", None) => *self = Repl::new(),
+            ("load", Some(path)) => {
+                self.interpreter.clear();
+                self.interpreter
+                    .feed_file(path)
+                    .map_err(|error| format!("can't load {path}: {error}"))?;
+                self.execute();
+            }
+            ("save", Some(path)) => self.save(Path::new(path))?,
+            ("stats", None) => {
+                let Stats {
+                    runs,
+                    commands,
+                    written,
+                    time,
+                } = self.stats;
+                let used = self
+                    .interpreter
+                    .memory()
+                    .iter()
+                    .filter(|&&c| c != 0)
+                    .count();
+
+                println!("runs:          {runs}");
+                println!("commands:      {commands}");
+                println!("bytes written: {written}");
+                println!("time running:  {time:?}");
+                println!("cells in use:  {used}");
+            }
+            ("help", None) => println!("{HELP}"),
+            ("ptr" | "reset" | "load" | "save" | "stats" | "help", _) => {
+                return Err(format!("wrong arguments for `:{name}`, see `:help`"));
+            }
+            _ => return Err(format!("unknown command `:{name}`, see `:help`")),
+        }
+
+        Ok(())
+    }
+
+    /// Cells named by the argument of `:mem`, the row of the pointer
+    /// without one
+    fn range(&self, argument: Option<&str>) -> Result<Range<usize>, String> {
+        let len = self.interpreter.memory().len();
+        let invalid = || format!("invalid range, expected something like `0..{ROW}` or `5`");
+        let number = |text: &str| text.trim().parse::<usize>().map_err(|_| invalid());
+
+        let range = match argument {
+            None => {
+                let start = self.interpreter.pointer() / ROW * ROW;
+                start..start + ROW
+            }
+            Some(argument) => match argument.split_once("..") {
+                Some((start, end)) => {
+                    let start = match start.trim() {
+                        "" => 0,
+                        start => number(start)?,
+                    };
+                    let end = match end.strip_prefix('=') {
+                        Some(end) => number(end)? + 1,
+                        None if end.trim().is_empty() => len,
+                        None => number(end)?,
+                    };
+                    start..end
+                }
+                None => {
+                    let cell = number(argument)?;
+                    cell..cell + 1
+                }
+            },
+        };
+
+        match range.start < range.end.min(len) {
+            true => Ok(range.start..range.end.min(len)),
+            false => Err(format!("the memory has cells from 0 to {}", len - 1)),
+        }
+    }
+
+    /// Shows the cells of `range`, [`ROW`] on each line, marking the one
+    /// the pointer is at
+    fn cells(&self, range: Range<usize>) -> String {
+        let memory = self.interpreter.memory();
+        let pointer = self.interpreter.pointer();
+        let mut text = String::new();
+
+        for start in range.clone().step_by(ROW) {
+            let _ = write!(text, "{start:>6}:");
+            let end = (start + ROW).min(range.end);
+            for (cell, value) in memory[start..end].iter().enumerate() {
+                let _ = match start + cell == pointer {
+                    true => write!(text, "[{value:>3}]"),
+                    false => write!(text, " {value:>3} "),
+                };
+            }
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /// Writes the memory and the pointer to `path` as JSON
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let cells: Vec<_> = self
+            .interpreter
+            .memory()
+            .iter()
+            .map(|cell| cell.to_string())
+            .collect();
+        let json = format!(
+            "{{\"pointer\":{},\"memory\":[{}]}}\n",
+            self.interpreter.pointer(),
+            cells.join(",")
+        );
+
+        std::fs::write(path, json)
+            .map_err(|error| format!("can't save {}: {error}", path.display()))
+    }
+}
+
+/// Prints the bytes written by a line, the same way the interpreter does,
+/// ending them with a line break
+fn show(output: &[u8]) {
+    if output.is_empty() {
+        return;
+    }
+
+    let text: String = output.iter().map(|&byte| byte as char).collect();
+    match text.ends_with('\n') {
+        true => print!("{text}"),
+        false => println!("{text}"),
+    }
+}
@@ -0,0 +1,196 @@
+use brainfuck::{BrainFuckInterpreter, SourceOffset, Span, Stopped, Token};
+use std::{
+    collections::BTreeSet,
+    io::{self, BufRead, Write},
+};
+
+const HELP: &str = "\
+s, step [N]          run N steps, or as many as `--interactive` says
+c, continue          run until a breakpoint or the end of the script
+p, print [CELL]      show the cell under the pointer, or CELL
+b, break [LINE:COL]  toggle a breakpoint on the instruction at LINE:COL,
+                     or on the one running next
+q, quit              stop the script
+h, help              show this help
+An empty line steps again.";
+
+/// Runs the code fed to `interpreter` as told by the `commands` read line
+/// by line, writing where it stopped and what the commands show to `log`
+///
+/// Instructions are found in the `tokens` of the code, and `step` runs
+/// `every` steps unless told how many. Stops once the code does, at `quit`
+/// or once there are no more commands.
+pub fn run<I>(
+    interpreter: &mut BrainFuckInterpreter,
+    tokens: I,
+    every: usize,
+    commands: &mut dyn BufRead,
+    log: &mut dyn Write,
+) -> io::Result<Stopped>
+where
+    I: IntoIterator<Item = (Token, Span)>,
+{
+    let mut stepper = Stepper {
+        interpreter,
+        instructions: tokens
+            .into_iter()
+            .filter(|(token, _)| !matches!(token, Token::Comment(_)))
+            .collect(),
+        breakpoints: BTreeSet::new(),
+    };
+
+    writeln!(log, "type `help` for the commands")?;
+    let mut line = String::new();
+    loop {
+        writeln!(log, "{}", stepper.describe())?;
+        write!(log, "(bf) ")?;
+        log.flush()?;
+
+        line.clear();
+        if commands.read_line(&mut line)? == 0 {
+            writeln!(log)?;
+            return Ok(Stopped::StepLimit);
+        }
+
+        let mut words = line.split_whitespace();
+        let stopped = match (words.next(), words.next()) {
+            (None | Some("s" | "step"), steps) => match steps.map(str::parse).transpose() {
+                Ok(steps) => stepper.advance(steps.unwrap_or(every)),
+                Err(_) => {
+                    writeln!(log, "expected a number of steps")?;
+                    continue;
+                }
+            },
+            (Some("c" | "continue"), _) => stepper.advance(usize::MAX),
+            (Some("p" | "print"), cell) => {
+                writeln!(log, "{}", stepper.print(cell))?;
+                continue;
+            }
+            (Some("b" | "break"), position) => {
+                writeln!(log, "{}", stepper.toggle_breakpoint(position))?;
+                continue;
+            }
+            (Some("q" | "quit"), _) => return Ok(Stopped::StepLimit),
+            (Some("h" | "help"), _) => {
+                writeln!(log, "{HELP}")?;
+                continue;
+            }
+            (Some(command), _) => {
+                writeln!(log, "unknown command `{command}`, see `help`")?;
+                continue;
+            }
+        };
+
+        match stopped {
+            Stopped::Breakpoint(offset) => {
+                writeln!(log, "breakpoint at {}", stepper.position(offset))?
+            }
+            Stopped::StepLimit => {}
+            stopped => return Ok(stopped),
+        }
+    }
+}
+
+struct Stepper<'a> {
+    interpreter: &'a mut BrainFuckInterpreter,
+    /// Every instruction of the script, in order
+    instructions: Vec<(Token, Span)>,
+    breakpoints: BTreeSet<SourceOffset>,
+}
+
+impl Stepper<'_> {
+    /// Runs up to `steps` steps, running past a breakpoint on the
+    /// instruction running next, which the script already stopped at
+    fn advance(&mut self, steps: usize) -> Stopped {
+        let next = self.interpreter.next_offset();
+        let Some(offset) = next.filter(|offset| self.breakpoints.contains(offset)) else {
+            return self.interpreter.step_by(steps);
+        };
+
+        self.interpreter.remove_breakpoint(offset);
+        let stopped = self.interpreter.step();
+        self.interpreter.add_breakpoint(offset);
+        match (stopped, steps) {
+            (Stopped::StepLimit, 2..) => self.interpreter.step_by(steps - 1),
+            (stopped, _) => stopped,
+        }
+    }
+
+    /// The instruction running next, the pointer and the cell under it
+    fn describe(&self) -> String {
+        let pointer = self.interpreter.pointer();
+        let cell = self.interpreter.memory()[pointer];
+        let next = match self.interpreter.next_offset() {
+            Some(offset) => match self.find(offset) {
+                Some((token, span)) => format!("{}:{} {token}", span.line, span.column),
+                None => format!("offset {offset}"),
+            },
+            None => "?".into(),
+        };
+
+        format!("{next}  pointer {pointer}  cell {cell}")
+    }
+
+    /// The value of `cell`, or of the cell under the pointer
+    fn print(&self, cell: Option<&str>) -> String {
+        let memory = self.interpreter.memory();
+        let index = match cell.map(str::parse::<usize>) {
+            None => self.interpreter.pointer(),
+            Some(Ok(index)) if index < memory.len() => index,
+            Some(_) => return format!("expected a cell from 0 to {}", memory.len() - 1),
+        };
+
+        format!("cell {index} holds {}", memory[index])
+    }
+
+    /// Toggles the breakpoint on the instruction at `position`, or on the
+    /// one running next
+    fn toggle_breakpoint(&mut self, position: Option<&str>) -> String {
+        let offset = match position {
+            Some(position) => {
+                let found = position.split_once(':').and_then(|(line, column)| {
+                    let (line, column): (usize, usize) = (line.parse().ok()?, column.parse().ok()?);
+                    self.instructions
+                        .iter()
+                        .find(|(_, span)| span.line == line && span.column == column)
+                });
+                match found {
+                    Some((_, span)) => span.start,
+                    None => return format!("no instruction at {position}"),
+                }
+            }
+            None => match self.interpreter.next_offset() {
+                Some(offset) => offset,
+                None => return "no instruction runs next".into(),
+            },
+        };
+
+        let position = self.position(offset);
+        match self.breakpoints.remove(&offset) {
+            true => {
+                self.interpreter.remove_breakpoint(offset);
+                format!("removed breakpoint at {position}")
+            }
+            false => {
+                self.breakpoints.insert(offset);
+                self.interpreter.add_breakpoint(offset);
+                format!("added breakpoint at {position}")
+            }
+        }
+    }
+
+    /// `line:column` of the instruction at `offset`
+    fn position(&self, offset: SourceOffset) -> String {
+        match self.find(offset) {
+            Some((_, span)) => format!("{}:{}", span.line, span.column),
+            None => format!("offset {offset}"),
+        }
+    }
+
+    /// Instruction at `offset`, with its span
+    fn find(&self, offset: SourceOffset) -> Option<&(Token, Span)> {
+        self.instructions
+            .iter()
+            .find(|(_, span)| span.start == offset)
+    }
+}
@@ -0,0 +1,74 @@
+use brainfuck::{BrainFuckInterpreter, SourceOffset, Span, Stopped, Token};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+};
+
+/// Times every instruction is logged before it is only counted
+const REPEATS: usize = 100;
+
+/// Runs the code fed to `interpreter` one step at a time, logging every
+/// step to `log` with its instruction, found in the `tokens` of the code,
+/// the pointer and the cell under it after the step
+///
+/// Instructions running more than [`REPEATS`] times, like the bodies of
+/// long loops, stop being logged and are summed up once the code stops,
+/// which is after `max_steps` steps if given.
+pub fn run<I>(
+    interpreter: &mut BrainFuckInterpreter,
+    tokens: I,
+    log: &mut dyn Write,
+    max_steps: Option<usize>,
+) -> io::Result<Stopped>
+where
+    I: IntoIterator<Item = (Token, Span)>,
+{
+    let instructions: HashMap<SourceOffset, (Span, Token)> = tokens
+        .into_iter()
+        .map(|(token, span)| (span.start, (span, token)))
+        .collect();
+    let describe = |offset: Option<SourceOffset>| match offset {
+        Some(offset) => match instructions.get(&offset) {
+            Some((span, token)) => format!("{}:{} {token}", span.line, span.column),
+            None => format!("offset {offset}"),
+        },
+        None => "?".into(),
+    };
+
+    let mut runs = BTreeMap::<Option<SourceOffset>, usize>::new();
+    let mut steps = 0;
+    let stopped = loop {
+        if max_steps == Some(steps) {
+            break Stopped::StepLimit;
+        }
+        steps += 1;
+
+        let offset = interpreter.next_offset();
+        let stopped = interpreter.step();
+
+        let count = runs.entry(offset).or_default();
+        *count += 1;
+        if *count <= REPEATS {
+            let pointer = interpreter.pointer();
+            let cell = interpreter.memory()[pointer];
+            writeln!(log, "{}  pointer {pointer}  cell {cell}", describe(offset))?;
+        } else if *count == REPEATS + 1 {
+            writeln!(
+                log,
+                "{}  ran {REPEATS} times, no longer logged",
+                describe(offset)
+            )?;
+        }
+
+        if stopped != Stopped::StepLimit {
+            break stopped;
+        }
+    };
+
+    for (offset, count) in runs.into_iter().filter(|&(_, count)| count > REPEATS) {
+        writeln!(log, "{}  ran {count} times in all", describe(offset))?;
+    }
+    log.flush()?;
+
+    Ok(stopped)
+}
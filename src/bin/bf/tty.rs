@@ -0,0 +1,73 @@
+use nix::{
+    libc,
+    sys::{
+        signal::{self, SigHandler, Signal},
+        termios::{self, LocalFlags, SetArg, SpecialCharacterIndices, Termios},
+    },
+};
+use std::{
+    ffi::c_int,
+    fs::File,
+    io,
+    os::fd::{AsRawFd, BorrowedFd, RawFd},
+    sync::OnceLock,
+};
+
+/// Signals restoring the terminal before ending the process
+const SIGNALS: [Signal; 3] = [Signal::SIGINT, Signal::SIGTERM, Signal::SIGHUP];
+
+/// Terminal and its state before raw mode, for signal handlers
+static ORIGINAL: OnceLock<(RawFd, libc::termios)> = OnceLock::new();
+
+/// Terminal in raw mode until dropped, sending every key to the script as
+/// soon as it is pressed, without echoing it
+///
+/// Its state is restored when dropped, also while panicking, and when the
+/// process is interrupted by a signal.
+pub struct RawTty {
+    tty: File,
+    original: Termios,
+}
+
+impl RawTty {
+    pub fn enable() -> io::Result<Self> {
+        let tty = File::options().read(true).write(true).open("/dev/tty")?;
+        let original = termios::tcgetattr(&tty)?;
+
+        let mut raw = original.clone();
+        raw.local_flags -= LocalFlags::ICANON | LocalFlags::ECHO;
+        raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+        raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+
+        let _ = ORIGINAL.set((tty.as_raw_fd(), original.clone().into()));
+        for signal in SIGNALS {
+            // SAFETY: the handler only calls async-signal-safe functions
+            unsafe { signal::signal(signal, SigHandler::Handler(interrupted)) }?;
+        }
+        termios::tcsetattr(&tty, SetArg::TCSANOW, &raw)?;
+
+        Ok(RawTty { tty, original })
+    }
+}
+
+impl Drop for RawTty {
+    fn drop(&mut self) {
+        let _ = termios::tcsetattr(&self.tty, SetArg::TCSANOW, &self.original);
+        for signal in SIGNALS {
+            // SAFETY: restores the default handler
+            let _ = unsafe { signal::signal(signal, SigHandler::SigDfl) };
+        }
+    }
+}
+
+/// Restores the terminal and ends the process, as `signal` would have
+extern "C" fn interrupted(signal: c_int) {
+    if let Some(&(tty, original)) = ORIGINAL.get() {
+        // SAFETY: the terminal stays open while raw mode is enabled
+        let tty = unsafe { BorrowedFd::borrow_raw(tty) };
+        // Copying the state and `tcsetattr` are async-signal-safe
+        let _ = termios::tcsetattr(tty, SetArg::TCSANOW, &Termios::from(original));
+    }
+    // SAFETY: `_exit` is async-signal-safe
+    unsafe { libc::_exit(128 + signal) }
+}
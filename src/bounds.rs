@@ -0,0 +1,147 @@
+use crate::bytecode::Op;
+
+/// Finds the instructions that can't take the pointer out of the memory
+///
+/// The pointer is followed as an offset from `start`, where the program
+/// starts, through loops moving it back to where they started on every
+/// iteration. It is lost after other loops, after calls and after moves
+/// that may wrap around the memory, and is never known inside procedures.
+///
+/// Each instruction is `true` when it only moves the pointer to, or only
+/// accesses, cells of a memory of `memory` cells without wrapping around
+/// it. Instructions that don't move the pointer nor access other cells
+/// are `false`.
+pub(crate) fn in_bounds(ops: &[Op], start: usize, memory: usize) -> Vec<bool> {
+    let balanced = balanced_loops(ops);
+    let inside = |offset: isize| {
+        start
+            .checked_add_signed(offset)
+            .is_some_and(|index| index < memory)
+    };
+
+    let mut proven = vec![false; ops.len()];
+    // Offset of the pointer before every open loop or procedure
+    let mut open = Vec::new();
+    let mut offset: Option<isize> = Some(0);
+
+    for (index, &op) in ops.iter().enumerate() {
+        match op {
+            Op::Move(amount) => {
+                offset = offset
+                    .and_then(|offset| offset.checked_add(amount))
+                    .filter(|&offset| inside(offset));
+                // A move that may wrap around leaves the pointer unknown
+                proven[index] = offset.is_some();
+            }
+            Op::Multiply { offset: target, .. } => {
+                proven[index] = offset
+                    .and_then(|offset| offset.checked_add(target))
+                    .is_some_and(inside);
+            }
+            Op::JumpIfZero(_) => {
+                open.push(offset);
+                if !balanced[index] {
+                    offset = None;
+                }
+            }
+            Op::Procedure(_) => {
+                open.push(offset);
+                offset = None;
+            }
+            Op::JumpIfNotZero(_) => {
+                let start = open.pop().expect("jumps are balanced");
+                offset = offset.and(start);
+            }
+            Op::Return => offset = open.pop().expect("procedures are balanced"),
+            Op::Call => offset = None,
+            _ => (),
+        }
+    }
+
+    proven
+}
+
+/// Finds the loops whose body moves the pointer back to where it started,
+/// marking their [`Op::JumpIfZero`]
+fn balanced_loops(ops: &[Op]) -> Vec<bool> {
+    let mut balanced = vec![false; ops.len()];
+    // Start, net move and whether it is still balanced, of every open loop
+    let mut open: Vec<(usize, isize, bool)> = Vec::new();
+
+    for (index, &op) in ops.iter().enumerate() {
+        match op {
+            Op::Move(amount) => {
+                if let Some((_, net, still)) = open.last_mut() {
+                    match net.checked_add(amount) {
+                        Some(sum) => *net = sum,
+                        None => *still = false,
+                    }
+                }
+            }
+            Op::JumpIfZero(_) => open.push((index, 0, true)),
+            Op::JumpIfNotZero(_) => {
+                let (start, net, still) = open.pop().expect("jumps are balanced");
+                balanced[start] = still && net == 0;
+                if let Some((_, _, parent)) = open.last_mut() {
+                    *parent &= balanced[start];
+                }
+            }
+            // Procedures are skipped where they are defined, and may move
+            // the pointer anywhere when called
+            Op::Procedure(_) => open.push((index, 0, true)),
+            Op::Return => {
+                open.pop();
+            }
+            Op::Call => {
+                if let Some((_, _, still)) = open.last_mut() {
+                    *still = false;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    balanced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dialect::Dialect, syntax::SyntaxTree, token::Lexer};
+
+    fn proven(code: &str, start: usize, memory: usize) -> Vec<bool> {
+        let lexer = Lexer::new(code.bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(lexer).unwrap();
+        let program = crate::bytecode::Program::new(&tree);
+
+        in_bounds(program.ops(), start, memory)
+    }
+
+    #[test]
+    fn bounds_follow_balanced_loops() {
+        // [ > [ < > ] < ] >
+        assert_eq!(
+            proven("[>[<>]<]>", 1, 3),
+            [false, true, false, true, true, false, true, false, true]
+        );
+        assert_eq!(proven(">>", 1, 3), [true, false]);
+        assert_eq!(proven("<<", 1, 3), [true, false]);
+        assert_eq!(proven(">><", 1, 3), [true, false, false]);
+    }
+
+    #[test]
+    fn bounds_lost_after_unbalanced_loops() {
+        // [ > ] < and [ [ > ] < ] <
+        assert_eq!(proven("[>]<", 1, 8), [false, false, false, false]);
+        assert_eq!(proven("[[>]<]<", 1, 8).iter().filter(|&&p| p).count(), 0);
+    }
+
+    #[test]
+    fn bounds_lost_inside_procedures_and_after_calls() {
+        // ( > ) > : >
+        assert_eq!(
+            proven("(>)>:>", 1, 8),
+            [false, false, false, true, false, false]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{
+    syntax::{BadExpressionError, SyntaxTree},
+    token::{Span, Token},
+};
+use derive_more::{Display, Error, From};
+use std::io::{BufRead, Seek};
+
+/// Error while reading a Brainloller image
+#[derive(Debug, Display, Error, From)]
+pub enum ImageError {
+    Decoding(png::DecodingError),
+    Syntax(BadExpressionError),
+}
+
+/// Direction the instruction pointer moves in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Direction {
+    East,
+    South,
+    West,
+    North,
+}
+
+impl Direction {
+    fn clockwise(self) -> Self {
+        match self {
+            Direction::East => Direction::South,
+            Direction::South => Direction::West,
+            Direction::West => Direction::North,
+            Direction::North => Direction::East,
+        }
+    }
+
+    fn counterclockwise(self) -> Self {
+        self.clockwise().clockwise().clockwise()
+    }
+}
+
+/// Follows the instruction pointer of a Brainloller image, giving the
+/// tokens of the pixels it goes through
+///
+/// `pixels` are the RGB colors of the image, row by row. The pointer starts
+/// at the top left corner moving right, turns on cyan pixels and ends
+/// when it leaves the image, which it always does since it can't come
+/// back to the same pixel in the same direction. The span of a token is
+/// the pixel it comes from, where `start` is the index of the pixel and
+/// lines are rows.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{brainloller_tokens, Token};
+/// const INCREMENT: [u8; 3] = [0, 255, 0];
+/// const TURN: [u8; 3] = [0, 255, 255];
+///
+/// let pixels = [INCREMENT, TURN, [0; 3], INCREMENT];
+/// let tokens = brainloller_tokens(&pixels, 2);
+///
+/// assert_eq!(tokens[1].0, Token::Increment);
+/// assert_eq!((tokens[1].1.start, tokens[1].1.line), (3, 2));
+/// ```
+pub fn brainloller_tokens(pixels: &[[u8; 3]], width: usize) -> Vec<(Token, Span)> {
+    let height = pixels.len().checked_div(width).unwrap_or(0);
+    let (mut x, mut y, mut direction) = (0, 0, Direction::East);
+    let mut tokens = Vec::new();
+
+    while x < width && y < height {
+        let index = y * width + x;
+
+        let token = match pixels[index] {
+            [255, 0, 0] => Some(Token::MoveRight),
+            [128, 0, 0] => Some(Token::MoveLeft),
+            [0, 255, 0] => Some(Token::Increment),
+            [0, 128, 0] => Some(Token::Decrement),
+            [0, 0, 255] => Some(Token::WriteByte),
+            [0, 0, 128] => Some(Token::ReadByte),
+            [255, 255, 0] => Some(Token::LoopStart),
+            [128, 128, 0] => Some(Token::LoopEnd),
+            [0, 255, 255] => {
+                direction = direction.clockwise();
+                None
+            }
+            [0, 128, 128] => {
+                direction = direction.counterclockwise();
+                None
+            }
+            _ => None,
+        };
+
+        if let Some(token) = token {
+            let span = Span {
+                start: index,
+                end: index + 1,
+                line: y + 1,
+                column: x + 1,
+            };
+            tokens.push((token, span));
+        }
+
+        // Leaving through the top or the left wraps to usize::MAX
+        (x, y) = match direction {
+            Direction::East => (x + 1, y),
+            Direction::South => (x, y + 1),
+            Direction::West => (x.wrapping_sub(1), y),
+            Direction::North => (x, y.wrapping_sub(1)),
+        };
+    }
+
+    tokens
+}
+
+impl SyntaxTree {
+    /// Parses a Brainloller program from a PNG image
+    ///
+    /// See [`brainloller_tokens`] for how pixels become tokens.
+    pub fn parse_brainloller<R: BufRead + Seek>(reader: R) -> Result<Self, ImageError> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info()?;
+        let size = reader
+            .output_buffer_size()
+            .expect("the decoder limits the size of images");
+        let mut buffer = vec![0; size];
+        let info = reader.next_frame(&mut buffer)?;
+
+        let samples = info.color_type.samples();
+        let pixels: Vec<_> = buffer[..info.buffer_size()]
+            .chunks(info.line_size)
+            .flat_map(|line| line[..info.width as usize * samples].chunks(samples))
+            .map(|pixel| match *pixel {
+                [gray] | [gray, _] => [gray; 3],
+                [red, green, blue, ..] => [red, green, blue],
+                _ => unreachable!("pixels have between 1 and 4 samples"),
+            })
+            .collect();
+
+        let tokens = brainloller_tokens(&pixels, info.width as usize);
+        Ok(Self::parse_spanned_tokens(tokens)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INCREMENT: [u8; 3] = [0, 255, 0];
+    const OUTPUT: [u8; 3] = [0, 0, 255];
+    const LOOP_START: [u8; 3] = [255, 255, 0];
+    const LOOP_END: [u8; 3] = [128, 128, 0];
+    const CLOCKWISE: [u8; 3] = [0, 255, 255];
+    const COUNTERCLOCKWISE: [u8; 3] = [0, 128, 128];
+    const NOTHING: [u8; 3] = [255, 255, 255];
+
+    fn code(pixels: &[[u8; 3]], width: usize) -> Result<String, BadExpressionError> {
+        let tokens = brainloller_tokens(pixels, width);
+        Ok(SyntaxTree::parse_spanned_tokens(tokens)?.to_code())
+    }
+
+    #[test]
+    fn brainloller_follows_rotations() {
+        #[rustfmt::skip]
+        let pixels = [
+            INCREMENT, LOOP_START, CLOCKWISE,
+            NOTHING,   OUTPUT,     INCREMENT,
+            NOTHING,   NOTHING,    LOOP_END,
+        ];
+
+        assert_eq!(code(&pixels, 3).unwrap(), "+[+]");
+
+        #[rustfmt::skip]
+        let pixels = [
+            INCREMENT, CLOCKWISE,        NOTHING,
+            NOTHING,   OUTPUT,           NOTHING,
+            NOTHING,   COUNTERCLOCKWISE, INCREMENT,
+        ];
+
+        assert_eq!(code(&pixels, 3).unwrap(), "+.+");
+    }
+
+    #[test]
+    fn brainloller_ignores_other_colors() {
+        let pixels = [NOTHING, [1, 2, 3], INCREMENT, [0, 127, 0]];
+
+        assert_eq!(code(&pixels, 4).unwrap(), "+");
+        assert_eq!(code(&[], 0).unwrap(), "");
+    }
+
+    #[test]
+    fn brainloller_decodes_png() {
+        let pixels = [INCREMENT, LOOP_START, LOOP_END, OUTPUT];
+        let mut image = Vec::new();
+        let mut encoder = png::Encoder::new(&mut image, 4, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(pixels.as_flattened())
+            .unwrap();
+
+        let tree = SyntaxTree::parse_brainloller(std::io::Cursor::new(image)).unwrap();
+
+        assert_eq!(tree.to_code(), "+[].");
+        assert_eq!(tree.span(2).map(|s| (s.start, s.column)), Some((3, 4)));
+    }
+}
@@ -0,0 +1,436 @@
+use crate::{
+    execution::DEFAULT_BRAINFUCK_STACK_SIZE,
+    optimizer::{OptLevel, PassManager},
+    profile::Profile,
+    syntax::{Expression, SyntaxTree},
+    token::Span,
+};
+
+/// Share of the steps of a profile, as a divisor, a loop must take to be
+/// hot
+const HOT_SHARE: u64 = 100;
+
+/// Instruction of a compiled [`Program`]
+///
+/// Jumps hold the index of the instruction to go to, so running a
+/// program never has to look for the matching bracket.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Op {
+    /// A run of `+` and `-`, adding their net amount to the current cell
+    Add(u8),
+    /// A run of `>` and `<`, moving the pointer by their net amount
+    Move(isize),
+    /// Sets the current cell to the given value, like `[-]` does with zero
+    Set(u8),
+    /// Adds the current cell times `factor` to the cell at `offset` from
+    /// the pointer, like `[->++<]` does before clearing the current cell
+    Multiply {
+        offset: isize,
+        factor: u8,
+    },
+    Input,
+    Output,
+    DebugDump,
+    /// `[`, jumping to the given index when the current cell is zero
+    JumpIfZero(usize),
+    /// `]`, jumping to the given index when the current cell is not zero
+    JumpIfNotZero(usize),
+    /// `(`, defining a procedure starting at the next index and jumping to
+    /// the given index, right after its [`Return`](Op::Return)
+    Procedure(usize),
+    /// `)`, going back to the instruction after the last call
+    Return,
+    Call,
+    End,
+    Store,
+    Retrieve,
+    ShiftLeft,
+    ShiftRight,
+    Not,
+    Xor,
+    And,
+    Or,
+}
+
+/// A [`SyntaxTree`] compiled into a flat list of instructions
+///
+/// # Example
+/// ```
+/// # use brainfuck::{Op, SyntaxTree};
+/// let tree: SyntaxTree = "+++[->><]".parse().unwrap();
+///
+/// assert_eq!(
+///     tree.compile().ops(),
+///     [Op::Add(3), Op::JumpIfZero(5), Op::Add(255), Op::Move(1), Op::JumpIfNotZero(2)]
+/// );
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
+pub struct Program {
+    pub(crate) ops: Vec<Op>,
+}
+
+impl Program {
+    /// Lowers `tree` into one instruction for each expression, without
+    /// optimizing it
+    pub fn new(tree: &SyntaxTree) -> Self {
+        Self::from_block(tree)
+    }
+
+    /// Lowers a block of expressions, like a part of a tree
+    pub(crate) fn from_block(block: &[Expression]) -> Self {
+        let mut program = Self::default();
+        program.lower(block);
+        program.relink();
+
+        program
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    fn lower(&mut self, block: &[Expression]) {
+        // Blocks being lowered, with the index of their next expression and
+        // the instruction closing them
+        let mut blocks = vec![(block, 0, None)];
+
+        while let Some((block, index, closing)) = blocks.last_mut() {
+            let Some(expr) = block.get(*index) else {
+                if let Some(op) = *closing {
+                    self.ops.push(op);
+                }
+                blocks.pop();
+                continue;
+            };
+            *index += 1;
+
+            let op = match expr {
+                Expression::Increment => Op::Add(1),
+                Expression::Decrement => Op::Add(u8::MAX),
+                Expression::Forward => Op::Move(1),
+                Expression::Backward => Op::Move(-1),
+                Expression::Input => Op::Input,
+                Expression::Output => Op::Output,
+                Expression::DebugDump => Op::DebugDump,
+                Expression::Loop(body) => {
+                    self.ops.push(Op::JumpIfZero(0));
+                    blocks.push((body, 0, Some(Op::JumpIfNotZero(0))));
+                    continue;
+                }
+                Expression::Procedure(body) => {
+                    self.ops.push(Op::Procedure(0));
+                    blocks.push((body, 0, Some(Op::Return)));
+                    continue;
+                }
+                Expression::Call => Op::Call,
+                Expression::End => Op::End,
+                Expression::Store => Op::Store,
+                Expression::Retrieve => Op::Retrieve,
+                Expression::ShiftLeft => Op::ShiftLeft,
+                Expression::ShiftRight => Op::ShiftRight,
+                Expression::Not => Op::Not,
+                Expression::Xor => Op::Xor,
+                Expression::And => Op::And,
+                Expression::Or => Op::Or,
+            };
+            self.ops.push(op);
+        }
+    }
+
+    /// Whether every jump, procedure and return is balanced and points
+    /// where [`relink`](Self::relink) would
+    pub(crate) fn is_linked(&self) -> bool {
+        let mut open = Vec::new();
+
+        for (index, &op) in self.ops.iter().enumerate() {
+            let linked = match op {
+                Op::JumpIfZero(_) | Op::Procedure(_) => {
+                    open.push(index);
+                    true
+                }
+                Op::JumpIfNotZero(target) => open.pop().is_some_and(|start| {
+                    self.ops[start] == Op::JumpIfZero(index + 1) && target == start + 1
+                }),
+                Op::Return => open
+                    .pop()
+                    .is_some_and(|start| self.ops[start] == Op::Procedure(index + 1)),
+                _ => true,
+            };
+
+            if !linked {
+                return false;
+            }
+        }
+
+        open.is_empty()
+    }
+
+    /// Points every jump to where it belongs after instructions were added
+    /// or removed
+    ///
+    /// # Panics
+    /// If jumps, procedures and returns are not balanced.
+    pub(crate) fn relink(&mut self) {
+        let mut opened = Vec::new();
+
+        for index in 0..self.ops.len() {
+            match self.ops[index] {
+                Op::JumpIfZero(_) | Op::Procedure(_) => opened.push(index),
+                Op::JumpIfNotZero(_) => {
+                    let start = opened.pop().expect("jumps are balanced");
+                    self.ops[start] = Op::JumpIfZero(index + 1);
+                    self.ops[index] = Op::JumpIfNotZero(start + 1);
+                }
+                Op::Return => {
+                    let start = opened.pop().expect("procedures are balanced");
+                    self.ops[start] = Op::Procedure(index + 1);
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl SyntaxTree {
+    /// Compiles this tree into a [`Program`] optimized by the default
+    /// [`PassManager`], which runs faster than walking the tree
+    pub fn compile(&self) -> Program {
+        self.compile_with(OptLevel::default())
+    }
+
+    /// Compiles this tree into a [`Program`] optimized as much as `level`
+    /// allows
+    ///
+    /// [`OptLevel::Aggressive`] assumes the program runs with a fresh
+    /// memory of the default size.
+    pub fn compile_with(&self, level: OptLevel) -> Program {
+        let mut program = Program::new(self);
+        PassManager::for_level(level, DEFAULT_BRAINFUCK_STACK_SIZE).run(&mut program);
+
+        program
+    }
+
+    /// Compiles this tree like [`compile_with`](Self::compile_with), after
+    /// optimizing harder the loops that were hot when `profile` was gathered
+    ///
+    /// A loop is hot when checking its condition again at its `]` took at
+    /// least 1% of the steps counted by `profile`, gathered by running this
+    /// same tree. Hot loops go through [`PassManager::for_hot_code`], while
+    /// the rest of the code is only optimized as `level` allows. Trees not
+    /// parsed from source have no hot loops.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{BrainFuckInterpreter, OptLevel, SyntaxTree};
+    /// let code = "++++++++[>+<->+<.-]";
+    /// let mut interpreter = BrainFuckInterpreter::new();
+    /// interpreter.feed(code.bytes()).unwrap();
+    /// interpreter.start_profiling();
+    /// interpreter.execute();
+    /// let profile = interpreter.stop_profiling().unwrap();
+    ///
+    /// let tree: SyntaxTree = code.parse().unwrap();
+    /// let program = tree.compile_with_profile(OptLevel::Basic, &profile);
+    /// assert!(program.ops().len() < tree.compile().ops().len());
+    /// ```
+    pub fn compile_with_profile(&self, level: OptLevel, profile: &Profile) -> Program {
+        let threshold = (profile.total() / HOT_SHARE).max(1);
+        let hot: Vec<bool> = loop_spans(self)
+            .into_iter()
+            .map(|span| span.is_some_and(|span| profile.count(span.end - 1) >= threshold))
+            .collect();
+
+        let lowered = Program::new(self);
+        let hot_passes = PassManager::for_hot_code();
+        let mut program = Program::default();
+        let mut loops = 0;
+        let mut index = 0;
+
+        while let Some(&op) = lowered.ops.get(index) {
+            if let Op::JumpIfZero(after) = op {
+                loops += 1;
+
+                if hot[loops - 1] {
+                    let mut hot_loop = Program {
+                        ops: lowered.ops[index..after].to_vec(),
+                    };
+                    hot_loop.relink();
+                    hot_passes.run(&mut hot_loop);
+                    program.ops.extend(hot_loop.ops);
+
+                    // Loops nested in a hot loop were optimized along with it
+                    loops += lowered.ops[index + 1..after]
+                        .iter()
+                        .filter(|op| matches!(op, Op::JumpIfZero(_)))
+                        .count();
+                    index = after;
+                    continue;
+                }
+            }
+
+            program.ops.push(op);
+            index += 1;
+        }
+
+        program.relink();
+        PassManager::for_level(level, DEFAULT_BRAINFUCK_STACK_SIZE).run(&mut program);
+
+        program
+    }
+}
+
+/// Location of every loop of `tree`, in the order [`Program::new`] lowers
+/// them
+fn loop_spans(tree: &SyntaxTree) -> Vec<Option<Span>> {
+    let mut spans = Vec::new();
+    // Blocks being looked through, with the index of their next expression
+    let mut blocks = vec![(tree, 0)];
+
+    while let Some((block, index)) = blocks.last_mut() {
+        let Some(expr) = block.get(*index) else {
+            blocks.pop();
+            continue;
+        };
+        let position = *index;
+        *index += 1;
+
+        match expr {
+            Expression::Loop(body) => {
+                spans.push(block.span(position));
+                blocks.push((body, 0));
+            }
+            Expression::Procedure(body) => blocks.push((body, 0)),
+            _ => (),
+        }
+    }
+
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dialect::Dialect, token::Lexer};
+
+    #[test]
+    fn compile_with_profile_matches_unoptimized_program() {
+        use crate::{execution::MemoryContext, input::Input};
+
+        // The hot loop doubles a cell known to hold 1 in place, then
+        // decrements it
+        let code = ",[>[-]+.[->++<]>[-<+>]<.-[>+<[-]]<-]>>.";
+        let tree: SyntaxTree = code.parse().unwrap();
+        let mut profile = Profile::new();
+        profile.record(code.rfind(']').unwrap());
+
+        let run = |program: &Program| {
+            let mut memory = MemoryContext::new();
+            memory.capture_output();
+            memory.set_input(Input::replay(vec![20]));
+            memory.run_program(program);
+            memory.take_output()
+        };
+        let expected = run(&Program::new(&tree));
+
+        for level in [OptLevel::Basic, OptLevel::Aggressive] {
+            let program = tree.compile_with_profile(level, &profile);
+            assert!(program.ops().contains(&Op::Multiply {
+                offset: 0,
+                factor: 1
+            }));
+            assert_eq!(run(&program), expected, "{level:?}");
+        }
+    }
+
+    #[test]
+    fn compile_with_profile_optimizes_hot_loops() {
+        // Both loops are the same, but only the first one is hot
+        let tree: SyntaxTree = "++++++++[>+<->+<.-]>>[>+<->+<.-]".parse().unwrap();
+        let mut profile = Profile::new();
+        for offset in [0, 8, 18, 18, 18, 18, 18, 18, 18, 21] {
+            profile.record(offset);
+        }
+
+        let program = tree.compile_with_profile(OptLevel::Basic, &profile);
+        assert_eq!(
+            program.ops()[..9],
+            [
+                Op::Add(8),
+                Op::JumpIfZero(9),
+                Op::Add(255),
+                Op::Move(1),
+                Op::Add(2),
+                Op::Move(-1),
+                Op::Output,
+                Op::Add(255),
+                Op::JumpIfNotZero(2),
+            ]
+        );
+        // The cold loop is left as it was
+        assert_eq!(program.ops().len(), 21);
+        assert_eq!(program.ops()[11..20], tree.compile().ops()[14..23]);
+        assert_eq!(
+            tree.compile_with_profile(OptLevel::Basic, &Profile::new()),
+            tree.compile()
+        );
+    }
+
+    #[test]
+    fn compile_nested_jumps() {
+        let tree: SyntaxTree = "[>[>]<]".parse().unwrap();
+
+        assert_eq!(
+            tree.compile().ops(),
+            [
+                Op::JumpIfZero(7),
+                Op::Move(1),
+                Op::JumpIfZero(5),
+                Op::Move(1),
+                Op::JumpIfNotZero(3),
+                Op::Move(-1),
+                Op::JumpIfNotZero(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn program_lowers_deep_nesting() {
+        let depth = 100_000;
+        let code = "[".repeat(depth) + "+" + &"]".repeat(depth);
+        let program = Program::new(&code.parse().unwrap());
+
+        assert_eq!(program.ops().len(), depth * 2 + 1);
+        assert_eq!(program.ops()[0], Op::JumpIfZero(depth * 2 + 1));
+        assert_eq!(program.ops()[depth], Op::Add(1));
+        assert_eq!(program.ops()[depth * 2], Op::JumpIfNotZero(1));
+    }
+
+    #[test]
+    fn program_lowers_every_expression() {
+        let tree: SyntaxTree = "+-[>+<]".parse().unwrap();
+
+        assert_eq!(
+            Program::new(&tree).ops(),
+            [
+                Op::Add(1),
+                Op::Add(255),
+                Op::JumpIfZero(7),
+                Op::Move(1),
+                Op::Add(1),
+                Op::Move(-1),
+                Op::JumpIfNotZero(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_procedures() {
+        let lexer = Lexer::new("(+):".bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(lexer).unwrap();
+
+        assert_eq!(
+            tree.compile().ops(),
+            [Op::Procedure(3), Op::Add(1), Op::Return, Op::Call]
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::bytecode::{Op, Program};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Start of every cached file, changing along with the crate so files
+/// written by other versions are never read
+const MAGIC: &[u8] = concat!("brainfuck ", env!("CARGO_PKG_VERSION"), "\n").as_bytes();
+
+/// Directory of compiled programs, kept between runs
+///
+/// Programs are stored along with the source they were compiled from and
+/// a description of the settings used, like the dialect or the
+/// optimization level, so they are only given back for the same source
+/// and settings. Files are named after a hash of both.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{ProgramCache, SyntaxTree};
+/// let cache = ProgramCache::new(std::env::temp_dir().join("brainfuck-cache-example"));
+/// let source = "+++[->++<]";
+/// let program = source.parse::<SyntaxTree>().unwrap().compile();
+///
+/// cache.insert(source.as_bytes(), "basic", &program).unwrap();
+/// assert_eq!(cache.get(source.as_bytes(), "basic"), Some(program));
+/// assert_eq!(cache.get(source.as_bytes(), "aggressive"), None);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct ProgramCache {
+    directory: PathBuf,
+}
+
+impl ProgramCache {
+    /// Keeps programs in `directory`, created when first needed
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        ProgramCache {
+            directory: directory.into(),
+        }
+    }
+
+    /// Cache in `brainfuck` under `$XDG_CACHE_HOME`, or under `~/.cache`
+    /// if it is not set
+    ///
+    /// Returns [`None`] if neither variable is set.
+    pub fn in_user_cache() -> Option<Self> {
+        let base = match env::var_os("XDG_CACHE_HOME") {
+            Some(base) if !base.is_empty() => PathBuf::from(base),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+        };
+
+        Some(Self::new(base.join("brainfuck")))
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Program compiled from `source` with `settings`, if it was stored
+    ///
+    /// Files that can't be read or are not valid are ignored.
+    pub fn get(&self, source: &[u8], settings: &str) -> Option<Program> {
+        let bytes = fs::read(self.path(source, settings)).ok()?;
+        let mut reader = Reader(&bytes);
+
+        if reader.take(MAGIC.len())? != MAGIC
+            || reader.chunk()? != settings.as_bytes()
+            || reader.chunk()? != source
+        {
+            return None;
+        }
+
+        let mut ops = Vec::new();
+        while !reader.0.is_empty() {
+            ops.push(reader.op()?);
+        }
+
+        let program = Program { ops };
+        program.is_linked().then_some(program)
+    }
+
+    /// Stores `program`, compiled from `source` with `settings`
+    pub fn insert(&self, source: &[u8], settings: &str, program: &Program) -> io::Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        write_chunk(&mut bytes, settings.as_bytes());
+        write_chunk(&mut bytes, source);
+        for &op in program.ops() {
+            write_op(&mut bytes, op);
+        }
+
+        fs::create_dir_all(&self.directory)?;
+        // Written aside and renamed, so a file is never read half written
+        let path = self.path(source, settings);
+        let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&partial, bytes)?;
+        fs::rename(&partial, path)
+    }
+
+    fn path(&self, source: &[u8], settings: &str) -> PathBuf {
+        let hash = fnv1a(fnv1a(FNV_OFFSET, settings.as_bytes()), source);
+        self.directory.join(format!("{hash:016x}.bfc"))
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Hashes `bytes` with FNV-1a, starting from `hash`
+///
+/// It is used instead of the hashers of the standard library since
+/// those may change between versions of Rust.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, chunk: &[u8]) {
+    bytes.extend_from_slice(&(chunk.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(chunk);
+}
+
+fn write_op(bytes: &mut Vec<u8>, op: Op) {
+    let (tag, operands): (u8, &[i64]) = match op {
+        Op::Add(value) => (0, &[value as i64]),
+        Op::Move(offset) => (1, &[offset as i64]),
+        Op::Set(value) => (2, &[value as i64]),
+        Op::Multiply { offset, factor } => (3, &[offset as i64, factor as i64]),
+        Op::Input => (4, &[]),
+        Op::Output => (5, &[]),
+        Op::DebugDump => (6, &[]),
+        Op::JumpIfZero(target) => (7, &[target as i64]),
+        Op::JumpIfNotZero(target) => (8, &[target as i64]),
+        Op::Procedure(after) => (9, &[after as i64]),
+        Op::Return => (10, &[]),
+        Op::Call => (11, &[]),
+        Op::End => (12, &[]),
+        Op::Store => (13, &[]),
+        Op::Retrieve => (14, &[]),
+        Op::ShiftLeft => (15, &[]),
+        Op::ShiftRight => (16, &[]),
+        Op::Not => (17, &[]),
+        Op::Xor => (18, &[]),
+        Op::And => (19, &[]),
+        Op::Or => (20, &[]),
+    };
+
+    bytes.push(tag);
+    for operand in operands {
+        bytes.extend_from_slice(&operand.to_le_bytes());
+    }
+}
+
+/// Reads what [`ProgramCache::insert`] writes, giving [`None`] when it
+/// isn't there
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = self.0.split_at_checked(len)?;
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        Some(i64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn chunk(&mut self) -> Option<&'a [u8]> {
+        let len = self.number()?;
+        self.take(usize::try_from(len).ok()?)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        u8::try_from(self.number()?).ok()
+    }
+
+    fn offset(&mut self) -> Option<isize> {
+        isize::try_from(self.number()?).ok()
+    }
+
+    fn index(&mut self) -> Option<usize> {
+        usize::try_from(self.number()?).ok()
+    }
+
+    fn op(&mut self) -> Option<Op> {
+        let op = match self.take(1)?[0] {
+            0 => Op::Add(self.byte()?),
+            1 => Op::Move(self.offset()?),
+            2 => Op::Set(self.byte()?),
+            3 => Op::Multiply {
+                offset: self.offset()?,
+                factor: self.byte()?,
+            },
+            4 => Op::Input,
+            5 => Op::Output,
+            6 => Op::DebugDump,
+            7 => Op::JumpIfZero(self.index()?),
+            8 => Op::JumpIfNotZero(self.index()?),
+            9 => Op::Procedure(self.index()?),
+            10 => Op::Return,
+            11 => Op::Call,
+            12 => Op::End,
+            13 => Op::Store,
+            14 => Op::Retrieve,
+            15 => Op::ShiftLeft,
+            16 => Op::ShiftRight,
+            17 => Op::Not,
+            18 => Op::Xor,
+            19 => Op::And,
+            20 => Op::Or,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dialect::Dialect, syntax::SyntaxTree, token::Lexer};
+
+    fn cache(name: &str) -> ProgramCache {
+        let directory =
+            env::temp_dir().join(format!("brainfuck-cache-{name}-{}", std::process::id()));
+        ProgramCache::new(directory)
+    }
+
+    #[test]
+    fn cache_round_trips_programs() {
+        let cache = cache("round-trip");
+        let source = b"+(>[-]<)>++[<+++>-]<[->>+<<]:,.#$!{}~^&|@";
+        let tokens = Lexer::new(source.iter().copied()).dialect(Dialect::extended());
+        let program = SyntaxTree::parse_spanned_tokens(tokens).unwrap().compile();
+
+        assert_eq!(cache.get(source, "extended"), None);
+        cache.insert(source, "extended", &program).unwrap();
+        assert_eq!(cache.get(source, "extended"), Some(program));
+        assert_eq!(cache.get(source, "pbrain"), None);
+        assert_eq!(cache.get(b"+", "extended"), None);
+
+        fs::remove_dir_all(cache.directory()).unwrap();
+    }
+
+    #[test]
+    fn cache_ignores_invalid_files() {
+        let cache = cache("invalid");
+        let program = Program::new(&"[+]".parse().unwrap());
+        cache.insert(b"[+]", "", &program).unwrap();
+        let path = cache.path(b"[+]", "");
+        let bytes = fs::read(&path).unwrap();
+
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(cache.get(b"[+]", ""), None);
+
+        // The last jump goes somewhere else
+        let mut relinked = bytes.clone();
+        let len = relinked.len();
+        relinked[len - 8] ^= 1;
+        fs::write(&path, &relinked).unwrap();
+        assert_eq!(cache.get(b"[+]", ""), None);
+
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(cache.get(b"[+]", ""), Some(program));
+
+        fs::remove_dir_all(cache.directory()).unwrap();
+    }
+}
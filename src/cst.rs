@@ -0,0 +1,431 @@
+use crate::{
+    syntax::{BadExpressionError, SyntaxTree},
+    token::{Lexer, Span, Token},
+};
+use std::mem;
+
+/// A piece of source code in a [`ConcreteSyntaxTree`]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum CstNode {
+    /// One of the commands `><+-,.`, `#` with the debug dump extension,
+    /// `:` with the pbrain dialect or `@$!{}~^&|` with Extended Type I
+    Command { token: Token, span: Span },
+
+    /// A run of bytes that are not commands and are all whitespace
+    Whitespace { text: Vec<u8>, span: Span },
+
+    /// A run of bytes that are not commands, with some non-whitespace
+    Comment { text: Vec<u8>, span: Span },
+
+    /// A loop, along with the location of its brackets
+    Loop {
+        open: Span,
+        body: Vec<CstNode>,
+        close: Span,
+    },
+
+    /// A procedure of the pbrain dialect, along with the location of its
+    /// parentheses
+    Procedure {
+        open: Span,
+        body: Vec<CstNode>,
+        close: Span,
+    },
+}
+
+impl CstNode {
+    /// Location of the whole node, including the brackets of a loop or a
+    /// procedure
+    pub fn span(&self) -> Span {
+        match self {
+            CstNode::Command { span, .. }
+            | CstNode::Whitespace { span, .. }
+            | CstNode::Comment { span, .. } => *span,
+            CstNode::Loop { open, close, .. } | CstNode::Procedure { open, close, .. } => Span {
+                end: close.end,
+                ..*open
+            },
+        }
+    }
+
+    /// Whether this node has no meaning for the program
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, CstNode::Whitespace { .. } | CstNode::Comment { .. })
+    }
+}
+
+/// Prose written in the source code, between the commands of a program
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Comment {
+    /// Text of the comment, without the whitespace around it
+    pub text: String,
+    /// Location of the comment, including the whitespace around it
+    pub span: Span,
+    /// Index on every nesting level of the expression following the
+    /// comment in the [`SyntaxTree`]. The last index is the length of the
+    /// block for a comment at its end.
+    pub path: Vec<usize>,
+}
+
+/// A lossless tree of BrainFuck source code
+///
+/// Unlike [`SyntaxTree`], it keeps comments and whitespace along with their
+/// location, so the original source can be rebuilt byte by byte. This is
+/// the base for tools that rewrite code while keeping what the author wrote.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
+pub struct ConcreteSyntaxTree {
+    nodes: Vec<CstNode>,
+}
+
+impl ConcreteSyntaxTree {
+    /// Parse a collection of located tokens into a valid tree
+    pub fn parse<T>(tokens: T) -> Result<Self, BadExpressionError>
+    where
+        T: IntoIterator<Item = (Token, Span)>,
+    {
+        let mut nodes = Vec::new();
+        let mut parents = Vec::new();
+
+        for (token, span) in tokens {
+            match token {
+                Token::LoopStart | Token::ProcedureStart => {
+                    parents.push((mem::take(&mut nodes), token, span))
+                }
+                Token::LoopEnd | Token::ProcedureEnd => {
+                    let node = match (parents.pop(), token) {
+                        (Some((parent, Token::LoopStart, open)), Token::LoopEnd) => {
+                            let body = mem::replace(&mut nodes, parent);
+                            CstNode::Loop {
+                                open,
+                                body,
+                                close: span,
+                            }
+                        }
+                        (Some((parent, Token::ProcedureStart, open)), Token::ProcedureEnd) => {
+                            let body = mem::replace(&mut nodes, parent);
+                            CstNode::Procedure {
+                                open,
+                                body,
+                                close: span,
+                            }
+                        }
+                        (_, Token::ProcedureEnd) => {
+                            return Err(BadExpressionError::ProcedureNotOpened { span })
+                        }
+                        _ => return Err(BadExpressionError::LoopNotOpened { span }),
+                    };
+                    nodes.push(node);
+                }
+                Token::Comment(byte) => Self::push_trivia(&mut nodes, byte, span),
+                token => nodes.push(CstNode::Command { token, span }),
+            }
+        }
+
+        match parents.pop() {
+            Some((_, Token::ProcedureStart, open)) => {
+                return Err(BadExpressionError::ProcedureNotClosed { span: open })
+            }
+            Some((_, _, open)) => return Err(BadExpressionError::LoopNotClosed { span: open }),
+            None => (),
+        }
+
+        Ok(ConcreteSyntaxTree { nodes })
+    }
+
+    fn push_trivia(nodes: &mut Vec<CstNode>, byte: u8, byte_span: Span) {
+        if let Some(last) = nodes.last_mut() {
+            if let CstNode::Whitespace { text, span } | CstNode::Comment { text, span } = last {
+                if span.end == byte_span.start {
+                    text.push(byte);
+                    span.end = byte_span.end;
+
+                    if let CstNode::Whitespace { text, span } = last {
+                        if !byte.is_ascii_whitespace() {
+                            let text = mem::take(text);
+                            *last = CstNode::Comment { text, span: *span };
+                        }
+                    }
+                    return;
+                }
+            }
+        }
+
+        let text = vec![byte];
+        let span = byte_span;
+        if byte.is_ascii_whitespace() {
+            nodes.push(CstNode::Whitespace { text, span });
+        } else {
+            nodes.push(CstNode::Comment { text, span });
+        }
+    }
+
+    /// Top level nodes of the tree
+    pub fn nodes(&self) -> &[CstNode] {
+        &self.nodes
+    }
+
+    /// Every comment of the tree, in the order they were written
+    ///
+    /// Bytes that are not valid UTF-8 are replaced.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::ConcreteSyntaxTree;
+    /// let cst: ConcreteSyntaxTree = "++++++++[>+++++++++<-]>. H\n+. I".parse().unwrap();
+    /// let comments = cst.comments();
+    ///
+    /// assert_eq!(comments[0].text, "H");
+    /// assert_eq!(comments[0].path, [11]);
+    /// assert_eq!(comments[1].path, [13]);
+    /// ```
+    pub fn comments(&self) -> Vec<Comment> {
+        let mut comments = Vec::new();
+        let mut path = Vec::new();
+        // Blocks being looked through, with the position of their next node
+        // and the index of their next expression
+        let mut blocks = vec![(&self.nodes[..], 0, 0)];
+
+        while let Some((nodes, position, index)) = blocks.last_mut() {
+            let Some(node) = nodes.get(*position) else {
+                blocks.pop();
+                path.pop();
+                continue;
+            };
+            *position += 1;
+
+            match node {
+                CstNode::Command { .. } => *index += 1,
+                CstNode::Whitespace { .. } => (),
+                CstNode::Comment { text, span } => {
+                    path.push(*index);
+                    comments.push(Comment {
+                        text: String::from_utf8_lossy(text).trim().to_string(),
+                        span: *span,
+                        path: path.clone(),
+                    });
+                    path.pop();
+                }
+                CstNode::Loop { body, .. } | CstNode::Procedure { body, .. } => {
+                    path.push(*index);
+                    *index += 1;
+                    blocks.push((body, 0, 0));
+                }
+            }
+        }
+
+        comments
+    }
+
+    /// Every token of the tree, along with its location
+    pub fn tokens(&self) -> Vec<(Token, Span)> {
+        let mut tokens = Vec::new();
+        // Blocks being looked through, with the position of their next node
+        // and the token closing them
+        let mut blocks = vec![(&self.nodes[..], 0, None)];
+
+        while let Some((nodes, position, closing)) = blocks.last_mut() {
+            let Some(node) = nodes.get(*position) else {
+                tokens.extend(*closing);
+                blocks.pop();
+                continue;
+            };
+            *position += 1;
+
+            match node {
+                CstNode::Command { token, span } => tokens.push((*token, *span)),
+                CstNode::Whitespace { text, span } | CstNode::Comment { text, span } => {
+                    let trivia = Lexer::resume(text.iter().copied(), *span);
+                    tokens.extend(trivia.map(|(t, span)| (Token::Comment(u8::from(t)), span)))
+                }
+                CstNode::Loop { open, body, close } => {
+                    tokens.push((Token::LoopStart, *open));
+                    blocks.push((body, 0, Some((Token::LoopEnd, *close))));
+                }
+                CstNode::Procedure { open, body, close } => {
+                    tokens.push((Token::ProcedureStart, *open));
+                    blocks.push((body, 0, Some((Token::ProcedureEnd, *close))));
+                }
+            }
+        }
+
+        tokens
+    }
+
+    /// Rebuilds the source code this tree was parsed from
+    pub fn to_source(&self) -> Vec<u8> {
+        self.tokens()
+            .into_iter()
+            .map(|(t, _)| u8::from(t))
+            .collect()
+    }
+
+    /// Drops comments and whitespace to get the meaning of the code
+    pub fn to_syntax_tree(&self) -> SyntaxTree {
+        SyntaxTree::parse_spanned_tokens(self.tokens()).expect("brackets are balanced")
+    }
+}
+
+impl Drop for ConcreteSyntaxTree {
+    /// Takes nested bodies apart one after another, so deeply nested trees
+    /// don't overflow the stack when dropped
+    fn drop(&mut self) {
+        let mut pending = mem::take(&mut self.nodes);
+        while let Some(node) = pending.pop() {
+            if let CstNode::Loop { mut body, .. } | CstNode::Procedure { mut body, .. } = node {
+                pending.append(&mut body);
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for ConcreteSyntaxTree {
+    type Err = BadExpressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(Lexer::new(s.bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = "Add two:\n ++ [->+<] \tdone!\n";
+
+    #[test]
+    fn cst_round_trips_source() {
+        let cst: ConcreteSyntaxTree = CODE.parse().unwrap();
+
+        assert_eq!(cst.to_source(), CODE.as_bytes());
+        assert_eq!(cst.tokens(), Lexer::new(CODE.bytes()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cst_groups_trivia() {
+        let cst: ConcreteSyntaxTree = CODE.parse().unwrap();
+        let kinds: Vec<_> = cst
+            .nodes()
+            .iter()
+            .map(|node| match node {
+                CstNode::Command { .. } => "command",
+                CstNode::Whitespace { .. } => "whitespace",
+                CstNode::Comment { .. } => "comment",
+                CstNode::Loop { .. } => "loop",
+                CstNode::Procedure { .. } => "procedure",
+            })
+            .collect();
+
+        assert_eq!(
+            kinds,
+            [
+                "comment",
+                "command",
+                "command",
+                "whitespace",
+                "loop",
+                "comment"
+            ]
+        );
+
+        let CstNode::Comment { text, span } = &cst.nodes()[5] else {
+            unreachable!()
+        };
+        assert_eq!(text, b" \tdone!\n");
+        assert_eq!((span.line, span.column), (2, 11));
+    }
+
+    #[test]
+    fn cst_extracts_comments() {
+        let cst: ConcreteSyntaxTree = CODE.parse().unwrap();
+        let comments: Vec<_> = cst
+            .comments()
+            .into_iter()
+            .map(|comment| (comment.text, comment.span.start, comment.path))
+            .collect();
+
+        assert_eq!(
+            comments,
+            [
+                (String::from("Add two:"), 0, vec![0]),
+                (String::from("done!"), 19, vec![3]),
+            ]
+        );
+
+        let cst = ConcreteSyntaxTree::parse(Lexer::new(*b"[- inner\xff ]")).unwrap();
+        assert_eq!(cst.comments()[0].text, "inner\u{fffd}");
+        assert_eq!(cst.comments()[0].path, [0, 1]);
+    }
+
+    #[test]
+    fn cst_converts_to_syntax_tree() {
+        let cst: ConcreteSyntaxTree = CODE.parse().unwrap();
+        let tree = cst.to_syntax_tree();
+
+        assert_eq!(tree, CODE.parse().unwrap());
+        assert_eq!(tree.span(2), Some(cst.nodes()[4].span()));
+        assert_eq!(
+            cst.nodes()[4].span(),
+            Span {
+                start: 13,
+                end: 19,
+                line: 2,
+                column: 5
+            }
+        );
+    }
+
+    #[test]
+    fn cst_rejects_unbalanced_brackets() {
+        assert!(matches!(
+            "+[[-]".parse::<ConcreteSyntaxTree>(),
+            Err(BadExpressionError::LoopNotClosed { span }) if span.start == 1
+        ));
+        assert!(matches!(
+            "+]".parse::<ConcreteSyntaxTree>(),
+            Err(BadExpressionError::LoopNotOpened { span }) if span.start == 1
+        ));
+    }
+
+    #[test]
+    fn cst_keeps_data_after_end() {
+        use crate::dialect::Dialect;
+
+        let code = "+@[+";
+        let lexer = Lexer::new(code.bytes()).dialect(Dialect::extended());
+        let cst = ConcreteSyntaxTree::parse(lexer).unwrap();
+
+        assert_eq!(cst.to_source(), code.as_bytes());
+        assert_eq!(cst.to_syntax_tree().to_code(), "+@");
+    }
+
+    #[test]
+    fn cst_parses_procedures() {
+        use crate::dialect::Dialect;
+
+        let code = "(+ add )[:)";
+        let lexer = || Lexer::new(code.bytes()).dialect(Dialect::pbrain());
+
+        assert!(matches!(
+            ConcreteSyntaxTree::parse(lexer()),
+            Err(BadExpressionError::ProcedureNotOpened { span }) if span.start == 10
+        ));
+
+        let code = "(+ add ):";
+        let lexer = Lexer::new(code.bytes()).dialect(Dialect::pbrain());
+        let cst = ConcreteSyntaxTree::parse(lexer).unwrap();
+
+        assert!(matches!(cst.nodes()[0], CstNode::Procedure { .. }));
+        assert_eq!(cst.to_source(), code.as_bytes());
+        assert_eq!(cst.to_syntax_tree().to_code(), "(+):");
+    }
+
+    #[test]
+    fn cst_deep_nesting() {
+        let depth = 100_000;
+        let code = "[ ".repeat(depth) + "x" + &"]".repeat(depth);
+        let cst: ConcreteSyntaxTree = code.parse().unwrap();
+
+        assert_eq!(cst.to_source(), code.as_bytes());
+        assert_eq!(cst.comments()[0].path, vec![0; depth + 1]);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{syntax::BadExpressionError, token::Span};
+use derive_more::Display;
+use std::fmt;
+
+/// How serious a [`Diagnostic`] is
+#[derive(Debug, Display, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum Severity {
+    #[display(fmt = "warning")]
+    Warning,
+
+    #[display(fmt = "error")]
+    Error,
+}
+
+/// A message about a location in the source code
+///
+/// It can be rendered along with an excerpt of the source, pointing
+/// at the location with a caret:
+///
+/// ```text
+/// error: '[' was never closed
+///  --> hello.bf:1:3
+///   |
+/// 1 | ++[>+<-
+///   |   ^ this loop
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// Short note shown next to the caret
+    pub label: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error<M: Into<String>>(message: M, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            label: None,
+        }
+    }
+
+    pub fn warning<M: Into<String>>(message: M, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Self::error(message, span)
+        }
+    }
+
+    pub fn with_label<L: Into<String>>(mut self, label: L) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Renders this diagnostic with an excerpt of `source`, the code
+    /// it refers to
+    pub fn render<'a>(&'a self, source: &'a [u8]) -> Rendered<'a> {
+        Rendered {
+            diagnostic: self,
+            source,
+            origin: None,
+            colored: false,
+        }
+    }
+}
+
+impl From<&BadExpressionError> for Diagnostic {
+    fn from(error: &BadExpressionError) -> Self {
+        let label = match error {
+            BadExpressionError::LoopNotClosed { .. } => "this loop",
+            BadExpressionError::ProcedureNotClosed { .. } => "this procedure",
+            BadExpressionError::LoopNotOpened { .. }
+            | BadExpressionError::ProcedureNotOpened { .. } => "this bracket",
+        };
+
+        Diagnostic::error(error.to_string(), error.span()).with_label(label)
+    }
+}
+
+impl From<BadExpressionError> for Diagnostic {
+    fn from(error: BadExpressionError) -> Self {
+        Diagnostic::from(&error)
+    }
+}
+
+/// A [`Diagnostic`] ready to be displayed with an excerpt of the source
+#[derive(Debug, Clone, Copy)]
+pub struct Rendered<'a> {
+    diagnostic: &'a Diagnostic,
+    source: &'a [u8],
+    origin: Option<&'a str>,
+    colored: bool,
+}
+
+impl<'a> Rendered<'a> {
+    /// Names where the source comes from, usually a file path
+    pub fn origin(mut self, origin: &'a str) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    /// Whether to color it with ANSI escape codes for terminals, by
+    /// severity, `false` by default
+    pub fn colored(mut self, colored: bool) -> Self {
+        self.colored = colored;
+        self
+    }
+
+    /// `text` in the color of `code` if colored
+    fn paint(&self, code: &str, text: &str) -> String {
+        match self.colored {
+            true => format!("\x1b[{code}m{text}\x1b[0m"),
+            false => text.into(),
+        }
+    }
+}
+
+impl Severity {
+    /// ANSI escape code of the color of this severity
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Warning => "1;33",
+            Severity::Error => "1;31",
+        }
+    }
+}
+
+/// ANSI escape code of the color of the margin of excerpts
+const MARGIN_COLOR: &str = "1;34";
+
+impl fmt::Display for Rendered<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Diagnostic {
+            severity,
+            message,
+            span,
+            label,
+        } = self.diagnostic;
+
+        let color = severity.color();
+        let head = self.paint(color, &format!("{severity}:"));
+        writeln!(f, "{head} {}", self.paint("1", message))?;
+
+        let number = span.line.to_string();
+        let margin = " ".repeat(number.len());
+        let origin = self.origin.map(|o| format!("{o}:")).unwrap_or_default();
+        let arrow = self.paint(MARGIN_COLOR, "-->");
+        writeln!(f, "{margin}{arrow} {origin}{}:{}", span.line, span.column)?;
+
+        let Some(line) = self.source.split(|&b| b == b'\n').nth(span.line - 1) else {
+            return Ok(());
+        };
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        let column = (span.column - 1).min(line.len());
+        let width = (span.end - span.start).clamp(1, (line.len() - column).max(1));
+        let indent: String = String::from_utf8_lossy(&line[..column])
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let bar = self.paint(MARGIN_COLOR, "|");
+        let number = self.paint(MARGIN_COLOR, &number);
+        writeln!(f, "{margin} {bar}")?;
+        writeln!(f, "{number} {bar} {}", String::from_utf8_lossy(line))?;
+        let caret = match label {
+            Some(label) => format!("{} {label}", "^".repeat(width)),
+            None => "^".repeat(width),
+        };
+        write!(f, "{margin} {bar} {indent}{}", self.paint(color, &caret))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::SyntaxTree;
+
+    #[test]
+    fn render_syntax_error() {
+        let source = "+++\n\t++[>+<-\n";
+        let error = source.parse::<SyntaxTree>().unwrap_err();
+        let diagnostic = Diagnostic::from(error);
+
+        assert_eq!(
+            diagnostic
+                .render(source.as_bytes())
+                .origin("a.bf")
+                .to_string(),
+            "error: '[' was never closed\n \
+             --> a.bf:2:4\n  \
+               |\n\
+             2 | \t++[>+<-\n  \
+               | \t  ^ this loop"
+        );
+    }
+
+    #[test]
+    fn render_span_wider_than_one_byte() {
+        let source = b"+[-]";
+        let span = Span {
+            start: 1,
+            end: 4,
+            line: 1,
+            column: 2,
+        };
+
+        assert_eq!(
+            Diagnostic::warning("useless loop", span)
+                .render(source)
+                .to_string(),
+            "warning: useless loop\n --> 1:2\n  |\n1 | +[-]\n  |  ^^^"
+        );
+    }
+
+    #[test]
+    fn render_colored_by_severity() {
+        let span = Span {
+            start: 0,
+            end: 1,
+            line: 1,
+            column: 1,
+        };
+        let diagnostic = Diagnostic::error("unmatched", span).with_label("here");
+
+        assert_eq!(
+            diagnostic.render(b"]").colored(true).to_string(),
+            "\x1b[1;31merror:\x1b[0m \x1b[1munmatched\x1b[0m\n \
+             \x1b[1;34m-->\x1b[0m 1:1\n  \
+             \x1b[1;34m|\x1b[0m\n\
+             \x1b[1;34m1\x1b[0m \x1b[1;34m|\x1b[0m ]\n  \
+             \x1b[1;34m|\x1b[0m \x1b[1;31m^ here\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,561 @@
+use crate::token::Token;
+use derive_more::{Display, Error};
+
+/// Error while defining a [`Dialect`]
+#[derive(Debug, Display, Error, PartialEq, Eq, Clone, Hash)]
+pub enum DialectError {
+    /// Patterns need at least one byte
+    #[display(fmt = "the pattern of '{}' is empty", token)]
+    EmptyPattern { token: Token },
+
+    /// Comments are whatever matches no pattern, they have none
+    #[display(fmt = "comments can't have a pattern")]
+    CommentPattern,
+
+    /// A line of a definition is not `command = "pattern"`
+    #[display(fmt = "line {} is not `command = \"pattern\"`", line)]
+    InvalidLine { line: usize },
+
+    /// A definition names a command that doesn't exist
+    #[display(fmt = "unknown command '{}' at line {}", name, line)]
+    UnknownCommand { line: usize, name: String },
+
+    /// A command has to be written in a dialect without a pattern for it
+    #[display(fmt = "there is no pattern for '{}'", token)]
+    MissingPattern { token: Token },
+}
+
+/// Commands in the order of [`Dialect::substitution`]
+const COMMANDS: [Token; 8] = [
+    Token::MoveRight,
+    Token::MoveLeft,
+    Token::Increment,
+    Token::Decrement,
+    Token::ReadByte,
+    Token::WriteByte,
+    Token::LoopStart,
+    Token::LoopEnd,
+];
+
+/// Mapping from pieces of source code to tokens
+///
+/// It allows to run languages that are BrainFuck with other symbols,
+/// where each command is written with any sequence of bytes. When several
+/// patterns match, the longest wins, and bytes matching no pattern are
+/// comments. The standard mapping is [`Dialect::brainfuck`], the same as
+/// [`Token::from`].
+///
+/// # Example
+/// ```
+/// # use brainfuck::{Dialect, Lexer, SyntaxTree, Token};
+/// let dialect = Dialect::from_patterns([
+///     ("up", Token::Increment),
+///     ("down", Token::Decrement),
+///     ("print", Token::WriteByte),
+/// ])
+/// .unwrap();
+///
+/// let tokens = Lexer::new("up up down print".bytes()).dialect(dialect);
+/// let tree = SyntaxTree::parse_spanned_tokens(tokens).unwrap();
+///
+/// assert_eq!(tree.to_code(), "++-.");
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Dialect {
+    /// Sorted from the longest pattern to the shortest
+    patterns: Vec<(Vec<u8>, Token)>,
+}
+
+impl Dialect {
+    /// A dialect without any pattern, where everything is a comment
+    pub fn new() -> Self {
+        Dialect {
+            patterns: Vec::new(),
+        }
+    }
+
+    /// The commands of standard BrainFuck: `><+-,.[]`
+    pub fn brainfuck() -> Self {
+        Self::from_patterns(b"><+-,.[]".map(|byte| ([byte], Token::from(byte))))
+            .expect("standard patterns are valid")
+    }
+
+    /// Builds a trivial substitution of BrainFuck, giving the patterns of
+    /// `><+-,.[]` in that order
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{Dialect, Lexer, SyntaxTree};
+    /// let dialect = Dialect::substitution(["R", "L", "I", "D", "in", "out", "(", ")"]).unwrap();
+    /// let tokens = Lexer::new("II(DRIL)R out".bytes()).dialect(dialect);
+    ///
+    /// assert_eq!(SyntaxTree::parse_spanned_tokens(tokens).unwrap().to_code(), "++[->+<]>.");
+    /// ```
+    pub fn substitution<P: AsRef<[u8]>>(patterns: [P; 8]) -> Result<Self, DialectError> {
+        Self::from_patterns(patterns.into_iter().zip(COMMANDS))
+    }
+
+    /// Loads a dialect from its definition
+    ///
+    /// Every line gives the pattern of a command as `command = "pattern"`,
+    /// like a TOML file. Commands are named `forward`, `backward`,
+    /// `increment`, `decrement`, `input`, `output`, `loop_start`,
+    /// `loop_end`, `debug_dump`, `procedure_start`, `procedure_end`,
+    /// `call`, `end`, `store`, `retrieve`, `shift_left`, `shift_right`,
+    /// `not`, `xor`, `and` and `or`. Patterns may use the escapes `\"`,
+    /// `\\`, `\n`, `\r` and `\t`. Empty lines and lines starting with `#`
+    /// are ignored.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{Dialect, Token};
+    /// let dialect = Dialect::from_definition(
+    ///     r#"
+    ///     forward = "Blub. Blub?"
+    ///     backward = "Blub? Blub."
+    ///     "#,
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(dialect.pattern(Token::MoveRight), Some(&b"Blub. Blub?"[..]));
+    /// ```
+    pub fn from_definition(definition: &str) -> Result<Self, DialectError> {
+        let mut dialect = Dialect::new();
+
+        for (index, line) in definition.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = DialectError::InvalidLine { line: line_number };
+            let Some((name, pattern)) = line.split_once('=') else {
+                return Err(invalid);
+            };
+
+            let name = name.trim();
+            let Some(token) = command(name) else {
+                return Err(DialectError::UnknownCommand {
+                    line: line_number,
+                    name: name.to_string(),
+                });
+            };
+            let pattern = unquote(pattern.trim()).ok_or(invalid)?;
+
+            dialect.insert(pattern, token)?;
+        }
+
+        Ok(dialect)
+    }
+
+    /// BrainFuck with the procedures of pbrain: `(`, `)` and `:`
+    pub fn pbrain() -> Self {
+        let mut dialect = Self::brainfuck();
+        for token in [Token::ProcedureStart, Token::ProcedureEnd, Token::Call] {
+            dialect
+                .insert([u8::from(token)], token)
+                .expect("pbrain patterns are valid");
+        }
+
+        dialect
+    }
+
+    /// BrainFuck with the commands of Extended Type I: `@$!{}~^&|`
+    ///
+    /// Its `!` is a command, so it can't be used to give the input
+    /// after the code, as with [`split_input`](crate::split_input).
+    pub fn extended() -> Self {
+        let mut dialect = Self::brainfuck();
+        let commands = [
+            Token::End,
+            Token::Store,
+            Token::Retrieve,
+            Token::ShiftLeft,
+            Token::ShiftRight,
+            Token::Not,
+            Token::Xor,
+            Token::And,
+            Token::Or,
+        ];
+        for token in commands {
+            dialect
+                .insert([u8::from(token)], token)
+                .expect("extended patterns are valid");
+        }
+
+        dialect
+    }
+
+    /// One of the built-in dialects: `brainfuck`, `pbrain`, `extended`,
+    /// `ook`, `blub` or `pikalang`
+    pub fn named(name: &str) -> Option<Self> {
+        let ook = |word: &str| {
+            let [dot, question, bang] = [".", "?", "!"].map(|mark| format!("{word}{mark}"));
+            Self::substitution([
+                format!("{dot} {question}"),
+                format!("{question} {dot}"),
+                format!("{dot} {dot}"),
+                format!("{bang} {bang}"),
+                format!("{dot} {bang}"),
+                format!("{bang} {dot}"),
+                format!("{bang} {question}"),
+                format!("{question} {bang}"),
+            ])
+        };
+
+        let dialect = match name {
+            "brainfuck" => Ok(Self::brainfuck()),
+            "pbrain" => Ok(Self::pbrain()),
+            "extended" => Ok(Self::extended()),
+            "ook" => ook("Ook"),
+            "blub" => ook("Blub"),
+            "pikalang" => Self::substitution([
+                "pipi", "pichu", "pi", "ka", "pikapi", "pikachu", "pika", "chu",
+            ]),
+            _ => return None,
+        };
+
+        Some(dialect.expect("built-in patterns are valid"))
+    }
+
+    /// Builds a dialect from pairs of pattern and token
+    pub fn from_patterns<I, P>(patterns: I) -> Result<Self, DialectError>
+    where
+        I: IntoIterator<Item = (P, Token)>,
+        P: AsRef<[u8]>,
+    {
+        let mut dialect = Dialect::new();
+        for (pattern, token) in patterns {
+            dialect.insert(pattern, token)?;
+        }
+
+        Ok(dialect)
+    }
+
+    /// Makes `pattern` stand for `token`, replacing what it stood for before
+    ///
+    /// A token may have several patterns.
+    pub fn insert<P: AsRef<[u8]>>(&mut self, pattern: P, token: Token) -> Result<(), DialectError> {
+        let pattern = pattern.as_ref();
+        if let Token::Comment(_) = token {
+            return Err(DialectError::CommentPattern);
+        }
+        if pattern.is_empty() {
+            return Err(DialectError::EmptyPattern { token });
+        }
+
+        self.patterns.retain(|(p, _)| p != pattern);
+        let index = self
+            .patterns
+            .partition_point(|(p, _)| p.len() >= pattern.len());
+        self.patterns.insert(index, (pattern.to_vec(), token));
+
+        Ok(())
+    }
+
+    /// A pattern standing for `token`, the shortest if there are several
+    pub fn pattern(&self, token: Token) -> Option<&[u8]> {
+        self.patterns
+            .iter()
+            .filter(|(_, t)| *t == token)
+            .min_by_key(|(pattern, _)| pattern.len())
+            .map(|(pattern, _)| pattern.as_slice())
+    }
+
+    /// Writes `tokens` in this dialect, with the shortest pattern of every
+    /// command
+    ///
+    /// Comments are left out, but for line breaks and the data after a
+    /// [`Token::End`], as they could be read as commands. Commands are
+    /// separated by spaces when some pattern is longer than a byte, so that
+    /// words are not read as one.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{Dialect, Lexer};
+    /// let ook = Dialect::named("ook").unwrap();
+    /// let tokens = Lexer::new("+[-] add\n.".bytes()).map(|(token, _)| token);
+    ///
+    /// assert_eq!(
+    ///     ook.encode(tokens).unwrap(),
+    ///     b"Ook. Ook. Ook! Ook? Ook! Ook! Ook? Ook!\nOok! Ook."
+    /// );
+    /// ```
+    pub fn encode<I>(&self, tokens: I) -> Result<Vec<u8>, DialectError>
+    where
+        I: IntoIterator<Item = Token>,
+    {
+        let spaced = self.longest() > 1;
+        let mut code = Vec::new();
+        let mut ended = false;
+
+        for token in tokens {
+            match token {
+                Token::Comment(byte) if ended || byte == b'\n' => code.push(byte),
+                Token::Comment(_) => {}
+                token => {
+                    let pattern = self
+                        .pattern(token)
+                        .ok_or(DialectError::MissingPattern { token })?;
+                    if spaced && code.last().is_some_and(|&byte| byte != b'\n') {
+                        code.push(b' ');
+                    }
+                    code.extend_from_slice(pattern);
+                    ended = token == Token::End;
+                }
+            }
+        }
+
+        Ok(code)
+    }
+
+    /// Length of the longest pattern
+    pub(crate) fn longest(&self) -> usize {
+        self.patterns
+            .first()
+            .map_or(0, |(pattern, _)| pattern.len())
+    }
+
+    /// Finds the token `bytes` start with, along with the length of its
+    /// pattern
+    pub(crate) fn find(&self, bytes: &[u8]) -> Option<(Token, usize)> {
+        self.patterns
+            .iter()
+            .find(|(pattern, _)| bytes.starts_with(pattern))
+            .map(|(pattern, token)| (*token, pattern.len()))
+    }
+}
+
+/// Token of a command named in a definition
+fn command(name: &str) -> Option<Token> {
+    let token = match name {
+        "forward" => Token::MoveRight,
+        "backward" => Token::MoveLeft,
+        "increment" => Token::Increment,
+        "decrement" => Token::Decrement,
+        "input" => Token::ReadByte,
+        "output" => Token::WriteByte,
+        "loop_start" => Token::LoopStart,
+        "loop_end" => Token::LoopEnd,
+        "debug_dump" => Token::DebugDump,
+        "procedure_start" => Token::ProcedureStart,
+        "procedure_end" => Token::ProcedureEnd,
+        "call" => Token::Call,
+        "end" => Token::End,
+        "store" => Token::Store,
+        "retrieve" => Token::Retrieve,
+        "shift_left" => Token::ShiftLeft,
+        "shift_right" => Token::ShiftRight,
+        "not" => Token::Not,
+        "xor" => Token::Xor,
+        "and" => Token::And,
+        "or" => Token::Or,
+        _ => return None,
+    };
+
+    Some(token)
+}
+
+/// Contents of a quoted string with escapes
+fn unquote(quoted: &str) -> Option<String> {
+    let mut chars = quoted.strip_prefix('"')?.strip_suffix('"')?.chars();
+    let mut string = String::new();
+
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '"' => return None,
+            '\\' => match chars.next()? {
+                '"' => '"',
+                '\\' => '\\',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                _ => return None,
+            },
+            c => c,
+        };
+        string.push(c);
+    }
+
+    Some(string)
+}
+
+impl std::default::Default for Dialect {
+    fn default() -> Self {
+        Self::brainfuck()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{syntax::SyntaxTree, token::Lexer};
+
+    #[test]
+    fn dialect_prefers_longest_pattern() {
+        let dialect =
+            Dialect::from_patterns([("a", Token::Increment), ("ab", Token::Decrement)]).unwrap();
+        let tokens: Vec<_> = Lexer::new("aab?".bytes())
+            .dialect(dialect)
+            .map(|(token, span)| (token, span.start, span.end))
+            .collect();
+
+        assert_eq!(
+            tokens,
+            [
+                (Token::Increment, 0, 1),
+                (Token::Decrement, 1, 3),
+                (Token::Comment(b'?'), 3, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn dialect_standard_matches_token_from() {
+        let code = "+[->,.<]#x";
+        let standard: Vec<_> = Lexer::new(code.bytes()).collect();
+        let dialect: Vec<_> = Lexer::new(code.bytes())
+            .dialect(Dialect::brainfuck())
+            .collect();
+
+        assert_eq!(dialect, standard);
+    }
+
+    #[test]
+    fn dialect_rejects_invalid_patterns() {
+        let mut dialect = Dialect::new();
+
+        assert_eq!(
+            dialect.insert("", Token::LoopStart),
+            Err(DialectError::EmptyPattern {
+                token: Token::LoopStart
+            })
+        );
+        assert_eq!(
+            dialect.insert("x", Token::Comment(b'x')),
+            Err(DialectError::CommentPattern)
+        );
+    }
+
+    #[test]
+    fn dialect_named_ook() {
+        let code = "Ook. Ook. Ook. Ook.\nOok! Ook? Ook! Ook! Ook? Ook!";
+        let tokens = Lexer::new(code.bytes()).dialect(Dialect::named("ook").unwrap());
+        let tree = SyntaxTree::parse_spanned_tokens(tokens).unwrap();
+
+        assert_eq!(tree.to_code(), "++[-]");
+        assert_eq!(tree.span(1).map(|s| (s.start, s.end)), Some((10, 19)));
+        assert!(Dialect::named("cobol").is_none());
+    }
+
+    #[test]
+    fn dialect_named_pbrain() {
+        let tokens: Vec<_> = Lexer::new("(:)".bytes())
+            .dialect(Dialect::named("pbrain").unwrap())
+            .map(|(token, _)| token)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            [Token::ProcedureStart, Token::Call, Token::ProcedureEnd]
+        );
+        assert_eq!(Token::from(b':'), Token::Comment(b':'));
+    }
+
+    #[test]
+    fn dialect_extended_ends_at_data() {
+        let tokens: Vec<_> = Lexer::new("$!@[".bytes())
+            .dialect(Dialect::named("extended").unwrap())
+            .map(|(token, _)| token)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            [
+                Token::Store,
+                Token::Retrieve,
+                Token::End,
+                Token::Comment(b'[')
+            ]
+        );
+    }
+
+    #[test]
+    fn dialect_from_definition() {
+        let dialect =
+            Dialect::from_definition("# comment\n\nincrement = \"a=\\\"b\"\n  loop_start=\"\\t\"")
+                .unwrap();
+
+        assert_eq!(dialect.pattern(Token::Increment), Some(&b"a=\"b"[..]));
+        assert_eq!(dialect.pattern(Token::LoopStart), Some(&b"\t"[..]));
+        assert_eq!(dialect.pattern(Token::LoopEnd), None);
+    }
+
+    #[test]
+    fn dialect_definition_errors() {
+        assert_eq!(
+            Dialect::from_definition("\nincrement \"+\""),
+            Err(DialectError::InvalidLine { line: 2 })
+        );
+        assert_eq!(
+            Dialect::from_definition("increment = +"),
+            Err(DialectError::InvalidLine { line: 1 })
+        );
+        assert_eq!(
+            Dialect::from_definition("jump = \"j\""),
+            Err(DialectError::UnknownCommand {
+                line: 1,
+                name: String::from("jump")
+            })
+        );
+        assert_eq!(
+            Dialect::from_definition("input = \"\""),
+            Err(DialectError::EmptyPattern {
+                token: Token::ReadByte
+            })
+        );
+    }
+
+    #[test]
+    fn dialect_patterns_of_tokens() {
+        let mut dialect = Dialect::brainfuck();
+        dialect.insert("plus", Token::Increment).unwrap();
+        dialect.insert("+", Token::Decrement).unwrap();
+
+        assert_eq!(dialect.pattern(Token::Increment), Some(&b"plus"[..]));
+        assert_eq!(dialect.pattern(Token::Decrement), Some(&b"-"[..]));
+        assert_eq!(dialect.pattern(Token::DebugDump), None);
+    }
+
+    #[test]
+    fn dialect_encodes_tokens() {
+        let pikalang = Dialect::named("pikalang").unwrap();
+        let tokens = |code: &str, dialect: &Dialect| -> Vec<Token> {
+            Lexer::new(code.bytes())
+                .dialect(dialect.clone())
+                .map(|(token, _)| token)
+                .collect()
+        };
+
+        let encoded = pikalang
+            .encode(tokens("+>.\n<x", &Dialect::brainfuck()))
+            .unwrap();
+        assert_eq!(encoded, b"pi pipi pikachu\npichu");
+        let decoded =
+            Dialect::brainfuck().encode(tokens(std::str::from_utf8(&encoded).unwrap(), &pikalang));
+        assert_eq!(decoded.unwrap(), b"+>.\n<");
+
+        let extended = Dialect::named("extended").unwrap();
+        assert_eq!(
+            extended.encode(tokens("+@ [data]", &extended)).unwrap(),
+            b"+@ [data]"
+        );
+        assert_eq!(
+            pikalang.encode(tokens("$", &extended)),
+            Err(DialectError::MissingPattern {
+                token: Token::Store
+            })
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::syntax::{Expression, SyntaxTree};
+use std::fmt::{self, Write};
+
+impl SyntaxTree {
+    /// Writes the control flow of this tree as a [Graphviz] DOT graph
+    ///
+    /// Every instruction is a node linked to the one that runs after it.
+    /// Loops are clusters holding their body, with a diamond node for the
+    /// check of the current cell. Procedures are clusters too, with a
+    /// dashed edge to the body that runs when they are called.
+    ///
+    /// [Graphviz]: https://graphviz.org
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph program {\n    node [shape=box];\n");
+        let mut next_id = 0;
+
+        write_block(&mut dot, self, 1, &mut next_id).expect("writing to a string can't fail");
+        dot.push_str("}\n");
+
+        dot
+    }
+}
+
+/// Writes the nodes and edges of `block`, returning the ids of the first
+/// and the last node at its level
+fn write_block(
+    dot: &mut String,
+    block: &SyntaxTree,
+    depth: usize,
+    next_id: &mut usize,
+) -> Result<Option<(usize, usize)>, fmt::Error> {
+    let indent = "    ".repeat(depth);
+    let mut bounds: Option<(usize, usize)> = None;
+
+    for (index, expr) in block.iter().enumerate() {
+        let id = *next_id;
+        *next_id += 1;
+
+        match expr {
+            Expression::Loop(body) => {
+                let label = match block.span(index) {
+                    Some(span) => format!("loop at {}:{}", span.line, span.column),
+                    None => String::from("loop"),
+                };
+
+                writeln!(dot, "{indent}subgraph cluster_{id} {{")?;
+                writeln!(dot, "{indent}    label=\"{label}\";")?;
+                writeln!(dot, "{indent}    n{id} [label=\"[\", shape=diamond];")?;
+                match write_block(dot, body, depth + 1, next_id)? {
+                    Some((first, last)) => {
+                        writeln!(dot, "{indent}    n{id} -> n{first};")?;
+                        writeln!(dot, "{indent}    n{last} -> n{id};")?;
+                    }
+                    None => writeln!(dot, "{indent}    n{id} -> n{id};")?,
+                }
+                writeln!(dot, "{indent}}}")?;
+            }
+            Expression::Procedure(body) => {
+                let label = match block.span(index) {
+                    Some(span) => format!("procedure at {}:{}", span.line, span.column),
+                    None => String::from("procedure"),
+                };
+
+                writeln!(dot, "{indent}subgraph cluster_{id} {{")?;
+                writeln!(dot, "{indent}    label=\"{label}\";")?;
+                writeln!(dot, "{indent}    n{id} [label=\"(\", shape=ellipse];")?;
+                if let Some((first, _)) = write_block(dot, body, depth + 1, next_id)? {
+                    writeln!(dot, "{indent}    n{id} -> n{first} [style=dashed];")?;
+                }
+                writeln!(dot, "{indent}}}")?;
+            }
+            expr => writeln!(dot, "{indent}n{id} [label=\"{expr}\"];")?,
+        }
+
+        if let Some((_, last)) = bounds {
+            writeln!(dot, "{indent}n{last} -> n{id};")?;
+        }
+        bounds = Some((bounds.map_or(id, |(first, _)| first), id));
+    }
+
+    Ok(bounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_links_instructions() {
+        let tree: SyntaxTree = "+[-]".parse().unwrap();
+
+        assert_eq!(
+            tree.to_dot(),
+            "digraph program {
+    node [shape=box];
+    n0 [label=\"+\"];
+    subgraph cluster_1 {
+        label=\"loop at 1:2\";
+        n1 [label=\"[\", shape=diamond];
+        n2 [label=\"-\"];
+        n1 -> n2;
+        n2 -> n1;
+    }
+    n0 -> n1;
+}
+"
+        );
+    }
+
+    #[test]
+    fn dot_empty_loop_checks_itself() {
+        let tree = SyntaxTree::from(vec![Expression::Loop(SyntaxTree::new())]);
+        let dot = tree.to_dot();
+
+        assert!(dot.contains("label=\"loop\";"));
+        assert!(dot.contains("n0 -> n0;"));
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{
+    bytecode::Op,
+    syntax::{Expression, SyntaxTree},
+    token::Span,
+};
+use std::collections::BTreeMap;
+
+/// Value of a cell after running some instructions, as the sum of a
+/// constant and the starting values of some cells times a factor each,
+/// wrapping around like cells do
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
+pub(crate) struct CellValue {
+    pub constant: u8,
+    /// Factor of the starting value of the cell at each offset, never zero
+    pub factors: BTreeMap<isize, u8>,
+}
+
+impl CellValue {
+    /// Starting value of the cell at `offset`
+    fn start(offset: isize) -> Self {
+        CellValue {
+            constant: 0,
+            factors: BTreeMap::from([(offset, 1)]),
+        }
+    }
+
+    /// Adds `other` times `factor` to this value
+    fn add_scaled(&mut self, other: &CellValue, factor: u8) {
+        self.constant = self
+            .constant
+            .wrapping_add(other.constant.wrapping_mul(factor));
+
+        for (&offset, &by) in &other.factors {
+            let sum = self.factors.get(&offset).copied().unwrap_or(0);
+            match sum.wrapping_add(by.wrapping_mul(factor)) {
+                0 => self.factors.remove(&offset),
+                sum => self.factors.insert(offset, sum),
+            };
+        }
+    }
+
+    /// Amount added to the starting value of the cell at `offset`, when
+    /// this value is only that
+    pub fn delta(&self, offset: isize) -> Option<u8> {
+        let mut factors = self.factors.iter();
+        match (factors.next(), factors.next()) {
+            (Some((&from, 1)), None) if from == offset => Some(self.constant),
+            _ => None,
+        }
+    }
+}
+
+/// What a run of instructions does to the memory, as the value it leaves
+/// in every cell it changes and the offset it leaves the pointer at
+///
+/// Only runs of [`Op::Add`], [`Op::Move`], [`Op::Set`] and
+/// [`Op::Multiply`] have an effect. Offsets are relative to where the
+/// pointer starts, and runs doing the same have equal effects.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
+pub(crate) struct Effect {
+    pub shift: isize,
+    /// Value of every changed cell, by offset
+    pub cells: BTreeMap<isize, CellValue>,
+}
+
+impl Effect {
+    /// Effect of running `ops`, if they only change cells and move the
+    /// pointer
+    pub fn of(ops: &[Op]) -> Option<Self> {
+        let mut effect = Effect::default();
+        ops.iter().all(|&op| effect.apply(op)).then_some(effect)
+    }
+
+    /// Adds the effect of `op` after this one, unless it does something
+    /// else than changing cells and moving the pointer
+    pub fn apply(&mut self, op: Op) -> bool {
+        match op {
+            Op::Add(amount) => {
+                let mut value = self.value(self.shift);
+                value.constant = value.constant.wrapping_add(amount);
+                self.set(self.shift, value);
+            }
+            Op::Move(by) => match self.shift.checked_add(by) {
+                Some(shift) => self.shift = shift,
+                None => return false,
+            },
+            Op::Set(constant) => self.set(
+                self.shift,
+                CellValue {
+                    constant,
+                    factors: BTreeMap::new(),
+                },
+            ),
+            Op::Multiply { offset, factor } => {
+                let Some(target) = self.shift.checked_add(offset) else {
+                    return false;
+                };
+                let source = self.value(self.shift);
+                let mut value = self.value(target);
+                value.add_scaled(&source, factor);
+                self.set(target, value);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Value of the cell at `offset`
+    pub fn value(&self, offset: isize) -> CellValue {
+        match self.cells.get(&offset) {
+            Some(value) => value.clone(),
+            None => CellValue::start(offset),
+        }
+    }
+
+    fn set(&mut self, offset: isize, value: CellValue) {
+        match value == CellValue::start(offset) {
+            true => self.cells.remove(&offset),
+            false => self.cells.insert(offset, value),
+        };
+    }
+
+    /// Least amount of instructions that could turn this effect into
+    /// `other`, since each one changes a single cell or the pointer
+    pub fn distance(&self, other: &Effect) -> usize {
+        let differ = |offset: &isize| self.value(*offset) != other.value(*offset);
+        let cells = self.cells.keys().filter(|offset| differ(offset)).count()
+            + other
+                .cells
+                .keys()
+                .filter(|offset| !self.cells.contains_key(offset) && differ(offset))
+                .count();
+
+        cells + usize::from(self.shift != other.shift)
+    }
+}
+
+/// What one iteration of a loop does, as found by
+/// [`SyntaxTree::loop_effects`]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct LoopEffect {
+    /// Index of the loop on every nesting level
+    pub path: Vec<usize>,
+    /// Location of the loop, if the tree was parsed from source
+    pub span: Option<Span>,
+    /// How far every iteration moves the pointer, if it is always the same
+    pub shift: Option<isize>,
+    /// Amount every iteration adds to each cell it changes, by offset from
+    /// where the iteration starts, if the body only has `+-<>`
+    pub deltas: Option<BTreeMap<isize, u8>>,
+}
+
+impl LoopEffect {
+    /// Whether every iteration leaves the pointer where it started
+    pub fn is_balanced(&self) -> bool {
+        self.shift == Some(0)
+    }
+
+    /// Whether the loop only moves the value of the current cell to other
+    /// cells, times a factor each, like `[->++<]` or `[-]`
+    ///
+    /// Such a loop runs as many times as the value of the current cell, or
+    /// its negation, and ends with it at zero.
+    pub fn is_data_move(&self) -> bool {
+        self.is_balanced()
+            && self
+                .deltas
+                .as_ref()
+                .is_some_and(|deltas| matches!(deltas.get(&0), Some(&(1 | u8::MAX))))
+    }
+}
+
+impl SyntaxTree {
+    /// Finds what one iteration of every loop does, including the loops of
+    /// procedures, in the order they appear
+    ///
+    /// The pointer moves the same on every iteration when the loops nested
+    /// in the body leave it where they found it, and no procedure is called.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::SyntaxTree;
+    /// let tree: SyntaxTree = ",[->++>+<<]>[>]".parse().unwrap();
+    /// let effects = tree.loop_effects();
+    ///
+    /// assert!(effects[0].is_data_move());
+    /// assert_eq!(effects[0].deltas.as_ref().unwrap()[&1], 2);
+    /// assert_eq!(effects[1].shift, Some(1));
+    /// assert!(!effects[1].is_balanced());
+    /// ```
+    pub fn loop_effects(&self) -> Vec<LoopEffect> {
+        let mut effects: Vec<LoopEffect> = Vec::new();
+        let mut path = Vec::new();
+        let mut blocks = vec![Body::new(self, None)];
+
+        while let Some(body) = blocks.last_mut() {
+            let Some(expr) = body.block.get(body.index) else {
+                let body = blocks.pop().expect("a block is open");
+                path.pop();
+                let Some(slot) = body.slot else {
+                    continue;
+                };
+
+                effects[slot].shift = body.shift;
+                effects[slot].deltas = body.effect.map(|effect| {
+                    effect
+                        .cells
+                        .into_iter()
+                        .filter_map(|(offset, value)| Some((offset, value.delta(offset)?)))
+                        .collect()
+                });
+
+                if let Some(parent) = blocks.last_mut() {
+                    if body.shift != Some(0) {
+                        parent.shift = None;
+                    }
+                    parent.effect = None;
+                }
+                continue;
+            };
+
+            let position = body.index;
+            body.index += 1;
+
+            let op = match expr {
+                Expression::Forward => Op::Move(1),
+                Expression::Backward => Op::Move(-1),
+                Expression::Increment => Op::Add(1),
+                Expression::Decrement => Op::Add(u8::MAX),
+                Expression::Loop(inner) => {
+                    path.push(position);
+                    effects.push(LoopEffect {
+                        path: path.clone(),
+                        span: body.block.span(position),
+                        shift: None,
+                        deltas: None,
+                    });
+                    blocks.push(Body::new(inner, Some(effects.len() - 1)));
+                    continue;
+                }
+                // Defining a procedure doesn't run it
+                Expression::Procedure(inner) => {
+                    path.push(position);
+                    blocks.push(Body::new(inner, None));
+                    continue;
+                }
+                Expression::Call => {
+                    body.shift = None;
+                    body.effect = None;
+                    continue;
+                }
+                _ => {
+                    body.effect = None;
+                    continue;
+                }
+            };
+
+            if let Op::Move(by) = op {
+                body.shift = body.shift.map(|shift| shift + by);
+            }
+            if let Some(effect) = &mut body.effect {
+                effect.apply(op);
+            }
+        }
+
+        effects
+    }
+}
+
+/// A block being analyzed by [`SyntaxTree::loop_effects`]
+struct Body<'a> {
+    block: &'a SyntaxTree,
+    /// Index of the next expression
+    index: usize,
+    /// Index of the effect of the loop, if the block is the body of one
+    slot: Option<usize>,
+    shift: Option<isize>,
+    effect: Option<Effect>,
+}
+
+impl<'a> Body<'a> {
+    fn new(block: &'a SyntaxTree, slot: Option<usize>) -> Self {
+        Body {
+            block,
+            index: 0,
+            slot,
+            shift: Some(0),
+            effect: Some(Effect::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effect_of_equivalent_runs_is_equal() {
+        let a = Effect::of(&[
+            Op::Add(1),
+            Op::Move(1),
+            Op::Add(2),
+            Op::Move(-1),
+            Op::Add(254),
+        ]);
+        let b = Effect::of(&[Op::Add(255), Op::Move(1), Op::Add(2), Op::Move(-1)]);
+        assert_eq!(a, b);
+
+        let a = Effect::of(&[Op::Set(3), Op::Add(253)]).unwrap();
+        assert_eq!(a.value(0).constant, 0);
+        assert!(a.value(0).factors.is_empty());
+        assert_eq!(
+            Effect::of(&[Op::Add(1), Op::Add(255)]),
+            Some(Effect::default())
+        );
+        assert_eq!(Effect::of(&[Op::Move(1), Op::Output]), None);
+    }
+
+    #[test]
+    fn effect_follows_multiplications() {
+        let effect = Effect::of(&[
+            Op::Add(2),
+            Op::Multiply {
+                offset: 1,
+                factor: 3,
+            },
+            Op::Multiply {
+                offset: 0,
+                factor: 1,
+            },
+        ])
+        .unwrap();
+
+        // The cell at 1 gets 3 times the cell at 0 plus 2, which is then
+        // doubled
+        assert_eq!(effect.value(1).constant, 6);
+        assert_eq!(effect.value(1).factors, BTreeMap::from([(0, 3), (1, 1)]));
+        assert_eq!(effect.value(0).constant, 4);
+        assert_eq!(effect.value(0).factors, BTreeMap::from([(0, 2)]));
+        assert_eq!(effect.value(1).delta(1), None);
+        assert_eq!(effect.value(2).delta(2), Some(0));
+        assert_eq!(effect.distance(&Effect::default()), 2);
+    }
+
+    #[test]
+    fn loop_effects_follow_nested_loops() {
+        let tree: SyntaxTree = "+[->+<[>]<]>[-[-]>>+<<]".parse().unwrap();
+        let effects = tree.loop_effects();
+
+        let paths: Vec<_> = effects.iter().map(|effect| effect.path.clone()).collect();
+        assert_eq!(paths, [vec![1], vec![1, 4], vec![3], vec![3, 1]]);
+
+        // The loop nested in the first one is not balanced
+        assert_eq!(effects[0].shift, None);
+        assert_eq!(effects[1].shift, Some(1));
+        assert_eq!(effects[0].deltas, None);
+
+        assert_eq!(effects[2].shift, Some(0));
+        assert_eq!(effects[2].deltas, None);
+        assert!(!effects[2].is_data_move());
+        assert!(effects[3].is_data_move());
+        assert_eq!(effects[3].deltas, Some(BTreeMap::from([(0, u8::MAX)])));
+    }
+
+    #[test]
+    fn loop_effects_lost_on_calls() {
+        use crate::{dialect::Dialect, token::Lexer};
+
+        let lexer = Lexer::new("([->+<]):[>:<],[.<>]".bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(lexer).unwrap();
+        let effects = tree.loop_effects();
+
+        assert_eq!(effects.len(), 3);
+        assert!(effects[0].is_data_move());
+        assert_eq!(effects[1].shift, None);
+        assert_eq!(effects[1].deltas, None);
+        assert!(effects[2].is_balanced());
+        assert_eq!(effects[2].deltas, None);
+        assert_eq!(
+            effects[2].span.map(|span| (span.start, span.end)),
+            Some((15, 20))
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    execution::{ExecutionState, MemoryContext, Stopped},
+    input::Input,
+    syntax::{Expression, SyntaxTree},
+};
+use std::collections::BTreeMap;
+
+impl SyntaxTree {
+    /// Whether this tree and `other` behave the same, as far as comparing
+    /// their normal forms can tell
+    ///
+    /// Normalizing merges runs of `+-<>` into their net effect on each cell,
+    /// which also cancels pairs like `+-`, and turns loops clearing the
+    /// current cell, like `[+]`, into `[-]`. Trees that behave the same but
+    /// have different normal forms are not detected, see
+    /// [`equivalent_on`](Self::equivalent_on).
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::SyntaxTree;
+    /// let tree: SyntaxTree = ">+<+-+[+++]".parse().unwrap();
+    /// let golfed: SyntaxTree = "+>+<[-]".parse().unwrap();
+    ///
+    /// assert!(tree.equivalent_to(&golfed));
+    /// ```
+    pub fn equivalent_to(&self, other: &SyntaxTree) -> bool {
+        normalize(self) == normalize(other)
+    }
+
+    /// Whether this tree and `other` behave the same, falling back to
+    /// running both on every one of `inputs` when their normal forms differ
+    ///
+    /// Both must finish within `steps` steps on every input, writing the
+    /// same output and leaving the same memory. Otherwise they are not
+    /// considered equivalent.
+    pub fn equivalent_on(&self, other: &SyntaxTree, inputs: &[&[u8]], steps: usize) -> bool {
+        if self.equivalent_to(other) {
+            return true;
+        }
+
+        inputs.iter().all(|input| {
+            let this = run_bounded(self, input, steps);
+            this.is_some() && this == run_bounded(other, input, steps)
+        })
+    }
+}
+
+/// Runs `tree` on `input`, returning its output and final memory if it
+/// finishes within `steps` steps
+fn run_bounded(tree: &SyntaxTree, input: &[u8], steps: usize) -> Option<(Vec<u8>, Vec<u8>, usize)> {
+    let mut memory = MemoryContext::new();
+    memory.set_input(Input::replay(input.to_vec()));
+    memory.capture_output();
+
+    match memory.run(tree, &mut ExecutionState::default(), Some(steps)) {
+        Stopped::Finished => {
+            let output = memory.take_output();
+            Some((output, memory.cells().to_vec(), memory.pointer()))
+        }
+        _ => None,
+    }
+}
+
+/// Net effect of a run of `+-<>`
+#[derive(Default)]
+struct Effect {
+    /// Change to the cell at each offset from where the run starts
+    deltas: BTreeMap<isize, u8>,
+    /// Offset the pointer ends at
+    shift: isize,
+}
+
+impl Effect {
+    /// Adds `expr` to this effect, if it is one of `+-<>`
+    fn add(&mut self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Forward => self.shift += 1,
+            Expression::Backward => self.shift -= 1,
+            Expression::Increment => {
+                let delta = self.deltas.entry(self.shift).or_default();
+                *delta = delta.wrapping_add(1);
+            }
+            Expression::Decrement => {
+                let delta = self.deltas.entry(self.shift).or_default();
+                *delta = delta.wrapping_sub(1);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Whether this effect only changes the current cell by an odd amount,
+    /// so repeating it until the cell is zero always ends there
+    fn clears(&self) -> bool {
+        let mut changed = self.deltas.iter().filter(|(_, &delta)| delta != 0);
+
+        self.shift == 0
+            && matches!(changed.next(), Some((0, delta)) if delta % 2 == 1)
+            && changed.next().is_none()
+    }
+
+    /// Writes this effect visiting the cells from left to right
+    fn write(&self, block: &mut Vec<Expression>) {
+        let mut position = 0;
+        let mut move_to = |block: &mut Vec<Expression>, offset: isize| {
+            let step = match offset > position {
+                true => Expression::Forward,
+                false => Expression::Backward,
+            };
+            block.extend(std::iter::repeat_n(step, offset.abs_diff(position)));
+            position = offset;
+        };
+
+        for (&offset, &delta) in self.deltas.iter().filter(|(_, &delta)| delta != 0) {
+            move_to(block, offset);
+            let (step, count) = match delta <= 128 {
+                true => (Expression::Increment, delta as usize),
+                false => (Expression::Decrement, 256 - delta as usize),
+            };
+            block.extend(std::iter::repeat_n(step, count));
+        }
+        move_to(block, self.shift);
+    }
+}
+
+/// Rewrites `block` in its normal form
+fn normalize(block: &[Expression]) -> Vec<Expression> {
+    let mut normalized = Vec::new();
+    let mut effect = Effect::default();
+
+    for expr in block {
+        if effect.add(expr) {
+            continue;
+        }
+
+        std::mem::take(&mut effect).write(&mut normalized);
+        normalized.push(match expr {
+            Expression::Loop(body) => normalize_loop(body),
+            Expression::Procedure(body) => Expression::Procedure(normalize(body).into()),
+            expr => expr.clone(),
+        });
+    }
+    effect.write(&mut normalized);
+
+    normalized
+}
+
+fn normalize_loop(body: &[Expression]) -> Expression {
+    let mut effect = Effect::default();
+    let only_effect = body.iter().all(|expr| effect.add(expr));
+
+    if only_effect && effect.clears() {
+        return Expression::Loop(vec![Expression::Decrement].into());
+    }
+
+    Expression::Loop(normalize(body).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn equivalent(a: &str, b: &str) -> bool {
+        let a: SyntaxTree = a.parse().unwrap();
+        a.equivalent_to(&b.parse().unwrap())
+    }
+
+    #[test]
+    fn equivalence_merges_runs() {
+        assert!(equivalent("+-+><>>-<", "+>>-<"));
+        assert!(equivalent(">+<-", "->+<"));
+        assert!(equivalent("+".repeat(255).as_str(), "-"));
+        assert!(!equivalent("+.", ".+"));
+        assert!(!equivalent("+>", "+"));
+    }
+
+    #[test]
+    fn equivalence_canonicalizes_clear_loops() {
+        assert!(equivalent(",[+]", ",[-]"));
+        assert!(equivalent(",[-+-]", ",[-]"));
+        assert!(equivalent(",[+++]", ",[-]"));
+        assert!(!equivalent(",[--]", ",[-]"));
+        assert!(!equivalent(",[->]", ",[-]"));
+        assert!(equivalent("[>+<-+-]", "[->+<]"));
+    }
+
+    #[test]
+    fn equivalence_runs_both_programs() {
+        let a: SyntaxTree = ",[->++<]>.".parse().unwrap();
+        let b: SyntaxTree = ",[->+>+<<]>[-<<+>>]>[-<<+>>]<<<[->+<]>.".parse().unwrap();
+
+        assert!(!a.equivalent_to(&b));
+        assert!(!a.equivalent_on(&b, &[b"\x03"], 1_000));
+
+        let b: SyntaxTree = ",[->+<]>[-<++>]<[->+<]>.".parse().unwrap();
+        assert!(a.equivalent_on(&b, &[b"\x00", b"\x03", b"\x7f"], 10_000));
+        assert!(!a.equivalent_on(&b, &[b"\x7f"], 100));
+    }
+}
@@ -1,16 +1,166 @@
-use crate::syntax::Expression;
+use crate::{
+    arena::{ArenaNode, ArenaTree},
+    bounds,
+    bytecode::Program,
+    input::{EofBehavior, Input},
+    output::{FlushPolicy, Output, OutputMode},
+    profile::{MemoryHeatmap, Profile},
+    syntax::{Expression, SyntaxTree},
+    token::{SourceOffset, Span},
+    vm::{self, Inst},
+};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 /// The default amount of memory allowed for a BrainFuck program
-const DEFAULT_BRAINFUCK_STACK_SIZE: usize = 32_768;
+pub(crate) const DEFAULT_BRAINFUCK_STACK_SIZE: usize = 32_768;
+
+/// The default amount of cells shown by `#`
+const DEFAULT_DUMP_CELLS: usize = 16;
+
+/// Amount of steps between two tracing events reporting progress
+#[cfg(feature = "tracing")]
+const TRACING_STEP_INTERVAL: u64 = 1_000_000;
 
 /// It holds the memory of the program
 pub type Memory = Vec<u8>;
 
+/// Called by `#` with the memory and the index of the pointer
+pub type DumpHandler = Box<dyn FnMut(&[u8], usize)>;
+
+/// Reason why the execution of a program stopped
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Stopped {
+    /// The program ran until the end, or until `@` of Extended Type I
+    Finished,
+
+    /// The program reached the instruction at the given source offset,
+    /// which has a breakpoint. That instruction has not run yet.
+    Breakpoint(SourceOffset),
+
+    /// The program ran the amount of steps it was allowed to
+    StepLimit,
+
+    /// The program read past the end of its input with
+    /// [`EofBehavior::Error`]. That `,` has not run yet, so it reads again
+    /// when resumed.
+    EndOfInput,
+}
+
+/// Position of the next instruction to run in a [`SyntaxTree`]
+///
+/// It stores the index of the current expression on every nesting level,
+/// so execution can be paused and resumed later.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Cursor {
+    path: Vec<usize>,
+    /// Nesting levels that are the body of a called procedure, along with
+    /// the path to that procedure in the tree
+    calls: Vec<(usize, Vec<usize>)>,
+    paused: bool,
+}
+
+impl Cursor {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+/// Changes made by a single step of execution
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Change {
+    /// Cursor before the step
+    cursor: Cursor,
+    /// Pointer before the step
+    pointer: usize,
+    /// Value of the cell under the pointer before the step
+    cell: u8,
+    /// Value of the storage before the step
+    storage: u8,
+    /// Byte consumed from the input by the step
+    input: Option<u8>,
+}
+
+/// Bounded log of the changes made by the last steps of execution
+///
+/// It allows to undo those steps later. A limit of zero disables it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct History {
+    limit: usize,
+    changes: VecDeque<Change>,
+}
+
+impl History {
+    /// Maximum amount of steps remembered
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Changes the maximum amount of steps remembered, forgetting
+    /// the oldest ones if needed
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.changes.len() > limit {
+            self.changes.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+
+    fn record(&mut self, change: Change) {
+        if self.limit == 0 {
+            return;
+        }
+
+        if self.changes.len() == self.limit {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(change);
+    }
+}
+
+/// Everything needed to pause, resume and rewind a running program
+#[derive(Debug, Default, Clone)]
+pub struct ExecutionState {
+    pub cursor: Cursor,
+    pub breakpoints: HashSet<SourceOffset>,
+    pub history: History,
+    /// Execution counts of each instruction, when profiling
+    pub profile: Option<Profile>,
+    /// Accesses made to each cell, when tracking them
+    pub heatmap: Option<MemoryHeatmap>,
+}
+
+/// One nesting level of the program being executed
+struct Frame<'a> {
+    expressions: &'a [Expression],
+    spans: &'a [Span],
+    index: usize,
+    /// Offset of the `]` or `)` closing this block, if it is the body of
+    /// a loop or a procedure
+    end: Option<SourceOffset>,
+    /// Path in the tree to the procedure, if this block is the body of a
+    /// called procedure
+    called: Option<Vec<usize>>,
+}
+
 /// This represents the running context of a BrainFuck program
-#[derive(Debug, Hash)]
 pub struct MemoryContext {
     memory: Memory,
     pointer_index: usize,
+    input: Input,
+    /// Replaces the dump to the standard error made by `#`
+    dump_handler: Option<DumpHandler>,
+    dump_cells: usize,
+    /// Path in the tree to the procedure defined for each cell value
+    procedures: HashMap<u8, Vec<usize>>,
+    /// Storage cell of Extended Type I
+    storage: u8,
+    /// Bytes written by the program, when kept instead of printed
+    output: Option<Vec<u8>>,
+    stdout: Output,
+    eof: EofBehavior,
 }
 
 impl MemoryContext {
@@ -25,6 +175,14 @@ impl MemoryContext {
         MemoryContext {
             memory,
             pointer_index,
+            input: Input::stdin(),
+            dump_handler: None,
+            dump_cells: DEFAULT_DUMP_CELLS,
+            procedures: HashMap::new(),
+            storage: 0,
+            output: None,
+            stdout: Output::stdout(),
+            eof: EofBehavior::default(),
         }
     }
 
@@ -50,6 +208,52 @@ impl MemoryContext {
         }
     }
 
+    /// Moves the pointer by `offset` cells, wrapping around the memory
+    /// like moving one cell at a time
+    #[inline]
+    pub fn move_by(&mut self, offset: isize) {
+        self.pointer_index = self.index_at(offset);
+    }
+
+    /// Index of the cell `offset` cells away from the pointer
+    #[inline]
+    fn index_at(&self, offset: isize) -> usize {
+        let len = self.memory.len() as isize;
+        (self.pointer_index as isize + offset).rem_euclid(len) as usize
+    }
+
+    /// Index of the cell the pointer is currently at
+    pub fn pointer(&self) -> usize {
+        self.pointer_index
+    }
+
+    /// Whether every cell is zero and the pointer is where it starts
+    pub fn is_fresh(&self) -> bool {
+        self.pointer_index == self.memory.len() / 2 && self.memory.iter().all(|&cell| cell == 0)
+    }
+
+    /// Takes the cells out, with the pointer and storage, for compiled
+    /// programs to change them while the context handles their input and
+    /// output
+    #[cfg(feature = "jit")]
+    pub(crate) fn take_registers(&mut self) -> (Memory, usize, u8) {
+        let memory = std::mem::take(&mut self.memory);
+        (memory, self.pointer_index, self.storage)
+    }
+
+    /// Puts back what [`take_registers`](Self::take_registers) took
+    #[cfg(feature = "jit")]
+    pub(crate) fn restore_registers(&mut self, (memory, pointer, storage): (Memory, usize, u8)) {
+        self.memory = memory;
+        self.pointer_index = pointer;
+        self.storage = storage;
+    }
+
+    /// Every cell of the memory
+    pub fn cells(&self) -> &[u8] {
+        &self.memory
+    }
+
     #[inline]
     pub fn set(&mut self, value: u8) {
         self.memory[self.pointer_index] = value;
@@ -70,32 +274,714 @@ impl MemoryContext {
         self.set(self.get().wrapping_sub(1))
     }
 
+    /// Source of the bytes read by the program
+    pub fn input(&mut self) -> &mut Input {
+        &mut self.input
+    }
+
+    /// Changes where the program reads its input from
+    pub fn set_input(&mut self, input: Input) {
+        self.input = input;
+    }
+
+    /// What `,` does once the input has no more bytes
+    pub fn eof(&self) -> EofBehavior {
+        self.eof
+    }
+
+    /// Changes what `,` does once the input has no more bytes
+    pub fn set_eof(&mut self, eof: EofBehavior) {
+        self.eof = eof;
+    }
+
+    /// Reads the next byte into the current cell, returning it, or does
+    /// what the EOF behavior says once the input has no more bytes
+    #[inline]
+    fn read_input(&mut self) -> Option<u8> {
+        let byte = self.input.read_byte();
+        match (byte, self.eof) {
+            (Some(byte), _) => self.set(byte),
+            (None, EofBehavior::Zero) => self.set(0),
+            (None, EofBehavior::MinusOne) => self.set(u8::MAX),
+            (None, EofBehavior::Unchanged | EofBehavior::Error) => (),
+        }
+
+        byte
+    }
+
+    /// Whether reading `byte` must stop the program
     #[inline]
+    fn stops_at(&self, byte: Option<u8>) -> bool {
+        byte.is_none() && self.eof == EofBehavior::Error
+    }
+
+    /// Keeps the bytes written by the program instead of printing them
+    pub fn capture_output(&mut self) {
+        self.output.get_or_insert_with(Vec::new);
+    }
+
+    /// Takes the bytes written since the output started to be captured
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.output.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Writes to `output` from now on, keeping the flush policy and the
+    /// output mode
+    pub fn set_output(&mut self, mut output: Output) {
+        self.stdout.flush();
+        output.set_policy(self.stdout.policy());
+        output.set_mode(self.stdout.mode());
+        self.stdout = output;
+    }
+
+    /// Changes when the bytes printed by the program are flushed
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.stdout.set_policy(policy);
+    }
+
+    /// Changes how the bytes printed by the program are written
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        self.stdout.set_mode(mode);
+    }
+
+    /// Holds the standard input while the program runs, until
+    /// [`finish_io`](Self::finish_io)
+    pub fn start_io(&mut self) {
+        self.input.hold();
+    }
+
+    /// Releases the standard input and prints every byte written by the
+    /// program and not flushed yet
+    pub fn finish_io(&mut self) {
+        self.input.release();
+        self.stdout.flush();
+    }
+
+    /// Handles `#` with `handler` instead of writing to the standard error
+    pub fn set_dump_handler(&mut self, handler: DumpHandler) {
+        self.dump_handler = Some(handler);
+    }
+
+    /// Changes the amount of cells written by `#` to the standard error
+    pub fn set_dump_cells(&mut self, cells: usize) {
+        self.dump_cells = cells;
+    }
+
+    /// Forgets every procedure defined by running a tree
+    ///
+    /// Procedures are remembered by their position in the tree, so they
+    /// must be forgotten before running a different tree.
+    pub fn clear_procedures(&mut self) {
+        self.procedures.clear();
+    }
+
+    /// Shows the pointer and the cells from where the pointer starts
+    pub(crate) fn debug_dump(&mut self) {
+        let memory = std::mem::take(&mut self.memory);
+        self.dump(&memory, self.pointer_index);
+        self.memory = memory;
+    }
+
+    /// Shows `pointer` and the `cells` from where the pointer starts, which
+    /// may be held by a compiled program instead of this context
+    pub(crate) fn dump(&mut self, cells: &[u8], pointer: usize) {
+        if let Some(handler) = self.dump_handler.as_mut() {
+            return handler(cells, pointer);
+        }
+
+        self.stdout.flush();
+        let start = cells.len() / 2;
+        let end = (start + self.dump_cells).min(cells.len());
+        eprintln!(
+            "pointer: {pointer}, cells {start}..{end}: {:?}",
+            &cells[start..end]
+        );
+    }
+
+    /// Writes the current cell to the output
+    #[inline]
+    fn write_output(&mut self) {
+        self.write_byte(self.get());
+    }
+
+    /// Writes `byte` to the output
+    #[inline]
+    pub(crate) fn write_byte(&mut self, byte: u8) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(byte, "output");
+
+        match self.output.as_mut() {
+            Some(output) => output.push(byte),
+            None => self.stdout.write_byte(byte),
+        }
+    }
+
+    /// Runs a single expression, including the whole body of loops
+    ///
+    /// Procedures are only defined and called by [`run`](Self::run), and
+    /// only it stops at `@` or at the end of the input, so they are ignored
+    /// here.
     pub fn execute_expression(&mut self, expr: &Expression) {
-        match expr {
-            Expression::Increment => self.increment(),
-            Expression::Decrement => self.decrement(),
-            Expression::Forward => self.move_forward(),
-            Expression::Backward => self.move_backward(),
-            Expression::Input => {
-                use std::io::{stdin, Read};
-
-                if let Some(Ok(byte)) = stdin().bytes().next() {
-                    self.set(byte);
-                } else {
-                    self.set(0)
+        // Loops being run, with the index of their next expression
+        let mut loops: Vec<(&SyntaxTree, usize)> = Vec::new();
+        let mut next = Some(expr);
+
+        loop {
+            let expr = match next.take() {
+                Some(expr) => expr,
+                None => {
+                    let Some((body, index)) = loops.last_mut() else {
+                        return;
+                    };
+                    match body.get(*index) {
+                        Some(expr) => {
+                            *index += 1;
+                            expr
+                        }
+                        None if self.get() != 0 => {
+                            *index = 0;
+                            continue;
+                        }
+                        None => {
+                            loops.pop();
+                            continue;
+                        }
+                    }
                 }
+            };
+
+            match expr {
+                Expression::Increment => self.increment(),
+                Expression::Decrement => self.decrement(),
+                Expression::Forward => self.move_forward(),
+                Expression::Backward => self.move_backward(),
+                Expression::Input => {
+                    self.read_input();
+                }
+                Expression::Output => self.write_output(),
+                Expression::DebugDump => self.debug_dump(),
+                Expression::Loop(body) => {
+                    if self.get() != 0 {
+                        loops.push((body, 0));
+                    }
+                }
+                Expression::Store => self.storage = self.get(),
+                Expression::Retrieve => self.set(self.storage),
+                Expression::ShiftLeft => self.set(self.get() << 1),
+                Expression::ShiftRight => self.set(self.get() >> 1),
+                Expression::Not => self.set(!self.get()),
+                Expression::Xor => self.set(self.get() ^ self.storage),
+                Expression::And => self.set(self.get() & self.storage),
+                Expression::Or => self.set(self.get() | self.storage),
+                Expression::Procedure(_) | Expression::Call | Expression::End => (),
             }
-            Expression::Output => print!("{}", self.get() as char),
-            Expression::Loop(expressions) => {
-                while self.get() != 0 {
-                    for expr in expressions {
-                        self.execute_expression(expr);
+        }
+    }
+
+    /// Runs `tree` until its end, or until `@` of Extended Type I
+    ///
+    /// Procedures defined by `tree` are only remembered while it runs.
+    pub fn run_arena(&mut self, tree: &ArenaTree) {
+        let nodes = tree.nodes();
+        // Node defining the procedure of each cell value
+        let mut procedures: HashMap<u8, usize> = HashMap::new();
+        // Blocks being run, with the node opening them and where to go back
+        // to if they are the body of a called procedure
+        let mut blocks: Vec<(usize, Option<usize>)> = Vec::new();
+        let mut index = 0;
+
+        loop {
+            let end = blocks
+                .last()
+                .map_or(nodes.len(), |&(start, _)| tree.block_end(start));
+
+            if index == end {
+                let Some(&(start, called)) = blocks.last() else {
+                    return;
+                };
+                match called {
+                    None if self.get() != 0 => index = start + 1,
+                    called => {
+                        index = called.unwrap_or(end);
+                        blocks.pop();
                     }
                 }
+                continue;
+            }
+
+            match &nodes[index] {
+                ArenaNode::Loop(_) if self.get() != 0 => {
+                    blocks.push((index, None));
+                    index += 1;
+                }
+                ArenaNode::Loop(end) => index = end.index(),
+                ArenaNode::Procedure(end) => {
+                    procedures.insert(self.get(), index);
+                    index = end.index();
+                }
+                ArenaNode::Expression(Expression::Call) => match procedures.get(&self.get()) {
+                    Some(&start) => {
+                        blocks.push((start, Some(index + 1)));
+                        index = start + 1;
+                    }
+                    None => index += 1,
+                },
+                ArenaNode::Expression(Expression::End) => return,
+                ArenaNode::Expression(expr) => {
+                    self.execute_expression(expr);
+                    index += 1;
+                }
             }
         }
     }
+
+    /// Runs `tree` from the position of the state cursor
+    ///
+    /// Stops before running any instruction found in the state breakpoints,
+    /// except the first one when resuming from a breakpoint, or after
+    /// running `steps` steps. On [`Stopped::Finished`] the cursor goes back
+    /// to the start of the tree.
+    ///
+    /// A step is running one instruction, checking the condition of a
+    /// loop, either when reaching it or at its end, or returning from a
+    /// procedure.
+    ///
+    /// A procedure is defined for the value of the current cell when it is
+    /// reached, and remembered until [`clear_procedures`]. Calling a value
+    /// without a procedure does nothing.
+    ///
+    /// [`clear_procedures`]: Self::clear_procedures
+    pub fn run(
+        &mut self,
+        tree: &SyntaxTree,
+        state: &mut ExecutionState,
+        mut steps: Option<usize>,
+    ) -> Stopped {
+        let ExecutionState {
+            cursor,
+            breakpoints,
+            history,
+            profile,
+            heatmap,
+        } = state;
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("execute").entered();
+        #[cfg(feature = "tracing")]
+        let mut executed: u64 = 0;
+
+        let mut frames = Self::restore_frames(tree, cursor);
+        let mut skip_breakpoint = cursor.paused;
+
+        loop {
+            let depth = frames.len();
+            let frame = frames
+                .last_mut()
+                .expect("there is always a top level frame");
+            let expr = frame.expressions.get(frame.index);
+
+            let offset = match expr {
+                Some(_) => frame.spans.get(frame.index).map(|s| s.start),
+                None => frame.end,
+            };
+
+            if let Some(offset) = offset {
+                if !skip_breakpoint && breakpoints.contains(&offset) {
+                    *cursor = Self::cursor(&frames, true);
+
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(offset, "breakpoint reached");
+
+                    return Stopped::Breakpoint(offset);
+                }
+            }
+
+            if (expr.is_none() && depth == 1) || matches!(expr, Some(Expression::End)) {
+                *cursor = Cursor::new();
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!(steps = executed, "finished");
+
+                return Stopped::Finished;
+            }
+
+            if let Some(remaining) = steps.as_mut() {
+                if *remaining == 0 {
+                    *cursor = Self::cursor(&frames, skip_breakpoint);
+                    return Stopped::StepLimit;
+                }
+                *remaining -= 1;
+            }
+            skip_breakpoint = false;
+
+            if let (Some(profile), Some(offset)) = (profile.as_mut(), offset) {
+                profile.record(offset);
+            }
+
+            if let Some(heatmap) = heatmap.as_mut() {
+                let cell = self.pointer_index;
+                match expr {
+                    None
+                    | Some(Expression::Loop(_))
+                    | Some(Expression::Output)
+                    | Some(Expression::Procedure(_))
+                    | Some(Expression::Call)
+                    | Some(Expression::Store) => heatmap.record_read(cell),
+                    Some(Expression::Increment)
+                    | Some(Expression::Decrement)
+                    | Some(Expression::ShiftLeft)
+                    | Some(Expression::ShiftRight)
+                    | Some(Expression::Not)
+                    | Some(Expression::Xor)
+                    | Some(Expression::And)
+                    | Some(Expression::Or) => {
+                        heatmap.record_read(cell);
+                        heatmap.record_write(cell);
+                    }
+                    Some(Expression::Input) | Some(Expression::Retrieve) => {
+                        heatmap.record_write(cell)
+                    }
+                    Some(Expression::Forward)
+                    | Some(Expression::Backward)
+                    | Some(Expression::DebugDump)
+                    | Some(Expression::End) => (),
+                }
+            }
+
+            let mut change = (history.limit() > 0).then(|| Change {
+                cursor: Self::cursor(&frames, false),
+                pointer: self.pointer_index,
+                cell: self.get(),
+                storage: self.storage,
+                input: None,
+            });
+
+            let frame = frames
+                .last_mut()
+                .expect("there is always a top level frame");
+
+            match expr {
+                None if frame.called.is_none() && self.get() != 0 => frame.index = 0,
+                None => {
+                    frames.pop();
+                    if let Some(parent) = frames.last_mut() {
+                        parent.index += 1;
+                    }
+                }
+                Some(Expression::Loop(body)) if self.get() != 0 => {
+                    let span = frame.spans.get(frame.index);
+
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(offset = ?span.map(|s| s.start), "loop entered");
+
+                    let frame = Frame {
+                        expressions: body,
+                        spans: body.spans(),
+                        index: 0,
+                        end: span.map(|s| s.end - 1),
+                        called: None,
+                    };
+                    frames.push(frame);
+                }
+                Some(Expression::Loop(_)) => frame.index += 1,
+                Some(Expression::Procedure(_)) => {
+                    let index = frame.index;
+                    frame.index += 1;
+
+                    let mut path = Self::block_path(&frames);
+                    path.push(index);
+                    self.procedures.insert(self.get(), path);
+                }
+                Some(Expression::Call) => {
+                    let called = self
+                        .procedures
+                        .get(&self.get())
+                        .and_then(|path| Self::procedure_frame(tree, path.clone()));
+
+                    match called {
+                        Some(called) => frames.push(called),
+                        None => frame.index += 1,
+                    }
+                }
+                Some(Expression::Input) => {
+                    let byte = self.read_input();
+                    if self.stops_at(byte) {
+                        *cursor = Self::cursor(&frames, true);
+                        return Stopped::EndOfInput;
+                    }
+
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(?byte, "input");
+
+                    if let Some(change) = change.as_mut() {
+                        change.input = byte;
+                    }
+                    frame.index += 1;
+                }
+                Some(expr) => {
+                    self.execute_expression(expr);
+                    frame.index += 1;
+                }
+            }
+
+            if let Some(change) = change {
+                history.record(change);
+            }
+
+            #[cfg(feature = "tracing")]
+            {
+                executed += 1;
+                if executed.is_multiple_of(TRACING_STEP_INTERVAL) {
+                    tracing::debug!(steps = executed, "executing");
+                }
+            }
+        }
+    }
+
+    /// Runs a compiled `program` from its start until its end, or until
+    /// `@` of Extended Type I
+    ///
+    /// Procedures defined by the program are only remembered while it
+    /// runs, so they are not the ones used by [`run`](Self::run). Programs
+    /// stopped by [`Stopped::EndOfInput`] can't be resumed.
+    pub fn run_program(&mut self, program: &Program) -> Stopped {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("execute_program").entered();
+
+        let ops = program.ops();
+        let in_bounds = bounds::in_bounds(ops, self.pointer_index, self.memory.len());
+        let insts = vm::lower(ops, &in_bounds);
+        let mut procedures = HashMap::new();
+        let mut calls = Vec::new();
+        let mut index = 0;
+
+        while let Some(&inst) = insts.get(index) {
+            index += 1;
+
+            match inst {
+                Inst::Add(amount) => self.set(self.get().wrapping_add(amount)),
+                Inst::Move(offset) => {
+                    self.pointer_index = self.pointer_index.wrapping_add_signed(offset)
+                }
+                Inst::MoveWrapping(offset) => self.move_by(offset),
+                Inst::Set(value) => self.set(value),
+                Inst::MoveAdd { offset, amount } => {
+                    self.pointer_index = self.pointer_index.wrapping_add_signed(offset);
+                    self.set(self.get().wrapping_add(amount));
+                }
+                Inst::AddTo(offset) => {
+                    let index = self.pointer_index.wrapping_add_signed(offset);
+                    self.memory[index] = self.memory[index].wrapping_add(self.get());
+                }
+                Inst::Multiply { offset, factor } => {
+                    let index = self.pointer_index.wrapping_add_signed(offset);
+                    let product = self.get().wrapping_mul(factor);
+                    self.memory[index] = self.memory[index].wrapping_add(product);
+                }
+                Inst::MultiplyWrapping { offset, factor } => {
+                    let index = self.index_at(offset);
+                    let product = self.get().wrapping_mul(factor);
+                    self.memory[index] = self.memory[index].wrapping_add(product);
+                }
+                Inst::Scan(offset) => {
+                    while self.get() != 0 {
+                        self.move_by(offset);
+                    }
+                }
+                Inst::AddJumpIfNotZero { amount, target } => {
+                    let value = self.get().wrapping_add(amount);
+                    self.set(value);
+                    if value != 0 {
+                        index = target;
+                    }
+                }
+                Inst::Input => {
+                    let byte = self.read_input();
+                    if self.stops_at(byte) {
+                        return Stopped::EndOfInput;
+                    }
+                }
+                Inst::Output => self.write_output(),
+                Inst::DebugDump => self.debug_dump(),
+                Inst::JumpIfZero(target) if self.get() == 0 => index = target,
+                Inst::JumpIfNotZero(target) if self.get() != 0 => index = target,
+                Inst::JumpIfZero(_) | Inst::JumpIfNotZero(_) => (),
+                Inst::Procedure(end) => {
+                    procedures.insert(self.get(), index);
+                    index = end;
+                }
+                Inst::Return => {
+                    if let Some(back) = calls.pop() {
+                        index = back;
+                    }
+                }
+                Inst::Call => {
+                    if let Some(&start) = procedures.get(&self.get()) {
+                        calls.push(index);
+                        index = start;
+                    }
+                }
+                Inst::End => break,
+                Inst::Store => self.storage = self.get(),
+                Inst::Retrieve => self.set(self.storage),
+                Inst::ShiftLeft => self.set(self.get() << 1),
+                Inst::ShiftRight => self.set(self.get() >> 1),
+                Inst::Not => self.set(!self.get()),
+                Inst::Xor => self.set(self.get() ^ self.storage),
+                Inst::And => self.set(self.get() & self.storage),
+                Inst::Or => self.set(self.get() | self.storage),
+            }
+        }
+
+        Stopped::Finished
+    }
+
+    /// Undoes the last step recorded in the state history
+    ///
+    /// Input read by that step will be read again. Output and procedure
+    /// definitions can't be undone.
+    /// Returns `false` if there was no step to undo.
+    pub fn undo(&mut self, state: &mut ExecutionState) -> bool {
+        let Some(change) = state.history.changes.pop_back() else {
+            return false;
+        };
+
+        self.pointer_index = change.pointer;
+        self.set(change.cell);
+        self.storage = change.storage;
+        if let Some(byte) = change.input {
+            self.input.unread(byte);
+        }
+
+        state.cursor = Cursor {
+            paused: true,
+            ..change.cursor
+        };
+
+        true
+    }
+
+    fn cursor(frames: &[Frame], paused: bool) -> Cursor {
+        Cursor {
+            path: frames.iter().map(|frame| frame.index).collect(),
+            calls: frames
+                .iter()
+                .enumerate()
+                .filter_map(|(depth, frame)| Some((depth, frame.called.clone()?)))
+                .collect(),
+            paused,
+        }
+    }
+
+    /// Offset in `tree` of the instruction that `cursor` runs next, or of
+    /// the `]` of the loop whose condition it checks next
+    pub fn next_offset(tree: &SyntaxTree, cursor: &Cursor) -> Option<SourceOffset> {
+        let frames = Self::restore_frames(tree, cursor);
+        let frame = frames.last()?;
+        match frame.expressions.get(frame.index) {
+            Some(_) => frame.spans.get(frame.index).map(|span| span.start),
+            None => frame.end,
+        }
+    }
+
+    /// Frame running the body of the procedure at `path` in `tree`
+    fn procedure_frame(tree: &SyntaxTree, path: Vec<usize>) -> Option<Frame<'_>> {
+        let (&index, parents) = path.split_last()?;
+        let mut block = tree;
+        for &index in parents {
+            match block.get(index)? {
+                Expression::Loop(body) | Expression::Procedure(body) => block = body,
+                _ => return None,
+            }
+        }
+
+        let Expression::Procedure(body) = block.get(index)? else {
+            return None;
+        };
+
+        Some(Frame {
+            expressions: body,
+            spans: body.spans(),
+            index: 0,
+            end: block.span(index).map(|s| s.end - 1),
+            called: Some(path),
+        })
+    }
+
+    /// Path in the tree to the block run by the last frame
+    fn block_path(frames: &[Frame]) -> Vec<usize> {
+        let start = frames
+            .iter()
+            .rposition(|frame| frame.called.is_some())
+            .unwrap_or(0);
+        let mut path = frames[start].called.clone().unwrap_or_default();
+        path.extend(
+            frames[start..frames.len() - 1]
+                .iter()
+                .map(|frame| frame.index),
+        );
+
+        path
+    }
+
+    fn restore_frames<'a>(tree: &'a SyntaxTree, cursor: &Cursor) -> Vec<Frame<'a>> {
+        let mut frames = vec![Frame {
+            expressions: tree,
+            spans: tree.spans(),
+            index: 0,
+            end: None,
+            called: None,
+        }];
+        let mut calls = cursor.calls.iter().peekable();
+
+        for (depth, &index) in cursor.path.iter().enumerate() {
+            let frame = frames
+                .last_mut()
+                .expect("there is always a top level frame");
+            frame.index = index;
+
+            if depth + 1 == cursor.path.len() {
+                break;
+            }
+
+            if let Some((_, path)) = calls.next_if(|(call_depth, _)| *call_depth == depth + 1) {
+                match Self::procedure_frame(tree, path.clone()) {
+                    Some(called) => frames.push(called),
+                    None => break,
+                }
+                continue;
+            }
+
+            let Some(Expression::Loop(body)) = frame.expressions.get(index) else {
+                break;
+            };
+            let span = frame.spans.get(index);
+            let frame = Frame {
+                expressions: body,
+                spans: body.spans(),
+                index: 0,
+                end: span.map(|s| s.end - 1),
+                called: None,
+            };
+            frames.push(frame);
+        }
+
+        frames
+    }
+}
+
+impl std::fmt::Debug for MemoryContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryContext")
+            .field("memory", &self.memory)
+            .field("pointer_index", &self.pointer_index)
+            .field("input", &self.input)
+            .field("dump_cells", &self.dump_cells)
+            .field("procedures", &self.procedures)
+            .field("storage", &self.storage)
+            .field("stdout", &self.stdout)
+            .finish_non_exhaustive()
+    }
 }
 
 impl std::default::Default for MemoryContext {
@@ -157,4 +1043,208 @@ mod tests {
 
         assert_eq!(m.memory, vec![1, 3, 6, 2, u8::MAX - 1, u8::MAX - 2]);
     }
+
+    #[test]
+    fn memory_execute_deep_nesting() {
+        let depth = 100_000;
+        let code = "+".to_string() + &"[".repeat(depth) + "-" + &"]".repeat(depth) + "+";
+        let tree = code.parse::<ET>().unwrap();
+
+        let mut m = tiny_memory();
+        for expr in &tree {
+            m.execute_expression(expr);
+        }
+        assert_eq!(m.memory[3], 1);
+
+        let mut m = tiny_memory();
+        assert_eq!(
+            m.run(&tree, &mut ExecutionState::default(), None),
+            Stopped::Finished
+        );
+        assert_eq!(m.memory[3], 1);
+    }
+
+    #[test]
+    fn memory_run_stops_at_breakpoints() {
+        let mut m = tiny_memory();
+        let tree = "+++[>+<-]>".parse::<ET>().unwrap();
+        let mut state = ExecutionState {
+            breakpoints: HashSet::from([4, 8]),
+            ..Default::default()
+        };
+
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Breakpoint(4));
+        assert_eq!((m.pointer(), m.get()), (3, 3));
+
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Breakpoint(8));
+        assert_eq!(m.cells()[3..5], [2, 1]);
+
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Breakpoint(4));
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Breakpoint(8));
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Breakpoint(4));
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Breakpoint(8));
+        assert_eq!(m.cells()[3..5], [0, 3]);
+
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Finished);
+        assert_eq!(state.cursor, Cursor::new());
+    }
+
+    #[test]
+    fn memory_undo_restores_every_step() {
+        let mut m = tiny_memory();
+        let tree = "+>+[-]<[->-<]".parse::<ET>().unwrap();
+        let mut state = ExecutionState::default();
+        state.history.set_limit(100);
+
+        let mut snapshots = vec![(m.pointer(), m.cells().to_vec())];
+        while m.run(&tree, &mut state, Some(1)) == Stopped::StepLimit {
+            snapshots.push((m.pointer(), m.cells().to_vec()));
+        }
+        assert_eq!(state.history.changes.len(), snapshots.len());
+
+        while let Some(snapshot) = snapshots.pop() {
+            assert!(m.undo(&mut state));
+            assert_eq!((m.pointer(), m.cells().to_vec()), snapshot);
+        }
+        assert!(!m.undo(&mut state));
+        assert_eq!(state.cursor.path, vec![0]);
+    }
+
+    #[test]
+    fn memory_run_program_matches_run() {
+        for code in [
+            "+++[>+<-]>",
+            "+>+[-]<[->-<]",
+            "-[>+++<---]>[<+>-]",
+            "<<+[>]",
+            "<<<<<<<<<+>>>>>>>>>>>>>-",
+        ] {
+            let tree = code.parse::<ET>().unwrap();
+            let mut walked = tiny_memory();
+            walked.run(&tree, &mut ExecutionState::default(), None);
+
+            let mut compiled = tiny_memory();
+            compiled.run_program(&tree.compile());
+
+            assert_eq!(compiled.memory, walked.memory, "{code}");
+            assert_eq!(compiled.pointer(), walked.pointer(), "{code}");
+        }
+    }
+
+    fn pbrain(code: &str) -> ET {
+        use crate::{dialect::Dialect, token::Lexer};
+
+        ET::parse_spanned_tokens(Lexer::new(code.bytes()).dialect(Dialect::pbrain())).unwrap()
+    }
+
+    #[test]
+    fn memory_run_calls_procedures() {
+        let mut m = tiny_memory();
+        let tree = pbrain("+(>+++<)::-:");
+        let mut state = ExecutionState {
+            breakpoints: HashSet::from([7]),
+            ..Default::default()
+        };
+
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Breakpoint(7));
+        assert_eq!(m.cells()[3..5], [1, 3]);
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Breakpoint(7));
+        assert_eq!(m.cells()[3..5], [1, 6]);
+
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Finished);
+        assert_eq!((m.pointer(), &m.cells()[3..5]), (3, &[0, 6][..]));
+    }
+
+    #[test]
+    fn memory_run_program_calls_procedures() {
+        let mut m = tiny_memory();
+        m.run_program(&pbrain("+(>+++<)::-:").compile());
+
+        assert_eq!((m.pointer(), &m.cells()[3..5]), (3, &[0, 6][..]));
+    }
+
+    #[test]
+    fn memory_run_arena_matches_run() {
+        for tree in [
+            pbrain("+(>+++<)::-:"),
+            pbrain("++([>+<-])>+:<:>[[-]+(-)<]"),
+            "-[>+++<---]>[<+>-]".parse().unwrap(),
+        ] {
+            let mut walked = tiny_memory();
+            walked.run(&tree, &mut ExecutionState::default(), None);
+
+            let mut arena = tiny_memory();
+            arena.run_arena(&ArenaTree::from(&tree));
+
+            assert_eq!(arena.memory, walked.memory, "{tree}");
+            assert_eq!(arena.pointer(), walked.pointer(), "{tree}");
+        }
+    }
+
+    #[test]
+    fn memory_undo_steps_inside_procedures() {
+        let mut m = tiny_memory();
+        let tree = pbrain("+([>+<-]):");
+        let mut state = ExecutionState::default();
+        state.history.set_limit(100);
+
+        let mut snapshots = vec![(m.pointer(), m.cells().to_vec())];
+        while m.run(&tree, &mut state, Some(1)) == Stopped::StepLimit {
+            snapshots.push((m.pointer(), m.cells().to_vec()));
+        }
+        assert_eq!(m.cells()[3..5], [0, 1]);
+
+        for _ in 0..3 {
+            let snapshot = snapshots.pop();
+            assert!(m.undo(&mut state));
+            assert_eq!(Some((m.pointer(), m.cells().to_vec())), snapshot);
+        }
+        assert_eq!(state.cursor.calls, [(1, vec![1])]);
+
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Finished);
+        assert_eq!(m.cells()[3..5], [0, 1]);
+    }
+
+    #[test]
+    fn memory_runs_extended_type_one() {
+        use crate::{dialect::Dialect, token::Lexer};
+
+        let mut m = tiny_memory();
+        let tokens = Lexer::new("+++$>!{{}~&|^<[-]!@+".bytes()).dialect(Dialect::extended());
+        let tree = ET::parse_spanned_tokens(tokens).unwrap();
+        let mut state = ExecutionState::default();
+        state.history.set_limit(100);
+
+        assert_eq!(m.run(&tree, &mut state, Some(11)), Stopped::StepLimit);
+        assert_eq!((m.get(), m.storage), (249 & 3, 3));
+
+        assert_eq!(m.run(&tree, &mut state, None), Stopped::Finished);
+        assert_eq!((m.cells()[3..5].to_vec(), m.storage), (vec![3, 0], 3));
+
+        for _ in 0..state.history.changes.len() - 3 {
+            m.undo(&mut state);
+        }
+        assert_eq!((m.get(), m.storage), (3, 0));
+    }
+
+    #[test]
+    fn memory_history_is_bounded() {
+        let mut history = History::default();
+        history.set_limit(2);
+
+        for pointer in 0..5 {
+            history.record(Change {
+                cursor: Cursor::new(),
+                pointer,
+                cell: 0,
+                storage: 0,
+                input: None,
+            });
+        }
+        assert_eq!(history.changes.len(), 2);
+        assert_eq!(history.changes[0].pointer, 3);
+
+        history.set_limit(1);
+        assert_eq!(history.changes[0].pointer, 4);
+    }
 }
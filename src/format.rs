@@ -0,0 +1,301 @@
+use crate::{
+    cst::{Comment, ConcreteSyntaxTree},
+    syntax::{Expression, SyntaxTree},
+};
+use std::iter;
+
+/// Pretty-printer for BrainFuck programs
+///
+/// Code between loop brackets goes in one line, while brackets go in
+/// their own lines with the body of the loop indented. Procedures are
+/// formatted the same way. With a maximum
+/// width, long lines are wrapped between runs of the same instruction,
+/// which are never split. Comments are only kept by
+/// [`format_source`](Self::format_source).
+///
+/// # Example
+/// ```
+/// # use brainfuck::{Formatter, SyntaxTree};
+/// let tree: SyntaxTree = "++[>+++<-]>.".parse().unwrap();
+///
+/// assert_eq!(
+///     Formatter::new().indent(2).format(&tree),
+///     "++\n[\n  >+++<-\n]\n>.\n"
+/// );
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Formatter {
+    indent: usize,
+    max_width: Option<usize>,
+}
+
+impl Formatter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Amount of spaces added on every nesting level, 4 by default
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    /// Column limit lines are wrapped at, if any
+    pub fn max_width(mut self, max_width: usize) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    /// Writes `tree` as formatted code
+    pub fn format(&self, tree: &SyntaxTree) -> String {
+        let mut output = String::new();
+        let mut comments = Comments::new(&[]);
+        self.format_block(&mut output, tree, &mut comments);
+
+        output
+    }
+
+    /// Writes the code of `cst` formatted, keeping every comment on its own
+    /// lines, before the code that followed it
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{ConcreteSyntaxTree, Formatter};
+    /// let cst: ConcreteSyntaxTree = "clear [-] then add ++".parse().unwrap();
+    ///
+    /// assert_eq!(
+    ///     Formatter::new().format_source(&cst),
+    ///     "clear\n[\n    -\n]\nthen add\n++\n"
+    /// );
+    /// ```
+    pub fn format_source(&self, cst: &ConcreteSyntaxTree) -> String {
+        let mut output = String::new();
+        let comments = cst.comments();
+        let mut comments = Comments::new(&comments);
+        self.format_block(&mut output, &cst.to_syntax_tree(), &mut comments);
+
+        output
+    }
+
+    fn format_block(&self, output: &mut String, tree: &[Expression], comments: &mut Comments) {
+        let mut indent = String::new();
+        let mut line = String::new();
+        // Blocks being formatted, with the index of their next expression
+        // and their nesting depth
+        let mut blocks = vec![(tree, 0, 0)];
+
+        while let Some((block, index, depth)) = blocks.last_mut() {
+            let (block, position, depth) = (*block, *index, *depth);
+            self.set_indent(&mut indent, depth);
+
+            let Some(expr) = block.get(position) else {
+                push_line(output, &indent, &mut line);
+                comments.path.push(block.len());
+                comments.write_next(output, &indent);
+                comments.path.pop();
+                blocks.pop();
+
+                if let Some(&(parent, index, depth)) = blocks.last() {
+                    comments.path.pop();
+                    self.set_indent(&mut indent, depth);
+                    line.push(match parent[index - 1] {
+                        Expression::Procedure(_) => ')',
+                        _ => ']',
+                    });
+                    push_line(output, &indent, &mut line);
+                }
+                continue;
+            };
+            *index += 1;
+
+            comments.path.push(position);
+            if comments.is_next() {
+                push_line(output, &indent, &mut line);
+                comments.write_next(output, &indent);
+            }
+            comments.path.pop();
+
+            let (open, body, close) = match expr {
+                Expression::Loop(body) => ('[', body, ']'),
+                Expression::Procedure(body) => ('(', body, ')'),
+                expr => {
+                    let is_run_start = position == 0 || block[position - 1] != *expr;
+                    if is_run_start {
+                        let run = block[position..].iter().take_while(|e| *e == expr).count();
+                        let width = indent.len() + line.len() + run;
+
+                        if !line.is_empty() && self.max_width.is_some_and(|max| width > max) {
+                            push_line(output, &indent, &mut line);
+                        }
+                    }
+
+                    line.push_str(&expr.to_code());
+                    continue;
+                }
+            };
+
+            push_line(output, &indent, &mut line);
+            if body.is_empty() {
+                line.push(open);
+                line.push(close);
+                push_line(output, &indent, &mut line);
+                continue;
+            }
+
+            line.push(open);
+            push_line(output, &indent, &mut line);
+            comments.path.push(position);
+            blocks.push((body, 0, depth + 1));
+        }
+    }
+
+    /// Makes `indent` as wide as a block at `depth`
+    fn set_indent(&self, indent: &mut String, depth: usize) {
+        let width = self.indent * depth;
+        indent.truncate(width);
+        indent.extend(iter::repeat_n(' ', width - indent.len()));
+    }
+}
+
+/// Comments left to write by [`Formatter`], in the order they appear
+struct Comments<'a> {
+    comments: std::iter::Peekable<std::slice::Iter<'a, Comment>>,
+    /// Path of the expression being formatted
+    path: Vec<usize>,
+}
+
+impl<'a> Comments<'a> {
+    fn new(comments: &'a [Comment]) -> Self {
+        Comments {
+            comments: comments.iter().peekable(),
+            path: Vec::new(),
+        }
+    }
+
+    /// Whether the next comment goes before the expression at the path
+    fn is_next(&mut self) -> bool {
+        self.comments
+            .peek()
+            .is_some_and(|comment| comment.path == self.path)
+    }
+
+    /// Writes every comment going before the expression at the path, each
+    /// line on its own
+    fn write_next(&mut self, output: &mut String, indent: &str) {
+        while self.is_next() {
+            let comment = self.comments.next().expect("a comment is next");
+            for line in comment.text.lines().map(str::trim) {
+                let mut line = line.to_string();
+                push_line(output, indent, &mut line);
+            }
+        }
+    }
+}
+
+impl std::default::Default for Formatter {
+    fn default() -> Self {
+        Formatter {
+            indent: 4,
+            max_width: None,
+        }
+    }
+}
+
+/// Moves `line` to the output, if there is anything in it
+fn push_line(output: &mut String, indent: &str, line: &mut String) {
+    if line.is_empty() {
+        return;
+    }
+
+    output.push_str(indent);
+    output.push_str(line);
+    output.push('\n');
+    line.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_indents_nested_loops() {
+        let tree: SyntaxTree = "+[>[-]<,]".parse().unwrap();
+
+        assert_eq!(
+            Formatter::new().format(&tree),
+            "+\n[\n    >\n    [\n        -\n    ]\n    <,\n]\n"
+        );
+    }
+
+    #[test]
+    fn format_indents_procedures() {
+        let tree = SyntaxTree::from(vec![
+            Expression::Procedure(vec![Expression::Increment].into()),
+            Expression::Procedure(SyntaxTree::new()),
+            Expression::Call,
+        ]);
+
+        assert_eq!(
+            Formatter::new().format(&tree),
+            "(
+    +
+)
+()
+:
+"
+        );
+    }
+
+    #[test]
+    fn format_wraps_between_runs() {
+        let tree: SyntaxTree = "++++>>>----[]".parse().unwrap();
+        let formatted = Formatter::new().max_width(6).format(&tree);
+
+        assert_eq!(formatted, "++++\n>>>\n----\n[]\n");
+        assert_eq!(formatted.parse::<SyntaxTree>(), Ok(tree));
+    }
+
+    #[test]
+    fn format_keeps_long_runs_together() {
+        let tree: SyntaxTree = "[+++++++.]".parse().unwrap();
+
+        assert_eq!(
+            Formatter::new().indent(1).max_width(4).format(&tree),
+            "[\n +++++++\n .\n]\n"
+        );
+    }
+
+    #[test]
+    fn format_source_keeps_comments() {
+        let source = "Add two\n++ to the next cell [->+<] done\n>. and print it";
+        let cst: ConcreteSyntaxTree = source.parse().unwrap();
+        let formatted = Formatter::new().format_source(&cst);
+
+        assert_eq!(
+            formatted,
+            "Add two\n++\nto the next cell\n[\n    ->+<\n]\ndone\n>.\nand print it\n"
+        );
+        assert_eq!(
+            formatted.parse::<SyntaxTree>().unwrap(),
+            cst.to_syntax_tree()
+        );
+
+        let cst: ConcreteSyntaxTree = "[ first\n  second\n+ inside ]".parse().unwrap();
+        assert_eq!(
+            Formatter::new().indent(2).format_source(&cst),
+            "[\n  first\n  second\n  +\n  inside\n]\n"
+        );
+    }
+
+    #[test]
+    fn format_deep_nesting() {
+        let depth = 100_000;
+        let code = "[+".repeat(depth) + "x" + &"]".repeat(depth);
+        let cst: ConcreteSyntaxTree = code.parse().unwrap();
+
+        assert_eq!(
+            Formatter::new().indent(0).format_source(&cst),
+            "[\n+\n".repeat(depth) + "x\n" + &"]\n".repeat(depth)
+        );
+    }
+}
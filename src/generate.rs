@@ -0,0 +1,148 @@
+use crate::syntax::{Expression, SyntaxTree};
+use std::iter;
+
+/// Factors tried by the loop setting up cells before printing
+const FACTORS: std::ops::RangeInclusive<u8> = 2..=16;
+
+/// Builds a short program printing `text`
+///
+/// A loop first sets up a few cells near the bytes of `text`, multiplying a
+/// counter by every one of them, then every byte is printed from the cell
+/// needing the fewest instructions to reach it and turn it into that byte.
+/// Every factor of the counter is tried, along with printing from a single
+/// cell, keeping the shortest program.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{generate, BrainFuckInterpreter};
+/// let tree = generate(b"Hello, World!");
+///
+/// let mut interpreter = BrainFuckInterpreter::new();
+/// interpreter.capture_output();
+/// interpreter.feed_tree(tree);
+/// interpreter.execute();
+///
+/// assert_eq!(interpreter.take_output(), b"Hello, World!");
+/// ```
+pub fn generate(text: &[u8]) -> SyntaxTree {
+    let plain = print(vec![0], text);
+
+    FACTORS
+        .map(|factor| set_up(factor, text))
+        .chain(iter::once(plain))
+        .map(SyntaxTree::from)
+        .min_by_key(|tree| tree.to_code().len())
+        .expect("there is always a program")
+}
+
+/// Program setting up cells with a counter of `factor` before printing
+/// `text` from them
+fn set_up(factor: u8, text: &[u8]) -> Vec<Expression> {
+    let mut multipliers: Vec<u8> = text
+        .iter()
+        .map(|&byte| ((u16::from(byte) + u16::from(factor / 2)) / u16::from(factor)) as u8)
+        .filter(|&multiplier| multiplier > 0)
+        .collect();
+    multipliers.sort_unstable();
+    multipliers.dedup();
+
+    let mut body = Vec::new();
+    for &multiplier in &multipliers {
+        body.push(Expression::Forward);
+        body.extend(iter::repeat_n(Expression::Increment, multiplier.into()));
+    }
+    body.extend(iter::repeat_n(Expression::Backward, multipliers.len()));
+    body.push(Expression::Decrement);
+
+    let mut code: Vec<Expression> = iter::repeat_n(Expression::Increment, factor.into()).collect();
+    code.push(Expression::Loop(body.into()));
+
+    let cells = iter::once(0)
+        .chain(
+            multipliers
+                .iter()
+                .map(|&multiplier| multiplier.wrapping_mul(factor)),
+        )
+        .collect();
+    code.extend(print(cells, text));
+
+    code
+}
+
+/// Program printing `text` from `cells` holding the given values, starting
+/// at the first one
+fn print(mut cells: Vec<u8>, text: &[u8]) -> Vec<Expression> {
+    let mut code = Vec::new();
+    let mut pointer = 0;
+
+    for &byte in text {
+        let cost = |(cell, &value): (usize, &u8)| cell.abs_diff(pointer) + distance(value, byte);
+        let (cell, _) = cells
+            .iter()
+            .enumerate()
+            .min_by_key(|&entry| cost(entry))
+            .expect("there is always a cell");
+
+        let moves = match cell > pointer {
+            true => Expression::Forward,
+            false => Expression::Backward,
+        };
+        code.extend(iter::repeat_n(moves, cell.abs_diff(pointer)));
+        pointer = cell;
+
+        let up = byte.wrapping_sub(cells[cell]);
+        match up <= 128 {
+            true => code.extend(iter::repeat_n(Expression::Increment, up.into())),
+            false => code.extend(iter::repeat_n(Expression::Decrement, 256 - usize::from(up))),
+        }
+        cells[cell] = byte;
+        code.push(Expression::Output);
+    }
+
+    code
+}
+
+/// Instructions turning a cell holding `from` into `to`, which wraps
+fn distance(from: u8, to: u8) -> usize {
+    let up = to.wrapping_sub(from);
+    usize::from(up.min(up.wrapping_neg()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::BrainFuckInterpreter;
+
+    fn output_of(tree: SyntaxTree) -> Vec<u8> {
+        let mut interpreter = BrainFuckInterpreter::new();
+        interpreter.capture_output();
+        interpreter.feed_tree(tree);
+        interpreter.execute();
+        interpreter.take_output()
+    }
+
+    #[test]
+    fn generate_prints_text() {
+        let texts: [&[u8]; 5] = [
+            b"",
+            b"A",
+            b"Hello, World!\n",
+            b"\x00\xff\x80\x7f",
+            "héllo €".as_bytes(),
+        ];
+
+        for text in texts {
+            assert_eq!(output_of(generate(text)), text, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn generate_beats_single_cell() {
+        let text = b"The quick brown fox jumps over the lazy dog";
+        let single = SyntaxTree::from(print(vec![0], text)).to_code();
+        let generated = generate(text).to_code();
+
+        assert!(generated.len() < single.len() / 2, "{generated}");
+        assert!(generated.contains('['));
+    }
+}
@@ -0,0 +1,219 @@
+use std::io::{self, Read, StdinLock};
+
+/// Splits code following the `!` convention, where everything after the
+/// first `!` is the input of the program instead of code
+///
+/// A shebang line at the start is kept as code.
+///
+/// # Example
+/// ```
+/// # use brainfuck::split_input;
+/// assert_eq!(split_input(b",[.,]!hi"), (&b",[.,]"[..], Some(&b"hi"[..])));
+/// assert_eq!(split_input(b"+."), (&b"+."[..], None));
+/// ```
+pub fn split_input(code: &[u8]) -> (&[u8], Option<&[u8]>) {
+    let shebang_end = match code.starts_with(b"#!") {
+        true => code
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .unwrap_or(code.len()),
+        false => 0,
+    };
+
+    match code[shebang_end..].iter().position(|&byte| byte == b'!') {
+        Some(index) => {
+            let index = shebang_end + index;
+            (&code[..index], Some(&code[index + 1..]))
+        }
+        None => (code, None),
+    }
+}
+
+/// What `,` does once the input has no more bytes
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum EofBehavior {
+    /// Sets the current cell to zero
+    #[default]
+    Zero,
+    /// Sets the current cell to 255, which is -1 for signed cells
+    MinusOne,
+    /// Leaves the current cell as it is
+    Unchanged,
+    /// Stops the program with [`Stopped::EndOfInput`], leaving the cell as
+    /// it is
+    ///
+    /// [`Stopped::EndOfInput`]: crate::Stopped::EndOfInput
+    Error,
+}
+
+/// Where the bytes of an [`Input`] come from
+enum Source {
+    /// The standard input, along with its lock while it is held
+    Stdin(Option<StdinLock<'static>>),
+    Reader(Box<dyn Read>),
+}
+
+/// Source of the bytes read by a BrainFuck program
+///
+/// It reads from the standard input by default, or from a replay log
+/// recorded from a previous execution. Every byte consumed can also be
+/// recorded to be replayed later.
+pub struct Input {
+    source: Source,
+    /// Bytes given back by undone steps, to be read again
+    unread: Vec<u8>,
+    recording: Option<Vec<u8>>,
+}
+
+impl Input {
+    /// Reads from the standard input
+    pub fn stdin() -> Self {
+        Self::from_source(Source::Stdin(None))
+    }
+
+    /// Reads the bytes of a log recorded by a previous execution
+    pub fn replay(log: Vec<u8>) -> Self {
+        Self::from_reader(io::Cursor::new(log))
+    }
+
+    /// Reads from `reader`, like a file or a terminal
+    pub fn from_reader<R: Read + 'static>(reader: R) -> Self {
+        Self::from_source(Source::Reader(Box::new(reader)))
+    }
+
+    fn from_source(source: Source) -> Self {
+        Input {
+            source,
+            unread: Vec::new(),
+            recording: None,
+        }
+    }
+
+    /// Reads the next byte, if any
+    pub fn read_byte(&mut self) -> Option<u8> {
+        let byte = match self.unread.pop() {
+            Some(byte) => Some(byte),
+            None => {
+                let mut byte = [0];
+                let read = match &mut self.source {
+                    Source::Stdin(Some(stdin)) => stdin.read_exact(&mut byte),
+                    Source::Stdin(None) => io::stdin().read_exact(&mut byte),
+                    Source::Reader(reader) => reader.read_exact(&mut byte),
+                };
+                read.ok().map(|_| byte[0])
+            }
+        };
+
+        if let (Some(recording), Some(byte)) = (self.recording.as_mut(), byte) {
+            recording.push(byte);
+        }
+
+        byte
+    }
+
+    /// Holds the standard input, when reading from it, until
+    /// [`release`](Self::release)
+    ///
+    /// Bytes are then read from its buffer without locking it every time.
+    pub fn hold(&mut self) {
+        if let Source::Stdin(stdin @ None) = &mut self.source {
+            *stdin = Some(io::stdin().lock());
+        }
+    }
+
+    /// Lets the standard input be read from elsewhere again
+    pub fn release(&mut self) {
+        if let Source::Stdin(stdin) = &mut self.source {
+            *stdin = None;
+        }
+    }
+
+    /// Gives back a byte so it is read again next
+    ///
+    /// If it was recorded, it is removed from the recording.
+    pub fn unread(&mut self, byte: u8) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.pop();
+        }
+
+        self.unread.push(byte);
+    }
+
+    /// Starts recording every byte read from now on
+    ///
+    /// Restarts the recording if it was already started.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    /// Stops recording and returns the recorded bytes
+    pub fn take_recording(&mut self) -> Vec<u8> {
+        self.recording.take().unwrap_or_default()
+    }
+}
+
+impl std::fmt::Debug for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Input")
+            .field("unread", &self.unread)
+            .field("recording", &self.recording)
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::default::Default for Input {
+    fn default() -> Self {
+        Self::stdin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_replays_log() {
+        let mut input = Input::replay(b"ab".to_vec());
+
+        assert_eq!(input.read_byte(), Some(b'a'));
+        assert_eq!(input.read_byte(), Some(b'b'));
+        assert_eq!(input.read_byte(), None);
+    }
+
+    #[test]
+    fn input_holds_only_stdin() {
+        let mut input = Input::replay(b"a".to_vec());
+        input.hold();
+        assert_eq!(input.read_byte(), Some(b'a'));
+        input.release();
+
+        let mut input = Input::stdin();
+        input.hold();
+        assert!(matches!(input.source, Source::Stdin(Some(_))));
+        input.release();
+        assert!(matches!(input.source, Source::Stdin(None)));
+    }
+
+    #[test]
+    fn input_records_consumed_bytes() {
+        let mut input = Input::replay(b"abcd".to_vec());
+        input.read_byte();
+        input.start_recording();
+
+        input.read_byte();
+        input.read_byte();
+        input.unread(b'c');
+        input.read_byte();
+        input.read_byte();
+
+        assert_eq!(input.take_recording(), b"bcd");
+        assert_eq!(input.take_recording(), b"");
+    }
+
+    #[test]
+    fn split_input_after_shebang() {
+        let code = b"#!/usr/bin/env bf\n,[.,]!input";
+        assert_eq!(split_input(code), (&code[..23], Some(&b"input"[..])));
+        assert_eq!(split_input(b"#!bf"), (&b"#!bf"[..], None));
+    }
+}
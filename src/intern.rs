@@ -0,0 +1,139 @@
+use crate::syntax::{Expression, SyntaxTree};
+use std::{collections::HashMap, rc::Rc};
+
+/// Identifies a block of expressions stored in an [`Interner`]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct BlockId(usize);
+
+/// An expression of an interned block, where bodies are blocks of the same
+/// [`Interner`]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub enum InternedNode {
+    /// Any expression without a body
+    Expression(Expression),
+    Loop(BlockId),
+    Procedure(BlockId),
+}
+
+/// Storage where identical blocks of expressions are kept only once
+///
+/// Interning a tree gives the same [`BlockId`] to equal blocks, so
+/// comparing two interned blocks only compares their ids. Bodies are
+/// interned before the blocks holding them, so hashing a block never
+/// walks into its loops. Locations of the expressions are not kept.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{Interner, SyntaxTree};
+/// let tree: SyntaxTree = "+[->+<]>[->+<]<[[->+<]]".parse().unwrap();
+/// let mut interner = Interner::new();
+/// let root = interner.intern(&tree);
+///
+/// assert_eq!(interner.len(), 3);
+/// assert_eq!(interner.to_syntax_tree(root), tree);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Interner {
+    blocks: Vec<Rc<[InternedNode]>>,
+    ids: HashMap<Rc<[InternedNode]>, BlockId>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Stores `block` along with the bodies of its loops and procedures,
+    /// reusing the blocks already stored
+    pub fn intern(&mut self, block: &[Expression]) -> BlockId {
+        let nodes: Rc<[InternedNode]> = block
+            .iter()
+            .map(|expr| match expr {
+                Expression::Loop(body) => InternedNode::Loop(self.intern(body)),
+                Expression::Procedure(body) => InternedNode::Procedure(self.intern(body)),
+                expr => InternedNode::Expression(expr.clone()),
+            })
+            .collect();
+
+        if let Some(&id) = self.ids.get(&nodes) {
+            return id;
+        }
+
+        let id = BlockId(self.blocks.len());
+        self.blocks.push(Rc::clone(&nodes));
+        self.ids.insert(nodes, id);
+
+        id
+    }
+
+    /// Nodes of the block identified by `id`
+    ///
+    /// # Panics
+    /// If `id` comes from another interner.
+    pub fn block(&self, id: BlockId) -> &[InternedNode] {
+        &self.blocks[id.0]
+    }
+
+    /// Amount of distinct blocks stored
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Rebuilds the tree whose top level is the block identified by `id`
+    ///
+    /// # Panics
+    /// If `id` comes from another interner.
+    pub fn to_syntax_tree(&self, id: BlockId) -> SyntaxTree {
+        self.block(id)
+            .iter()
+            .map(|node| match node {
+                InternedNode::Expression(expr) => expr.clone(),
+                InternedNode::Loop(body) => Expression::Loop(self.to_syntax_tree(*body)),
+                InternedNode::Procedure(body) => Expression::Procedure(self.to_syntax_tree(*body)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interner_shares_equal_blocks() {
+        let tree: SyntaxTree = "[-][-]>[[-]+][[-]+]".parse().unwrap();
+        let mut interner = Interner::new();
+        let root = interner.intern(&tree);
+
+        let nodes = interner.block(root);
+        assert_eq!(nodes[0], nodes[1]);
+        assert_eq!(nodes[3], nodes[4]);
+        assert_ne!(nodes[0], nodes[3]);
+        assert_eq!(interner.len(), 3);
+
+        let other: SyntaxTree = "[[-]+]".parse().unwrap();
+        let InternedNode::Loop(body) = nodes[3] else {
+            unreachable!()
+        };
+        assert_eq!(
+            interner.intern(&other[0..1]),
+            interner.intern(&[tree[3].clone()])
+        );
+        assert_eq!(interner.to_syntax_tree(body).to_code(), "[-]+");
+    }
+
+    #[test]
+    fn interner_round_trips_trees() {
+        let tree: SyntaxTree = ",[>[-]<[->+<]]".parse().unwrap();
+        let mut interner = Interner::new();
+        let root = interner.intern(&tree);
+
+        assert_eq!(interner.to_syntax_tree(root), tree);
+        assert_eq!(interner.intern(&tree), root);
+        assert!(Interner::new().is_empty());
+    }
+}
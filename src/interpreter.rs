@@ -1,7 +1,19 @@
 use crate::{
-    execution::MemoryContext,
-    syntax::{BadExpressionError, SyntaxTree},
-    token::Token,
+    arena::ArenaTree,
+    bytecode::Program,
+    dialect::Dialect,
+    execution::{Cursor, ExecutionState, MemoryContext, Stopped},
+    input::{split_input, EofBehavior, Input},
+    optimizer::{OptLevel, PassManager},
+    output::{FlushPolicy, Output, OutputMode},
+    profile::{MemoryHeatmap, Profile},
+    syntax::{BadExpressionError, ReadError, SyntaxTree},
+    token::{Lexer, SourceOffset, Span},
+};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+    path::Path,
 };
 
 /// A Brainfuck interpreter
@@ -11,6 +23,16 @@ use crate::{
 /// (default 32K), then parses the given BrainFuck code into a
 /// valid syntax tree, which then executes step by step.
 ///
+/// Execution can be paused with breakpoints placed on source offsets.
+/// When one is reached, [`execute`](Self::execute) returns
+/// [`Stopped::Breakpoint`] and the memory can be inspected before
+/// resuming with another call to [`execute`](Self::execute).
+///
+/// It can also run one step at a time with [`step`](Self::step), and
+/// undo recent steps with [`step_back`](Self::step_back) after setting
+/// how many of them to remember with
+/// [`set_history_limit`](Self::set_history_limit).
+///
 /// # Example
 /// ```
 /// # use brainfuck::{BrainFuckInterpreter, BadExpressionError};
@@ -29,6 +51,17 @@ use crate::{
 pub struct BrainFuckInterpreter {
     memory: MemoryContext,
     instructions: SyntaxTree,
+    state: ExecutionState,
+    /// Where the next fed code starts, as if all was one string
+    fed_position: Span,
+    /// Whether fed code uses the debug dump extension
+    debug_dump: bool,
+    /// How fed code is written, if not in standard BrainFuck
+    dialect: Option<Dialect>,
+    opt_level: OptLevel,
+    /// Internal syntax tree compiled by the last execution, with the level
+    /// it was optimized at
+    compiled: Option<(OptLevel, Program)>,
 }
 
 impl BrainFuckInterpreter {
@@ -37,6 +70,12 @@ impl BrainFuckInterpreter {
         BrainFuckInterpreter {
             memory: MemoryContext::new(),
             instructions: SyntaxTree::new(),
+            state: ExecutionState::default(),
+            fed_position: Span::default(),
+            debug_dump: false,
+            dialect: None,
+            opt_level: OptLevel::default(),
+            compiled: None,
         }
     }
 
@@ -45,6 +84,12 @@ impl BrainFuckInterpreter {
         BrainFuckInterpreter {
             memory: MemoryContext::with_capacity(size),
             instructions: SyntaxTree::new(),
+            state: ExecutionState::default(),
+            fed_position: Span::default(),
+            debug_dump: false,
+            dialect: None,
+            opt_level: OptLevel::default(),
+            compiled: None,
         }
     }
 
@@ -55,9 +100,12 @@ impl BrainFuckInterpreter {
     where
         T: IntoIterator<Item = u8>,
     {
-        let tokens = bytes.into_iter().map(Token::from);
-        let tree = SyntaxTree::parse_tokens(tokens)?;
-        self.instructions.extend(tree);
+        let mut lexer = self.lexer(bytes);
+        let mut tree = SyntaxTree::parse_spanned_tokens(lexer.by_ref())?;
+
+        self.fed_position = lexer.position();
+        self.instructions.append(&mut tree);
+        self.compiled = None;
 
         Ok(())
     }
@@ -69,21 +117,423 @@ impl BrainFuckInterpreter {
         self.feed(code.bytes())
     }
 
+    /// Feeds the interpreter code following the `!` convention
+    ///
+    /// Everything after the first `!` is not code but the input of the
+    /// program, read by `,` instead of the standard input. Without a `!`,
+    /// this is the same as [`feed`](Self::feed).
+    pub fn feed_with_input(&mut self, code: &[u8]) -> Result<(), BadExpressionError> {
+        let (code, input) = split_input(code);
+        self.feed(code.iter().copied())?;
+
+        if let Some(input) = input {
+            self.memory.set_input(Input::replay(input.to_vec()));
+        }
+
+        Ok(())
+    }
+
+    /// Feeds the interpreter the code given by `reader`
+    ///
+    /// The code is read through a small buffer and parsed as it comes.
+    /// Updates the internal syntax tree only if all the code could be
+    /// read and is valid.
+    pub fn feed_reader<R: Read>(&mut self, reader: R) -> Result<(), ReadError> {
+        let mut error = None;
+        let bytes = BufReader::new(reader)
+            .bytes()
+            .map_while(|byte| byte.map_err(|e| error = Some(e)).ok());
+        let mut lexer = self.lexer(bytes);
+        let tree = SyntaxTree::parse_spanned_tokens(lexer.by_ref());
+        let position = lexer.position();
+        drop(lexer);
+
+        if let Some(error) = error {
+            return Err(error.into());
+        }
+
+        self.fed_position = position;
+        self.instructions.append(&mut tree?);
+        self.compiled = None;
+
+        Ok(())
+    }
+
+    /// Feeds the interpreter the code of the file at `path`
+    ///
+    /// The file is read as bytes, so comments don't need to be valid
+    /// UTF-8. Updates the internal syntax tree only if all the file could
+    /// be read and is valid.
+    pub fn feed_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), ReadError> {
+        self.feed_reader(File::open(path)?)
+    }
+
+    /// Feeds the interpreter some code that was already parsed
+    ///
+    /// The locations of its expressions are kept as they are, so they only
+    /// match the positions of other fed code if this is the first one.
+    pub fn feed_tree(&mut self, mut tree: SyntaxTree) {
+        self.instructions.append(&mut tree);
+        self.compiled = None;
+    }
+
     /// Get this interpreter [`SyntaxTree`]
     pub fn syntax_tree(&self) -> &SyntaxTree {
         &self.instructions
     }
 
+    /// Get the memory cells of this interpreter
+    pub fn memory(&self) -> &[u8] {
+        self.memory.cells()
+    }
+
+    /// Get the index of the cell the memory pointer is at
+    pub fn pointer(&self) -> usize {
+        self.memory.pointer()
+    }
+
     /// Clears the internal syntax tree
+    ///
+    /// A paused execution and its history are discarded as well, and offsets of the
+    /// positions of the code fed afterwards start from zero again.
     pub fn clear(&mut self) {
         self.instructions.clear();
+        self.compiled = None;
+        self.state.cursor = Default::default();
+        self.state.history.clear();
+        self.memory.clear_procedures();
+        self.fed_position = Span::default();
+    }
+
+    /// Starts recording every byte read by `,` from now on
+    ///
+    /// The recording can be given later to
+    /// [`replay_input`](Self::replay_input) to run the program again
+    /// with the exact same input.
+    pub fn record_input(&mut self) {
+        self.memory.input().start_recording();
+    }
+
+    /// Stops recording input and returns every byte read since
+    /// [`record_input`](Self::record_input) was called
+    pub fn take_recorded_input(&mut self) -> Vec<u8> {
+        self.memory.input().take_recording()
+    }
+
+    /// Reads input from a log recorded by a previous execution instead
+    /// of the standard input
+    ///
+    /// Once the log is exhausted, the program reaches the end of its input.
+    pub fn replay_input(&mut self, log: Vec<u8>) {
+        self.memory.set_input(Input::replay(log));
+    }
+
+    /// Sets what `,` does once the input has no more bytes, setting the
+    /// cell to zero by default
+    pub fn set_eof_behavior(&mut self, eof: EofBehavior) {
+        self.memory.set_eof(eof);
+    }
+
+    /// Reads input from `reader` instead of the standard input
+    pub fn read_input_from<R: Read + 'static>(&mut self, reader: R) {
+        self.memory.set_input(Input::from_reader(reader));
+    }
+
+    /// Writes the bytes of the program to `writer` as they are, instead of
+    /// as characters to the standard output
+    pub fn write_output_to<W: Write + 'static>(&mut self, writer: W) {
+        self.memory.set_output(Output::raw(writer));
+    }
+
+    /// Keeps the bytes written by the program from now on, instead of
+    /// writing them to the standard output
+    pub fn capture_output(&mut self) {
+        self.memory.capture_output();
+    }
+
+    /// Takes the bytes written since the output started to be captured
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.memory.take_output()
+    }
+
+    /// Enables the debug dump extension for the code fed from now on
+    ///
+    /// With it, `#` is not a comment but an instruction showing the pointer
+    /// and the first cells of memory in the standard error.
+    pub fn set_debug_dump(&mut self, enabled: bool) {
+        self.debug_dump = enabled;
+    }
+
+    /// Reads the code fed from now on as written in `dialect`
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = Some(dialect);
+    }
+
+    /// Lexer for the next fed code
+    fn lexer<T>(&self, bytes: T) -> Lexer<T::IntoIter>
+    where
+        T: IntoIterator<Item = u8>,
+    {
+        let lexer = Lexer::resume(bytes, self.fed_position).debug_dump(self.debug_dump);
+
+        match &self.dialect {
+            Some(dialect) => lexer.dialect(dialect.clone()),
+            None => lexer,
+        }
+    }
+
+    /// Changes when the output of the program is flushed
+    ///
+    /// Defaults to [`FlushPolicy::Line`]. Whatever the policy, the output
+    /// is flushed every time the program stops running.
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.memory.set_flush_policy(policy);
+    }
+
+    /// Changes how the bytes printed by the program are written, as
+    /// [`OutputMode::Chars`] by default
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        self.memory.set_output_mode(mode);
+    }
+
+    /// Changes the amount of cells shown by `#`, 16 by default
+    ///
+    /// Cells are shown from the one the pointer starts at.
+    pub fn set_debug_dump_cells(&mut self, cells: usize) {
+        self.memory.set_dump_cells(cells);
+    }
+
+    /// Calls `handler` with the memory and the index of the pointer on
+    /// every `#`, instead of writing to the standard error
+    pub fn on_debug_dump<F>(&mut self, handler: F)
+    where
+        F: FnMut(&[u8], usize) + 'static,
+    {
+        self.memory.set_dump_handler(Box::new(handler));
+    }
+
+    /// Starts counting how many times each instruction is executed
+    ///
+    /// Restarts the counts if profiling was already started.
+    pub fn start_profiling(&mut self) {
+        self.state.profile = Some(Profile::new());
+    }
+
+    /// Get the execution counts gathered since profiling started
+    pub fn profile(&self) -> Option<&Profile> {
+        self.state.profile.as_ref()
+    }
+
+    /// Stops profiling and returns the execution counts gathered
+    pub fn stop_profiling(&mut self) -> Option<Profile> {
+        self.state.profile.take()
+    }
+
+    /// Starts counting the reads and writes made to each memory cell
+    ///
+    /// Restarts the counts if they were already being tracked.
+    pub fn start_memory_heatmap(&mut self) {
+        let size = self.memory.cells().len();
+        self.state.heatmap = Some(MemoryHeatmap::new(size));
+    }
+
+    /// Get the memory accesses counted since the heatmap started
+    pub fn memory_heatmap(&self) -> Option<&MemoryHeatmap> {
+        self.state.heatmap.as_ref()
+    }
+
+    /// Stops counting memory accesses and returns the heatmap
+    pub fn stop_memory_heatmap(&mut self) -> Option<MemoryHeatmap> {
+        self.state.heatmap.take()
+    }
+
+    /// Pauses the execution before running the instruction found at
+    /// the byte `offset` of the fed code
+    ///
+    /// Offsets are counted as if all the fed code was one string. For loops,
+    /// both the offset of `[` and `]` are valid: the former stops when the
+    /// loop is reached, and the latter every time its condition is checked
+    /// again.
+    pub fn add_breakpoint(&mut self, offset: SourceOffset) {
+        self.state.breakpoints.insert(offset);
+    }
+
+    /// Removes a breakpoint previously added at `offset`
+    pub fn remove_breakpoint(&mut self, offset: SourceOffset) {
+        self.state.breakpoints.remove(&offset);
     }
 
     /// Executes the internal syntax tree
-    pub fn execute(&mut self) {
-        for expr in self.instructions.iter() {
-            self.memory.execute_expression(expr);
+    ///
+    /// If the last execution stopped at a breakpoint, it resumes
+    /// from there. Otherwise it runs from the start.
+    ///
+    /// Code is compiled with the optimization level of the interpreter
+    /// before running it, down to native code with the `jit` feature,
+    /// unless that level is [`OptLevel::None`] or there are breakpoints,
+    /// profiling, a memory heatmap, a history, procedures, a paused
+    /// execution or [`EofBehavior::Error`], which walk the syntax tree
+    /// instead so the code can be resumed where it stopped. The compiled
+    /// code is kept until more code is fed.
+    ///
+    /// The standard input is held while the code runs, so no one else
+    /// can read from it, and the output is flushed once it stops.
+    pub fn execute(&mut self) -> Stopped {
+        self.memory.start_io();
+        let stopped = self.run();
+        self.memory.finish_io();
+
+        stopped
+    }
+
+    /// Runs the internal syntax tree as [`execute`](Self::execute)
+    /// describes, without flushing the output
+    fn run(&mut self) -> Stopped {
+        if !self.can_compile() {
+            return self.memory.run(&self.instructions, &mut self.state, None);
         }
+
+        let level = match self.opt_level {
+            OptLevel::Aggressive if !self.memory.is_fresh() => OptLevel::Basic,
+            level => level,
+        };
+        let program = match self.compiled.take() {
+            Some((compiled, program)) if compiled == level => program,
+            _ => {
+                let mut program = Program::new(&self.instructions);
+                PassManager::for_level(level, self.memory.cells().len()).run(&mut program);
+                program
+            }
+        };
+        let stopped = self.run_program(&program);
+        self.compiled = Some((level, program));
+
+        stopped
+    }
+
+    /// Runs `program` on the memory, down to native code with the `jit`
+    /// feature
+    fn run_program(&mut self, program: &Program) -> Stopped {
+        #[cfg(feature = "jit")]
+        match program.jit(self.memory.cells().len(), self.memory.pointer()) {
+            Ok(compiled) => return compiled.run(&mut self.memory),
+            Err(_error) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(%_error, "running without native code");
+            }
+        }
+
+        self.memory.run_program(program)
+    }
+
+    /// Sets how much code is optimized before [`execute`](Self::execute)
+    /// runs it
+    ///
+    /// Defaults to [`OptLevel::Basic`]. [`OptLevel::Aggressive`] is only
+    /// used when the memory is fresh, since it runs part of the code at
+    /// compile time. Debugging and profiling always run the code as it is.
+    pub fn set_opt_level(&mut self, level: OptLevel) {
+        self.opt_level = level;
+    }
+
+    /// Whether running the compiled code gives the same result as walking
+    /// the syntax tree
+    fn can_compile(&self) -> bool {
+        let ExecutionState {
+            cursor,
+            breakpoints,
+            history,
+            profile,
+            heatmap,
+        } = &self.state;
+
+        self.opt_level != OptLevel::None
+            && *cursor == Cursor::new()
+            && breakpoints.is_empty()
+            && history.limit() == 0
+            && profile.is_none()
+            && heatmap.is_none()
+            && self.memory.eof() != EofBehavior::Error
+            && !self.instructions.has_procedures()
+    }
+
+    /// Runs `tree` on the memory of the interpreter, until its end or
+    /// until `@` of Extended Type I
+    ///
+    /// The internal syntax tree is left as is, and neither breakpoints,
+    /// history nor profiling apply. Procedures defined by `tree` are
+    /// forgotten once it finishes.
+    pub fn execute_arena(&mut self, tree: &ArenaTree) {
+        self.memory.start_io();
+        self.memory.run_arena(tree);
+        self.memory.finish_io();
+    }
+
+    /// Runs an already compiled `program` on the memory of the
+    /// interpreter, down to native code with the `jit` feature
+    ///
+    /// The internal syntax tree is left as is, and neither breakpoints,
+    /// history nor profiling apply. `program` runs as it is, so it must
+    /// have been optimized for the memory it runs on. It can't be resumed
+    /// once stopped at the end of the input, running again from its start
+    /// instead.
+    pub fn execute_program(&mut self, program: &Program) -> Stopped {
+        self.memory.start_io();
+        let stopped = self.run_program(program);
+        self.memory.finish_io();
+
+        stopped
+    }
+
+    /// Executes a single step of the internal syntax tree
+    ///
+    /// A step is running one instruction, or checking the condition of
+    /// a loop when reaching its `[` or its `]`. Returns
+    /// [`Stopped::StepLimit`] if there are more steps to run.
+    pub fn step(&mut self) -> Stopped {
+        self.step_by(1)
+    }
+
+    /// Executes up to `steps` steps of the internal syntax tree, as
+    /// [`step`](Self::step) does one
+    ///
+    /// Stops early at breakpoints and at the end of the code, returning
+    /// [`Stopped::StepLimit`] if there are more steps to run.
+    pub fn step_by(&mut self, steps: usize) -> Stopped {
+        self.memory.start_io();
+        let stopped = self
+            .memory
+            .run(&self.instructions, &mut self.state, Some(steps));
+        self.memory.finish_io();
+
+        stopped
+    }
+
+    /// Offset in the fed code of the instruction that runs next
+    ///
+    /// For loops whose condition is checked next when reaching their `]`,
+    /// it is the offset of that `]`. Once the code finishes, it is the
+    /// offset of its first instruction again. Returns [`None`] without
+    /// code, or if the tree has been modified since it was fed.
+    pub fn next_offset(&self) -> Option<SourceOffset> {
+        MemoryContext::next_offset(&self.instructions, &self.state.cursor)
+    }
+
+    /// Sets how many of the last steps are remembered to be undone
+    ///
+    /// Defaults to zero, which disables the history.
+    pub fn set_history_limit(&mut self, steps: usize) {
+        self.state.history.set_limit(steps);
+    }
+
+    /// Undoes the last step that was executed
+    ///
+    /// Memory, pointer and position in the code go back to what they were
+    /// before that step. Input read by the step will be read again, but
+    /// output can't be taken back. Returns `false` if there is no step left
+    /// in the history.
+    pub fn step_back(&mut self) -> bool {
+        self.memory.undo(&mut self.state)
     }
 }
 
@@ -95,11 +545,339 @@ impl std::default::Default for BrainFuckInterpreter {
 
 /// Run some Brainfuck code
 ///
-/// This is a fast way to initialize, feed and execute a [`BrainFuckInterpreter`].
+/// This is a fast way to initialize, feed and execute a [`BrainFuckInterpreter`],
+/// optimizing the code with [`OptLevel::Aggressive`].
 pub fn evaluate(code: &str) -> Result<(), BadExpressionError> {
     let mut interpreter = BrainFuckInterpreter::new();
+    interpreter.set_opt_level(OptLevel::Aggressive);
     interpreter.feed_string(code)?;
     interpreter.execute();
 
     Ok(())
 }
+
+/// Run some Brainfuck code following the `!` convention
+///
+/// Like [`evaluate`], but everything after the first `!` of `code` is read
+/// by the program as its input.
+pub fn evaluate_with_input(code: &str) -> Result<(), BadExpressionError> {
+    let mut interpreter = BrainFuckInterpreter::new();
+    interpreter.set_opt_level(OptLevel::Aggressive);
+    interpreter.feed_with_input(code.as_bytes())?;
+    interpreter.execute();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Token;
+
+    #[test]
+    fn interpreter_breakpoints_across_feeds() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.feed_string("++ [->+<]").unwrap();
+        bf.feed_string(">>+").unwrap();
+        bf.add_breakpoint(3);
+        bf.add_breakpoint(11);
+
+        assert_eq!(bf.execute(), Stopped::Breakpoint(3));
+        assert_eq!(bf.memory(), [0, 0, 2, 0]);
+
+        bf.remove_breakpoint(3);
+        assert_eq!(bf.execute(), Stopped::Breakpoint(11));
+        assert_eq!((bf.pointer(), bf.memory()), (0, [0, 0, 0, 2].as_slice()));
+
+        assert_eq!(bf.execute(), Stopped::Finished);
+        assert_eq!(bf.memory(), [1, 0, 0, 2]);
+    }
+
+    #[test]
+    fn interpreter_deep_nesting() {
+        let depth = 100_000;
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.set_opt_level(OptLevel::None);
+        bf.feed_string(&("++".to_string() + &"[".repeat(depth) + "-" + &"]".repeat(depth)))
+            .unwrap();
+
+        assert_eq!(bf.execute(), Stopped::Finished);
+        assert_eq!(bf.memory(), [0; 4]);
+    }
+
+    #[test]
+    fn interpreter_executes_arena_trees() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.feed_string("+").unwrap();
+        bf.execute();
+
+        bf.execute_arena(&"[->++<]>[>+<-]>+".parse().unwrap());
+        assert_eq!((bf.pointer(), bf.memory()), (0, [3, 0, 0, 0].as_slice()));
+        assert_eq!(bf.syntax_tree().to_code(), "+");
+    }
+
+    #[test]
+    fn interpreter_reads_input_after_bang() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.feed_with_input(b",>,>,!ab").unwrap();
+        bf.execute();
+
+        assert_eq!(bf.memory(), [0, 0, b'a', b'b']);
+        assert!(bf.feed_with_input(b"]!+").is_err());
+    }
+
+    #[test]
+    fn interpreter_debug_dump_extension() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let dumps = Rc::new(RefCell::new(Vec::new()));
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        let handler_dumps = Rc::clone(&dumps);
+        bf.on_debug_dump(move |memory, pointer| {
+            handler_dumps.borrow_mut().push((memory.to_vec(), pointer))
+        });
+
+        bf.feed_string("+#").unwrap();
+        bf.set_debug_dump(true);
+        bf.feed_string(">++#").unwrap();
+        bf.execute();
+
+        assert_eq!(bf.syntax_tree().to_code(), "+>++#");
+        assert_eq!(*dumps.borrow(), [(vec![0, 0, 1, 2], 3)]);
+    }
+
+    #[test]
+    fn interpreter_feeds_dialect() {
+        let dialect = Dialect::from_patterns([("inc ", Token::Increment)]).unwrap();
+        let mut bf = BrainFuckInterpreter::with_memory_size(2);
+        bf.feed_string("+").unwrap();
+        bf.set_dialect(dialect);
+        bf.feed_string("inc inc +").unwrap();
+        bf.execute();
+
+        assert_eq!(bf.memory(), [0, 3]);
+        assert_eq!(
+            bf.syntax_tree().span(2).map(|s| (s.start, s.end)),
+            Some((5, 9))
+        );
+    }
+
+    #[test]
+    fn interpreter_calls_pbrain_procedures() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(2);
+        bf.set_dialect(Dialect::pbrain());
+        bf.feed_string("+(<+++>)").unwrap();
+        bf.feed_string("::").unwrap();
+        bf.execute();
+
+        assert_eq!(bf.memory(), [6, 1]);
+
+        bf.clear();
+        bf.feed_string(":").unwrap();
+        bf.execute();
+
+        assert_eq!(bf.memory(), [6, 1]);
+    }
+
+    #[test]
+    fn interpreter_feeds_from_reader() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.feed_reader("++\n>".as_bytes()).unwrap();
+        bf.feed_reader("+".as_bytes()).unwrap();
+        assert!(bf.feed_reader("[".as_bytes()).is_err());
+
+        assert_eq!(
+            bf.syntax_tree().span(2).map(|s| (s.line, s.column)),
+            Some((2, 1))
+        );
+        assert_eq!(bf.syntax_tree().span(3).map(|s| s.start), Some(4));
+
+        assert_eq!(bf.execute(), Stopped::Finished);
+        assert_eq!(bf.memory(), [0, 0, 2, 1]);
+    }
+
+    #[test]
+    fn interpreter_optimizes_only_fresh_memory() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.set_opt_level(OptLevel::Aggressive);
+        bf.feed_string("+++[>+<-]>").unwrap();
+
+        bf.execute();
+        assert_eq!((bf.pointer(), bf.memory()), (3, [0, 0, 0, 3].as_slice()));
+
+        bf.execute();
+        assert_eq!((bf.pointer(), bf.memory()), (0, [6, 0, 0, 0].as_slice()));
+
+        bf.set_opt_level(OptLevel::None);
+        bf.execute();
+        assert_eq!((bf.pointer(), bf.memory()), (1, [0, 9, 0, 0].as_slice()));
+    }
+
+    #[test]
+    fn interpreter_compiles_again_after_feeding() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.feed_string("+").unwrap();
+
+        bf.execute();
+        bf.execute();
+        assert_eq!(bf.memory(), [0, 0, 2, 0]);
+
+        bf.feed_string(">+").unwrap();
+        bf.execute();
+        assert_eq!((bf.pointer(), bf.memory()), (3, [0, 0, 3, 1].as_slice()));
+    }
+
+    #[test]
+    fn interpreter_resumes_at_end_of_input() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(2);
+        bf.feed_string(",>,").unwrap();
+        bf.set_eof_behavior(EofBehavior::Error);
+        bf.replay_input(b"a".to_vec());
+
+        assert_eq!(bf.execute(), Stopped::EndOfInput);
+        bf.replay_input(b"b".to_vec());
+        assert_eq!(bf.execute(), Stopped::Finished);
+        assert_eq!(bf.memory(), [b'b', b'a']);
+    }
+
+    #[test]
+    fn interpreter_feeds_file() {
+        let path = std::env::temp_dir().join(format!("bf-interpreter-{}.bf", std::process::id()));
+        std::fs::write(&path, b"+\xfe+").unwrap();
+        let mut bf = BrainFuckInterpreter::with_memory_size(2);
+        let fed = bf.feed_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        fed.unwrap();
+        bf.execute();
+        assert_eq!(bf.memory(), [0, 2]);
+        assert!(matches!(bf.feed_file(&path), Err(ReadError::IO(_))));
+    }
+
+    #[test]
+    fn interpreter_profiles_hot_spots() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.feed_string("+++ [>++<-]").unwrap();
+        bf.start_profiling();
+
+        assert_eq!(bf.execute(), Stopped::Finished);
+
+        let profile = bf.stop_profiling().unwrap();
+        assert_eq!(profile.hot_spots()[..3], [(5, 3), (6, 3), (7, 3)]);
+        assert_eq!(profile.hot_spots().last(), Some(&(4, 1)));
+        assert_eq!(profile.total(), 22);
+        assert_eq!(profile.count(10), 3);
+        assert!(bf.profile().is_none());
+    }
+
+    #[test]
+    fn interpreter_tracks_memory_heatmap() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(6);
+        bf.feed_string("++[>+<-]>>>").unwrap();
+        bf.start_memory_heatmap();
+
+        assert_eq!(bf.execute(), Stopped::Finished);
+
+        let heatmap = bf.stop_memory_heatmap().unwrap();
+        assert_eq!(heatmap.reads(), [0, 0, 0, 7, 2, 0]);
+        assert_eq!(heatmap.writes(), [0, 0, 0, 4, 2, 0]);
+        assert_eq!(heatmap.touched_range(), Some(3..=4));
+    }
+
+    #[test]
+    fn interpreter_records_and_replays_input() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.feed_string(",[>,]").unwrap();
+        bf.replay_input(b"abc".to_vec());
+        bf.record_input();
+
+        assert_eq!(bf.execute(), Stopped::Finished);
+        assert_eq!(bf.memory(), [b'c', 0, b'a', b'b']);
+
+        let log = bf.take_recorded_input();
+        assert_eq!(log, b"abc");
+
+        let mut replay = BrainFuckInterpreter::with_memory_size(4);
+        replay.feed_string(",[>,]").unwrap();
+        replay.replay_input(log);
+
+        assert_eq!(replay.execute(), Stopped::Finished);
+        assert_eq!(replay.memory(), bf.memory());
+    }
+
+    #[test]
+    fn interpreter_reads_input_from_readers() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.feed_string(",>,>,").unwrap();
+        bf.read_input_from(&b"hi"[..]);
+
+        assert_eq!(bf.execute(), Stopped::Finished);
+        assert_eq!(bf.memory(), [0, 0, b'h', b'i']);
+    }
+
+    #[test]
+    fn interpreter_follows_eof_behavior() {
+        let cases = [
+            (EofBehavior::Zero, 0, Stopped::Finished),
+            (EofBehavior::MinusOne, u8::MAX, Stopped::Finished),
+            (EofBehavior::Unchanged, 7, Stopped::Finished),
+            (EofBehavior::Error, 7, Stopped::EndOfInput),
+        ];
+
+        for (eof, cell, stopped) in cases {
+            for level in [OptLevel::None, OptLevel::Basic] {
+                let mut bf = BrainFuckInterpreter::with_memory_size(2);
+                bf.feed_string("+++++++>,<,").unwrap();
+                bf.set_opt_level(level);
+                bf.set_eof_behavior(eof);
+                bf.replay_input(b"a".to_vec());
+
+                assert_eq!(bf.execute(), stopped, "{eof:?} {level:?}");
+                assert_eq!(bf.memory(), [b'a', cell], "{eof:?} {level:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn interpreter_steps_back_and_forth() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.feed_string("++[->+<]").unwrap();
+        bf.set_history_limit(3);
+
+        assert_eq!(bf.execute(), Stopped::Finished);
+        assert_eq!(bf.memory(), [0, 0, 0, 2]);
+
+        assert!(bf.step_back());
+        assert!(bf.step_back());
+        assert!(bf.step_back());
+        assert!(!bf.step_back());
+        assert_eq!((bf.pointer(), bf.memory()), (3, [0, 0, 0, 1].as_slice()));
+
+        assert_eq!(bf.step(), Stopped::StepLimit);
+        assert_eq!((bf.pointer(), bf.memory()), (3, [0, 0, 0, 2].as_slice()));
+
+        bf.add_breakpoint(7);
+        assert_eq!(bf.execute(), Stopped::Breakpoint(7));
+        assert_eq!(bf.memory(), [0, 0, 0, 2]);
+        assert_eq!(bf.step(), Stopped::Finished);
+    }
+
+    #[test]
+    fn interpreter_tells_next_offset() {
+        let mut bf = BrainFuckInterpreter::with_memory_size(4);
+        bf.feed_string("+ [->+<] .").unwrap();
+        assert_eq!(bf.next_offset(), Some(0));
+
+        assert_eq!(bf.step_by(2), Stopped::StepLimit);
+        assert_eq!(bf.next_offset(), Some(3));
+
+        assert_eq!(bf.step_by(4), Stopped::StepLimit);
+        assert_eq!(bf.next_offset(), Some(7));
+        assert_eq!(bf.memory(), [0, 0, 0, 1]);
+
+        bf.add_breakpoint(9);
+        assert_eq!(bf.step_by(100), Stopped::Breakpoint(9));
+        assert_eq!(bf.step_by(100), Stopped::Finished);
+        assert_eq!(bf.next_offset(), Some(0));
+    }
+}
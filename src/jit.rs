@@ -0,0 +1,582 @@
+use crate::{
+    bounds,
+    bytecode::{Op, Program},
+    execution::{MemoryContext, Stopped},
+    input::EofBehavior,
+};
+use cranelift_codegen::{
+    ir::{condcodes::IntCC, types, AbiParam, Block, FuncRef, InstBuilder, MemFlagsData, Value},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module, ModuleError};
+use derive_more::{Display, Error, From};
+
+/// Native function running a program, given the memory, the pointer, the
+/// storage cell and the context handling input and output, which the
+/// memory, pointer and storage were taken out of
+///
+/// Returns [`FINISHED`] or [`END_OF_INPUT`].
+type Compiled = unsafe extern "C" fn(*mut u8, *mut usize, *mut u8, *mut MemoryContext) -> u8;
+
+/// Returned by compiled programs that ran until the end, or until `@`
+const FINISHED: u8 = 0;
+
+/// Returned by compiled programs that read past the end of the input with
+/// [`EofBehavior::Error`]
+const END_OF_INPUT: u8 = 1;
+
+/// Returned by `bf_read` instead of a byte when the program must stop
+const READ_STOPPED: u16 = 0x100;
+
+/// Error while compiling a program to native code
+#[derive(Debug, Display, Error, From)]
+pub enum JitError {
+    /// The host is not supported by Cranelift
+    #[display(fmt = "native code is not supported on this host")]
+    UnsupportedHost,
+
+    /// The memory has no cells, or the pointer starts outside of it
+    #[display(fmt = "the pointer must start inside a memory of at least one cell")]
+    InvalidMemory,
+
+    /// The program defines or calls procedures, which only the
+    /// interpreter can run
+    #[display(fmt = "procedures can't be compiled to native code")]
+    Procedures,
+
+    Settings(settings::SetError),
+    Codegen(cranelift_codegen::CodegenError),
+    Module(Box<ModuleError>),
+}
+
+impl From<ModuleError> for JitError {
+    fn from(error: ModuleError) -> Self {
+        JitError::Module(Box::new(error))
+    }
+}
+
+/// A [`Program`] compiled to native code for a memory of a given size
+/// and a given starting pointer
+pub struct JitProgram {
+    module: Option<JITModule>,
+    function: Compiled,
+    memory: usize,
+    start: usize,
+}
+
+impl Program {
+    /// Compiles this program to native code, for a memory of `memory` cells
+    /// and the pointer starting at `start`
+    ///
+    /// Moves that can't leave the memory from `start` are compiled without
+    /// wrapping around it.
+    pub(crate) fn jit(&self, memory: usize, start: usize) -> Result<JitProgram, JitError> {
+        if memory == 0 || start >= memory {
+            return Err(JitError::InvalidMemory);
+        }
+
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed")?;
+        flags.set("is_pic", "false")?;
+        let isa = cranelift_native::builder()
+            .map_err(|_| JitError::UnsupportedHost)?
+            .finish(settings::Flags::new(flags))?;
+
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        builder.symbol("bf_read", read as *const u8);
+        builder.symbol("bf_write", write as *const u8);
+        builder.symbol("bf_dump", dump as *const u8);
+        let mut module = JITModule::new(builder);
+
+        let pointer_type = module.target_config().pointer_type();
+        let mut context = module.make_context();
+        for _ in 0..4 {
+            context
+                .func
+                .signature
+                .params
+                .push(AbiParam::new(pointer_type));
+        }
+        context
+            .func
+            .signature
+            .returns
+            .push(AbiParam::new(types::I8));
+
+        let mut read_signature = module.make_signature();
+        read_signature.params.push(AbiParam::new(pointer_type));
+        read_signature.params.push(AbiParam::new(types::I8));
+        read_signature.returns.push(AbiParam::new(types::I16));
+        let mut write_signature = module.make_signature();
+        write_signature.params.push(AbiParam::new(pointer_type));
+        write_signature.params.push(AbiParam::new(types::I8));
+        let mut dump_signature = module.make_signature();
+        for _ in 0..4 {
+            dump_signature.params.push(AbiParam::new(pointer_type));
+        }
+
+        let read = module.declare_function("bf_read", Linkage::Import, &read_signature)?;
+        let write = module.declare_function("bf_write", Linkage::Import, &write_signature)?;
+        let dump = module.declare_function("bf_dump", Linkage::Import, &dump_signature)?;
+
+        let mut function_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut function_context);
+        let imports = Imports {
+            read: module.declare_func_in_func(read, builder.func),
+            write: module.declare_func_in_func(write, builder.func),
+            dump: module.declare_func_in_func(dump, builder.func),
+        };
+
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+
+        let params = builder.block_params(entry).to_vec();
+        let pointer = builder.declare_var(pointer_type);
+        let initial = builder
+            .ins()
+            .load(pointer_type, MemFlagsData::trusted(), params[1], 0);
+        builder.def_var(pointer, initial);
+
+        let mut translator = Translator {
+            builder,
+            imports,
+            tape: params[0],
+            pointer_slot: params[1],
+            storage: params[2],
+            context: params[3],
+            pointer,
+            memory: memory as i64,
+            exit: Block::from_u32(0),
+            end_of_input: Block::from_u32(0),
+            in_bounds: bounds::in_bounds(self.ops(), start, memory),
+        };
+        translator.exit = translator.builder.create_block();
+        translator.end_of_input = translator.builder.create_block();
+        translator.translate(self.ops())?;
+
+        let Translator { mut builder, .. } = translator;
+        builder.seal_all_blocks();
+        builder.finalize(module.target_config());
+
+        let id = module.declare_function("run", Linkage::Export, &context.func.signature)?;
+        module.define_function(id, &mut context)?;
+        module.clear_context(&mut context);
+        module.finalize_definitions()?;
+
+        let code = module.get_finalized_function(id);
+        // SAFETY: the function was built with the signature of `Compiled`
+        let function = unsafe { std::mem::transmute::<*const u8, Compiled>(code) };
+
+        Ok(JitProgram {
+            module: Some(module),
+            function,
+            memory,
+            start,
+        })
+    }
+}
+
+impl JitProgram {
+    /// Runs this program on `memory`, until its end or until it reads past
+    /// the end of the input with [`EofBehavior::Error`]
+    ///
+    /// # Panics
+    /// If the size of `memory` or its pointer are not the ones the program
+    /// was compiled for.
+    pub(crate) fn run(&self, memory: &mut MemoryContext) -> Stopped {
+        let (mut tape, mut pointer, mut storage) = memory.take_registers();
+        if tape.len() != self.memory || pointer != self.start {
+            memory.restore_registers((tape, pointer, storage));
+            panic!("compiled for another memory size or pointer");
+        }
+
+        // SAFETY: the program only accesses cells of the memory, since
+        // every move wraps around it or was proven not to leave it from
+        // the starting pointer, and the context the imported functions
+        // use no longer holds the memory, pointer or storage
+        let stopped =
+            unsafe { (self.function)(tape.as_mut_ptr(), &mut pointer, &mut storage, memory) };
+        memory.restore_registers((tape, pointer, storage));
+
+        match stopped {
+            END_OF_INPUT => Stopped::EndOfInput,
+            _ => Stopped::Finished,
+        }
+    }
+}
+
+impl Drop for JitProgram {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // SAFETY: the compiled function can't be called anymore
+            unsafe { module.free_memory() }
+        }
+    }
+}
+
+impl std::fmt::Debug for JitProgram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JitProgram")
+            .field("memory", &self.memory)
+            .field("start", &self.start)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Imports {
+    read: FuncRef,
+    write: FuncRef,
+    dump: FuncRef,
+}
+
+/// Translates instructions into Cranelift IR
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    imports: Imports,
+    tape: Value,
+    pointer_slot: Value,
+    storage: Value,
+    context: Value,
+    /// Index of the current cell
+    pointer: Variable,
+    memory: i64,
+    exit: Block,
+    /// Block stopping the program at a `,` past the end of the input
+    end_of_input: Block,
+    /// Instructions that can't take the pointer out of the memory
+    in_bounds: Vec<bool>,
+}
+
+impl Translator<'_> {
+    fn translate(&mut self, ops: &[Op]) -> Result<(), JitError> {
+        // Body and exit of every loop being translated
+        let mut loops = Vec::new();
+
+        for (index, &op) in ops.iter().enumerate() {
+            let proven = self.in_bounds[index];
+            match op {
+                Op::Add(amount) => {
+                    let cell = self.load();
+                    let sum = self.builder.ins().iadd_imm_u(cell, amount as i64);
+                    self.store(sum);
+                }
+                Op::Move(offset) => {
+                    let index = self.index(offset as i64, proven);
+                    self.builder.def_var(self.pointer, index);
+                }
+                Op::Set(value) => {
+                    let value = self.builder.ins().iconst(types::I8, value as i64);
+                    self.store(value);
+                }
+                Op::Multiply { offset, factor } => {
+                    let cell = self.load();
+                    let product = self.builder.ins().imul_imm_u(cell, factor as i64);
+                    let index = self.index(offset as i64, proven);
+                    let address = self.builder.ins().iadd(self.tape, index);
+                    let target = self.load_at(address);
+                    let sum = self.builder.ins().iadd(target, product);
+                    self.store_at(address, sum);
+                }
+                Op::Input => {
+                    let cell = self.load();
+                    let call = self
+                        .builder
+                        .ins()
+                        .call(self.imports.read, &[self.context, cell]);
+                    let read = self.builder.inst_results(call)[0];
+                    let stopped =
+                        self.builder
+                            .ins()
+                            .icmp_imm_u(IntCC::Equal, read, i64::from(READ_STOPPED));
+                    let next = self.builder.create_block();
+                    self.builder
+                        .ins()
+                        .brif(stopped, self.end_of_input, &[], next, &[]);
+                    self.builder.switch_to_block(next);
+
+                    let byte = self.builder.ins().ireduce(types::I8, read);
+                    self.store(byte);
+                }
+                Op::Output => {
+                    let cell = self.load();
+                    self.builder
+                        .ins()
+                        .call(self.imports.write, &[self.context, cell]);
+                }
+                Op::DebugDump => {
+                    let pointer = self.builder.use_var(self.pointer);
+                    let pointer_type = self.builder.func.dfg.value_type(self.tape);
+                    let memory = self.builder.ins().iconst(pointer_type, self.memory);
+                    let args = [self.context, self.tape, memory, pointer];
+                    self.builder.ins().call(self.imports.dump, &args);
+                }
+                Op::JumpIfZero(_) => {
+                    let (body, exit) = (self.builder.create_block(), self.builder.create_block());
+                    let cell = self.load();
+                    self.builder.ins().brif(cell, body, &[], exit, &[]);
+                    self.builder.switch_to_block(body);
+                    loops.push((body, exit));
+                }
+                Op::JumpIfNotZero(_) => {
+                    let (body, exit) = loops.pop().expect("jumps are balanced");
+                    let cell = self.load();
+                    self.builder.ins().brif(cell, body, &[], exit, &[]);
+                    self.builder.switch_to_block(exit);
+                }
+                Op::End => {
+                    self.builder.ins().jump(self.exit, &[]);
+                    let unreachable = self.builder.create_block();
+                    self.builder.switch_to_block(unreachable);
+                }
+                Op::Store => {
+                    let cell = self.load();
+                    self.builder
+                        .ins()
+                        .store(MemFlagsData::trusted(), cell, self.storage, 0);
+                }
+                Op::Retrieve => {
+                    let storage = self.storage();
+                    self.store(storage);
+                }
+                Op::ShiftLeft => {
+                    let cell = self.load();
+                    let shifted = self.builder.ins().ishl_imm_u(cell, 1);
+                    self.store(shifted);
+                }
+                Op::ShiftRight => {
+                    let cell = self.load();
+                    let shifted = self.builder.ins().ushr_imm_u(cell, 1);
+                    self.store(shifted);
+                }
+                Op::Not => {
+                    let cell = self.load();
+                    let inverted = self.builder.ins().bnot(cell);
+                    self.store(inverted);
+                }
+                Op::Xor | Op::And | Op::Or => {
+                    let (cell, storage) = (self.load(), self.storage());
+                    let result = match op {
+                        Op::Xor => self.builder.ins().bxor(cell, storage),
+                        Op::And => self.builder.ins().band(cell, storage),
+                        _ => self.builder.ins().bor(cell, storage),
+                    };
+                    self.store(result);
+                }
+                Op::Procedure(_) | Op::Return | Op::Call => return Err(JitError::Procedures),
+            }
+        }
+
+        self.builder.ins().jump(self.exit, &[]);
+        for (block, stopped) in [(self.exit, FINISHED), (self.end_of_input, END_OF_INPUT)] {
+            self.builder.switch_to_block(block);
+            self.save_pointer();
+            let stopped = self.builder.ins().iconst(types::I8, i64::from(stopped));
+            self.builder.ins().return_(&[stopped]);
+        }
+
+        Ok(())
+    }
+
+    /// Index of the cell `offset` cells away from the pointer, wrapping
+    /// around the memory unless it was `proven` not to leave it
+    fn index(&mut self, offset: i64, proven: bool) -> Value {
+        let pointer = self.builder.use_var(self.pointer);
+        if proven {
+            return self.builder.ins().iadd_imm_s(pointer, offset);
+        }
+
+        let sum = self
+            .builder
+            .ins()
+            .iadd_imm_u(pointer, offset.rem_euclid(self.memory));
+        let wrapped = self.builder.ins().iadd_imm_s(sum, -self.memory);
+        let overflow =
+            self.builder
+                .ins()
+                .icmp_imm_u(IntCC::UnsignedGreaterThanOrEqual, sum, self.memory);
+
+        self.builder.ins().select(overflow, wrapped, sum)
+    }
+
+    /// Address of the current cell
+    fn address(&mut self) -> Value {
+        let index = self.builder.use_var(self.pointer);
+        self.builder.ins().iadd(self.tape, index)
+    }
+
+    /// Loads the current cell
+    fn load(&mut self) -> Value {
+        let address = self.address();
+        self.load_at(address)
+    }
+
+    /// Stores `value` in the current cell
+    fn store(&mut self, value: Value) {
+        let address = self.address();
+        self.store_at(address, value);
+    }
+
+    fn load_at(&mut self, address: Value) -> Value {
+        self.builder
+            .ins()
+            .load(types::I8, MemFlagsData::trusted(), address, 0)
+    }
+
+    fn store_at(&mut self, address: Value, value: Value) {
+        self.builder
+            .ins()
+            .store(MemFlagsData::trusted(), value, address, 0);
+    }
+
+    fn storage(&mut self) -> Value {
+        self.builder
+            .ins()
+            .load(types::I8, MemFlagsData::trusted(), self.storage, 0)
+    }
+
+    /// Writes the pointer back, for the caller
+    fn save_pointer(&mut self) {
+        let pointer = self.builder.use_var(self.pointer);
+        self.builder
+            .ins()
+            .store(MemFlagsData::trusted(), pointer, self.pointer_slot, 0);
+    }
+}
+
+/// Reads the next byte, or what the current `cell` becomes once the input
+/// has no more bytes, or [`READ_STOPPED`] if the program must stop then
+extern "C" fn read(context: *mut MemoryContext, cell: u8) -> u16 {
+    // SAFETY: compiled programs are only given a valid context
+    let context = unsafe { &mut *context };
+    match (context.input().read_byte(), context.eof()) {
+        (Some(byte), _) => byte.into(),
+        (None, EofBehavior::Zero) => 0,
+        (None, EofBehavior::MinusOne) => u8::MAX.into(),
+        (None, EofBehavior::Unchanged) => cell.into(),
+        (None, EofBehavior::Error) => READ_STOPPED,
+    }
+}
+
+extern "C" fn write(context: *mut MemoryContext, byte: u8) {
+    // SAFETY: compiled programs are only given a valid context
+    let context = unsafe { &mut *context };
+    context.write_byte(byte);
+}
+
+/// Shows the `pointer` and the `memory` cells of `tape`, which the running
+/// program holds instead of the context
+extern "C" fn dump(context: *mut MemoryContext, tape: *const u8, memory: usize, pointer: usize) {
+    // SAFETY: compiled programs are only given a valid context, and pass
+    // their own memory while they wait for the dump
+    let (context, cells) = unsafe { (&mut *context, std::slice::from_raw_parts(tape, memory)) };
+    context.dump(cells, pointer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{input::Input, syntax::SyntaxTree};
+
+    fn run(code: &str, memory: usize, input: &[u8]) -> (MemoryContext, Vec<u8>) {
+        let tree: SyntaxTree = code.parse().unwrap();
+        let mut context = MemoryContext::with_capacity(memory);
+        context.set_input(Input::replay(input.to_vec()));
+        context.capture_output();
+
+        let start = context.pointer();
+        tree.compile().jit(memory, start).unwrap().run(&mut context);
+        let output = context.take_output();
+
+        (context, output)
+    }
+
+    #[test]
+    fn jit_matches_interpreter() {
+        for code in [
+            "+++[>+<-]>",
+            "-[>+++<---]>[<+>-]",
+            "<<+[>]",
+            "<<<<<<<<<+>>>>>>>>>>>>>-",
+            "++[->>+<<]>>[-<<<+>>>]>>+>+",
+            "+[>+<-]>[>>+<<-]<<<-",
+            ">>>+<+<+",
+        ] {
+            let tree: SyntaxTree = code.parse().unwrap();
+            let mut walked = MemoryContext::with_capacity(6);
+            walked.run_program(&tree.compile());
+
+            let (compiled, _) = run(code, 6, b"");
+            assert_eq!(compiled.cells(), walked.cells(), "{code}");
+            assert_eq!(compiled.pointer(), walked.pointer(), "{code}");
+        }
+    }
+
+    #[test]
+    fn jit_reads_and_writes() {
+        let (_, output) = run(",[.,]", 8, b"hello");
+        assert_eq!(output, b"hello");
+
+        let (_, output) = run(",>,<[->+<]>.", 8, b"\x02\x03");
+        assert_eq!(output, b"\x05");
+    }
+
+    #[test]
+    fn jit_stops_at_end_of_input() {
+        let tree: SyntaxTree = ",[.>,]".parse().unwrap();
+        let mut context = MemoryContext::with_capacity(8);
+        context.set_input(Input::replay(b"ab".to_vec()));
+        context.set_eof(EofBehavior::Error);
+        context.capture_output();
+
+        let compiled = tree.compile().jit(8, 4).unwrap();
+        assert_eq!(compiled.run(&mut context), Stopped::EndOfInput);
+        assert_eq!(context.take_output(), b"ab");
+        assert_eq!(context.pointer(), 6);
+        assert_eq!(context.cells()[4..], [b'a', b'b', 0, 0]);
+    }
+
+    #[test]
+    fn jit_rejects_procedures() {
+        use crate::{dialect::Dialect, token::Lexer};
+
+        let lexer = Lexer::new("(+):".bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(lexer).unwrap();
+
+        assert!(matches!(
+            tree.compile().jit(8, 4),
+            Err(JitError::Procedures)
+        ));
+    }
+
+    #[test]
+    fn jit_rejects_invalid_memory() {
+        let program = "+".parse::<SyntaxTree>().unwrap().compile();
+
+        assert!(matches!(program.jit(0, 0), Err(JitError::InvalidMemory)));
+        assert!(matches!(program.jit(8, 8), Err(JitError::InvalidMemory)));
+    }
+
+    #[test]
+    fn jit_dumps_memory_it_holds() {
+        use crate::token::Lexer;
+        use std::{cell::RefCell, rc::Rc};
+
+        let lexer = Lexer::new("+>++#<".bytes()).debug_dump(true);
+        let tree = SyntaxTree::parse_spanned_tokens(lexer).unwrap();
+        let mut context = MemoryContext::with_capacity(4);
+        let dumps = Rc::new(RefCell::new(Vec::new()));
+        let seen = Rc::clone(&dumps);
+        context.set_dump_handler(Box::new(move |cells, pointer| {
+            seen.borrow_mut().push((cells.to_vec(), pointer))
+        }));
+
+        tree.compile().jit(4, 2).unwrap().run(&mut context);
+
+        assert_eq!(*dumps.borrow(), [(vec![0, 0, 1, 2], 3)]);
+        assert_eq!(context.cells(), [0, 0, 1, 2]);
+        assert_eq!(context.pointer(), 2);
+    }
+}
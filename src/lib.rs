@@ -3,7 +3,7 @@
 //! You can run BrainFuck code in two ways:
 //!
 //! 1. Using the [`BrainFuckInterpreter`] to get more control over
-//! the interpreter.
+//!    the interpreter.
 //! 2. Or simply run code with [`evaluate`].
 //!
 //! ## Example
@@ -32,11 +32,69 @@
 //! # }
 //! ```
 
+mod arena;
+mod asm;
+pub mod bench;
+mod bounds;
+#[cfg(feature = "image")]
+mod brainloller;
+mod bytecode;
+mod cache;
+mod cst;
+mod diagnostic;
+mod dialect;
+mod dot;
+mod effect;
+mod equivalence;
 mod execution;
+mod format;
+mod generate;
+mod input;
+mod intern;
 mod interpreter;
+#[cfg(feature = "jit")]
+mod jit;
+mod lint;
+mod llvm;
+mod metrics;
+mod minify;
+mod native;
+mod optimizer;
+mod output;
+mod profile;
+mod session;
 mod syntax;
 mod token;
+pub mod transpile;
+pub mod visit;
+mod vm;
+mod wasm;
 
-pub use interpreter::{evaluate, BrainFuckInterpreter};
-pub use syntax::{BadExpressionError, Expression, SyntaxTree};
-pub use token::Token;
+pub use arena::{ArenaChildren, ArenaNode, ArenaTree, NodeId};
+#[cfg(feature = "image")]
+pub use brainloller::{brainloller_tokens, ImageError};
+pub use bytecode::{Op, Program};
+pub use cache::ProgramCache;
+pub use cst::{Comment, ConcreteSyntaxTree, CstNode};
+pub use diagnostic::{Diagnostic, Rendered, Severity};
+pub use dialect::{Dialect, DialectError};
+pub use effect::LoopEffect;
+pub use execution::Stopped;
+pub use format::Formatter;
+pub use generate::generate;
+pub use input::{split_input, EofBehavior};
+pub use intern::{BlockId, InternedNode, Interner};
+pub use interpreter::{evaluate, evaluate_with_input, BrainFuckInterpreter};
+pub use lint::{Lint, LintKind};
+pub use metrics::Metrics;
+pub use minify::minify;
+pub use native::NativeError;
+pub use optimizer::{
+    ClearLoops, ConstantFolding, DeadCode, MultiplyLoops, OptLevel, Pass, PassManager, PassReport,
+    RunLength, Superoptimize, UnrollLoops,
+};
+pub use output::{FlushPolicy, OutputMode};
+pub use profile::{ExecutionStats, MemoryHeatmap, Profile, ProfileError};
+pub use session::{Fed, ParserSession};
+pub use syntax::{BadExpressionError, Expression, ReadError, SyntaxTree};
+pub use token::{Lexer, SourceOffset, Span, Token};
@@ -0,0 +1,438 @@
+use crate::{
+    diagnostic::{Diagnostic, Severity},
+    minify::cancel_out,
+    syntax::{Expression, SyntaxTree},
+    token::Span,
+};
+use derive_more::Display;
+
+/// Kind of problem found by [`SyntaxTree::lint`]
+#[derive(Debug, Display, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum LintKind {
+    /// Adjacent instructions undoing each other, like `+-`
+    #[display(fmt = "these instructions cancel each other")]
+    CancellingPair,
+
+    /// A loop reached when the current cell is always zero
+    #[display(fmt = "this loop never runs, the cell is always zero here")]
+    DeadLoop,
+
+    /// A loop whose body can't change the current cell
+    #[display(fmt = "this loop never ends once entered")]
+    InfiniteLoop,
+
+    /// Code after a loop that never ends or after `@`
+    #[display(fmt = "this code is never reached")]
+    UnreachableCode,
+
+    /// A loop changing cells while moving the pointer on every iteration
+    #[display(fmt = "the pointer moves {} cells on every iteration", drift)]
+    PointerDrift { drift: isize },
+}
+
+/// A useless or suspicious construct in a [`SyntaxTree`]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Lint {
+    pub kind: LintKind,
+    pub severity: Severity,
+    /// Index of the first offending expression on every nesting level
+    pub path: Vec<usize>,
+    /// Location of the offending code, if the tree was parsed from source
+    pub span: Option<Span>,
+}
+
+impl Lint {
+    /// Turns this lint into a diagnostic, if its location is known
+    pub fn to_diagnostic(&self) -> Option<Diagnostic> {
+        let diagnostic = Diagnostic::warning(self.kind.to_string(), self.span?);
+
+        Some(Diagnostic {
+            severity: self.severity,
+            ..diagnostic
+        })
+    }
+}
+
+impl SyntaxTree {
+    /// Looks for useless and suspicious constructs in this tree
+    ///
+    /// Lints are ordered by position.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{LintKind, SyntaxTree};
+    /// let tree: SyntaxTree = "[-]+-".parse().unwrap();
+    /// let kinds: Vec<_> = tree.lint().into_iter().map(|lint| lint.kind).collect();
+    ///
+    /// assert_eq!(kinds, [LintKind::DeadLoop, LintKind::CancellingPair]);
+    /// ```
+    pub fn lint(&self) -> Vec<Lint> {
+        let mut linter = Linter {
+            lints: Vec::new(),
+            path: Vec::new(),
+            pristine: true,
+        };
+        linter.lint_block(self, Cell::Known(0));
+
+        linter.lints
+    }
+}
+
+impl SyntaxTree {
+    /// Removes the loops that never run and the code that is never reached,
+    /// as found by [`lint`](Self::lint)
+    ///
+    /// Returns the lints of the removed code. Like them, it assumes every
+    /// cell is zero when the program starts.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{LintKind, SyntaxTree};
+    /// let mut tree: SyntaxTree = "[.]+[]<-".parse().unwrap();
+    /// let removed = tree.remove_dead_code();
+    ///
+    /// assert_eq!(tree.to_code(), "+[]");
+    /// assert_eq!(removed[0].span.map(|s| (s.start, s.end)), Some((0, 3)));
+    /// assert_eq!(removed[1].kind, LintKind::UnreachableCode);
+    /// ```
+    pub fn remove_dead_code(&mut self) -> Vec<Lint> {
+        let mut dead: Vec<Lint> = self
+            .lint()
+            .into_iter()
+            .filter(|lint| matches!(lint.kind, LintKind::DeadLoop | LintKind::UnreachableCode))
+            .collect();
+
+        // Removing the last ones first keeps the paths of the others valid
+        dead.sort_by(|a, b| b.path.cmp(&a.path));
+        for lint in &dead {
+            let Some((&index, parents)) = lint.path.split_last() else {
+                continue;
+            };
+            let Some(block) = self.block_at_mut(parents) else {
+                continue;
+            };
+
+            match lint.kind {
+                LintKind::DeadLoop => block.remove_range(index..index + 1),
+                _ => block.remove_range(index..block.len()),
+            }
+        }
+        dead.reverse();
+
+        dead
+    }
+}
+
+/// What is known about the value of the current cell
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Cell {
+    Known(u8),
+    NonZero,
+    Unknown,
+}
+
+impl Cell {
+    fn is_nonzero(self) -> bool {
+        matches!(self, Cell::NonZero) || matches!(self, Cell::Known(value) if value != 0)
+    }
+}
+
+struct Linter {
+    lints: Vec<Lint>,
+    path: Vec<usize>,
+    /// Whether no cell has been written yet, so they are all zero
+    pristine: bool,
+}
+
+impl Linter {
+    fn lint_block(&mut self, block: &SyntaxTree, mut cell: Cell) {
+        let mut pair_end = None;
+
+        for (index, expr) in block.iter().enumerate() {
+            let next = block.get(index + 1);
+            if pair_end != Some(index) && next.is_some_and(|next| cancel_out(expr, next)) {
+                let span = join(block.span(index), block.span(index + 1));
+                self.report(LintKind::CancellingPair, Severity::Warning, index, span);
+                pair_end = Some(index + 1);
+            }
+
+            cell = match expr {
+                Expression::Increment | Expression::Decrement => {
+                    self.pristine = false;
+                    match (cell, expr) {
+                        (Cell::Known(value), Expression::Increment) => {
+                            Cell::Known(value.wrapping_add(1))
+                        }
+                        (Cell::Known(value), _) => Cell::Known(value.wrapping_sub(1)),
+                        _ => Cell::Unknown,
+                    }
+                }
+                Expression::Forward | Expression::Backward if self.pristine => Cell::Known(0),
+                Expression::Forward | Expression::Backward => Cell::Unknown,
+                Expression::Input | Expression::Call => {
+                    self.pristine = false;
+                    Cell::Unknown
+                }
+                Expression::Procedure(body) => {
+                    // The body runs later, from any cell
+                    let pristine = std::mem::replace(&mut self.pristine, false);
+                    self.path.push(index);
+                    self.lint_block(body, Cell::Unknown);
+                    self.path.pop();
+                    self.pristine = pristine;
+
+                    cell
+                }
+                Expression::Output | Expression::DebugDump | Expression::Store => cell,
+                Expression::ShiftLeft | Expression::ShiftRight | Expression::Not => {
+                    self.pristine = false;
+                    match (cell, expr) {
+                        (Cell::Known(value), Expression::ShiftLeft) => Cell::Known(value << 1),
+                        (Cell::Known(value), Expression::ShiftRight) => Cell::Known(value >> 1),
+                        (Cell::Known(value), _) => Cell::Known(!value),
+                        _ => Cell::Unknown,
+                    }
+                }
+                Expression::Retrieve | Expression::Xor | Expression::And | Expression::Or => {
+                    self.pristine = false;
+                    Cell::Unknown
+                }
+                Expression::End => {
+                    self.report_unreachable(block, index + 1);
+                    return;
+                }
+                Expression::Loop(_) if cell == Cell::Known(0) => {
+                    let span = block.span(index);
+                    self.report(LintKind::DeadLoop, Severity::Warning, index, span);
+                    cell
+                }
+                Expression::Loop(body) if is_inert(body) => {
+                    let span = block.span(index);
+                    if !cell.is_nonzero() {
+                        self.report(LintKind::InfiniteLoop, Severity::Warning, index, span);
+                        Cell::Known(0)
+                    } else {
+                        self.report(LintKind::InfiniteLoop, Severity::Error, index, span);
+                        self.report_unreachable(block, index + 1);
+                        return;
+                    }
+                }
+                Expression::Loop(body) => {
+                    if let Some(drift) = drift(body).filter(|&drift| drift != 0) {
+                        let writes = body.iter().any(|expr| {
+                            matches!(
+                                expr,
+                                Expression::Increment | Expression::Decrement | Expression::Input
+                            )
+                        });
+
+                        if writes {
+                            let kind = LintKind::PointerDrift { drift };
+                            self.report(kind, Severity::Warning, index, block.span(index));
+                        }
+                    }
+
+                    self.path.push(index);
+                    self.lint_block(body, Cell::NonZero);
+                    self.path.pop();
+
+                    Cell::Known(0)
+                }
+            };
+        }
+    }
+
+    /// Reports the code of `block` from `start` on as unreachable, if any
+    fn report_unreachable(&mut self, block: &SyntaxTree, start: usize) {
+        if start < block.len() {
+            let span = join(block.span(start), block.span(block.len() - 1));
+            self.report(LintKind::UnreachableCode, Severity::Warning, start, span);
+        }
+    }
+
+    fn report(&mut self, kind: LintKind, severity: Severity, index: usize, span: Option<Span>) {
+        let mut path = self.path.clone();
+        path.push(index);
+
+        self.lints.push(Lint {
+            kind,
+            severity,
+            path,
+            span,
+        });
+    }
+}
+
+/// Net pointer movement of a block without loops
+fn drift(block: &[Expression]) -> Option<isize> {
+    block.iter().try_fold(0, |drift, expr| match expr {
+        Expression::Forward => Some(drift + 1),
+        Expression::Backward => Some(drift - 1),
+        Expression::Loop(_) | Expression::Call => None,
+        _ => Some(drift),
+    })
+}
+
+/// Whether running `block` leaves memory and pointer as they were
+fn is_inert(block: &[Expression]) -> bool {
+    let only_moves = block.iter().all(|expr| {
+        matches!(
+            expr,
+            Expression::Forward | Expression::Backward | Expression::Output | Expression::DebugDump
+        )
+    });
+
+    only_moves && drift(block) == Some(0)
+}
+
+fn join(first: Option<Span>, last: Option<Span>) -> Option<Span> {
+    let (first, last) = (first?, last?);
+
+    Some(Span {
+        end: last.end,
+        ..first
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lints(code: &str) -> Vec<(LintKind, Severity, Vec<usize>, usize, usize)> {
+        let tree: SyntaxTree = code.parse().unwrap();
+        tree.lint()
+            .into_iter()
+            .map(|lint| {
+                let span = lint.span.unwrap();
+                (lint.kind, lint.severity, lint.path, span.start, span.end)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lint_cancelling_pairs() {
+        use LintKind::CancellingPair as Pair;
+        use Severity::Warning;
+
+        assert_eq!(
+            lints("+-+ ><"),
+            [
+                (Pair, Warning, vec![0], 0, 2),
+                (Pair, Warning, vec![3], 4, 6)
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_dead_loops() {
+        use LintKind::DeadLoop;
+        use Severity::Warning;
+
+        assert_eq!(
+            lints("[.]>[+]+[-][>]"),
+            [
+                (DeadLoop, Warning, vec![0], 0, 3),
+                (DeadLoop, Warning, vec![2], 4, 7),
+                (DeadLoop, Warning, vec![5], 11, 14)
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_infinite_loops() {
+        use LintKind::{InfiniteLoop, UnreachableCode};
+        use Severity::{Error, Warning};
+
+        assert_eq!(
+            lints(",[]+[><]."),
+            [
+                (InfiniteLoop, Warning, vec![1], 1, 3),
+                (InfiniteLoop, Error, vec![3], 4, 8),
+                (UnreachableCode, Warning, vec![4], 8, 9)
+            ]
+        );
+    }
+
+    #[test]
+    fn lint_pointer_drift() {
+        use LintKind::PointerDrift;
+        use Severity::Warning;
+
+        assert_eq!(
+            lints(",[>+>] ,[>] ,[->+<]"),
+            [(PointerDrift { drift: 2 }, Warning, vec![1], 1, 6)]
+        );
+    }
+
+    #[test]
+    fn lint_inside_loops() {
+        assert_eq!(
+            lints(",[-[<>-]]"),
+            [(
+                LintKind::CancellingPair,
+                Severity::Warning,
+                vec![1, 1, 0],
+                4,
+                6
+            )]
+        );
+    }
+
+    #[test]
+    fn lint_inside_procedures() {
+        use crate::{dialect::Dialect, token::Lexer};
+
+        let tokens = Lexer::new("+([-]+-):[-]".bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(tokens).unwrap();
+        let lints: Vec<_> = tree
+            .lint()
+            .into_iter()
+            .map(|lint| (lint.kind, lint.path))
+            .collect();
+
+        assert_eq!(lints, [(LintKind::CancellingPair, vec![1, 1])]);
+    }
+
+    #[test]
+    fn lint_code_after_end() {
+        use Expression as E;
+
+        let tree = SyntaxTree::from(vec![
+            E::Input,
+            E::Loop(vec![E::End, E::Output].into()),
+            E::Increment,
+        ]);
+        let lints: Vec<_> = tree
+            .lint()
+            .into_iter()
+            .map(|lint| (lint.kind, lint.path))
+            .collect();
+
+        assert_eq!(lints, [(LintKind::UnreachableCode, vec![1, 1])]);
+    }
+
+    #[test]
+    fn lint_removes_dead_code() {
+        let mut tree: SyntaxTree = "[-]+[>[-][<]<[-]+[]>>.]".parse().unwrap();
+        let removed = tree.remove_dead_code();
+
+        assert_eq!(tree.to_code(), "+[>[-]<[-]+[]]");
+        assert_eq!(
+            removed
+                .iter()
+                .map(|lint| lint.path.clone())
+                .collect::<Vec<_>>(),
+            [vec![0], vec![2, 2], vec![2, 7]]
+        );
+        assert_eq!(tree.span_at(&[1, 4]).map(|s| s.start), Some(16));
+    }
+
+    #[test]
+    fn lint_to_diagnostic() {
+        let tree: SyntaxTree = "+[]".parse().unwrap();
+        let diagnostic = tree.lint()[0].to_diagnostic().unwrap();
+
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.message, "this loop never ends once entered");
+    }
+}
@@ -0,0 +1,419 @@
+use crate::{
+    bytecode::{Op, Program},
+    execution::DEFAULT_BRAINFUCK_STACK_SIZE,
+    optimizer::OptLevel,
+    syntax::SyntaxTree,
+};
+use std::fmt::Write;
+
+/// Cells printed by `#`, like the interpreter does
+const DUMP_CELLS: usize = 16;
+
+impl Program {
+    /// Writes this program as a textual LLVM IR module running it on a
+    /// memory of `memory` cells
+    ///
+    /// The module defines `main`, reading and writing through the C
+    /// library, so `clang program.ll` builds it into an executable. It uses
+    /// opaque pointers, which LLVM supports since its version 15.
+    pub fn to_llvm_ir(&self, memory: usize) -> String {
+        let mut writer = IrWriter {
+            ops: self.ops(),
+            memory: memory.max(1),
+            values: 0,
+            labels: 0,
+            dumps: false,
+        };
+
+        let mut functions = String::new();
+        writer.function(
+            "i32 @main()",
+            0,
+            self.ops().len(),
+            "ret i32 0",
+            &mut functions,
+        );
+
+        let mut procedures = 0;
+        for (index, op) in self.ops().iter().enumerate() {
+            if let Op::Procedure(after) = op {
+                let signature = format!("internal void @procedure_{index}()");
+                writer.function(&signature, index + 1, after - 1, "ret void", &mut functions);
+                procedures += 1;
+            }
+        }
+
+        writer.module(&functions, procedures > 0)
+    }
+}
+
+impl SyntaxTree {
+    /// Writes this tree as a textual LLVM IR module, fully optimized for a
+    /// memory of the default size
+    ///
+    /// See [`Program::to_llvm_ir`] for what the module defines.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::SyntaxTree;
+    /// let tree: SyntaxTree = ",[.,]".parse().unwrap();
+    /// let module = tree.to_llvm_ir();
+    ///
+    /// assert!(module.contains("define i32 @main()"));
+    /// assert!(module.contains("call i32 @getchar()"));
+    /// ```
+    pub fn to_llvm_ir(&self) -> String {
+        self.compile_with(OptLevel::Aggressive)
+            .to_llvm_ir(DEFAULT_BRAINFUCK_STACK_SIZE)
+    }
+}
+
+/// Writes the functions of an LLVM IR module
+struct IrWriter<'a> {
+    ops: &'a [Op],
+    memory: usize,
+    /// Amount of values named so far in the current function
+    values: usize,
+    /// Amount of labels used so far in the current function
+    labels: usize,
+    /// Whether `#` is used, needing the function dumping the memory
+    dumps: bool,
+}
+
+impl IrWriter<'_> {
+    /// Writes a function running the instructions from `start` to `end`,
+    /// skipping the bodies of procedures
+    fn function(
+        &mut self,
+        signature: &str,
+        start: usize,
+        end: usize,
+        exit: &str,
+        out: &mut String,
+    ) {
+        self.values = 0;
+        self.labels = 0;
+
+        let _ = writeln!(out, "define {signature} {{");
+        let _ = writeln!(out, "entry:");
+
+        let mut loops = Vec::new();
+        let mut index = start;
+        while index < end {
+            let op = self.ops[index];
+            index += 1;
+
+            match op {
+                Op::Add(amount) => {
+                    let (address, cell) = self.load(0, out);
+                    let sum = self.value();
+                    let _ = writeln!(out, "  {sum} = add i8 {cell}, {}", amount as i8);
+                    let _ = writeln!(out, "  store i8 {sum}, ptr {address}");
+                }
+                Op::Move(offset) => {
+                    let index = self.index(offset, out);
+                    let _ = writeln!(out, "  store i64 {index}, ptr @pointer");
+                }
+                Op::Set(value) => {
+                    let address = self.address(0, out);
+                    let _ = writeln!(out, "  store i8 {}, ptr {address}", value as i8);
+                }
+                Op::Multiply { offset, factor } => {
+                    let (_, cell) = self.load(0, out);
+                    let product = self.value();
+                    let _ = writeln!(out, "  {product} = mul i8 {cell}, {}", factor as i8);
+                    let (address, target) = self.load(offset, out);
+                    let sum = self.value();
+                    let _ = writeln!(out, "  {sum} = add i8 {target}, {product}");
+                    let _ = writeln!(out, "  store i8 {sum}, ptr {address}");
+                }
+                Op::Input => {
+                    let (read, end, byte) = (self.value(), self.value(), self.value());
+                    let _ = writeln!(out, "  {read} = call i32 @getchar()");
+                    let _ = writeln!(out, "  {end} = icmp eq i32 {read}, -1");
+                    let _ = writeln!(out, "  {byte} = select i1 {end}, i32 0, i32 {read}");
+                    let cell = self.value();
+                    let _ = writeln!(out, "  {cell} = trunc i32 {byte} to i8");
+                    let address = self.address(0, out);
+                    let _ = writeln!(out, "  store i8 {cell}, ptr {address}");
+                }
+                Op::Output => {
+                    let (_, cell) = self.load(0, out);
+                    let byte = self.value();
+                    let _ = writeln!(out, "  {byte} = zext i8 {cell} to i32");
+                    let _ = writeln!(out, "  call i32 @putchar(i32 {byte})");
+                }
+                Op::DebugDump => {
+                    self.dumps = true;
+                    let _ = writeln!(out, "  call void @dump()");
+                }
+                Op::JumpIfZero(_) => {
+                    let label = self.label();
+                    let _ = writeln!(out, "  br label %loop{label}");
+                    let _ = writeln!(out, "loop{label}:");
+                    let (_, cell) = self.load(0, out);
+                    let nonzero = self.value();
+                    let _ = writeln!(out, "  {nonzero} = icmp ne i8 {cell}, 0");
+                    let _ = writeln!(
+                        out,
+                        "  br i1 {nonzero}, label %body{label}, label %end{label}"
+                    );
+                    let _ = writeln!(out, "body{label}:");
+                    loops.push(label);
+                }
+                Op::JumpIfNotZero(_) => {
+                    let label = loops.pop().expect("jumps are balanced");
+                    let _ = writeln!(out, "  br label %loop{label}");
+                    let _ = writeln!(out, "end{label}:");
+                }
+                Op::Procedure(after) => {
+                    let slot = self.procedure_slot(out);
+                    let _ = writeln!(out, "  store ptr @procedure_{}, ptr {slot}", index - 1);
+                    index = after;
+                }
+                Op::Return => (),
+                Op::Call => {
+                    let slot = self.procedure_slot(out);
+                    let (procedure, defined) = (self.value(), self.value());
+                    let label = self.label();
+                    let _ = writeln!(out, "  {procedure} = load ptr, ptr {slot}");
+                    let _ = writeln!(out, "  {defined} = icmp ne ptr {procedure}, null");
+                    let _ = writeln!(
+                        out,
+                        "  br i1 {defined}, label %call{label}, label %called{label}"
+                    );
+                    let _ = writeln!(out, "call{label}:");
+                    let _ = writeln!(out, "  call void {procedure}()");
+                    let _ = writeln!(out, "  br label %called{label}");
+                    let _ = writeln!(out, "called{label}:");
+                }
+                Op::End => {
+                    let label = self.label();
+                    let _ = writeln!(out, "  call void @exit(i32 0)");
+                    let _ = writeln!(out, "  unreachable");
+                    let _ = writeln!(out, "ended{label}:");
+                }
+                Op::Store => {
+                    let (_, cell) = self.load(0, out);
+                    let _ = writeln!(out, "  store i8 {cell}, ptr @storage");
+                }
+                Op::Retrieve => {
+                    let storage = self.value();
+                    let _ = writeln!(out, "  {storage} = load i8, ptr @storage");
+                    let address = self.address(0, out);
+                    let _ = writeln!(out, "  store i8 {storage}, ptr {address}");
+                }
+                Op::ShiftLeft | Op::ShiftRight | Op::Not | Op::Xor | Op::And | Op::Or => {
+                    let (address, cell) = self.load(0, out);
+                    let operand = match op {
+                        Op::ShiftLeft | Op::ShiftRight | Op::Not => String::new(),
+                        _ => {
+                            let storage = self.value();
+                            let _ = writeln!(out, "  {storage} = load i8, ptr @storage");
+                            storage
+                        }
+                    };
+                    let result = self.value();
+                    let instruction = match op {
+                        Op::ShiftLeft => format!("shl i8 {cell}, 1"),
+                        Op::ShiftRight => format!("lshr i8 {cell}, 1"),
+                        Op::Not => format!("xor i8 {cell}, -1"),
+                        Op::Xor => format!("xor i8 {cell}, {operand}"),
+                        Op::And => format!("and i8 {cell}, {operand}"),
+                        _ => format!("or i8 {cell}, {operand}"),
+                    };
+                    let _ = writeln!(out, "  {result} = {instruction}");
+                    let _ = writeln!(out, "  store i8 {result}, ptr {address}");
+                }
+            }
+        }
+
+        let _ = writeln!(out, "  {exit}\n}}\n");
+    }
+
+    /// Index of the cell `offset` cells away from the pointer, wrapping
+    /// around the memory
+    fn index(&mut self, offset: isize, out: &mut String) -> String {
+        let pointer = self.value();
+        let _ = writeln!(out, "  {pointer} = load i64, ptr @pointer");
+
+        match offset.rem_euclid(self.memory as isize) {
+            0 => pointer,
+            offset => {
+                let (sum, index) = (self.value(), self.value());
+                let _ = writeln!(out, "  {sum} = add i64 {pointer}, {offset}");
+                let _ = writeln!(out, "  {index} = urem i64 {sum}, {}", self.memory);
+                index
+            }
+        }
+    }
+
+    fn address(&mut self, offset: isize, out: &mut String) -> String {
+        let index = self.index(offset, out);
+        let address = self.value();
+        let _ = writeln!(
+            out,
+            "  {address} = getelementptr inbounds [{} x i8], ptr @tape, i64 0, i64 {index}",
+            self.memory
+        );
+
+        address
+    }
+
+    /// Address and value of the cell `offset` cells away from the pointer
+    fn load(&mut self, offset: isize, out: &mut String) -> (String, String) {
+        let address = self.address(offset, out);
+        let cell = self.value();
+        let _ = writeln!(out, "  {cell} = load i8, ptr {address}");
+
+        (address, cell)
+    }
+
+    /// Address of the procedure defined for the value of the current cell
+    fn procedure_slot(&mut self, out: &mut String) -> String {
+        let (_, cell) = self.load(0, out);
+        let (index, slot) = (self.value(), self.value());
+        let _ = writeln!(out, "  {index} = zext i8 {cell} to i64");
+        let _ = writeln!(
+            out,
+            "  {slot} = getelementptr inbounds [256 x ptr], ptr @procedures, i64 0, i64 {index}"
+        );
+
+        slot
+    }
+
+    fn value(&mut self) -> String {
+        self.values += 1;
+        format!("%v{}", self.values - 1)
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    /// Whole module holding `functions`
+    fn module(&self, functions: &str, procedures: bool) -> String {
+        let memory = self.memory;
+        let mut module = format!(
+            "@tape = internal global [{memory} x i8] zeroinitializer\n\
+             @pointer = internal global i64 {pointer}\n\
+             @storage = internal global i8 0\n",
+            pointer = memory / 2,
+        );
+        if procedures {
+            module.push_str("@procedures = internal global [256 x ptr] zeroinitializer\n");
+        }
+
+        module.push_str(
+            "\ndeclare i32 @getchar()\n\
+             declare i32 @putchar(i32)\n\
+             declare void @exit(i32) noreturn\n\n",
+        );
+        module.push_str(functions);
+
+        if self.dumps {
+            let start = memory / 2;
+            let end = (start + DUMP_CELLS).min(memory);
+            let _ = write!(
+                module,
+                "@stdout = external global ptr\n\
+                 @stderr = external global ptr\n\
+                 {head}\
+                 {cell}\
+                 {tail}\
+                 \n\
+                 declare i32 @fflush(ptr)\n\
+                 declare i32 @fprintf(ptr, ptr, ...)\n\
+                 declare i32 @fputs(ptr, ptr)\n\
+                 \n\
+                 define internal void @dump() {{\n\
+                 entry:\n  \
+                   %out = load ptr, ptr @stdout\n  \
+                   call i32 @fflush(ptr %out)\n  \
+                   %err = load ptr, ptr @stderr\n  \
+                   %pointer = load i64, ptr @pointer\n  \
+                   %first.address = getelementptr inbounds [{memory} x i8], ptr @tape, i64 0, i64 {start}\n  \
+                   %first = load i8, ptr %first.address\n  \
+                   %first.int = zext i8 %first to i32\n  \
+                   call i32 (ptr, ptr, ...) @fprintf(ptr %err, ptr @dump.head, i64 %pointer, i64 {start}, i64 {end}, i32 %first.int)\n  \
+                   br label %loop\n\
+                 loop:\n  \
+                   %index = phi i64 [ {next}, %entry ], [ %next, %body ]\n  \
+                   %done = icmp uge i64 %index, {end}\n  \
+                   br i1 %done, label %exit, label %body\n\
+                 body:\n  \
+                   %address = getelementptr inbounds [{memory} x i8], ptr @tape, i64 0, i64 %index\n  \
+                   %cell = load i8, ptr %address\n  \
+                   %cell.int = zext i8 %cell to i32\n  \
+                   call i32 (ptr, ptr, ...) @fprintf(ptr %err, ptr @dump.cell, i32 %cell.int)\n  \
+                   %next = add i64 %index, 1\n  \
+                   br label %loop\n\
+                 exit:\n  \
+                   call i32 @fputs(ptr @dump.tail, ptr %err)\n  \
+                   ret void\n\
+                 }}\n",
+                head = c_string("dump.head", "pointer: %zu, cells %zu..%zu: [%d"),
+                cell = c_string("dump.cell", ", %d"),
+                tail = c_string("dump.tail", "]\n"),
+                next = start + 1,
+            );
+        }
+
+        module
+    }
+}
+
+/// Global constant named `name` holding `text` as a C string
+fn c_string(name: &str, text: &str) -> String {
+    let escaped: String = text
+        .bytes()
+        .map(|byte| match byte {
+            b' '..=b'~' if byte != b'"' && byte != b'\\' => (byte as char).to_string(),
+            byte => format!("\\{byte:02X}"),
+        })
+        .collect();
+
+    format!(
+        "@{name} = private constant [{} x i8] c\"{escaped}\\00\"\n",
+        text.len() + 1
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dialect::Dialect, token::Lexer};
+
+    #[test]
+    fn llvm_ir_writes_loops() {
+        let tree: SyntaxTree = ",[.-]".parse().unwrap();
+        let module = tree.compile().to_llvm_ir(8);
+
+        assert!(module.starts_with("@tape = internal global [8 x i8] zeroinitializer\n"));
+        assert!(module.contains(
+            "  br label %loop0\n\
+             loop0:\n  \
+               %v6 = load i64, ptr @pointer\n  \
+               %v7 = getelementptr inbounds [8 x i8], ptr @tape, i64 0, i64 %v6\n  \
+               %v8 = load i8, ptr %v7\n  \
+               %v9 = icmp ne i8 %v8, 0\n  \
+               br i1 %v9, label %body0, label %end0\n\
+             body0:\n"
+        ));
+        assert!(module.contains("  %v17 = add i8 %v16, -1\n"));
+        assert!(module.contains("  br label %loop0\nend0:\n  ret i32 0\n}\n"));
+        assert!(!module.contains("@dump"));
+    }
+
+    #[test]
+    fn llvm_ir_writes_procedures() {
+        let lexer = Lexer::new("(.):".bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(lexer).unwrap();
+        let module = tree.compile().to_llvm_ir(8);
+
+        assert!(module.contains("  store ptr @procedure_0, ptr %v4\n"));
+        assert!(module.contains("define internal void @procedure_0() {\n"));
+        assert!(module.contains("  call void %v10()\n"));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    cst::{ConcreteSyntaxTree, CstNode},
+    syntax::{Expression, SyntaxTree},
+    visit::{self, ExpressionVisitor},
+};
+
+/// Statistics about a BrainFuck program
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Metrics {
+    /// Amount of `>`
+    pub forward: usize,
+    /// Amount of `<`
+    pub backward: usize,
+    /// Amount of `+`
+    pub increment: usize,
+    /// Amount of `-`
+    pub decrement: usize,
+    /// Amount of `,`
+    pub input: usize,
+    /// Amount of `.`
+    pub output: usize,
+    /// Amount of `#`, from the debug dump extension
+    pub debug_dumps: usize,
+    /// Amount of `[]` pairs
+    pub loops: usize,
+    /// Amount of `()` pairs, from the pbrain dialect
+    pub procedures: usize,
+    /// Amount of `:`, from the pbrain dialect
+    pub calls: usize,
+    /// Amount of commands from Extended Type I
+    pub extended: usize,
+    /// Deepest nesting of loops, zero without loops
+    pub max_depth: usize,
+    /// Amount of bytes that are not commands, only known when computed
+    /// from source code
+    pub comment_bytes: Option<usize>,
+}
+
+impl Metrics {
+    /// Amount of command bytes, including both brackets of loops and
+    /// procedures
+    pub fn commands(&self) -> usize {
+        self.forward
+            + self.backward
+            + self.increment
+            + self.decrement
+            + self.input
+            + self.output
+            + self.debug_dumps
+            + self.calls
+            + self.extended
+            + (self.loops + self.procedures) * 2
+    }
+}
+
+impl SyntaxTree {
+    /// Computes statistics about this tree
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::SyntaxTree;
+    /// let metrics = "++[>+[-]<-]".parse::<SyntaxTree>().unwrap().metrics();
+    ///
+    /// assert_eq!(metrics.increment, 3);
+    /// assert_eq!(metrics.max_depth, 2);
+    /// assert_eq!(metrics.commands(), 11);
+    /// ```
+    pub fn metrics(&self) -> Metrics {
+        let mut counter = Counter {
+            metrics: Metrics::default(),
+            depth: 0,
+        };
+        counter.visit_block(self);
+
+        counter.metrics
+    }
+}
+
+impl ConcreteSyntaxTree {
+    /// Computes statistics about this tree, counting comments too
+    pub fn metrics(&self) -> Metrics {
+        fn comment_bytes(nodes: &[CstNode]) -> usize {
+            nodes
+                .iter()
+                .map(|node| match node {
+                    CstNode::Command { .. } => 0,
+                    CstNode::Whitespace { text, .. } | CstNode::Comment { text, .. } => text.len(),
+                    CstNode::Loop { body, .. } | CstNode::Procedure { body, .. } => {
+                        comment_bytes(body)
+                    }
+                })
+                .sum()
+        }
+
+        Metrics {
+            comment_bytes: Some(comment_bytes(self.nodes())),
+            ..self.to_syntax_tree().metrics()
+        }
+    }
+}
+
+struct Counter {
+    metrics: Metrics,
+    depth: usize,
+}
+
+impl ExpressionVisitor for Counter {
+    fn visit_expression(&mut self, expr: &Expression) {
+        let metrics = &mut self.metrics;
+        match expr {
+            Expression::Forward => metrics.forward += 1,
+            Expression::Backward => metrics.backward += 1,
+            Expression::Increment => metrics.increment += 1,
+            Expression::Decrement => metrics.decrement += 1,
+            Expression::Input => metrics.input += 1,
+            Expression::Output => metrics.output += 1,
+            Expression::DebugDump => metrics.debug_dumps += 1,
+            Expression::Loop(_) => metrics.loops += 1,
+            Expression::Procedure(_) => metrics.procedures += 1,
+            Expression::Call => metrics.calls += 1,
+            Expression::End
+            | Expression::Store
+            | Expression::Retrieve
+            | Expression::ShiftLeft
+            | Expression::ShiftRight
+            | Expression::Not
+            | Expression::Xor
+            | Expression::And
+            | Expression::Or => metrics.extended += 1,
+        }
+
+        visit::walk_expression(self, expr);
+    }
+
+    fn visit_loop(&mut self, body: &SyntaxTree) {
+        self.depth += 1;
+        self.metrics.max_depth = self.metrics.max_depth.max(self.depth);
+        visit::walk_loop(self, body);
+        self.depth -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_count_every_kind() {
+        let tree: SyntaxTree = ">><+-,.[[]][.]".parse().unwrap();
+
+        assert_eq!(
+            tree.metrics(),
+            Metrics {
+                forward: 2,
+                backward: 1,
+                increment: 1,
+                decrement: 1,
+                input: 1,
+                output: 2,
+                debug_dumps: 0,
+                loops: 3,
+                procedures: 0,
+                calls: 0,
+                extended: 0,
+                max_depth: 2,
+                comment_bytes: None,
+            }
+        );
+    }
+
+    #[test]
+    fn metrics_count_comments() {
+        let code = "add: [->+<]\n";
+        let cst: ConcreteSyntaxTree = code.parse().unwrap();
+        let metrics = cst.metrics();
+
+        assert_eq!(metrics.comment_bytes, Some(6));
+        assert_eq!(metrics.commands() + 6, code.len());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{
+    syntax::{Expression, SyntaxTree},
+    token::Token,
+};
+
+/// Removes every byte of `code` that is not a command
+///
+/// Unlike parsing, it keeps unbalanced brackets, so it works on any code.
+///
+/// # Example
+/// ```
+/// assert_eq!(brainfuck::minify("Add: [->+<] done"), "[->+<]");
+/// ```
+pub fn minify(code: &str) -> String {
+    code.chars()
+        .filter(|&c| u8::try_from(c).is_ok_and(|b| !matches!(Token::from(b), Token::Comment(_))))
+        .collect()
+}
+
+impl SyntaxTree {
+    /// Writes the smallest code equivalent to this tree
+    ///
+    /// Besides leaving comments out, adjacent instructions that undo each
+    /// other, like `+-` or `><`, are removed.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::SyntaxTree;
+    /// let tree: SyntaxTree = "+>+-<<>[+><-]".parse().unwrap();
+    /// assert_eq!(tree.minify(), "+[]");
+    /// ```
+    pub fn minify(&self) -> String {
+        cancel_pairs(self).to_code()
+    }
+}
+
+/// Whether running `a` and then `b` leaves everything as it was
+pub(crate) fn cancel_out(a: &Expression, b: &Expression) -> bool {
+    use Expression as E;
+
+    matches!(
+        (a, b),
+        (E::Increment, E::Decrement)
+            | (E::Decrement, E::Increment)
+            | (E::Forward, E::Backward)
+            | (E::Backward, E::Forward)
+    )
+}
+
+/// Rebuilds `tree` without the pairs of [`cancel_out`], bottom-up so
+/// deeply nested trees don't overflow the stack
+fn cancel_pairs(tree: &[Expression]) -> SyntaxTree {
+    // Blocks being rebuilt, with the index of their next expression and
+    // the expressions kept so far
+    let mut blocks = vec![(tree, 0, Vec::with_capacity(tree.len()))];
+
+    loop {
+        let (block, index, kept) = blocks.last_mut().expect("the outer block ends last");
+        let Some(expr) = block.get(*index) else {
+            let (_, _, kept) = blocks.pop().expect("a block is being rebuilt");
+            let body = SyntaxTree::from(kept);
+
+            match blocks.last_mut() {
+                Some((parent, index, kept)) => kept.push(match parent[*index - 1] {
+                    Expression::Procedure(_) => Expression::Procedure(body),
+                    _ => Expression::Loop(body),
+                }),
+                None => return body,
+            }
+            continue;
+        };
+        *index += 1;
+
+        match expr {
+            Expression::Loop(body) | Expression::Procedure(body) => {
+                blocks.push((body, 0, Vec::with_capacity(body.len())))
+            }
+            expr if kept.last().is_some_and(|last| cancel_out(last, expr)) => {
+                kept.pop();
+            }
+            expr => kept.push(expr.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minify_keeps_unbalanced_code() {
+        assert_eq!(minify("a]b\n[c+ñ-"), "][+-");
+    }
+
+    #[test]
+    fn minify_cancels_nested_pairs() {
+        let tree: SyntaxTree = "+<+->>[-<><>+]-".parse().unwrap();
+        let minified = tree.minify();
+
+        assert_eq!(minified, "+>[]-");
+        assert_eq!(minified.parse::<SyntaxTree>().unwrap().minify(), minified);
+    }
+
+    #[test]
+    fn minify_deep_nesting() {
+        let depth = 100_000;
+        let tree: SyntaxTree = ("[+-".repeat(depth) + &"]".repeat(depth)).parse().unwrap();
+
+        assert_eq!(tree.minify(), "[".repeat(depth) + &"]".repeat(depth));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{
+    bytecode::Program,
+    syntax::SyntaxTree,
+    transpile::{self, CTranspiler},
+};
+use derive_more::{Display, Error, From};
+use std::{
+    env,
+    ffi::OsString,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Error while building a native executable
+#[derive(Debug, Display, Error, From)]
+pub enum NativeError {
+    /// The C compiler could not be started
+    IO(io::Error),
+
+    /// The C compiler rejected the generated code, with its diagnostics
+    #[display(fmt = "the C compiler failed: {}", _0)]
+    #[from(ignore)]
+    Compiler(#[error(not(source))] String),
+}
+
+impl Program {
+    /// Builds a standalone executable at `output` running this program on
+    /// a memory of `memory` cells
+    ///
+    /// The program is translated to C and compiled by the compiler named
+    /// by the `CC` environment variable, or `cc` otherwise. The executable
+    /// reads from its standard input and writes to its standard output.
+    pub fn build_native(&self, memory: usize, output: &Path) -> Result<(), NativeError> {
+        let source = CTranspiler::new().memory(memory).transpile_program(self);
+        compile_c(&source, output)
+    }
+}
+
+impl SyntaxTree {
+    /// Builds a standalone executable at `output` running this tree, as
+    /// translated by [`transpile::to_c`]
+    ///
+    /// See [`Program::build_native`] for how it is built.
+    pub fn build_native(&self, output: &Path) -> Result<(), NativeError> {
+        compile_c(&transpile::to_c(self), output)
+    }
+}
+
+impl CTranspiler {
+    /// Builds a standalone executable at `output` running `tree`, as
+    /// translated by this transpiler
+    ///
+    /// See [`Program::build_native`] for how it is built.
+    pub fn build_native(&self, tree: &SyntaxTree, output: &Path) -> Result<(), NativeError> {
+        compile_c(&self.transpile(tree), output)
+    }
+}
+
+/// Compiles the C `source` into an executable at `output`
+fn compile_c(source: &str, output: &Path) -> Result<(), NativeError> {
+    let compiler = env::var_os("CC").unwrap_or_else(|| OsString::from("cc"));
+    let mut child = Command::new(compiler)
+        .args(["-O2", "-x", "c", "-o"])
+        .arg(output)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    child
+        .stdin
+        .take()
+        .expect("the standard input is piped")
+        .write_all(source.as_bytes())?;
+
+    let result = child.wait_with_output()?;
+    match result.status.success() {
+        true => Ok(()),
+        false => Err(NativeError::Compiler(
+            String::from_utf8_lossy(&result.stderr).trim().to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_executable_runs() {
+        let tree: SyntaxTree = ",[>+++[<++>-]<.,]".parse().unwrap();
+        let output = env::temp_dir().join(format!("brainfuck-native-{}", std::process::id()));
+
+        match tree.build_native(&output) {
+            Err(NativeError::IO(error)) if error.kind() == io::ErrorKind::NotFound => return,
+            result => result.unwrap(),
+        }
+
+        let mut child = Command::new(&output)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"\x01\x02").unwrap();
+        let result = child.wait_with_output().unwrap();
+        std::fs::remove_file(&output).unwrap();
+
+        assert_eq!(result.stdout, b"\x07\x08");
+    }
+}
@@ -0,0 +1,1135 @@
+use crate::{
+    bytecode::{Op, Program},
+    effect::Effect,
+    execution::DEFAULT_BRAINFUCK_STACK_SIZE,
+};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+/// Rewrites the instructions of a [`Program`] into faster ones doing the
+/// same
+pub trait Pass {
+    /// Name of the pass in a [`PassReport`]
+    fn name(&self) -> &'static str;
+
+    /// Rewrites `ops` in place
+    ///
+    /// Jumps may point anywhere afterwards, since the [`PassManager`] fixes
+    /// them, but every `[` must still have its `]` and every procedure its
+    /// return.
+    fn run(&self, ops: &mut Vec<Op>);
+}
+
+/// How much a program is optimized when compiled
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum OptLevel {
+    /// One instruction for each expression, so running it matches
+    /// stepping through the source
+    None,
+    /// Optimizations that don't assume anything about the memory
+    #[default]
+    Basic,
+    /// Also runs the start of the program at compile time and removes
+    /// dead code, assuming the program starts with a fresh memory
+    Aggressive,
+}
+
+/// What running a [`Pass`] changed in a program
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct PassReport {
+    pub name: &'static str,
+    /// Amount of instructions before the pass
+    pub before: usize,
+    /// Amount of instructions after the pass
+    pub after: usize,
+    /// Whether any instruction changed
+    pub changed: bool,
+    /// Time the pass took, fixing the jumps included
+    pub time: Duration,
+}
+
+/// A pipeline of [`Pass`]es run one after another
+///
+/// The default pipeline is the one for [`OptLevel::Basic`], which is what
+/// [`SyntaxTree::compile`] uses.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{Op, PassManager, Program, RunLength, SyntaxTree};
+/// let tree: SyntaxTree = "+++[-]".parse().unwrap();
+/// let mut program = Program::new(&tree);
+///
+/// let mut passes = PassManager::new();
+/// passes.push(RunLength);
+/// let reports = passes.run(&mut program);
+///
+/// assert_eq!((reports[0].before, reports[0].after), (6, 4));
+/// assert_eq!(program.ops()[0], Op::Add(3));
+/// ```
+///
+/// [`SyntaxTree::compile`]: crate::SyntaxTree::compile
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    /// Starts an empty pipeline, which leaves programs as they are
+    pub fn new() -> Self {
+        PassManager { passes: Vec::new() }
+    }
+
+    /// Pipeline optimizing programs as much as `level` allows, for a
+    /// memory of `memory` cells
+    ///
+    /// [`OptLevel::Basic`] runs [`ClearLoops`], [`MultiplyLoops`],
+    /// [`RunLength`], [`UnrollLoops`], [`RunLength`] again and [`DeadCode`].
+    /// [`OptLevel::Aggressive`] goes on with [`ConstantFolding`],
+    /// [`RunLength`] and [`DeadCode`] for zeroed memory.
+    pub fn for_level(level: OptLevel, memory: usize) -> Self {
+        let mut passes = Self::new();
+        if level == OptLevel::None {
+            return passes;
+        }
+
+        passes.push(ClearLoops);
+        passes.push(MultiplyLoops);
+        passes.push(RunLength);
+        passes.push(UnrollLoops::default());
+        passes.push(RunLength);
+        passes.push(DeadCode::default());
+
+        if level == OptLevel::Aggressive {
+            passes.push(ConstantFolding {
+                memory,
+                ..Default::default()
+            });
+            passes.push(RunLength);
+            passes.push(DeadCode { zeroed: true });
+        }
+
+        passes
+    }
+
+    /// Pipeline for the code a program spends most of its time in, trading
+    /// compile time for speed
+    ///
+    /// It runs the passes of [`OptLevel::Basic`] that only look at the code
+    /// they change, unrolling loops of up to 1024 instructions, then
+    /// [`Superoptimize`] and [`RunLength`].
+    pub fn for_hot_code() -> Self {
+        let mut passes = Self::new();
+        passes.push(ClearLoops);
+        passes.push(MultiplyLoops);
+        passes.push(RunLength);
+        passes.push(UnrollLoops { max_ops: 1024 });
+        passes.push(RunLength);
+        passes.push(Superoptimize::default());
+        passes.push(RunLength);
+
+        passes
+    }
+
+    /// Adds `pass` at the end of the pipeline
+    pub fn push<P: Pass + 'static>(&mut self, pass: P) {
+        self.passes.push(Box::new(pass));
+    }
+
+    /// Runs every pass of the pipeline over `program`, in order
+    pub fn run(&self, program: &mut Program) -> Vec<PassReport> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("optimize").entered();
+
+        self.passes
+            .iter()
+            .map(|pass| {
+                #[cfg(feature = "tracing")]
+                let _span = tracing::debug_span!("pass", name = pass.name()).entered();
+
+                let before = program.ops.clone();
+                let start = Instant::now();
+                pass.run(&mut program.ops);
+                program.relink();
+                let time = start.elapsed();
+
+                let report = PassReport {
+                    name: pass.name(),
+                    before: before.len(),
+                    after: program.ops.len(),
+                    changed: before != program.ops,
+                    time,
+                };
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!(report.before, report.after, report.changed, "pass finished");
+
+                report
+            })
+            .collect()
+    }
+}
+
+impl std::default::Default for PassManager {
+    fn default() -> Self {
+        Self::for_level(OptLevel::default(), DEFAULT_BRAINFUCK_STACK_SIZE)
+    }
+}
+
+impl std::fmt::Debug for PassManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.passes.iter().map(|pass| pass.name()))
+            .finish()
+    }
+}
+
+/// Merges runs of `+-` and `><` into a single instruction, leaving it out
+/// when they cancel each other
+///
+/// Writes to the current cell are merged as well, so `[-]+++` becomes a
+/// single [`Op::Set`] after [`ClearLoops`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RunLength;
+
+impl Pass for RunLength {
+    fn name(&self) -> &'static str {
+        "run-length"
+    }
+
+    fn run(&self, ops: &mut Vec<Op>) {
+        let mut merged: Vec<Op> = Vec::with_capacity(ops.len());
+
+        for op in ops.drain(..) {
+            let op = match (merged.last(), op) {
+                (Some(&Op::Add(last)), Op::Add(amount)) => Op::Add(last.wrapping_add(amount)),
+                (Some(&Op::Move(last)), Op::Move(amount)) => Op::Move(last + amount),
+                (Some(&Op::Set(value)), Op::Add(amount)) => Op::Set(value.wrapping_add(amount)),
+                (Some(Op::Add(_) | Op::Set(_)), Op::Set(value)) => Op::Set(value),
+                _ => {
+                    merged.push(op);
+                    continue;
+                }
+            };
+
+            merged.pop();
+            if !matches!(op, Op::Add(0) | Op::Move(0)) {
+                merged.push(op);
+            }
+        }
+
+        *ops = merged;
+    }
+}
+
+/// Turns loops clearing the current cell, like `[-]` or `[+]`, into
+/// [`Op::Set`]
+///
+/// Adding an odd amount to a cell over and over always reaches zero.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ClearLoops;
+
+impl Pass for ClearLoops {
+    fn name(&self) -> &'static str {
+        "clear-loops"
+    }
+
+    fn run(&self, ops: &mut Vec<Op>) {
+        replace_loops(ops, |_, body| {
+            let mut step = 0u8;
+            for op in body {
+                match *op {
+                    Op::Add(amount) => step = step.wrapping_add(amount),
+                    _ => return None,
+                }
+            }
+
+            (step % 2 == 1).then(|| vec![Op::Set(0)])
+        });
+    }
+}
+
+/// Turns loops adding to other cells while counting the current one down,
+/// like `[->++>+++<<]`, into [`Op::Multiply`] for each of those cells
+/// before clearing the current one
+///
+/// The loop must only add to cells, end where it starts and change the
+/// current cell by one, so it runs as many times as the value of that
+/// cell, or its negation.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MultiplyLoops;
+
+impl Pass for MultiplyLoops {
+    fn name(&self) -> &'static str {
+        "multiply-loops"
+    }
+
+    fn run(&self, ops: &mut Vec<Op>) {
+        replace_loops(ops, |_, body| {
+            let effect = Effect::of(body).filter(|effect| effect.shift == 0)?;
+            let mut deltas = BTreeMap::new();
+            for (&offset, value) in &effect.cells {
+                deltas.insert(offset, value.delta(offset)?);
+            }
+
+            let negate = match deltas.remove(&0) {
+                Some(u8::MAX) => false,
+                Some(1) => true,
+                _ => return None,
+            };
+
+            let mut ops: Vec<_> = deltas
+                .into_iter()
+                .filter(|&(_, delta)| delta != 0)
+                .map(|(offset, delta)| Op::Multiply {
+                    offset,
+                    factor: if negate { delta.wrapping_neg() } else { delta },
+                })
+                .collect();
+            ops.push(Op::Set(0));
+
+            Some(ops)
+        });
+    }
+}
+
+/// Repeats the body of loops that run a known amount of times instead of
+/// looping
+///
+/// The counter of the loop must be set to a constant by [`Op::Set`] right
+/// before it, and the body must only add to it, taking one away on every
+/// iteration. Loops whose unrolled body would take more than `max_ops`
+/// instructions are left alone.
+#[derive(Debug, Clone, Copy)]
+pub struct UnrollLoops {
+    pub max_ops: usize,
+}
+
+impl std::default::Default for UnrollLoops {
+    fn default() -> Self {
+        UnrollLoops { max_ops: 128 }
+    }
+}
+
+impl Pass for UnrollLoops {
+    fn name(&self) -> &'static str {
+        "unroll-loops"
+    }
+
+    fn run(&self, ops: &mut Vec<Op>) {
+        replace_loops(ops, |before, body| {
+            let &Op::Set(trips) = before? else {
+                return None;
+            };
+            if body.len() * trips as usize > self.max_ops {
+                return None;
+            }
+
+            let mut offset = 0;
+            let mut step = 0u8;
+            for op in body {
+                match *op {
+                    Op::Move(by) => offset += by,
+                    Op::Add(amount) if offset == 0 => step = step.wrapping_add(amount),
+                    Op::Multiply { offset: to, .. } if offset + to == 0 => return None,
+                    Op::Add(_) | Op::Multiply { .. } | Op::Output | Op::DebugDump | Op::Store => {}
+                    Op::Set(_) | Op::Input | Op::Retrieve if offset != 0 => {}
+                    Op::ShiftLeft | Op::ShiftRight | Op::Not if offset != 0 => {}
+                    Op::Xor | Op::And | Op::Or if offset != 0 => {}
+                    _ => return None,
+                }
+            }
+
+            (offset == 0 && step == u8::MAX).then(|| body.repeat(trips as usize))
+        });
+    }
+}
+
+/// Removes loops reached when the current cell is always zero, and the
+/// instructions after a loop that never ends or after `@`
+///
+/// A cell is known to be zero right after a loop or [`Op::Set`] with zero.
+/// When `zeroed`, every cell is also known to be zero until the first
+/// write, which assumes the program starts with a fresh memory, like
+/// [`SyntaxTree::lint`] does. [`SyntaxTree::remove_dead_code`] does the
+/// same on a tree, reporting where the removed code was.
+///
+/// [`SyntaxTree::lint`]: crate::SyntaxTree::lint
+/// [`SyntaxTree::remove_dead_code`]: crate::SyntaxTree::remove_dead_code
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DeadCode {
+    pub zeroed: bool,
+}
+
+impl Pass for DeadCode {
+    fn name(&self) -> &'static str {
+        "dead-code"
+    }
+
+    fn run(&self, ops: &mut Vec<Op>) {
+        let mut live = Vec::with_capacity(ops.len());
+        // Value of the current cell, if known
+        let mut cell = self.zeroed.then_some(0u8);
+        let mut pristine = self.zeroed;
+        // Cell and pristine before every procedure being defined, since
+        // its body runs later
+        let mut procedures = Vec::new();
+        let mut depth = 0;
+        let mut index = 0;
+
+        while let Some(&op) = ops.get(index) {
+            index += 1;
+
+            cell = match op {
+                Op::JumpIfZero(end) if cell == Some(0) => {
+                    index = end;
+                    continue;
+                }
+                Op::JumpIfZero(_) => {
+                    depth += 1;
+                    live.push(op);
+
+                    // An empty loop reached with a nonzero cell never ends
+                    if depth == 1
+                        && cell.is_some()
+                        && ops.get(index) == Some(&Op::JumpIfNotZero(index))
+                    {
+                        live.push(ops[index]);
+                        break;
+                    }
+
+                    pristine = false;
+                    None
+                }
+                Op::JumpIfNotZero(_) => {
+                    depth -= 1;
+                    Some(0)
+                }
+                Op::Procedure(_) => {
+                    depth += 1;
+                    procedures.push((cell, pristine));
+                    pristine = false;
+                    None
+                }
+                Op::Return => {
+                    depth -= 1;
+                    let before;
+                    (before, pristine) = procedures.pop().expect("procedures are balanced");
+                    before
+                }
+                Op::End if depth == 0 => {
+                    live.push(op);
+                    break;
+                }
+                Op::Add(amount) => {
+                    pristine = false;
+                    cell.map(|value| value.wrapping_add(amount))
+                }
+                Op::Set(value) => {
+                    pristine = false;
+                    Some(value)
+                }
+                Op::Move(_) => pristine.then_some(0),
+                Op::Multiply { offset: 0, factor } => {
+                    pristine = false;
+                    cell.map(|value| value.wrapping_add(value.wrapping_mul(factor)))
+                }
+                Op::Multiply { .. } => {
+                    pristine = false;
+                    cell
+                }
+                Op::Output | Op::DebugDump | Op::Store | Op::End => cell,
+                Op::Input
+                | Op::Call
+                | Op::Retrieve
+                | Op::ShiftLeft
+                | Op::ShiftRight
+                | Op::Not
+                | Op::Xor
+                | Op::And
+                | Op::Or => {
+                    pristine = false;
+                    None
+                }
+            };
+
+            if !matches!(op, Op::JumpIfZero(_)) {
+                live.push(op);
+            }
+        }
+
+        *ops = live;
+    }
+}
+
+/// Runs the start of a program at compile time, replacing it with the
+/// bytes it writes and the cells it leaves behind
+///
+/// Only instructions that don't depend on the input are run, stopping
+/// before the first one that does, or that can't be known at compile time,
+/// like `,` or `#`. Whole loops are run or left alone, and one that takes
+/// more than `steps` steps is left alone.
+///
+/// It assumes the program starts with every cell of a memory of `memory`
+/// cells at zero, so it is not part of the default [`PassManager`].
+#[derive(Debug, Clone, Copy)]
+pub struct ConstantFolding {
+    pub memory: usize,
+    /// Steps allowed to run at compile time
+    pub steps: usize,
+}
+
+impl std::default::Default for ConstantFolding {
+    fn default() -> Self {
+        ConstantFolding {
+            memory: DEFAULT_BRAINFUCK_STACK_SIZE,
+            steps: 100_000,
+        }
+    }
+}
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "constant-folding"
+    }
+
+    fn run(&self, ops: &mut Vec<Op>) {
+        if self.memory == 0 {
+            return;
+        }
+
+        let mut memory = vec![0u8; self.memory];
+        let start = self.memory / 2;
+        let mut pointer = start;
+        let mut output = Vec::new();
+        let mut steps = 0;
+        let mut folded = 0;
+
+        // Cells written by the current loop, to restore them if it can't
+        // be run entirely
+        let mut written: Vec<(usize, u8)> = Vec::new();
+
+        while folded < ops.len() {
+            let end = match ops[folded] {
+                Op::JumpIfZero(end) => end,
+                _ => folded + 1,
+            };
+            let saved = (pointer, output.len());
+            written.clear();
+
+            let mut index = folded;
+            while index < end && steps <= self.steps {
+                let cell =
+                    |by: isize| (pointer as isize + by).rem_euclid(self.memory as isize) as usize;
+
+                let write = match ops[index] {
+                    Op::Add(amount) => Some((pointer, memory[pointer].wrapping_add(amount))),
+                    Op::Move(by) => {
+                        pointer = cell(by);
+                        None
+                    }
+                    Op::Set(value) => Some((pointer, value)),
+                    Op::Multiply { offset, factor } => {
+                        let target = cell(offset);
+                        let product = memory[pointer].wrapping_mul(factor);
+                        Some((target, memory[target].wrapping_add(product)))
+                    }
+                    Op::Output => {
+                        output.push(memory[pointer]);
+                        None
+                    }
+                    Op::JumpIfZero(target) if memory[pointer] == 0 => {
+                        index = target - 1;
+                        None
+                    }
+                    Op::JumpIfNotZero(target) if memory[pointer] != 0 => {
+                        index = target - 1;
+                        None
+                    }
+                    Op::JumpIfZero(_) | Op::JumpIfNotZero(_) => None,
+                    _ => break,
+                };
+
+                if let Some((cell, value)) = write {
+                    written.push((cell, memory[cell]));
+                    memory[cell] = value;
+                }
+                index += 1;
+                steps += 1;
+            }
+
+            if index != end || steps > self.steps {
+                for &(cell, value) in written.iter().rev() {
+                    memory[cell] = value;
+                }
+                pointer = saved.0;
+                output.truncate(saved.1);
+                break;
+            }
+
+            folded = end;
+        }
+
+        let mut replaced = Vec::new();
+        for byte in output {
+            replaced.extend([Op::Set(byte), Op::Output]);
+        }
+
+        let mut at = start;
+        for (cell, &value) in memory.iter().enumerate() {
+            if value != 0 || (cell == start && !replaced.is_empty()) {
+                if cell != at {
+                    replaced.push(Op::Move(cell as isize - at as isize));
+                }
+                replaced.push(Op::Set(value));
+                at = cell;
+            }
+        }
+        if pointer != at {
+            replaced.push(Op::Move(pointer as isize - at as isize));
+        }
+
+        ops.splice(..folded, replaced);
+    }
+}
+
+/// Replaces runs of instructions that only change cells and move the
+/// pointer with the shortest run doing the same, found by trying every
+/// one up to that length
+///
+/// Runs are cut in windows of `window` instructions, and runs of each
+/// length are built from the instructions that could make up the effect
+/// of the window, like the values it leaves in cells. A window is left
+/// alone when no shorter run is found within `budget` tries. The search
+/// grows quickly with the window, so it is not part of any [`OptLevel`].
+///
+/// # Example
+/// ```
+/// # use brainfuck::{Op, PassManager, Program, Superoptimize};
+/// let tree = "+>+<->".parse().unwrap();
+/// let mut program = Program::new(&tree);
+///
+/// let mut passes = PassManager::new();
+/// passes.push(Superoptimize::default());
+/// passes.run(&mut program);
+///
+/// assert_eq!(program.ops(), [Op::Move(1), Op::Add(1)]);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Superoptimize {
+    pub window: usize,
+    /// Runs tried for each window
+    pub budget: usize,
+}
+
+impl std::default::Default for Superoptimize {
+    fn default() -> Self {
+        Superoptimize {
+            window: 6,
+            budget: 100_000,
+        }
+    }
+}
+
+impl Pass for Superoptimize {
+    fn name(&self) -> &'static str {
+        "superoptimize"
+    }
+
+    fn run(&self, ops: &mut Vec<Op>) {
+        let mut optimized = Vec::with_capacity(ops.len());
+        let mut index = 0;
+
+        while index < ops.len() {
+            let run = ops[index..]
+                .iter()
+                .take(self.window.max(1))
+                .take_while(|&&op| Effect::of(&[op]).is_some())
+                .count();
+            let window = &ops[index..index + run.max(1)];
+            index += window.len();
+
+            let shorter = match Effect::of(window) {
+                Some(effect) if window.len() > 1 => {
+                    Search::new(&effect, self.budget).shortest(window.len() - 1)
+                }
+                _ => None,
+            };
+            optimized.extend_from_slice(shorter.as_deref().unwrap_or(window));
+        }
+
+        *ops = optimized;
+    }
+}
+
+/// Search for the shortest run of instructions with a given effect
+struct Search<'a> {
+    target: &'a Effect,
+    /// Instructions runs are built from
+    candidates: Vec<Op>,
+    budget: usize,
+}
+
+impl<'a> Search<'a> {
+    fn new(target: &'a Effect, budget: usize) -> Self {
+        let mut offsets = vec![0, target.shift];
+        let mut candidates = Vec::new();
+        let mut candidate = |op: Op| {
+            if !candidates.contains(&op) {
+                candidates.push(op);
+            }
+        };
+
+        for (&offset, value) in &target.cells {
+            offsets.push(offset);
+            candidate(Op::Set(value.constant));
+            candidate(Op::Set(0));
+            if value.constant != 0 {
+                candidate(Op::Add(value.constant));
+            }
+
+            for (&from, &factor) in &value.factors {
+                offsets.push(from);
+                match from == offset {
+                    true if factor != 1 => candidate(Op::Multiply {
+                        offset: 0,
+                        factor: factor.wrapping_sub(1),
+                    }),
+                    true => (),
+                    false => candidate(Op::Multiply {
+                        offset: offset - from,
+                        factor,
+                    }),
+                }
+            }
+        }
+
+        for &from in &offsets {
+            for &to in &offsets {
+                if from != to {
+                    candidate(Op::Move(to - from));
+                }
+            }
+        }
+
+        Search {
+            target,
+            candidates,
+            budget,
+        }
+    }
+
+    /// Shortest run doing the same as the target, of at most `longest`
+    /// instructions
+    fn shortest(&mut self, longest: usize) -> Option<Vec<Op>> {
+        let mut run = Vec::with_capacity(longest);
+        (0..=longest).find_map(|len| {
+            self.extend(&Effect::default(), &mut run, len)
+                .then(|| run.clone())
+        })
+    }
+
+    /// Whether `run`, with `effect`, can be extended to `len` instructions
+    /// doing the same as the target
+    fn extend(&mut self, effect: &Effect, run: &mut Vec<Op>, len: usize) -> bool {
+        if run.len() == len {
+            return effect == self.target;
+        }
+        if self.budget == 0 || effect.distance(self.target) > len - run.len() {
+            return false;
+        }
+        self.budget -= 1;
+
+        for index in 0..self.candidates.len() {
+            let op = self.candidates[index];
+            // Runs with these pairs are never the shortest, since they
+            // merge into one instruction
+            let merges = matches!(
+                (run.last(), op),
+                (Some(Op::Move(_)), Op::Move(_))
+                    | (Some(Op::Add(_) | Op::Set(_)), Op::Add(_) | Op::Set(_))
+            );
+            if merges {
+                continue;
+            }
+
+            let mut next = effect.clone();
+            next.apply(op);
+            run.push(op);
+            if self.extend(&next, run, len) {
+                return true;
+            }
+            run.pop();
+        }
+
+        false
+    }
+}
+
+/// Replaces every innermost loop for which `replace` gives instructions
+/// doing the same, given the instruction before the loop and its body
+fn replace_loops<F>(ops: &mut Vec<Op>, replace: F)
+where
+    F: Fn(Option<&Op>, &[Op]) -> Option<Vec<Op>>,
+{
+    let mut replaced = Vec::with_capacity(ops.len());
+    let mut index = 0;
+
+    while index < ops.len() {
+        if let Op::JumpIfZero(_) = ops[index] {
+            let body = &ops[index + 1..];
+            let end = body.iter().position(|op| {
+                matches!(
+                    op,
+                    Op::JumpIfZero(_) | Op::JumpIfNotZero(_) | Op::Procedure(_) | Op::Return
+                )
+            });
+
+            if let Some(end) = end.filter(|&end| matches!(body[end], Op::JumpIfNotZero(_))) {
+                if let Some(ops) = replace(replaced.last(), &body[..end]) {
+                    replaced.extend(ops);
+                    index += end + 2;
+                    continue;
+                }
+            }
+        }
+
+        replaced.push(ops[index]);
+        index += 1;
+    }
+
+    *ops = replaced;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::SyntaxTree;
+
+    #[test]
+    fn compile_merges_runs() {
+        let tree: SyntaxTree = "++-+>>><<.+-<>[+-]>".parse().unwrap();
+
+        assert_eq!(
+            tree.compile().ops(),
+            [
+                Op::Add(2),
+                Op::Move(1),
+                Op::Output,
+                Op::JumpIfZero(5),
+                Op::JumpIfNotZero(4),
+                Op::Move(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_clear_loops() {
+        let tree: SyntaxTree = ",[-]>,[+++]+++>,[-],[--]-[>]".parse().unwrap();
+
+        assert_eq!(
+            tree.compile().ops(),
+            [
+                Op::Input,
+                Op::Set(0),
+                Op::Move(1),
+                Op::Input,
+                Op::Set(3),
+                Op::Move(1),
+                Op::Input,
+                Op::Set(0),
+                Op::Input,
+                Op::JumpIfZero(12),
+                Op::Add(254),
+                Op::JumpIfNotZero(10),
+                Op::Add(255),
+                Op::JumpIfZero(16),
+                Op::Move(1),
+                Op::JumpIfNotZero(14),
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_multiply_loops() {
+        let tree: SyntaxTree = "+[->++>+++<<]>>[<<+>>+]<[>-<--]".parse().unwrap();
+
+        assert_eq!(
+            tree.compile().ops(),
+            [
+                Op::Add(1),
+                Op::Multiply {
+                    offset: 1,
+                    factor: 2
+                },
+                Op::Multiply {
+                    offset: 2,
+                    factor: 3
+                },
+                Op::Set(0),
+                Op::Move(2),
+                Op::Multiply {
+                    offset: -2,
+                    factor: 255
+                },
+                Op::Set(0),
+                Op::Move(-1),
+                Op::JumpIfZero(14),
+                Op::Move(1),
+                Op::Add(255),
+                Op::Move(-1),
+                Op::Add(254),
+                Op::JumpIfNotZero(9),
+            ]
+        );
+    }
+
+    fn fold(code: &str) -> Vec<Op> {
+        let tree: SyntaxTree = code.parse().unwrap();
+        let mut program = Program::new(&tree);
+
+        let mut passes = PassManager::new();
+        passes.push(ConstantFolding {
+            memory: 8,
+            steps: 100,
+        });
+        passes.push(RunLength);
+        passes.run(&mut program);
+
+        program.ops
+    }
+
+    #[test]
+    fn constant_folding_runs_input_free_prefix() {
+        assert_eq!(
+            fold("++[>+++<-]>.<+,."),
+            [
+                Op::Set(6),
+                Op::Output,
+                Op::Set(1),
+                Op::Move(1),
+                Op::Set(6),
+                Op::Move(-1),
+                Op::Input,
+                Op::Output,
+            ]
+        );
+        assert_eq!(
+            fold("+[>+<]"),
+            [
+                Op::Set(1),
+                Op::JumpIfZero(6),
+                Op::Move(1),
+                Op::Add(1),
+                Op::Move(-1),
+                Op::JumpIfNotZero(2),
+            ]
+        );
+        assert_eq!(fold("<<<<<<+"), [Op::Move(2), Op::Set(1)]);
+        assert_eq!(fold(",+"), [Op::Input, Op::Add(1)]);
+    }
+
+    #[test]
+    fn unroll_loops_with_known_trips() {
+        let tree: SyntaxTree = ",[-]++[>.+<-]>[-]+++[<+>,-]".parse().unwrap();
+
+        assert_eq!(
+            tree.compile().ops(),
+            [
+                Op::Input,
+                Op::Set(2),
+                Op::Move(1),
+                Op::Output,
+                Op::Add(1),
+                Op::Move(-1),
+                Op::Add(255),
+                Op::Move(1),
+                Op::Output,
+                Op::Add(1),
+                Op::Move(-1),
+                Op::Add(255),
+                Op::Move(1),
+                Op::Set(3),
+                Op::JumpIfZero(21),
+                Op::Move(-1),
+                Op::Add(1),
+                Op::Move(1),
+                Op::Input,
+                Op::Add(255),
+                Op::JumpIfNotZero(15),
+            ]
+        );
+
+        let tree: SyntaxTree = "[-]+++[-.]".parse().unwrap();
+        let mut program = Program::new(&tree);
+        let mut passes = PassManager::new();
+        passes.push(ClearLoops);
+        passes.push(RunLength);
+        passes.push(UnrollLoops { max_ops: 5 });
+        passes.run(&mut program);
+
+        assert_eq!(
+            program.ops[1..],
+            [
+                Op::JumpIfZero(5),
+                Op::Add(255),
+                Op::Output,
+                Op::JumpIfNotZero(2)
+            ]
+        );
+    }
+
+    fn eliminate(code: &str, zeroed: bool) -> String {
+        let tree: SyntaxTree = code.parse().unwrap();
+        let mut program = Program::new(&tree);
+
+        let mut passes = PassManager::new();
+        passes.push(ClearLoops);
+        passes.push(DeadCode { zeroed });
+        passes.run(&mut program);
+
+        program
+            .ops
+            .iter()
+            .map(|op| match op {
+                Op::Add(1) => '+',
+                Op::Move(1) => '>',
+                Op::Output => '.',
+                Op::Set(0) => '0',
+                Op::JumpIfZero(_) => '[',
+                Op::JumpIfNotZero(_) => ']',
+                _ => '?',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dead_code_removes_loops_at_zero() {
+        assert_eq!(eliminate("[.]>[.]+[>]+[.]", true), ">+[>]+[.]");
+        assert_eq!(eliminate("[.]>[.]+[>]+[.]", false), "[.]>[.]+[>]+[.]");
+        assert_eq!(eliminate(".[-][.]+[>[-][.].]", false), ".0+[>0.]");
+    }
+
+    #[test]
+    fn dead_code_stops_at_endless_loops() {
+        assert_eq!(eliminate("+[]>.", true), "+[]");
+        assert_eq!(eliminate("+[+[]>]>.", true), "+[+[]>]>.");
+        assert_eq!(eliminate(".[]>.", false), ".[]>.");
+    }
+
+    #[test]
+    fn dead_code_follows_multiply_in_place() {
+        // Doubles a cell holding 128, which wraps around to zero
+        let mut ops = vec![
+            Op::Set(128),
+            Op::Multiply {
+                offset: 0,
+                factor: 1,
+            },
+            Op::JumpIfZero(5),
+            Op::Output,
+            Op::JumpIfNotZero(3),
+        ];
+        DeadCode::default().run(&mut ops);
+
+        assert_eq!(
+            ops,
+            [
+                Op::Set(128),
+                Op::Multiply {
+                    offset: 0,
+                    factor: 1
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn opt_levels_optimize_more() {
+        let tree: SyntaxTree = "++[>+++<-]>.".parse().unwrap();
+        let compile = |level| tree.compile_with(level).ops().len();
+
+        assert_eq!(compile(OptLevel::None), 12);
+        assert_eq!(compile(OptLevel::Basic), 5);
+        assert_eq!(
+            tree.compile_with(OptLevel::Aggressive).ops(),
+            [Op::Set(6), Op::Output, Op::Set(0), Op::Move(1), Op::Set(6),]
+        );
+    }
+
+    #[test]
+    fn pass_manager_reports_changes() {
+        let tree: SyntaxTree = "++[>+<-]".parse().unwrap();
+        let mut program = Program::new(&tree);
+        let reports = PassManager::default().run(&mut program);
+
+        assert_eq!(
+            reports
+                .iter()
+                .map(|r| (r.name, r.after, r.changed))
+                .collect::<Vec<_>>(),
+            [
+                ("clear-loops", 8, false),
+                ("multiply-loops", 4, true),
+                ("run-length", 3, true),
+                ("unroll-loops", 3, false),
+                ("run-length", 3, false),
+                ("dead-code", 3, false),
+            ]
+        );
+        assert_eq!(program, tree.compile());
+        assert!(PassManager::new().run(&mut program).is_empty());
+    }
+
+    fn superoptimize(mut ops: Vec<Op>) -> Vec<Op> {
+        Superoptimize::default().run(&mut ops);
+        ops
+    }
+
+    #[test]
+    fn superoptimize_finds_shorter_runs() {
+        assert_eq!(
+            superoptimize(vec![
+                Op::Add(1),
+                Op::Move(1),
+                Op::Add(1),
+                Op::Move(-1),
+                Op::Add(255),
+                Op::Move(1),
+            ]),
+            [Op::Move(1), Op::Add(1)]
+        );
+        assert_eq!(
+            superoptimize(vec![
+                Op::Multiply {
+                    offset: 1,
+                    factor: 2
+                },
+                Op::Multiply {
+                    offset: 1,
+                    factor: 3
+                },
+                Op::Set(0),
+                Op::Add(3),
+            ]),
+            [
+                Op::Multiply {
+                    offset: 1,
+                    factor: 5
+                },
+                Op::Set(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn superoptimize_keeps_shortest_runs() {
+        for ops in [
+            vec![Op::Add(1), Op::Move(1), Op::Add(2)],
+            vec![Op::Move(1), Op::Output, Op::Move(-1)],
+            vec![Op::Set(0), Op::Move(2), Op::Set(0), Op::Move(-1)],
+        ] {
+            assert_eq!(superoptimize(ops.clone()), ops);
+        }
+    }
+}
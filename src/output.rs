@@ -0,0 +1,264 @@
+use std::io::{self, BufWriter, Write};
+
+/// When the bytes written by a BrainFuck program are flushed to where
+/// they go
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum FlushPolicy {
+    /// After every byte, for interactive programs
+    Byte,
+    /// After every line break
+    #[default]
+    Line,
+    /// Only when the program stops running, or when the buffer is full
+    End,
+}
+
+/// How the bytes written by a BrainFuck program are printed
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum OutputMode {
+    /// As the characters they encode, or as they are for raw outputs
+    #[default]
+    Chars,
+    /// As decimal numbers, each followed by a space, or by a line break
+    /// for line feeds
+    Decimal,
+    /// As two hexadecimal digits, separated like [`OutputMode::Decimal`]
+    Hex,
+    /// As UTF-8 text, gathering bytes until they make up a character, and
+    /// writing U+FFFD for every invalid sequence
+    Utf8,
+}
+
+/// Destination of the bytes written by a BrainFuck program
+///
+/// It writes to the standard output by default, encoding every byte as a
+/// character. Bytes are buffered and flushed following a [`FlushPolicy`].
+pub struct Output {
+    writer: BufWriter<Box<dyn Write>>,
+    policy: FlushPolicy,
+    mode: OutputMode,
+    /// Bytes of a character not finished yet, in [`OutputMode::Utf8`]
+    pending: Vec<u8>,
+    /// Whether bytes are written as they are instead of as characters
+    raw: bool,
+}
+
+impl Output {
+    /// Writes to the standard output
+    pub fn stdout() -> Self {
+        Self::from_writer(io::stdout())
+    }
+
+    fn from_writer<W: Write + 'static>(writer: W) -> Self {
+        Output {
+            writer: BufWriter::new(Box::new(writer)),
+            policy: FlushPolicy::default(),
+            mode: OutputMode::default(),
+            pending: Vec::new(),
+            raw: false,
+        }
+    }
+
+    /// Writes the bytes to `writer` as they are, like binary files need
+    pub fn raw<W: Write + 'static>(writer: W) -> Self {
+        Output {
+            raw: true,
+            ..Self::from_writer(writer)
+        }
+    }
+
+    /// When bytes are flushed
+    pub fn policy(&self) -> FlushPolicy {
+        self.policy
+    }
+
+    /// Changes when bytes are flushed, flushing the ones already written
+    pub fn set_policy(&mut self, policy: FlushPolicy) {
+        self.flush();
+        self.policy = policy;
+    }
+
+    /// How bytes are printed
+    pub fn mode(&self) -> OutputMode {
+        self.mode
+    }
+
+    /// Changes how bytes are printed from now on
+    pub fn set_mode(&mut self, mode: OutputMode) {
+        self.mode = mode;
+    }
+
+    /// Writes `byte` as the character it encodes, as it is for raw
+    /// outputs, or as a number following the [`OutputMode`]
+    ///
+    /// Errors are ignored, as the program can't do anything about them.
+    pub fn write_byte(&mut self, byte: u8) {
+        if self.mode == OutputMode::Utf8 {
+            self.write_utf8(byte);
+        } else {
+            self.write_encoded(byte);
+        }
+
+        match self.policy {
+            FlushPolicy::Byte => self.flush(),
+            FlushPolicy::Line if byte == b'\n' => self.flush(),
+            FlushPolicy::Line | FlushPolicy::End => (),
+        }
+    }
+
+    fn write_encoded(&mut self, byte: u8) {
+        let separator = if byte == b'\n' { '\n' } else { ' ' };
+        let number = match self.mode {
+            OutputMode::Chars | OutputMode::Utf8 => None,
+            OutputMode::Decimal => Some(format!("{byte}{separator}")),
+            OutputMode::Hex => Some(format!("{byte:02x}{separator}")),
+        };
+        let mut encoded = [0; 4];
+        let encoded = match (&number, self.raw) {
+            (Some(number), _) => number.as_bytes(),
+            (None, true) => std::slice::from_ref(&byte),
+            (None, false) => (byte as char).encode_utf8(&mut encoded).as_bytes(),
+        };
+        let _ = self.writer.write_all(encoded);
+    }
+
+    /// Writes the characters finished by `byte`, if any
+    fn write_utf8(&mut self, byte: u8) {
+        self.pending.push(byte);
+
+        loop {
+            let error = match std::str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    let _ = self.writer.write_all(text.as_bytes());
+                    self.pending.clear();
+                    return;
+                }
+                Err(error) => error,
+            };
+
+            let valid = error.valid_up_to();
+            let _ = self.writer.write_all(&self.pending[..valid]);
+            match error.error_len() {
+                Some(invalid) => {
+                    let _ = self.writer.write_all("\u{fffd}".as_bytes());
+                    self.pending.drain(..valid + invalid);
+                }
+                None => {
+                    self.pending.drain(..valid);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Sends every buffered byte to where it goes
+    pub fn flush(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+impl std::fmt::Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Output")
+            .field("buffered", &self.writer.buffer().len())
+            .field("policy", &self.policy)
+            .field("mode", &self.mode)
+            .field("raw", &self.raw)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Writer keeping what reaches it, to be looked at from outside
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn output_flushes_following_policy() {
+        let shared = Shared::default();
+        let mut output = Output::from_writer(shared.clone());
+
+        output.write_byte(b'a');
+        assert_eq!(*shared.0.borrow(), b"");
+        output.write_byte(b'\n');
+        assert_eq!(*shared.0.borrow(), b"a\n");
+
+        output.set_policy(FlushPolicy::End);
+        output.write_byte(b'b');
+        output.write_byte(b'\n');
+        assert_eq!(*shared.0.borrow(), b"a\n");
+
+        output.set_policy(FlushPolicy::Byte);
+        assert_eq!(*shared.0.borrow(), b"a\nb\n");
+        output.write_byte(b'c');
+        assert_eq!(*shared.0.borrow(), b"a\nb\nc");
+    }
+
+    #[test]
+    fn raw_output_keeps_bytes() {
+        let shared = Shared::default();
+        let mut output = Output::raw(shared.clone());
+        output.set_policy(FlushPolicy::Byte);
+
+        output.write_byte(0xe9);
+        output.write_byte(b'\n');
+        assert_eq!(*shared.0.borrow(), [0xe9, b'\n']);
+
+        let shared = Shared::default();
+        let mut output = Output::from_writer(shared.clone());
+        output.set_policy(FlushPolicy::Byte);
+
+        output.write_byte(0xe9);
+        assert_eq!(*shared.0.borrow(), "é".as_bytes());
+    }
+
+    #[test]
+    fn numeric_output_separates_values() {
+        let shared = Shared::default();
+        let mut output = Output::raw(shared.clone());
+        output.set_policy(FlushPolicy::Byte);
+
+        output.set_mode(OutputMode::Decimal);
+        for byte in [0, 255, b'\n'] {
+            output.write_byte(byte);
+        }
+        output.set_mode(OutputMode::Hex);
+        for byte in [0, 255, b'\n'] {
+            output.write_byte(byte);
+        }
+        assert_eq!(*shared.0.borrow(), b"0 255 10\n00 ff 0a\n");
+    }
+
+    #[test]
+    fn utf8_output_waits_for_whole_characters() {
+        let shared = Shared::default();
+        let mut output = Output::raw(shared.clone());
+        output.set_policy(FlushPolicy::Byte);
+        output.set_mode(OutputMode::Utf8);
+
+        output.write_byte(0xc3);
+        assert_eq!(*shared.0.borrow(), b"");
+        output.write_byte(0xa9);
+        assert_eq!(*shared.0.borrow(), "é".as_bytes());
+
+        for byte in [0xff, b'a', 0xe2, 0x82, b'b'] {
+            output.write_byte(byte);
+        }
+        assert_eq!(*shared.0.borrow(), "é\u{fffd}a\u{fffd}b".as_bytes());
+    }
+}
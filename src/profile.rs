@@ -0,0 +1,315 @@
+use crate::{
+    syntax::{Expression, SyntaxTree},
+    token::{SourceOffset, Token},
+};
+use derive_more::{Display, Error};
+use std::{cmp::Reverse, collections::HashMap, fmt::Write, ops::RangeInclusive};
+
+/// Error while reading a [`Profile`] written by [`Profile::to_text`]
+#[derive(Debug, Display, Error, PartialEq, Eq, Clone, Hash)]
+pub enum ProfileError {
+    /// A line of the profile is not `offset count`
+    #[display(fmt = "line {} is not `offset count`", line)]
+    InvalidLine { line: usize },
+}
+
+/// Execution counts of every instruction of a program, by source offset
+///
+/// Loops are counted twice: at their `[` every time they are reached,
+/// and at their `]` every time their condition is checked again.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Profile {
+    counts: HashMap<SourceOffset, u64>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Counts one more execution of the instruction at `offset`
+    pub fn record(&mut self, offset: SourceOffset) {
+        *self.counts.entry(offset).or_default() += 1;
+    }
+
+    /// Times the instruction at `offset` was executed
+    pub fn count(&self, offset: SourceOffset) -> u64 {
+        self.counts.get(&offset).copied().unwrap_or_default()
+    }
+
+    /// Total amount of instructions executed
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Every executed instruction with its count, from the most
+    /// executed to the least. Ties are ordered by offset.
+    pub fn hot_spots(&self) -> Vec<(SourceOffset, u64)> {
+        let mut spots: Vec<_> = self.counts.iter().map(|(&o, &c)| (o, c)).collect();
+        spots.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        spots
+    }
+
+    /// Writes the counts as lines of `offset count`, ordered by offset,
+    /// to be read back by [`from_text`](Self::from_text)
+    pub fn to_text(&self) -> String {
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort_unstable();
+
+        let mut text = String::new();
+        for (offset, count) in counts {
+            let _ = writeln!(text, "{offset} {count}");
+        }
+
+        text
+    }
+
+    /// Reads the counts written by [`to_text`](Self::to_text)
+    ///
+    /// Empty lines and lines starting with `#` are ignored.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::Profile;
+    /// let profile = Profile::from_text("# a loop\n3 10\n8 9\n").unwrap();
+    ///
+    /// assert_eq!(profile.count(3), 10);
+    /// assert_eq!(profile.total(), 19);
+    /// assert_eq!(Profile::from_text(&profile.to_text()), Ok(profile));
+    /// ```
+    pub fn from_text(text: &str) -> Result<Self, ProfileError> {
+        let mut profile = Profile::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = ProfileError::InvalidLine { line: index + 1 };
+            let (offset, count) = line.split_once(' ').ok_or(invalid.clone())?;
+            let offset = offset.parse().map_err(|_| invalid.clone())?;
+            let count: u64 = count.trim().parse().map_err(|_| invalid)?;
+            *profile.counts.entry(offset).or_default() += count;
+        }
+
+        Ok(profile)
+    }
+}
+
+/// Amount of reads and writes made to every cell of the memory
+///
+/// Checking the condition of a loop and `.` read the current cell,
+/// `,` writes it, and `+` and `-` both read and write it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct MemoryHeatmap {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl MemoryHeatmap {
+    /// Heatmap for a memory of `size` cells
+    pub fn new(size: usize) -> Self {
+        MemoryHeatmap {
+            reads: vec![0; size],
+            writes: vec![0; size],
+        }
+    }
+
+    pub fn record_read(&mut self, cell: usize) {
+        self.reads[cell] += 1;
+    }
+
+    pub fn record_write(&mut self, cell: usize) {
+        self.writes[cell] += 1;
+    }
+
+    /// Reads made to each cell
+    pub fn reads(&self) -> &[u64] {
+        &self.reads
+    }
+
+    /// Writes made to each cell
+    pub fn writes(&self) -> &[u64] {
+        &self.writes
+    }
+
+    /// Reads and writes made to `cell`
+    pub fn accesses(&self, cell: usize) -> u64 {
+        self.reads[cell] + self.writes[cell]
+    }
+
+    /// Amount of cells in the memory
+    pub fn len(&self) -> usize {
+        self.reads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reads.is_empty()
+    }
+
+    /// Lowest and highest cells that were accessed at least once
+    ///
+    /// The memory wraps around, so this range only tells how much of it
+    /// a program needs if the pointer never crossed its edges.
+    pub fn touched_range(&self) -> Option<RangeInclusive<usize>> {
+        let touched = |cell: &usize| self.accesses(*cell) > 0;
+        let start = (0..self.len()).find(touched)?;
+        let end = (0..self.len()).rfind(touched)?;
+
+        Some(start..=end)
+    }
+}
+
+/// Summary of a run, from its [`Profile`] and [`MemoryHeatmap`]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ExecutionStats {
+    /// Instructions run of every kind, from the most run to the least,
+    /// counting loops at their `[` and `]` like [`Profile`] does
+    pub instructions: Vec<(Token, u64)>,
+    /// Times the bodies of loops ran
+    pub loop_iterations: u64,
+    /// Times `,` ran, reading a byte or finding the end of the input
+    pub reads: u64,
+    /// Bytes written by `.`
+    pub writes: u64,
+    /// Lowest and highest cells accessed, see
+    /// [`MemoryHeatmap::touched_range`]
+    pub touched: Option<RangeInclusive<usize>>,
+}
+
+impl ExecutionStats {
+    /// Sums up the run of `tree` counted by `profile`, along with the
+    /// cells accessed if `heatmap` tracked them
+    ///
+    /// Instructions are found by their source offset, so `tree` must still
+    /// be as it was fed to the interpreter that ran it.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{BrainFuckInterpreter, ExecutionStats, Token};
+    /// let mut bf = BrainFuckInterpreter::new();
+    /// bf.feed_string("++[->+<]>.").unwrap();
+    /// bf.start_profiling();
+    /// bf.execute();
+    ///
+    /// let stats = ExecutionStats::new(bf.syntax_tree(), bf.profile().unwrap(), None);
+    /// assert_eq!(stats.instructions[0], (Token::Increment, 4));
+    /// assert_eq!(stats.loop_iterations, 2);
+    /// assert_eq!(stats.writes, 1);
+    /// ```
+    pub fn new(tree: &SyntaxTree, profile: &Profile, heatmap: Option<&MemoryHeatmap>) -> Self {
+        let mut instructions = Vec::<(Token, u64)>::new();
+        let mut add = |token, count| match instructions.iter_mut().find(|(t, _)| *t == token) {
+            Some((_, total)) => *total += count,
+            None if count > 0 => instructions.push((token, count)),
+            None => (),
+        };
+
+        let mut loop_iterations = 0;
+        let mut blocks = vec![tree];
+        while let Some(block) = blocks.pop() {
+            for (expr, span) in block.iter().zip(block.spans()) {
+                add(expr.token(), profile.count(span.start));
+
+                // Blocks are counted again at their closing bracket
+                let end = profile.count(span.end - 1);
+                match expr {
+                    Expression::Loop(body) => {
+                        add(Token::LoopEnd, end);
+                        loop_iterations += end;
+                        blocks.push(body);
+                    }
+                    Expression::Procedure(body) => {
+                        add(Token::ProcedureEnd, end);
+                        blocks.push(body);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        instructions.sort_by_key(|&(_, count)| Reverse(count));
+
+        let count = |token| {
+            let found = instructions.iter().find(|(t, _)| *t == token);
+            found.map_or(0, |&(_, count)| count)
+        };
+        ExecutionStats {
+            reads: count(Token::ReadByte),
+            writes: count(Token::WriteByte),
+            instructions,
+            loop_iterations,
+            touched: heatmap.and_then(MemoryHeatmap::touched_range),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_sorts_hot_spots() {
+        let mut profile = Profile::new();
+        for offset in [4, 2, 4, 7, 2, 4] {
+            profile.record(offset);
+        }
+
+        assert_eq!(profile.hot_spots(), vec![(4, 3), (2, 2), (7, 1)]);
+        assert_eq!(profile.count(2), 2);
+        assert_eq!(profile.count(3), 0);
+        assert_eq!(profile.total(), 6);
+    }
+
+    #[test]
+    fn profile_reads_its_text() {
+        let mut profile = Profile::new();
+        for offset in [12, 3, 12] {
+            profile.record(offset);
+        }
+
+        assert_eq!(profile.to_text(), "3 1\n12 2\n");
+        assert_eq!(Profile::from_text(&profile.to_text()), Ok(profile));
+        assert_eq!(
+            Profile::from_text("1 2\n\n3\n"),
+            Err(ProfileError::InvalidLine { line: 3 })
+        );
+        assert_eq!(
+            Profile::from_text("1 -2"),
+            Err(ProfileError::InvalidLine { line: 1 })
+        );
+    }
+
+    #[test]
+    fn heatmap_touched_range() {
+        let mut heatmap = MemoryHeatmap::new(8);
+        assert_eq!(heatmap.touched_range(), None);
+
+        heatmap.record_read(5);
+        heatmap.record_write(2);
+        heatmap.record_write(2);
+
+        assert_eq!(heatmap.touched_range(), Some(2..=5));
+        assert_eq!(heatmap.accesses(2), 2);
+        assert_eq!(heatmap.reads()[5], 1);
+    }
+
+    #[test]
+    fn stats_sum_up_runs() {
+        let mut bf = crate::BrainFuckInterpreter::with_memory_size(8);
+        bf.feed_string(",[>+++[<.>-]<-]").unwrap();
+        bf.replay_input(vec![2]);
+        bf.start_profiling();
+        bf.start_memory_heatmap();
+        bf.execute();
+
+        let stats =
+            ExecutionStats::new(bf.syntax_tree(), bf.profile().unwrap(), bf.memory_heatmap());
+        assert!(stats.instructions.contains(&(Token::Increment, 6)));
+        assert!(stats.instructions.contains(&(Token::LoopEnd, 2 + 6)));
+        assert_eq!(stats.loop_iterations, 2 + 6);
+        assert_eq!((stats.reads, stats.writes), (1, 6));
+        assert_eq!(stats.touched, Some(4..=5));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    syntax::{BadExpressionError, SyntaxTree},
+    token::{Lexer, Span, Token},
+};
+
+/// Outcome of feeding code to a [`ParserSession`]
+#[derive(Debug, PartialEq, Clone, Hash)]
+pub enum Fed {
+    /// Every loop is closed, giving all the code fed since the last
+    /// complete tree
+    Complete(SyntaxTree),
+
+    /// Some loops are still open and wait for more code
+    Incomplete { open_loops: usize },
+}
+
+/// Parser that accepts code in pieces, like lines typed in a REPL
+///
+/// Loops may span several pieces: the session remembers them until they
+/// are closed, telling apart code that is incomplete from code that is
+/// invalid. Locations are counted as if all the pieces were one string.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{ParserSession, Fed};
+/// let mut session = ParserSession::new();
+///
+/// assert_eq!(session.feed("++[>+".bytes()), Ok(Fed::Incomplete { open_loops: 1 }));
+/// assert!(matches!(session.feed("<-]".bytes()), Ok(Fed::Complete(_))));
+/// assert!(session.feed("]".bytes()).is_err());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ParserSession {
+    pending: Vec<(Token, Span)>,
+    open_loops: usize,
+    position: Span,
+}
+
+impl ParserSession {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Feeds the next piece of code
+    ///
+    /// On an unmatched `]`, the pending code is discarded along with
+    /// this piece, and parsing starts over with the next one.
+    pub fn feed<T>(&mut self, bytes: T) -> Result<Fed, BadExpressionError>
+    where
+        T: IntoIterator<Item = u8>,
+    {
+        let mut lexer = Lexer::resume(bytes, self.position);
+
+        while let Some((token, span)) = lexer.next() {
+            match token {
+                Token::LoopStart => self.open_loops += 1,
+                Token::LoopEnd if self.open_loops == 0 => {
+                    lexer.by_ref().for_each(drop);
+                    self.position = lexer.position();
+                    self.pending.clear();
+
+                    return Err(BadExpressionError::LoopNotOpened { span });
+                }
+                Token::LoopEnd => self.open_loops -= 1,
+                _ => (),
+            }
+            self.pending.push((token, span));
+        }
+        self.position = lexer.position();
+
+        if self.open_loops > 0 {
+            return Ok(Fed::Incomplete {
+                open_loops: self.open_loops,
+            });
+        }
+
+        let tree = SyntaxTree::parse_spanned_tokens(self.pending.drain(..))?;
+        Ok(Fed::Complete(tree))
+    }
+
+    /// Whether there are loops waiting to be closed
+    pub fn is_incomplete(&self) -> bool {
+        self.open_loops > 0
+    }
+
+    /// Amount of loops waiting to be closed
+    pub fn open_loops(&self) -> usize {
+        self.open_loops
+    }
+
+    /// Discards the pending code, keeping the count of locations
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.open_loops = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::Expression as E;
+
+    #[test]
+    fn session_waits_for_open_loops() {
+        let mut session = ParserSession::new();
+
+        assert_eq!(
+            session.feed("+[\n".bytes()),
+            Ok(Fed::Incomplete { open_loops: 1 })
+        );
+        assert_eq!(
+            session.feed("-[\n".bytes()),
+            Ok(Fed::Incomplete { open_loops: 2 })
+        );
+        assert!(session.is_incomplete());
+
+        let Ok(Fed::Complete(tree)) = session.feed("]]\n".bytes()) else {
+            panic!("loops should be closed");
+        };
+        assert_eq!(
+            *tree,
+            vec![
+                E::Increment,
+                E::Loop(vec![E::Decrement, E::Loop(vec![].into())].into())
+            ]
+        );
+        assert_eq!(tree.span_at(&[1, 1]).map(|s| s.line), Some(2));
+    }
+
+    #[test]
+    fn session_rejects_unmatched_bracket() {
+        let mut session = ParserSession::new();
+        session.feed("[+".bytes()).unwrap();
+
+        let error = session.feed("]] +".bytes()).unwrap_err();
+        assert_eq!(error.span().start, 3);
+        assert!(!session.is_incomplete());
+
+        let Ok(Fed::Complete(tree)) = session.feed(">".bytes()) else {
+            panic!("code should be complete");
+        };
+        assert_eq!(*tree, vec![E::Forward]);
+        assert_eq!(tree.span(0).map(|s| s.start), Some(6));
+    }
+}
@@ -1,18 +1,63 @@
-use crate::token::Token;
-use derive_more::{Deref, DerefMut, Display, Error};
+use crate::{
+    diagnostic::{Diagnostic, Severity},
+    token::{locate_tokens, Lexer, Span, Token},
+};
+use derive_more::{Deref, DerefMut, Display, Error, From};
+use std::{
+    fmt::Write,
+    fs::File,
+    io::{self, BufReader, Read},
+    mem,
+    ops::Range,
+    path::Path,
+    slice,
+};
 
 /// Syntactic error while parsing Brainfuck code
+///
+/// It can be turned into a [`Diagnostic`](crate::Diagnostic) to show
+/// where in the source code it happened.
 #[derive(Debug, Display, Error, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum BadExpressionError {
+    /// The `[` at `span` has no matching `]`
     #[display(fmt = "'[' was never closed")]
-    LoopNotClosed,
+    LoopNotClosed { span: Span },
 
+    /// The `]` at `span` has no matching `[`
     #[display(fmt = "unmatched ']' symbol")]
-    LoopNotOpened,
+    LoopNotOpened { span: Span },
+
+    /// The `(` at `span` has no matching `)`
+    #[display(fmt = "'(' was never closed")]
+    ProcedureNotClosed { span: Span },
+
+    /// The `)` at `span` has no matching `(`
+    #[display(fmt = "unmatched ')' symbol")]
+    ProcedureNotOpened { span: Span },
+}
+
+impl BadExpressionError {
+    /// Location of the offending bracket
+    pub fn span(&self) -> Span {
+        match self {
+            BadExpressionError::LoopNotClosed { span }
+            | BadExpressionError::LoopNotOpened { span }
+            | BadExpressionError::ProcedureNotClosed { span }
+            | BadExpressionError::ProcedureNotOpened { span } => *span,
+        }
+    }
+}
+
+/// Error while reading and parsing Brainfuck code
+#[derive(Debug, Display, Error, From)]
+pub enum ReadError {
+    IO(io::Error),
+    Syntax(BadExpressionError),
 }
 
 /// This represents one unit of execution in the program
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expression {
     Forward,
     Backward,
@@ -20,12 +65,57 @@ pub enum Expression {
     Decrement,
     Input,
     Output,
-    Loop(Vec<Expression>),
+    /// Dumps memory for debugging, from the debug dump extension
+    DebugDump,
+    /// Repeats its body while the current cell is not zero
+    Loop(SyntaxTree),
+    /// Defines its body as the procedure numbered by the current cell,
+    /// from the pbrain dialect
+    Procedure(SyntaxTree),
+    /// Runs the procedure numbered by the current cell, from the pbrain
+    /// dialect
+    Call,
+    /// Ends the program, from Extended Type I
+    End,
+    /// Copies the current cell to the storage, from Extended Type I
+    Store,
+    /// Copies the storage to the current cell, from Extended Type I
+    Retrieve,
+    /// Shifts the bits of the current cell one to the left, from Extended
+    /// Type I
+    ShiftLeft,
+    /// Shifts the bits of the current cell one to the right, from Extended
+    /// Type I
+    ShiftRight,
+    /// Flips the bits of the current cell, from Extended Type I
+    Not,
+    /// Bitwise xor of the current cell with the storage, from Extended
+    /// Type I
+    Xor,
+    /// Bitwise and of the current cell with the storage, from Extended
+    /// Type I
+    And,
+    /// Bitwise or of the current cell with the storage, from Extended
+    /// Type I
+    Or,
 }
 
 /// This represents a tree of expressions for a valid BrainFuck script
-#[derive(Default, Debug, PartialEq, Clone, Hash, Deref, DerefMut)]
-pub struct SyntaxTree(Vec<Expression>);
+///
+/// When parsed from source code, the tree also remembers where each
+/// expression came from, loops covering both of their brackets. Those
+/// positions are not taken into account when comparing or hashing trees,
+/// nor when serializing them.
+#[derive(Default, Debug, Clone, Deref, DerefMut)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct SyntaxTree {
+    #[deref]
+    #[deref_mut]
+    expressions: Vec<Expression>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    spans: Vec<Span>,
+}
 
 impl SyntaxTree {
     pub fn new() -> Self {
@@ -33,57 +123,598 @@ impl SyntaxTree {
     }
 
     /// Parse a collection of tokens into a valid [`SyntaxTree`]
+    ///
+    /// Each token is assumed to come from one byte of the source, so the
+    /// tokens are located as if they were that source.
     pub fn parse_tokens<T>(tokens: T) -> Result<Self, BadExpressionError>
     where
         T: IntoIterator<Item = Token>,
     {
-        let mut tokens = tokens.into_iter();
-        let mut expressions = Vec::new();
+        Self::parse_spanned_tokens(locate_tokens(tokens))
+    }
+
+    /// Parse a collection of tokens, along with their location in the
+    /// source, into a valid [`SyntaxTree`]
+    ///
+    /// See [`Lexer`] to get located tokens from source code.
+    pub fn parse_spanned_tokens<T>(tokens: T) -> Result<Self, BadExpressionError>
+    where
+        T: IntoIterator<Item = (Token, Span)>,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parse").entered();
+
+        // Blocks still open, with the token and location opening them and
+        // the tree they are part of
+        let mut open: Vec<(Token, Span, SyntaxTree)> = Vec::new();
+        let mut tree = SyntaxTree::new();
+
+        for (token, mut span) in tokens {
+            let expr = match token {
+                Token::LoopStart | Token::ProcedureStart => {
+                    open.push((token, span, mem::take(&mut tree)));
+                    continue;
+                }
+                Token::LoopEnd | Token::ProcedureEnd => {
+                    let (opening, start, parent) = match open.pop() {
+                        Some(block) if closing(block.0) == token => block,
+                        _ => return Err(not_opened(token, span)),
+                    };
+                    let body = mem::replace(&mut tree, parent);
+                    span = Span {
+                        end: span.end,
+                        ..start
+                    };
+                    match opening {
+                        Token::ProcedureStart => Expression::Procedure(body),
+                        _ => Expression::Loop(body),
+                    }
+                }
+                token => match simple_expression(token) {
+                    Some(expr) => expr,
+                    None => continue,
+                },
+            };
+
+            tree.expressions.push(expr);
+            tree.spans.push(span);
+        }
+
+        if let Some((opening, start, _)) = open.pop() {
+            return Err(not_closed(opening, start));
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(expressions = tree.len(), "parsed");
+
+        Ok(tree)
+    }
+
+    /// Parse the code given by `reader` into a valid [`SyntaxTree`]
+    ///
+    /// The code is read through a small buffer and parsed as it comes,
+    /// so it is never loaded whole in memory.
+    pub fn parse_reader<R: Read>(reader: R) -> Result<Self, ReadError> {
+        let mut error = None;
+        let bytes = BufReader::new(reader)
+            .bytes()
+            .map_while(|byte| byte.map_err(|e| error = Some(e)).ok());
+        let tree = Self::parse_spanned_tokens(Lexer::new(bytes));
+
+        match error {
+            Some(error) => Err(error.into()),
+            None => Ok(tree?),
+        }
+    }
+
+    /// Reads and parses the code of the file at `path`
+    ///
+    /// The file is read as bytes, so comments don't need to be valid UTF-8.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ReadError> {
+        Self::parse_reader(File::open(path)?)
+    }
+
+    /// Parse a collection of located tokens into a valid [`SyntaxTree`],
+    /// reporting every unbalanced bracket if it is not valid
+    ///
+    /// Unlike [`parse_spanned_tokens`](Self::parse_spanned_tokens), parsing
+    /// doesn't stop at the first error. Errors are ordered by position.
+    pub fn parse_all_errors<T>(tokens: T) -> Result<Self, Vec<BadExpressionError>>
+    where
+        T: IntoIterator<Item = (Token, Span)>,
+    {
+        let tokens: Vec<_> = tokens.into_iter().collect();
+        let (stray, open_blocks) = check_balance(&tokens);
 
-        while let Some(expr) = SyntaxTree::parse_next_generic_token(&mut tokens) {
-            expressions.push(expr?);
+        if stray.is_empty() && open_blocks.is_empty() {
+            return Self::parse_spanned_tokens(tokens).map_err(|error| vec![error]);
         }
 
-        Ok(SyntaxTree(expressions))
+        let mut errors: Vec<_> = stray
+            .into_iter()
+            .map(|index| not_opened(tokens[index].0, tokens[index].1))
+            .chain(
+                open_blocks
+                    .into_iter()
+                    .map(|(token, span)| not_closed(token, span)),
+            )
+            .collect();
+        errors.sort_by_key(|error| error.span().start);
+
+        Err(errors)
     }
 
-    fn parse_next_generic_token<T>(tokens: &mut T) -> Option<Result<Expression, BadExpressionError>>
+    /// Parse a collection of located tokens into a [`SyntaxTree`],
+    /// repairing unbalanced brackets
+    ///
+    /// Closing brackets without an opening one are ignored, and blocks
+    /// still open at the end are closed there. Every repair is described
+    /// by a warning, ordered by position.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{Lexer, SyntaxTree};
+    /// let (tree, warnings) = SyntaxTree::parse_lenient(Lexer::new("+]-[>".bytes()));
+    ///
+    /// assert_eq!(tree.to_code(), "+-[>]");
+    /// assert_eq!(warnings.len(), 2);
+    /// ```
+    pub fn parse_lenient<T>(tokens: T) -> (Self, Vec<Diagnostic>)
     where
-        T: Iterator<Item = Token>,
+        T: IntoIterator<Item = (Token, Span)>,
     {
-        let token = match tokens.next()? {
-            Token::MoveRight => Ok(Expression::Forward),
-            Token::MoveLeft => Ok(Expression::Backward),
-            Token::Increment => Ok(Expression::Increment),
-            Token::Decrement => Ok(Expression::Decrement),
-            Token::ReadByte => Ok(Expression::Input),
-            Token::WriteByte => Ok(Expression::Output),
-            Token::LoopStart => SyntaxTree::parse_next_loop_token(tokens)?,
-            Token::LoopEnd => Err(BadExpressionError::LoopNotOpened),
-            Token::Comment(_) => SyntaxTree::parse_next_generic_token(tokens)?,
+        let mut tokens: Vec<_> = tokens.into_iter().collect();
+        let (stray, open_blocks) = check_balance(&tokens);
+
+        // Right after the last token, assuming it is on a single line
+        let end = tokens.last().map_or(Span::default(), |&(_, span)| Span {
+            start: span.end,
+            column: span.column + span.end - span.start,
+            ..span
+        });
+        let mut warnings = Vec::new();
+
+        for &index in stray.iter().rev() {
+            let (token, span) = tokens.remove(index);
+            let warning = Diagnostic::from(not_opened(token, span)).with_label("ignored");
+            warnings.push(Diagnostic {
+                severity: Severity::Warning,
+                ..warning
+            });
+        }
+
+        for (token, span) in open_blocks.into_iter().rev() {
+            let closed = Diagnostic::from(not_closed(token, span)).with_label("closed at the end");
+            warnings.push(Diagnostic {
+                severity: Severity::Warning,
+                ..closed
+            });
+            tokens.push((closing(token), end));
+        }
+        warnings.sort_by_key(|warning| warning.span.start);
+
+        let tree = Self::parse_spanned_tokens(tokens).expect("brackets are balanced");
+        (tree, warnings)
+    }
+
+    /// Get the location in the source of the expression at `index`
+    ///
+    /// Returns [`None`] if the tree was not parsed from source or has been
+    /// modified since.
+    pub fn span(&self, index: usize) -> Option<Span> {
+        self.span_at(&[index])
+    }
+
+    /// Get the location in the source of a nested expression
+    ///
+    /// `path` holds the index of the expression on every nesting level:
+    /// `[2, 0]` is the first expression in the body of the loop at index 2.
+    /// Returns [`None`] if the path leads nowhere, or if the tree was not
+    /// parsed from source or has been modified since.
+    pub fn span_at(&self, path: &[usize]) -> Option<Span> {
+        let (&last, parents) = path.split_last()?;
+        let mut tree = self;
+
+        for &index in parents {
+            match tree.expressions.get(index)? {
+                Expression::Loop(body) | Expression::Procedure(body) => tree = body,
+                _ => return None,
+            }
+        }
+
+        tree.spans().get(last).copied()
+    }
+
+    /// Get the block nested in this tree at `path`, which holds the index
+    /// of a loop or procedure on every nesting level
+    pub(crate) fn block_at_mut(&mut self, path: &[usize]) -> Option<&mut SyntaxTree> {
+        let Some((&first, rest)) = path.split_first() else {
+            return Some(self);
         };
 
-        Some(token)
+        match self.expressions.get_mut(first)? {
+            Expression::Loop(body) | Expression::Procedure(body) => body.block_at_mut(rest),
+            _ => None,
+        }
+    }
+
+    /// Adds `expr` at the end of the tree, located at `span` if known
+    pub(crate) fn push_located(&mut self, expr: Expression, span: Option<Span>) {
+        match span {
+            Some(span) if self.spans.len() == self.expressions.len() => self.spans.push(span),
+            _ => self.spans.clear(),
+        }
+        self.expressions.push(expr);
+    }
+
+    /// Removes the expressions in `range` along with their locations
+    pub(crate) fn remove_range(&mut self, range: Range<usize>) {
+        if self.spans.len() == self.expressions.len() {
+            self.spans.drain(range.clone());
+        }
+        self.expressions.drain(range);
+    }
+
+    /// Moves all the expressions of `other` to the end of this tree
+    pub fn append(&mut self, other: &mut SyntaxTree) {
+        if self.spans().len() == self.expressions.len() {
+            let other_spans = other.spans();
+            if other_spans.len() == other.expressions.len() {
+                self.spans.extend_from_slice(other_spans);
+            }
+        }
+
+        self.expressions.append(&mut other.expressions);
+        other.spans.clear();
+    }
+
+    /// Transforms every expression of the tree with `f`, removing the ones
+    /// it returns [`None`] for
+    ///
+    /// Loop bodies are transformed first, so `f` receives loops with their
+    /// body already transformed. New expressions keep the location of the
+    /// expressions they replace.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{Expression, SyntaxTree};
+    /// let tree: SyntaxTree = ",[->+<,]".parse().unwrap();
+    /// let tree = tree.map(|expr| match expr {
+    ///     Expression::Input => None,
+    ///     Expression::Forward => Some(Expression::Backward),
+    ///     Expression::Backward => Some(Expression::Forward),
+    ///     expr => Some(expr),
+    /// });
+    ///
+    /// assert_eq!(tree.to_code(), "[-<+>]");
+    /// ```
+    pub fn map<F>(self, mut f: F) -> Self
+    where
+        F: FnMut(Expression) -> Option<Expression>,
+    {
+        self.map_with(&mut f)
     }
 
-    fn parse_next_loop_token<T>(tokens: &mut T) -> Option<Result<Expression, BadExpressionError>>
+    fn map_with<F>(mut self, f: &mut F) -> Self
     where
-        T: Iterator<Item = Token>,
+        F: FnMut(Expression) -> Option<Expression>,
     {
-        use BadExpressionError as Error;
-        use Expression as E;
-
-        let mut expressions = Vec::new();
-        loop {
-            let expr = match SyntaxTree::parse_next_generic_token(tokens) {
-                Some(expr) => expr,
-                None => return Some(Err(Error::LoopNotClosed)),
+        let aligned = self.spans.len() == self.expressions.len();
+        let mut spans = mem::take(&mut self.spans).into_iter();
+        let mut tree = SyntaxTree::new();
+
+        for expr in mem::take(&mut self.expressions) {
+            let span = spans.next();
+            let expr = match expr {
+                Expression::Loop(body) => Expression::Loop(body.map_with(f)),
+                Expression::Procedure(body) => Expression::Procedure(body.map_with(f)),
+                expr => expr,
             };
 
+            if let Some(expr) = f(expr) {
+                tree.expressions.push(expr);
+                tree.spans.extend(span.filter(|_| aligned));
+            }
+        }
+
+        tree
+    }
+
+    /// Keeps only the expressions `f` returns `true` for, at every
+    /// nesting level
+    ///
+    /// Like with [`map`](Self::map), loops are given to `f` with their body
+    /// already filtered.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&Expression) -> bool,
+    {
+        let tree = mem::take(self);
+        *self = tree.map(|expr| f(&expr).then_some(expr));
+    }
+
+    /// Writes this tree as JSON
+    ///
+    /// Simple instructions are strings, like `"Increment"`, and loops are
+    /// objects holding their body: `{"Loop":["Decrement"]}`.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("syntax trees are valid JSON")
+    }
+
+    /// Reads a tree written by [`to_json`](Self::to_json)
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Writes this tree as BrainFuck code, without any comment
+    pub fn to_code(&self) -> String {
+        self.to_string()
+    }
+
+    /// Lowers this tree back into tokens, the inverse of
+    /// [`parse_tokens`](Self::parse_tokens)
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::{Dialect, SyntaxTree};
+    /// let tree: SyntaxTree = "+[-]".parse().unwrap();
+    /// let ook = Dialect::named("ook").unwrap();
+    /// let code: Vec<_> = tree
+    ///     .to_tokens()
+    ///     .into_iter()
+    ///     .map(|token| String::from_utf8_lossy(ook.pattern(token).unwrap()).into_owned())
+    ///     .collect();
+    ///
+    /// assert_eq!(code.join(" "), "Ook. Ook. Ook! Ook? Ook! Ook! Ook? Ook!");
+    /// ```
+    pub fn to_tokens(&self) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        // Blocks being lowered, with the index of their next expression and
+        // the token closing them
+        let mut blocks = vec![(&self.expressions[..], 0, None)];
+
+        while let Some((block, index, closing)) = blocks.last_mut() {
+            let Some(expr) = block.get(*index) else {
+                tokens.extend(*closing);
+                blocks.pop();
+                continue;
+            };
+            *index += 1;
+
+            tokens.push(expr.token());
             match expr {
-                Ok(expr) => expressions.push(expr),
-                Err(Error::LoopNotOpened) => return Some(Ok(E::Loop(expressions))),
-                Err(err) => return Some(Err(err)),
+                Expression::Loop(body) => blocks.push((body, 0, Some(Token::LoopEnd))),
+                Expression::Procedure(body) => blocks.push((body, 0, Some(Token::ProcedureEnd))),
+                _ => (),
+            }
+        }
+
+        tokens
+    }
+
+    /// Whether a procedure is defined anywhere in this tree
+    pub(crate) fn has_procedures(&self) -> bool {
+        let mut blocks = vec![&self.expressions[..]];
+        while let Some(block) = blocks.pop() {
+            for expr in block {
+                match expr {
+                    Expression::Procedure(_) => return true,
+                    Expression::Loop(body) => blocks.push(body),
+                    _ => (),
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Source locations, only if they still match the expressions
+    pub(crate) fn spans(&self) -> &[Span] {
+        if self.spans.len() == self.expressions.len() {
+            &self.spans
+        } else {
+            &[]
+        }
+    }
+}
+
+/// Finds the indices of the closing brackets without an opening one, and
+/// the blocks left open at the end
+fn check_balance(tokens: &[(Token, Span)]) -> (Vec<usize>, Vec<(Token, Span)>) {
+    let mut open_blocks = Vec::new();
+    let mut stray = Vec::new();
+
+    for (index, &(token, span)) in tokens.iter().enumerate() {
+        match token {
+            Token::LoopStart | Token::ProcedureStart => open_blocks.push((token, span)),
+            Token::LoopEnd | Token::ProcedureEnd => {
+                match open_blocks.last() {
+                    Some(&(start, _)) if closing(start) == token => {
+                        open_blocks.pop();
+                    }
+                    _ => stray.push(index),
+                };
+            }
+            _ => (),
+        }
+    }
+
+    (stray, open_blocks)
+}
+
+/// Expression written by `token` alone, unless it opens or closes a block
+/// or is a comment
+pub(crate) fn simple_expression(token: Token) -> Option<Expression> {
+    let expr = match token {
+        Token::MoveRight => Expression::Forward,
+        Token::MoveLeft => Expression::Backward,
+        Token::Increment => Expression::Increment,
+        Token::Decrement => Expression::Decrement,
+        Token::ReadByte => Expression::Input,
+        Token::WriteByte => Expression::Output,
+        Token::DebugDump => Expression::DebugDump,
+        Token::Call => Expression::Call,
+        Token::End => Expression::End,
+        Token::Store => Expression::Store,
+        Token::Retrieve => Expression::Retrieve,
+        Token::ShiftLeft => Expression::ShiftLeft,
+        Token::ShiftRight => Expression::ShiftRight,
+        Token::Not => Expression::Not,
+        Token::Xor => Expression::Xor,
+        Token::And => Expression::And,
+        Token::Or => Expression::Or,
+        Token::LoopStart
+        | Token::LoopEnd
+        | Token::ProcedureStart
+        | Token::ProcedureEnd
+        | Token::Comment(_) => return None,
+    };
+
+    Some(expr)
+}
+
+/// Token closing the block opened by `opening`
+pub(crate) fn closing(opening: Token) -> Token {
+    match opening {
+        Token::ProcedureStart => Token::ProcedureEnd,
+        _ => Token::LoopEnd,
+    }
+}
+
+pub(crate) fn not_closed(opening: Token, span: Span) -> BadExpressionError {
+    match opening {
+        Token::ProcedureStart => BadExpressionError::ProcedureNotClosed { span },
+        _ => BadExpressionError::LoopNotClosed { span },
+    }
+}
+
+pub(crate) fn not_opened(closing: Token, span: Span) -> BadExpressionError {
+    match closing {
+        Token::ProcedureEnd => BadExpressionError::ProcedureNotOpened { span },
+        _ => BadExpressionError::LoopNotOpened { span },
+    }
+}
+
+impl Expression {
+    /// Writes this expression as BrainFuck code
+    pub fn to_code(&self) -> String {
+        self.to_string()
+    }
+
+    /// Token written for this expression, the opening one for blocks
+    pub(crate) fn token(&self) -> Token {
+        match self {
+            Expression::Forward => Token::MoveRight,
+            Expression::Backward => Token::MoveLeft,
+            Expression::Increment => Token::Increment,
+            Expression::Decrement => Token::Decrement,
+            Expression::Input => Token::ReadByte,
+            Expression::Output => Token::WriteByte,
+            Expression::DebugDump => Token::DebugDump,
+            Expression::Loop(_) => Token::LoopStart,
+            Expression::Procedure(_) => Token::ProcedureStart,
+            Expression::Call => Token::Call,
+            Expression::End => Token::End,
+            Expression::Store => Token::Store,
+            Expression::Retrieve => Token::Retrieve,
+            Expression::ShiftLeft => Token::ShiftLeft,
+            Expression::ShiftRight => Token::ShiftRight,
+            Expression::Not => Token::Not,
+            Expression::Xor => Token::Xor,
+            Expression::And => Token::And,
+            Expression::Or => Token::Or,
+        }
+    }
+}
+
+impl std::fmt::Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expression::Forward => f.write_str(">"),
+            Expression::Backward => f.write_str("<"),
+            Expression::Increment => f.write_str("+"),
+            Expression::Decrement => f.write_str("-"),
+            Expression::Input => f.write_str(","),
+            Expression::Output => f.write_str("."),
+            Expression::DebugDump => f.write_str("#"),
+            Expression::Loop(_) | Expression::Procedure(_) => write_code(f, slice::from_ref(self)),
+            Expression::Call => f.write_str(":"),
+            Expression::End => f.write_str("@"),
+            Expression::Store => f.write_str("$"),
+            Expression::Retrieve => f.write_str("!"),
+            Expression::ShiftLeft => f.write_str("{"),
+            Expression::ShiftRight => f.write_str("}"),
+            Expression::Not => f.write_str("~"),
+            Expression::Xor => f.write_str("^"),
+            Expression::And => f.write_str("&"),
+            Expression::Or => f.write_str("|"),
+        }
+    }
+}
+
+impl std::fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_code(f, &self.expressions)
+    }
+}
+
+/// Writes `block` as code, going through nested bodies one after another
+/// so deeply nested trees don't overflow the stack
+fn write_code(f: &mut std::fmt::Formatter<'_>, block: &[Expression]) -> std::fmt::Result {
+    // Blocks being written, with the index of their next expression and
+    // the bracket closing them
+    let mut blocks = vec![(block, 0, None)];
+
+    while let Some((block, index, closing)) = blocks.last_mut() {
+        let Some(expr) = block.get(*index) else {
+            if let Some(closing) = *closing {
+                f.write_char(closing)?;
+            }
+            blocks.pop();
+            continue;
+        };
+        *index += 1;
+
+        match expr {
+            Expression::Loop(body) => {
+                f.write_char('[')?;
+                blocks.push((body, 0, Some(']')));
+            }
+            Expression::Procedure(body) => {
+                f.write_char('(')?;
+                blocks.push((body, 0, Some(')')));
+            }
+            expr => write!(f, "{expr}")?,
+        }
+    }
+
+    Ok(())
+}
+
+impl PartialEq for SyntaxTree {
+    fn eq(&self, other: &Self) -> bool {
+        self.expressions == other.expressions
+    }
+}
+
+impl Eq for SyntaxTree {}
+
+impl std::hash::Hash for SyntaxTree {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.expressions.hash(state);
+    }
+}
+
+impl Drop for SyntaxTree {
+    /// Takes nested bodies apart one after another, so deeply nested trees
+    /// don't overflow the stack when dropped
+    fn drop(&mut self) {
+        let mut pending = mem::take(&mut self.expressions);
+        while let Some(expr) = pending.pop() {
+            if let Expression::Loop(mut body) | Expression::Procedure(mut body) = expr {
+                pending.append(&mut body.expressions);
             }
         }
     }
@@ -93,7 +724,7 @@ impl std::str::FromStr for SyntaxTree {
     type Err = BadExpressionError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Self::parse_tokens(s.bytes().map(Token::from))
+        Self::parse_spanned_tokens(Lexer::new(s.bytes()))
     }
 }
 
@@ -101,36 +732,139 @@ impl IntoIterator for SyntaxTree {
     type Item = Expression;
     type IntoIter = <Vec<Expression> as IntoIterator>::IntoIter;
 
+    fn into_iter(mut self) -> Self::IntoIter {
+        mem::take(&mut self.expressions).into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a SyntaxTree {
+    type Item = &'a Expression;
+    type IntoIter = std::slice::Iter<'a, Expression>;
+
     fn into_iter(self) -> Self::IntoIter {
-        self.0.into_iter()
+        self.expressions.iter()
+    }
+}
+
+impl FromIterator<Expression> for SyntaxTree {
+    fn from_iter<T: IntoIterator<Item = Expression>>(iter: T) -> Self {
+        SyntaxTree {
+            expressions: iter.into_iter().collect(),
+            spans: Vec::new(),
+        }
+    }
+}
+
+impl From<Vec<Expression>> for SyntaxTree {
+    fn from(expressions: Vec<Expression>) -> Self {
+        SyntaxTree {
+            expressions,
+            spans: Vec::new(),
+        }
+    }
+}
+
+impl Extend<Expression> for SyntaxTree {
+    /// Appends expressions without a location
+    fn extend<T: IntoIterator<Item = Expression>>(&mut self, iter: T) {
+        self.expressions.extend(iter)
+    }
+}
+
+impl TryFrom<Vec<Token>> for SyntaxTree {
+    type Error = BadExpressionError;
+
+    fn try_from(tokens: Vec<Token>) -> Result<Self, Self::Error> {
+        Self::parse_tokens(tokens)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{BadExpressionError as Bad, Expression as E, SyntaxTree as ET};
+    use super::{
+        BadExpressionError as Bad, Expression as E, Lexer, ReadError, Span, SyntaxTree as ET, Token,
+    };
+    use std::io::Read;
+
+    fn span(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn parse_deep_nesting() {
+        let depth = 100_000;
+        let code = "[+".repeat(depth) + &"]".repeat(depth);
+        let mut tree: ET = code.parse().unwrap();
+
+        for _ in 0..depth {
+            tree = match tree.pop() {
+                Some(E::Loop(body)) => body,
+                expr => panic!("expected a loop, found {expr:?}"),
+            };
+            assert_eq!(tree.first(), Some(&E::Increment));
+        }
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn to_code_deep_nesting() {
+        let depth = 100_000;
+        let code = "[+".repeat(depth) + &"]-".repeat(depth);
+        let tree: ET = code.parse().unwrap();
+
+        assert_eq!(tree.to_code(), code);
+        assert_eq!(tree.to_tokens().len(), code.len());
+    }
+
+    #[test]
+    fn parse_many_comments() {
+        let code = "a".repeat(100_000) + "+" + &"b".repeat(100_000) + "[";
+
+        assert_eq!(
+            code.parse::<ET>(),
+            Err(Bad::LoopNotClosed {
+                span: span(200_001, 200_002, 1, 200_002)
+            })
+        );
+    }
+
+    #[test]
+    fn parse_string_skips_shebang() {
+        let tree: ET = "#!/usr/bin/env -S bf --eof=zero\n+.".parse().unwrap();
+
+        assert_eq!(*tree, [E::Increment, E::Output]);
+        assert_eq!(tree.span(0).map(|span| span.line), Some(2));
+    }
 
     #[test]
     fn parse_valid_string() {
         let tree: ET = "+++>[<--->]<.".parse().unwrap();
 
         assert_eq!(
-            tree,
-            ET(vec![
+            *tree,
+            vec![
                 E::Increment,
                 E::Increment,
                 E::Increment,
                 E::Forward,
-                E::Loop(vec![
-                    E::Backward,
-                    E::Decrement,
-                    E::Decrement,
-                    E::Decrement,
-                    E::Forward,
-                ]),
+                E::Loop(
+                    vec![
+                        E::Backward,
+                        E::Decrement,
+                        E::Decrement,
+                        E::Decrement,
+                        E::Forward,
+                    ]
+                    .into()
+                ),
                 E::Backward,
                 E::Output
-            ])
+            ]
         );
     }
 
@@ -138,13 +872,311 @@ mod tests {
     fn parse_error_loop_not_opened() {
         let tree_error: Result<ET, Bad> = "+++><--->]<.".parse();
 
-        assert_eq!(tree_error, Err(Bad::LoopNotOpened));
+        assert_eq!(
+            tree_error,
+            Err(Bad::LoopNotOpened {
+                span: span(9, 10, 1, 10)
+            })
+        );
     }
 
     #[test]
     fn parse_error_loop_not_closed() {
-        let tree_error: Result<ET, Bad> = "+++>[<---><.".parse();
+        let tree_error: Result<ET, Bad> = "+++>[\n[<--]-><.".parse();
+
+        assert_eq!(
+            tree_error,
+            Err(Bad::LoopNotClosed {
+                span: span(4, 5, 1, 5)
+            })
+        );
+    }
+
+    #[test]
+    fn parse_reports_all_errors() {
+        let tokens = Lexer::new("]+[[-]\n]]>[".bytes());
+        let errors = ET::parse_all_errors(tokens).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                Bad::LoopNotOpened {
+                    span: span(0, 1, 1, 1)
+                },
+                Bad::LoopNotOpened {
+                    span: span(8, 9, 2, 2)
+                },
+                Bad::LoopNotClosed {
+                    span: span(10, 11, 2, 4)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_procedures() {
+        use crate::dialect::Dialect;
+
+        let lexer = |code: &'static str| Lexer::new(code.bytes()).dialect(Dialect::pbrain());
+        let tree = ET::parse_spanned_tokens(lexer("(+[-)]:")).unwrap_err();
+        assert_eq!(
+            tree,
+            Bad::ProcedureNotOpened {
+                span: span(4, 5, 1, 5)
+            }
+        );
+
+        let tree = ET::parse_spanned_tokens(lexer("+(-[>]):")).unwrap();
+        assert_eq!(
+            *tree,
+            vec![
+                E::Increment,
+                E::Procedure(vec![E::Decrement, E::Loop(vec![E::Forward].into())].into()),
+                E::Call
+            ]
+        );
+        assert_eq!(tree.span(1), Some(span(1, 7, 1, 2)));
+        assert_eq!(tree.to_code(), "+(-[>]):");
+
+        let errors = ET::parse_all_errors(lexer("(]([)")).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                Bad::ProcedureNotClosed {
+                    span: span(0, 1, 1, 1)
+                },
+                Bad::LoopNotOpened {
+                    span: span(1, 2, 1, 2)
+                },
+                Bad::ProcedureNotClosed {
+                    span: span(2, 3, 1, 3)
+                },
+                Bad::LoopNotClosed {
+                    span: span(3, 4, 1, 4)
+                },
+                Bad::ProcedureNotOpened {
+                    span: span(4, 5, 1, 5)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_lenient_repairs_brackets() {
+        use crate::diagnostic::Severity;
+
+        let (tree, warnings) = ET::parse_lenient(Lexer::new("]+[[-]\n]]>[".bytes()));
+        let warnings: Vec<_> = warnings
+            .into_iter()
+            .map(|w| (w.severity, w.span.start, w.label.unwrap()))
+            .collect();
+
+        assert_eq!(tree.to_code(), "+[[-]]>[]");
+        assert_eq!(tree.span(3), Some(span(10, 11, 2, 4)));
+        assert_eq!(
+            warnings,
+            [
+                (Severity::Warning, 0, String::from("ignored")),
+                (Severity::Warning, 8, String::from("ignored")),
+                (Severity::Warning, 10, String::from("closed at the end")),
+            ]
+        );
+
+        let (tree, warnings) = ET::parse_lenient(Lexer::new("+[-]".bytes()));
+        assert_eq!((tree.to_code(), warnings), (String::from("+[-]"), vec![]));
+    }
+
+    #[test]
+    fn parse_all_errors_of_valid_code() {
+        let tokens = Lexer::new("+[-[+]]".bytes());
+
+        assert_eq!(
+            ET::parse_all_errors(tokens),
+            "+[-[+]]".parse().map_err(|_| vec![])
+        );
+    }
+
+    #[test]
+    fn parse_from_reader() {
+        let tree = ET::parse_reader("+[-]>.".as_bytes()).unwrap();
+        assert_eq!(tree, "+[-]>.".parse().unwrap());
+
+        let error = ET::parse_reader("+[->".as_bytes()).unwrap_err();
+        assert!(matches!(
+            error,
+            ReadError::Syntax(Bad::LoopNotClosed { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_from_file() {
+        let path = std::env::temp_dir().join(format!("bf-syntax-{}.bf", std::process::id()));
+        std::fs::write(&path, b"\xff comment\n+[-]").unwrap();
+        let tree = ET::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            tree.unwrap().span(0).map(|s| (s.start, s.line)),
+            Some((10, 2))
+        );
+        assert!(matches!(ET::from_file(&path), Err(ReadError::IO(_))));
+    }
+
+    #[test]
+    fn parse_from_failing_reader() {
+        struct Failing;
+        impl std::io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+        }
+
+        let error = ET::parse_reader(b"[-]".chain(Failing)).unwrap_err();
+        assert!(matches!(error, ReadError::IO(e) if e.kind() == std::io::ErrorKind::BrokenPipe));
+    }
+
+    #[test]
+    fn code_round_trips() {
+        let code = "+++[>-[<+>,]<-]>.";
+        let tree: ET = format!("comment {code} comment").parse().unwrap();
+
+        assert_eq!(tree.to_code(), code);
+        assert_eq!(tree.to_code().parse::<ET>().unwrap(), tree);
+        assert_eq!(
+            E::Loop(vec![E::Forward, E::Loop(vec![].into())].into()).to_code(),
+            "[>[]]"
+        );
+    }
+
+    #[test]
+    fn collect_from_iterators() {
+        let tree: ET = "+>,[-]".parse().unwrap();
+        let mut collected: ET = tree
+            .clone()
+            .into_iter()
+            .filter(|e| *e != E::Input)
+            .collect();
+        collected.extend([E::Output]);
+
+        assert_eq!(collected.to_code(), "+>[-].");
+        assert_eq!(collected.span(0), None);
+    }
+
+    #[test]
+    fn try_from_tokens() {
+        let tokens: Vec<Token> = "+[->+<]".bytes().map(Token::from).collect();
+        assert_eq!(ET::try_from(tokens), "+[->+<]".parse());
+
+        let tokens = vec![Token::LoopStart, Token::Increment];
+        assert!(matches!(
+            ET::try_from(tokens),
+            Err(Bad::LoopNotClosed { span }) if span.start == 0
+        ));
+    }
+
+    #[test]
+    fn to_tokens_round_trips() {
+        let tree: ET = "+[->[.],<]".parse().unwrap();
+        let tokens = tree.to_tokens();
+
+        assert_eq!(
+            tokens[..3],
+            [Token::Increment, Token::LoopStart, Token::Decrement]
+        );
+        assert_eq!(ET::parse_tokens(tokens), Ok(tree));
+
+        let tree = ET::from(vec![E::Procedure(vec![E::Call].into()), E::End]);
+        assert_eq!(ET::parse_tokens(tree.to_tokens()), Ok(tree));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let tree: ET = "+[->.<]".parse().unwrap();
+        let json = tree.to_json();
+
+        assert_eq!(
+            json,
+            r#"["Increment",{"Loop":["Decrement","Forward","Output","Backward"]}]"#
+        );
+        assert_eq!(ET::from_json(&json).unwrap(), tree);
+        assert!(ET::from_json(r#"["Jump"]"#).is_err());
+    }
+
+    #[test]
+    fn loop_bodies_are_trees() {
+        let tree: ET = "+\n[>.<-]".parse().unwrap();
+        let E::Loop(body) = &tree[1] else {
+            panic!("expected a loop");
+        };
+
+        assert_eq!(body.to_code(), ">.<-");
+        assert_eq!(body.span(1), Some(span(4, 5, 2, 3)));
+        assert_eq!(body.span(1), tree.span_at(&[1, 1]));
+    }
+
+    #[test]
+    fn map_keeps_source_positions() {
+        let tree: ET = "+[>, [-]]".parse().unwrap();
+        let tree = tree.map(|expr| match expr {
+            E::Input => None,
+            E::Decrement => Some(E::Increment),
+            expr => Some(expr),
+        });
+
+        assert_eq!(tree.to_code(), "+[>[+]]");
+        assert_eq!(tree.span_at(&[1, 1]).map(|s| s.start), Some(5));
+        assert_eq!(tree.span_at(&[1, 1, 0]).map(|s| s.start), Some(6));
+    }
+
+    #[test]
+    fn map_drops_positions_of_replaced_loop_bodies() {
+        let tree: ET = "[-]+".parse().unwrap();
+        let tree = tree.map(|expr| match expr {
+            E::Loop(_) => Some(E::Loop(vec![E::Forward, E::Backward].into())),
+            expr => Some(expr),
+        });
+
+        assert_eq!(tree.to_code(), "[><]+");
+        assert_eq!(tree.span(0).map(|s| s.start), Some(0));
+        assert_eq!(tree.span_at(&[0, 0]), None);
+    }
+
+    #[test]
+    fn retain_filters_nested_loops() {
+        let mut tree: ET = ",[,.[,]]".parse().unwrap();
+        tree.retain(|expr| *expr != E::Input && *expr != E::Loop(vec![].into()));
+
+        assert_eq!(tree.to_code(), "[.]");
+        assert_eq!(tree.span_at(&[0, 0]).map(|s| s.start), Some(3));
+    }
+
+    #[test]
+    fn parse_keeps_source_positions() {
+        let tree: ET = "+ comment [-]\n.".parse().unwrap();
+
+        assert_eq!(tree.span(0), Some(span(0, 1, 1, 1)));
+        assert_eq!(tree.span(1), Some(span(10, 13, 1, 11)));
+        assert_eq!(tree.span(2), Some(span(14, 15, 2, 1)));
+        assert_eq!(tree.span(3), None);
+    }
+
+    #[test]
+    fn parse_keeps_nested_source_positions() {
+        let tree: ET = "+[\n >[-]\n]".parse().unwrap();
+
+        assert_eq!(tree.span_at(&[1]), Some(span(1, 10, 1, 2)));
+        assert_eq!(tree.span_at(&[1, 0]), Some(span(4, 5, 2, 2)));
+        assert_eq!(tree.span_at(&[1, 1, 0]), Some(span(6, 7, 2, 4)));
+        assert_eq!(tree.span_at(&[1, 2]), None);
+        assert_eq!(tree.span_at(&[]), None);
+    }
+
+    #[test]
+    fn positions_are_ignored_by_equality() {
+        let a: ET = "+[-]".parse().unwrap();
+        let b: ET = "  +  [ - ]  ".parse().unwrap();
 
-        assert_eq!(tree_error, Err(Bad::LoopNotClosed));
+        assert_eq!(a, b);
     }
 }
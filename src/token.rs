@@ -1,6 +1,37 @@
+use crate::dialect::Dialect;
 use derive_more::Display;
+use std::collections::VecDeque;
+
+/// Byte offset into the original BrainFuck source
+pub type SourceOffset = usize;
+
+/// Location of a piece of the original source
+///
+/// `end` is exclusive, while `line` and `column` are where the piece
+/// starts, counting from 1. Columns are counted in bytes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Span {
+    pub start: SourceOffset,
+    pub end: SourceOffset,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::default::Default for Span {
+    fn default() -> Self {
+        Span {
+            start: 0,
+            end: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
 
 /// Represents possible tokens found in a BrainFuck script
+///
+/// Converting from a byte uses the standard mapping of BrainFuck. Other
+/// mappings can be defined with a [`Dialect`].
 #[derive(Debug, Display, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum Token {
     #[display(fmt = ">")]
@@ -27,6 +58,58 @@ pub enum Token {
     #[display(fmt = "]")]
     LoopEnd,
 
+    /// `#`, only recognized when the debug dump extension is enabled
+    #[display(fmt = "#")]
+    DebugDump,
+
+    /// `(` of the pbrain dialect
+    #[display(fmt = "(")]
+    ProcedureStart,
+
+    /// `)` of the pbrain dialect
+    #[display(fmt = ")")]
+    ProcedureEnd,
+
+    /// `:` of the pbrain dialect
+    #[display(fmt = ":")]
+    Call,
+
+    /// `@` of Extended Type I, everything after it is data
+    #[display(fmt = "@")]
+    End,
+
+    /// `$` of Extended Type I
+    #[display(fmt = "$")]
+    Store,
+
+    /// `!` of Extended Type I
+    #[display(fmt = "!")]
+    Retrieve,
+
+    /// `{` of Extended Type I
+    #[display(fmt = "{{")]
+    ShiftLeft,
+
+    /// `}` of Extended Type I
+    #[display(fmt = "}}")]
+    ShiftRight,
+
+    /// `~` of Extended Type I
+    #[display(fmt = "~")]
+    Not,
+
+    /// `^` of Extended Type I
+    #[display(fmt = "^")]
+    Xor,
+
+    /// `&` of Extended Type I
+    #[display(fmt = "&")]
+    And,
+
+    /// `|` of Extended Type I
+    #[display(fmt = "|")]
+    Or,
+
     #[display(fmt = "{}", "*_0 as char")]
     Comment(u8),
 }
@@ -53,6 +136,193 @@ impl From<char> for Token {
     }
 }
 
+impl From<Token> for u8 {
+    fn from(value: Token) -> Self {
+        match value {
+            Token::MoveRight => b'>',
+            Token::MoveLeft => b'<',
+            Token::Increment => b'+',
+            Token::Decrement => b'-',
+            Token::ReadByte => b',',
+            Token::WriteByte => b'.',
+            Token::LoopStart => b'[',
+            Token::LoopEnd => b']',
+            Token::DebugDump => b'#',
+            Token::ProcedureStart => b'(',
+            Token::ProcedureEnd => b')',
+            Token::Call => b':',
+            Token::End => b'@',
+            Token::Store => b'$',
+            Token::Retrieve => b'!',
+            Token::ShiftLeft => b'{',
+            Token::ShiftRight => b'}',
+            Token::Not => b'~',
+            Token::Xor => b'^',
+            Token::And => b'&',
+            Token::Or => b'|',
+            Token::Comment(byte) => byte,
+        }
+    }
+}
+
+/// Turns a stream of bytes into tokens, along with their location
+///
+/// If the bytes start with `#!`, that first line is a shebang and is made
+/// only of comments, so scripts can be run directly on Unix. After a
+/// [`Token::End`], the remaining bytes are data and are lexed as comments.
+#[derive(Debug, Clone)]
+pub struct Lexer<I> {
+    bytes: I,
+    /// Bytes taken from `bytes` to look ahead, not lexed yet
+    lookahead: VecDeque<u8>,
+    position: Span,
+    /// Mapping used instead of the standard one
+    dialect: Option<Dialect>,
+    debug_dump: bool,
+    in_shebang: bool,
+    /// Whether [`Token::End`] was lexed already
+    ended: bool,
+}
+
+impl<I> Lexer<I>
+where
+    I: Iterator<Item = u8>,
+{
+    pub fn new<T>(bytes: T) -> Self
+    where
+        T: IntoIterator<IntoIter = I>,
+    {
+        Self::resume(bytes, Span::default())
+    }
+
+    /// Starts lexing at the given position, as if `bytes` followed
+    /// some code that was already lexed
+    pub fn resume<T>(bytes: T, position: Span) -> Self
+    where
+        T: IntoIterator<IntoIter = I>,
+    {
+        Lexer {
+            bytes: bytes.into_iter(),
+            lookahead: VecDeque::new(),
+            position,
+            dialect: None,
+            debug_dump: false,
+            in_shebang: false,
+            ended: false,
+        }
+    }
+
+    /// Whether `#` is lexed as [`Token::DebugDump`] instead of a comment
+    ///
+    /// With a dialect, it only applies to a `#` that is not part of any of
+    /// its patterns.
+    pub fn debug_dump(mut self, enabled: bool) -> Self {
+        self.debug_dump = enabled;
+        self
+    }
+
+    /// Lexes tokens written as in `dialect`, instead of standard BrainFuck
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = Some(dialect);
+        self
+    }
+
+    /// Takes bytes until `len` of them are available to look ahead, if
+    /// there are enough
+    fn fill_lookahead(&mut self, len: usize) {
+        while self.lookahead.len() < len {
+            match self.bytes.next() {
+                Some(byte) => self.lookahead.push_back(byte),
+                None => break,
+            }
+        }
+    }
+
+    /// Empty span where the next token starts
+    pub fn position(&self) -> Span {
+        self.position
+    }
+}
+
+impl<I> Iterator for Lexer<I>
+where
+    I: Iterator<Item = u8>,
+{
+    type Item = (Token, Span);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fill_lookahead(2);
+        let &byte = self.lookahead.front()?;
+        if self.position.start == 0 && byte == b'#' {
+            self.in_shebang = self.lookahead.get(1) == Some(&b'!');
+        }
+
+        if self.in_shebang || self.ended {
+            self.lookahead.pop_front();
+            self.in_shebang = self.in_shebang && byte != b'\n';
+            return Some((Token::Comment(byte), advance(&mut self.position, byte)));
+        }
+
+        let longest = self.dialect.as_ref().map_or(1, Dialect::longest);
+        self.fill_lookahead(longest);
+
+        let found = match &self.dialect {
+            Some(dialect) => dialect.find(self.lookahead.make_contiguous()),
+            None => Some((Token::from(byte), 1)),
+        };
+        let (token, len) = match found {
+            Some((Token::Comment(b'#'), _)) | None if byte == b'#' && self.debug_dump => {
+                (Token::DebugDump, 1)
+            }
+            Some(found) => found,
+            None => (Token::Comment(byte), 1),
+        };
+
+        self.ended = token == Token::End;
+        let start = self.position;
+        for byte in self.lookahead.drain(..len) {
+            advance(&mut self.position, byte);
+        }
+        let span = Span {
+            end: self.position.start,
+            ..start
+        };
+
+        Some((token, span))
+    }
+}
+
+/// Locates tokens as if each one came from one byte of the source
+pub(crate) fn locate_tokens<T>(tokens: T) -> impl Iterator<Item = (Token, Span)>
+where
+    T: IntoIterator<Item = Token>,
+{
+    let mut position = Span::default();
+
+    tokens
+        .into_iter()
+        .map(move |token| (token, advance(&mut position, u8::from(token))))
+}
+
+/// Moves `position` past `byte`, returning the span of that byte
+fn advance(position: &mut Span, byte: u8) -> Span {
+    let span = Span {
+        end: position.start + 1,
+        ..*position
+    };
+
+    position.start = span.end;
+    position.end = span.end;
+    if byte == b'\n' {
+        position.line += 1;
+        position.column = 1;
+    } else {
+        position.column += 1;
+    }
+
+    span
+}
+
 #[cfg(test)]
 mod tests {
     use super::Token as TO;
@@ -87,6 +357,73 @@ mod tests {
 
         for (byte, token) in pairs {
             assert_eq!(token, byte.into());
+            assert_eq!(byte, u8::from(token));
         }
     }
+
+    #[test]
+    fn lexer_tracks_lines_and_columns() {
+        use super::{Lexer, Span};
+
+        let spans: Vec<_> = Lexer::new("+\n a".bytes()).collect();
+        let span = |start, line, column| Span {
+            start,
+            end: start + 1,
+            line,
+            column,
+        };
+
+        assert_eq!(
+            spans,
+            vec![
+                (TO::Increment, span(0, 1, 1)),
+                (TO::Comment(b'\n'), span(1, 1, 2)),
+                (TO::Comment(b' '), span(2, 2, 1)),
+                (TO::Comment(b'a'), span(3, 2, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_debug_dump_extension() {
+        use super::Lexer;
+
+        let tokens = |enabled| -> Vec<_> {
+            Lexer::new("+#".bytes())
+                .debug_dump(enabled)
+                .map(|(token, _)| token)
+                .collect()
+        };
+
+        assert_eq!(tokens(false), [TO::Increment, TO::Comment(b'#')]);
+        assert_eq!(tokens(true), [TO::Increment, TO::DebugDump]);
+        assert_eq!(u8::from(TO::DebugDump), b'#');
+    }
+
+    #[test]
+    fn lexer_skips_shebang() {
+        use super::Lexer;
+
+        let tokens: Vec<_> = Lexer::new("#!bf -\n#-".bytes()).debug_dump(true).collect();
+        let commands: Vec<_> = tokens
+            .iter()
+            .filter(|(token, _)| !matches!(token, TO::Comment(_)))
+            .map(|&(token, span)| (token, span.start, span.line))
+            .collect();
+
+        assert_eq!(commands, [(TO::DebugDump, 7, 2), (TO::Decrement, 8, 2)]);
+    }
+
+    #[test]
+    fn lexer_resumes_from_position() {
+        use super::Lexer;
+
+        let mut lexer = Lexer::new("+\n+".bytes());
+        lexer.by_ref().for_each(drop);
+
+        let mut resumed = Lexer::resume(">".bytes(), lexer.position());
+        let (_, span) = resumed.next().unwrap();
+
+        assert_eq!((span.start, span.line, span.column), (3, 2, 2));
+    }
 }
@@ -0,0 +1,1098 @@
+//! Translation of programs into the source code of other languages
+//!
+//! Trees are compiled with [`OptLevel::Aggressive`] first, so the
+//! translations run the optimized instructions on a fresh memory, with the
+//! pointer starting at its middle. Unless told otherwise, the memory has
+//! the default size of the interpreter.
+
+use crate::{
+    bytecode::{Op, Program},
+    execution::DEFAULT_BRAINFUCK_STACK_SIZE,
+    input::EofBehavior,
+    optimizer::{OptLevel, PassManager},
+    syntax::SyntaxTree,
+};
+use std::{fmt::Write, ops::Range};
+
+/// Cells printed by `#` in translated programs, like the interpreter does
+const DUMP_CELLS: usize = 16;
+
+/// Translates `tree` into a self-contained `main.rs`
+///
+/// Reading past the end of the input gives zero and cells wrap around on
+/// overflow, like the interpreter does. See [`RustTranspiler`] to choose
+/// the size of the memory and what reading past the end does.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{transpile, SyntaxTree};
+/// let tree: SyntaxTree = ",[.,]".parse().unwrap();
+/// let source = transpile::to_rust(&tree);
+///
+/// assert!(source.contains("fn main() {"));
+/// assert!(source.contains("while bf.m[bf.p] != 0 {"));
+/// ```
+pub fn to_rust(tree: &SyntaxTree) -> String {
+    RustTranspiler::new().transpile(tree)
+}
+
+/// Translates `tree` into a portable C99 program with 8-bit cells
+///
+/// See [`CTranspiler`] to choose the type of the cells, the size of the
+/// memory and what reading past the end of the input does.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{transpile, SyntaxTree};
+/// let tree: SyntaxTree = ",[.,]".parse().unwrap();
+/// let source = transpile::to_c(&tree);
+///
+/// assert!(source.contains("int main(void) {"));
+/// assert!(source.contains("while (m[p]) {"));
+/// ```
+pub fn to_c(tree: &SyntaxTree) -> String {
+    CTranspiler::new().transpile(tree)
+}
+
+/// Translates `tree` into a Node.js script
+///
+/// The memory is a `Uint8Array`, and the input and output are the
+/// standard ones of the process.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{transpile, SyntaxTree};
+/// let tree: SyntaxTree = ",[.,]".parse().unwrap();
+/// let source = transpile::to_js(&tree);
+///
+/// assert!(source.contains("const m = new Uint8Array(MEMORY);"));
+/// assert!(source.contains("while (m[p]) {"));
+/// ```
+pub fn to_js(tree: &SyntaxTree) -> String {
+    let program = tree.compile_with(OptLevel::Aggressive);
+    Translation::new(&program, DEFAULT_BRAINFUCK_STACK_SIZE).source(&JavaScript)
+}
+
+/// Translates `tree` into a Python 3 script
+///
+/// The memory is a `bytearray`, and the input and output are the standard
+/// ones of the process.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{transpile, SyntaxTree};
+/// let tree: SyntaxTree = ",[.,]".parse().unwrap();
+/// let source = transpile::to_python(&tree);
+///
+/// assert!(source.contains("m = bytearray(MEMORY)"));
+/// assert!(source.contains("while m[p]:"));
+/// ```
+pub fn to_python(tree: &SyntaxTree) -> String {
+    let program = tree.compile_with(OptLevel::Aggressive);
+    Translation::new(&program, DEFAULT_BRAINFUCK_STACK_SIZE).source(&Python)
+}
+
+/// Unsigned integer type of the cells of a translated program
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum CellType {
+    #[default]
+    U8,
+    U16,
+    U32,
+}
+
+impl CellType {
+    fn c_type(self) -> &'static str {
+        match self {
+            CellType::U8 => "uint8_t",
+            CellType::U16 => "uint16_t",
+            CellType::U32 => "uint32_t",
+        }
+    }
+}
+
+/// Translator of trees into portable C99 programs
+///
+/// The programs read from their standard input, giving zero past its end
+/// unless told otherwise, and write to their standard output. Cells wider
+/// than 8 bits wrap around at their own width, so the tree is not
+/// optimized for them, since the optimizations compute with 8-bit cells.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{transpile::{CTranspiler, CellType}, SyntaxTree};
+/// let tree: SyntaxTree = "-.".parse().unwrap();
+/// let source = CTranspiler::new()
+///     .cell_type(CellType::U16)
+///     .memory(1024)
+///     .transpile(&tree);
+///
+/// assert!(source.contains("typedef uint16_t cell;"));
+/// assert!(source.contains("#define MEMORY 1024"));
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct CTranspiler {
+    cell: CellType,
+    memory: usize,
+    eof: EofBehavior,
+    unicode: bool,
+}
+
+impl Default for CTranspiler {
+    fn default() -> Self {
+        CTranspiler {
+            cell: CellType::default(),
+            memory: DEFAULT_BRAINFUCK_STACK_SIZE,
+            eof: EofBehavior::default(),
+            unicode: false,
+        }
+    }
+}
+
+impl CTranspiler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Type of the cells, [`CellType::U8`] by default
+    pub fn cell_type(mut self, cell: CellType) -> Self {
+        self.cell = cell;
+        self
+    }
+
+    /// Amount of cells of the memory, the default size of the interpreter
+    /// by default
+    pub fn memory(mut self, cells: usize) -> Self {
+        self.memory = cells;
+        self
+    }
+
+    /// What `,` does past the end of the input, [`EofBehavior::Zero`] by
+    /// default
+    ///
+    /// [`EofBehavior::MinusOne`] sets every bit of the cell, whatever its
+    /// width, and [`EofBehavior::Error`] exits with status 1.
+    pub fn eof(mut self, eof: EofBehavior) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    /// Whether cells wider than 8 bits hold Unicode code points, `false` by
+    /// default
+    ///
+    /// `.` then writes the cell encoded as UTF-8, and `,` decodes a
+    /// character of the input into the cell. Cells of 8 bits are always
+    /// written and read as bytes.
+    pub fn unicode(mut self, unicode: bool) -> Self {
+        self.unicode = unicode;
+        self
+    }
+
+    /// Translates `tree` into a whole C program
+    pub fn transpile(&self, tree: &SyntaxTree) -> String {
+        let level = match self.cell {
+            CellType::U8 => OptLevel::Aggressive,
+            CellType::U16 | CellType::U32 => OptLevel::None,
+        };
+
+        let mut program = Program::new(tree);
+        PassManager::for_level(level, self.memory).run(&mut program);
+
+        self.transpile_program(&program)
+    }
+
+    /// Translates `program` as it is, which must be optimized for cells of
+    /// 8 bits unless it was not optimized at all
+    pub(crate) fn transpile_program(&self, program: &Program) -> String {
+        let language = C {
+            cell: self.cell,
+            eof: self.eof,
+            unicode: self.unicode && self.cell != CellType::U8,
+        };
+        Translation::new(program, self.memory).source(&language)
+    }
+}
+
+/// Translator of trees into self-contained `main.rs` files
+///
+/// The programs read from their standard input, giving zero past its end
+/// unless told otherwise, and write to their standard output.
+///
+/// # Example
+/// ```
+/// # use brainfuck::{transpile::RustTranspiler, EofBehavior, SyntaxTree};
+/// let tree: SyntaxTree = ",.".parse().unwrap();
+/// let source = RustTranspiler::new()
+///     .memory(1024)
+///     .eof(EofBehavior::Unchanged)
+///     .transpile(&tree);
+///
+/// assert!(source.contains("const MEMORY: usize = 1024;"));
+/// assert!(source.contains("_ => current,"));
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct RustTranspiler {
+    memory: usize,
+    eof: EofBehavior,
+}
+
+impl Default for RustTranspiler {
+    fn default() -> Self {
+        RustTranspiler {
+            memory: DEFAULT_BRAINFUCK_STACK_SIZE,
+            eof: EofBehavior::default(),
+        }
+    }
+}
+
+impl RustTranspiler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Amount of cells of the memory, the default size of the interpreter
+    /// by default
+    pub fn memory(mut self, cells: usize) -> Self {
+        self.memory = cells;
+        self
+    }
+
+    /// What `,` does past the end of the input, [`EofBehavior::Zero`] by
+    /// default
+    ///
+    /// [`EofBehavior::Error`] exits with status 1.
+    pub fn eof(mut self, eof: EofBehavior) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    /// Translates `tree` into a whole Rust program
+    pub fn transpile(&self, tree: &SyntaxTree) -> String {
+        let mut program = Program::new(tree);
+        PassManager::for_level(OptLevel::Aggressive, self.memory).run(&mut program);
+
+        let language = Rust { eof: self.eof };
+        Translation::new(&program, self.memory).source(&language)
+    }
+}
+
+/// Statements running instructions in some language
+pub(crate) trait Language {
+    /// Statement running `op`, which is neither a jump nor a procedure
+    /// definition
+    ///
+    /// Offsets of moves and multiplications are already wrapped around the
+    /// memory, so they are never negative.
+    fn statement(&self, op: Op) -> String;
+
+    /// Statement opening the body of a loop
+    fn loop_start(&self) -> String;
+
+    /// Statement closing the body of a loop
+    fn loop_end(&self) -> String;
+
+    /// Statement making up the body of a loop without instructions
+    fn empty_body(&self) -> String {
+        String::new()
+    }
+
+    /// Statement defining the procedure numbered `id` for the current cell
+    fn define(&self, id: usize) -> String;
+
+    /// Whole source running `main`, given the bodies of the procedures
+    fn source(&self, memory: usize, main: &str, procedures: &[String]) -> String;
+}
+
+/// Bodies of a program and of its procedures, as lines of any language
+pub(crate) struct Translation<'a> {
+    ops: &'a [Op],
+    memory: usize,
+}
+
+impl<'a> Translation<'a> {
+    pub(crate) fn new(program: &'a Program, memory: usize) -> Self {
+        Translation {
+            ops: program.ops(),
+            memory: memory.max(1),
+        }
+    }
+
+    /// Whole source of the program in `language`
+    pub(crate) fn source<L: Language>(&self, language: &L) -> String {
+        let mut procedures = Vec::new();
+        let mut main = String::new();
+        self.block(language, 0..self.ops.len(), 1, &mut main, &mut procedures);
+
+        language.source(self.memory, &main, &procedures)
+    }
+
+    fn block<L: Language>(
+        &self,
+        language: &L,
+        range: Range<usize>,
+        mut depth: usize,
+        out: &mut String,
+        procedures: &mut Vec<String>,
+    ) {
+        let mut index = range.start;
+
+        while index < range.end {
+            let op = self.ops[index];
+            index += 1;
+
+            let statement = match op {
+                Op::Move(offset) => language.statement(Op::Move(self.wrap(offset))),
+                Op::Multiply { offset, factor } => language.statement(Op::Multiply {
+                    offset: self.wrap(offset),
+                    factor,
+                }),
+                Op::JumpIfZero(_) => {
+                    line(out, depth, &language.loop_start());
+                    depth += 1;
+                    continue;
+                }
+                Op::JumpIfNotZero(_) => {
+                    if let Op::JumpIfZero(_) = self.ops[index - 2] {
+                        line(out, depth, &language.empty_body());
+                    }
+                    depth -= 1;
+                    language.loop_end()
+                }
+                Op::Procedure(after) => {
+                    let id = procedures.len();
+                    procedures.push(String::new());
+
+                    let mut body = String::new();
+                    self.block(language, index..after - 1, 1, &mut body, procedures);
+                    procedures[id] = body;
+                    index = after;
+
+                    language.define(id)
+                }
+                Op::Return => continue,
+                op => language.statement(op),
+            };
+
+            line(out, depth, &statement);
+        }
+    }
+
+    fn wrap(&self, offset: isize) -> isize {
+        offset.rem_euclid(self.memory as isize)
+    }
+}
+
+/// Writes `statement` indented `depth` times, unless it's empty
+fn line(out: &mut String, depth: usize, statement: &str) {
+    if statement.is_empty() {
+        return;
+    }
+
+    out.push_str(&"    ".repeat(depth));
+    out.push_str(statement);
+    out.push('\n');
+}
+
+/// Index of the cell `offset` cells away from `pointer`, wrapping around
+/// `memory`
+pub(crate) fn wrapped(pointer: &str, offset: isize, memory: &str) -> String {
+    match offset {
+        0 => pointer.to_string(),
+        offset => format!("({pointer} + {offset}) % {memory}"),
+    }
+}
+
+struct C {
+    cell: CellType,
+    eof: EofBehavior,
+    /// Whether cells are written and read as UTF-8 characters
+    unicode: bool,
+}
+
+impl Language for C {
+    fn statement(&self, op: Op) -> String {
+        match op {
+            Op::Add(amount) => format!("m[p] {};", c_delta(amount)),
+            Op::Move(offset) => format!("p = {};", wrapped("p", offset, "MEMORY")),
+            Op::Set(value) => format!("m[p] = {value};"),
+            Op::Multiply { offset, factor } => {
+                let (sign, factor) = match factor {
+                    0..=127 => ('+', factor),
+                    _ => ('-', factor.wrapping_neg()),
+                };
+                let target = wrapped("p", offset, "MEMORY");
+                format!("m[{target}] {sign}= {};", product("m[p]", factor))
+            }
+            Op::Input => "m[p] = input(m[p]);".into(),
+            Op::Output if self.unicode => "output(m[p]);".into(),
+            Op::Output => "putchar(m[p]);".into(),
+            Op::DebugDump => "dump();".into(),
+            Op::Call => "if (procedures[m[p] & 255]) procedures[m[p] & 255]();".into(),
+            Op::End => "exit(0);".into(),
+            Op::Store => "s = m[p];".into(),
+            Op::Retrieve => "m[p] = s;".into(),
+            Op::ShiftLeft => "m[p] <<= 1;".into(),
+            Op::ShiftRight => "m[p] >>= 1;".into(),
+            Op::Not => "m[p] = ~m[p];".into(),
+            Op::Xor => "m[p] ^= s;".into(),
+            Op::And => "m[p] &= s;".into(),
+            Op::Or => "m[p] |= s;".into(),
+            Op::JumpIfZero(_) | Op::JumpIfNotZero(_) | Op::Procedure(_) | Op::Return => {
+                unreachable!("translated by the caller")
+            }
+        }
+    }
+
+    fn loop_start(&self) -> String {
+        "while (m[p]) {".into()
+    }
+
+    fn loop_end(&self) -> String {
+        "}".into()
+    }
+
+    fn define(&self, id: usize) -> String {
+        format!("procedures[m[p] & 255] = procedure_{id};")
+    }
+
+    fn source(&self, memory: usize, main: &str, procedures: &[String]) -> String {
+        let mut source = format!(
+            r#"#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#define MEMORY {memory}
+
+typedef {cell} cell;
+
+static cell m[MEMORY];
+static size_t p = MEMORY / 2;
+cell s;
+void (*procedures[256])(void);
+
+cell input(cell current) {{
+    int c = getchar();
+    {eof}{decode}
+    return c;
+}}
+{output}
+void dump(void) {{
+    size_t start = MEMORY / 2;
+    size_t end = start + {DUMP_CELLS} < MEMORY ? start + {DUMP_CELLS} : MEMORY;
+    fflush(stdout);
+    fprintf(stderr, "pointer: %zu, cells %zu..%zu: [", p, start, end);
+    for (size_t i = start; i < end; i++) {{
+        fprintf(stderr, i == start ? "%lu" : ", %lu", (unsigned long)m[i]);
+    }}
+    fputs("]\n", stderr);
+}}
+"#,
+            cell = self.cell.c_type(),
+            eof = c_eof(self.eof),
+            decode = if self.unicode { C_DECODE } else { "" },
+            output = if self.unicode { C_OUTPUT } else { "" },
+        );
+
+        for id in 0..procedures.len() {
+            let _ = write!(source, "\nstatic void procedure_{id}(void);");
+        }
+        if !procedures.is_empty() {
+            source.push('\n');
+        }
+        for (id, body) in procedures.iter().enumerate() {
+            let _ = write!(source, "\nstatic void procedure_{id}(void) {{\n{body}}}\n");
+        }
+        let _ = write!(source, "\nint main(void) {{\n{main}    return 0;\n}}\n");
+
+        source
+    }
+}
+
+/// Statements of `input` reading the rest of the UTF-8 character started
+/// by `c`, leaving its code point in `c`
+const C_DECODE: &str = r#"
+    int extra = c < 0x80 ? 0 : c < 0xC0 ? -1 : c < 0xE0 ? 1 : c < 0xF0 ? 2 : c < 0xF8 ? 3 : -1;
+    if (extra < 0) return 0xFFFD;
+    int code = extra ? c & (0x3F >> extra) : c;
+    while (extra--) {
+        c = getchar();
+        if (c == EOF || (c & 0xC0) != 0x80) {
+            if (c != EOF) ungetc(c, stdin);
+            return 0xFFFD;
+        }
+        code = code << 6 | (c & 0x3F);
+    }
+    c = code;"#;
+
+/// Function writing a cell as the UTF-8 encoding of its code point
+const C_OUTPUT: &str = r#"
+void output(cell c) {
+    uint32_t code = c;
+    if (code > 0x10FFFF || (code >= 0xD800 && code <= 0xDFFF)) code = 0xFFFD;
+    if (code < 0x80) {
+        putchar(code);
+    } else if (code < 0x800) {
+        putchar(0xC0 | code >> 6);
+        putchar(0x80 | (code & 0x3F));
+    } else if (code < 0x10000) {
+        putchar(0xE0 | code >> 12);
+        putchar(0x80 | (code >> 6 & 0x3F));
+        putchar(0x80 | (code & 0x3F));
+    } else {
+        putchar(0xF0 | code >> 18);
+        putchar(0x80 | (code >> 12 & 0x3F));
+        putchar(0x80 | (code >> 6 & 0x3F));
+        putchar(0x80 | (code & 0x3F));
+    }
+}
+"#;
+
+/// Statement of `input` handling the end of the input, when `c` is `EOF`
+fn c_eof(eof: EofBehavior) -> &'static str {
+    match eof {
+        EofBehavior::Zero => "if (c == EOF) return 0;",
+        EofBehavior::MinusOne => "if (c == EOF) return (cell)-1;",
+        EofBehavior::Unchanged => "if (c == EOF) return current;",
+        EofBehavior::Error => {
+            "if (c == EOF) {\n        \
+                fflush(stdout);\n        \
+                fputs(\"error: the program read past the end of its input\\n\", stderr);\n        \
+                exit(1);\n    \
+            }"
+        }
+    }
+}
+
+/// `cell` times `factor`, leaving out a factor of one
+fn product(cell: &str, factor: u8) -> String {
+    match factor {
+        1 => cell.to_string(),
+        factor => format!("{cell} * {factor}"),
+    }
+}
+
+/// Compound assignment adding `amount` to a cell of any width, taking
+/// amounts above 127 as negative
+fn c_delta(amount: u8) -> String {
+    match amount {
+        0..=127 => format!("+= {amount}"),
+        _ => format!("-= {}", amount.wrapping_neg()),
+    }
+}
+
+struct Rust {
+    eof: EofBehavior,
+}
+
+impl Language for Rust {
+    fn statement(&self, op: Op) -> String {
+        let cell = "bf.m[bf.p]";
+
+        match op {
+            Op::Add(amount) => format!("{cell} = {cell}.wrapping_add({amount});"),
+            Op::Move(offset) => format!("bf.p = {};", wrapped("bf.p", offset, "MEMORY")),
+            Op::Set(value) => format!("{cell} = {value};"),
+            Op::Multiply { offset, factor } => {
+                let target = format!("bf.m[{}]", wrapped("bf.p", offset, "MEMORY"));
+                format!("{target} = {target}.wrapping_add({cell}.wrapping_mul({factor}));")
+            }
+            Op::Input => format!("{cell} = bf.read({cell});"),
+            Op::Output => format!("bf.write({cell});"),
+            Op::DebugDump => "bf.dump();".into(),
+            Op::Call => format!(
+                "if let Some(procedure) = bf.procedures[{cell} as usize] {{ procedure(bf); }}"
+            ),
+            Op::End => "bf.end();".into(),
+            Op::Store => format!("bf.s = {cell};"),
+            Op::Retrieve => format!("{cell} = bf.s;"),
+            Op::ShiftLeft => format!("{cell} <<= 1;"),
+            Op::ShiftRight => format!("{cell} >>= 1;"),
+            Op::Not => format!("{cell} = !{cell};"),
+            Op::Xor => format!("{cell} ^= bf.s;"),
+            Op::And => format!("{cell} &= bf.s;"),
+            Op::Or => format!("{cell} |= bf.s;"),
+            Op::JumpIfZero(_) | Op::JumpIfNotZero(_) | Op::Procedure(_) | Op::Return => {
+                unreachable!("translated by the caller")
+            }
+        }
+    }
+
+    fn loop_start(&self) -> String {
+        "while bf.m[bf.p] != 0 {".into()
+    }
+
+    fn loop_end(&self) -> String {
+        "}".into()
+    }
+
+    fn define(&self, id: usize) -> String {
+        format!("bf.procedures[bf.m[bf.p] as usize] = Some(procedure_{id});")
+    }
+
+    fn source(&self, memory: usize, main: &str, procedures: &[String]) -> String {
+        let mut source = format!(
+            r#"#![allow(dead_code, unused_variables)]
+
+use std::io::{{self, Read, Write}};
+
+const MEMORY: usize = {memory};
+
+struct Machine {{
+    m: Vec<u8>,
+    p: usize,
+    s: u8,
+    procedures: [Option<fn(&mut Machine)>; 256],
+    input: io::Bytes<io::StdinLock<'static>>,
+    output: io::BufWriter<io::StdoutLock<'static>>,
+}}
+
+impl Machine {{
+    fn read(&mut self, current: u8) -> u8 {{
+        self.output.flush().unwrap();
+        match self.input.next() {{
+            Some(Ok(byte)) => byte,
+            _ => {eof},
+        }}
+    }}
+
+    fn write(&mut self, byte: u8) {{
+        self.output.write_all(&[byte]).unwrap();
+    }}
+
+    fn dump(&mut self) {{
+        self.output.flush().unwrap();
+        let start = MEMORY / 2;
+        let end = (start + 16).min(MEMORY);
+        eprintln!("pointer: {{}}, cells {{start}}..{{end}}: {{:?}}", self.p, &self.m[start..end]);
+    }}
+
+    fn end(&mut self) -> ! {{
+        self.output.flush().unwrap();
+        std::process::exit(0)
+    }}
+}}
+
+fn main() {{
+    let mut bf = Machine {{
+        m: vec![0; MEMORY],
+        p: MEMORY / 2,
+        s: 0,
+        procedures: [None; 256],
+        input: io::stdin().lock().bytes(),
+        output: io::BufWriter::new(io::stdout().lock()),
+    }};
+
+    run(&mut bf);
+    bf.output.flush().unwrap();
+}}
+
+fn run(bf: &mut Machine) {{
+{main}}}
+"#,
+            eof = rust_eof(self.eof),
+        );
+
+        for (id, body) in procedures.iter().enumerate() {
+            let _ = write!(
+                source,
+                "\nfn procedure_{id}(bf: &mut Machine) {{\n{body}}}\n"
+            );
+        }
+
+        source
+    }
+}
+
+/// Value `read` gives past the end of the input
+fn rust_eof(eof: EofBehavior) -> &'static str {
+    match eof {
+        EofBehavior::Zero => "0",
+        EofBehavior::MinusOne => "u8::MAX",
+        EofBehavior::Unchanged => "current",
+        EofBehavior::Error => {
+            "{\n                \
+                self.output.flush().unwrap();\n                \
+                eprintln!(\"error: the program read past the end of its input\");\n                \
+                std::process::exit(1)\n            \
+            }"
+        }
+    }
+}
+
+struct JavaScript;
+
+impl Language for JavaScript {
+    fn statement(&self, op: Op) -> String {
+        match op {
+            Op::Add(amount) => format!("m[p] {};", c_delta(amount)),
+            Op::Move(offset) => format!("p = {};", wrapped("p", offset, "MEMORY")),
+            Op::Set(value) => format!("m[p] = {value};"),
+            Op::Multiply { offset, factor } => {
+                let (sign, factor) = match factor {
+                    0..=127 => ('+', factor),
+                    _ => ('-', factor.wrapping_neg()),
+                };
+                let target = wrapped("p", offset, "MEMORY");
+                format!("m[{target}] {sign}= {};", product("m[p]", factor))
+            }
+            Op::Input => "m[p] = read();".into(),
+            Op::Output => "write(m[p]);".into(),
+            Op::DebugDump => "dump();".into(),
+            Op::Call => "if (procedures[m[p]]) procedures[m[p]]();".into(),
+            Op::End => "end();".into(),
+            Op::Store => "s = m[p];".into(),
+            Op::Retrieve => "m[p] = s;".into(),
+            Op::ShiftLeft => "m[p] <<= 1;".into(),
+            Op::ShiftRight => "m[p] >>= 1;".into(),
+            Op::Not => "m[p] = ~m[p];".into(),
+            Op::Xor => "m[p] ^= s;".into(),
+            Op::And => "m[p] &= s;".into(),
+            Op::Or => "m[p] |= s;".into(),
+            Op::JumpIfZero(_) | Op::JumpIfNotZero(_) | Op::Procedure(_) | Op::Return => {
+                unreachable!("translated by the caller")
+            }
+        }
+    }
+
+    fn loop_start(&self) -> String {
+        "while (m[p]) {".into()
+    }
+
+    fn loop_end(&self) -> String {
+        "}".into()
+    }
+
+    fn define(&self, id: usize) -> String {
+        format!("procedures[m[p]] = procedure_{id};")
+    }
+
+    fn source(&self, memory: usize, main: &str, procedures: &[String]) -> String {
+        let mut source = format!(
+            r#""use strict";
+
+const fs = require("fs");
+
+const MEMORY = {memory};
+const m = new Uint8Array(MEMORY);
+let p = {pointer};
+let s = 0;
+const procedures = new Array(256).fill(null);
+const output = [];
+
+function flush() {{
+    if (output.length > 0) {{
+        fs.writeSync(1, Uint8Array.from(output));
+        output.length = 0;
+    }}
+}}
+
+function read() {{
+    flush();
+    const byte = new Uint8Array(1);
+    try {{
+        return fs.readSync(0, byte, 0, 1, null) === 1 ? byte[0] : 0;
+    }} catch (error) {{
+        if (error.code === "EOF") {{
+            return 0;
+        }}
+        throw error;
+    }}
+}}
+
+function write(byte) {{
+    output.push(byte);
+}}
+
+function dump() {{
+    flush();
+    const start = Math.floor(MEMORY / 2);
+    const end = Math.min(start + {DUMP_CELLS}, MEMORY);
+    const cells = Array.from(m.subarray(start, end)).join(", ");
+    console.error(`pointer: ${{p}}, cells ${{start}}..${{end}}: [${{cells}}]`);
+}}
+
+function end() {{
+    flush();
+    process.exit(0);
+}}
+"#,
+            pointer = memory / 2,
+        );
+
+        for (id, body) in procedures.iter().enumerate() {
+            let _ = write!(source, "\nfunction procedure_{id}() {{\n{body}}}\n");
+        }
+        let _ = write!(
+            source,
+            "\nfunction run() {{\n{main}}}\n\nrun();\nflush();\n"
+        );
+
+        source
+    }
+}
+
+struct Python;
+
+impl Language for Python {
+    fn statement(&self, op: Op) -> String {
+        match op {
+            Op::Add(amount) => match amount {
+                0..=127 => format!("m[p] = (m[p] + {amount}) & 255"),
+                _ => format!("m[p] = (m[p] - {}) & 255", amount.wrapping_neg()),
+            },
+            Op::Move(offset) => format!("p = {}", wrapped("p", offset, "MEMORY")),
+            Op::Set(value) => format!("m[p] = {value}"),
+            Op::Multiply { offset, factor } => {
+                let target = format!("m[{}]", wrapped("p", offset, "MEMORY"));
+                format!("{target} = ({target} + {}) & 255", product("m[p]", factor))
+            }
+            Op::Input => "m[p] = read()".into(),
+            Op::Output => "write(m[p])".into(),
+            Op::DebugDump => "dump()".into(),
+            Op::Call => "if procedures[m[p]]: procedures[m[p]]()".into(),
+            Op::End => "end()".into(),
+            Op::Store => "s = m[p]".into(),
+            Op::Retrieve => "m[p] = s".into(),
+            Op::ShiftLeft => "m[p] = (m[p] << 1) & 255".into(),
+            Op::ShiftRight => "m[p] >>= 1".into(),
+            Op::Not => "m[p] ^= 255".into(),
+            Op::Xor => "m[p] ^= s".into(),
+            Op::And => "m[p] &= s".into(),
+            Op::Or => "m[p] |= s".into(),
+            Op::JumpIfZero(_) | Op::JumpIfNotZero(_) | Op::Procedure(_) | Op::Return => {
+                unreachable!("translated by the caller")
+            }
+        }
+    }
+
+    fn loop_start(&self) -> String {
+        "while m[p]:".into()
+    }
+
+    fn loop_end(&self) -> String {
+        String::new()
+    }
+
+    fn empty_body(&self) -> String {
+        "pass".into()
+    }
+
+    fn define(&self, id: usize) -> String {
+        format!("procedures[m[p]] = procedure_{id}")
+    }
+
+    fn source(&self, memory: usize, main: &str, procedures: &[String]) -> String {
+        let mut source = format!(
+            r#"import sys
+
+MEMORY = {memory}
+m = bytearray(MEMORY)
+p = {pointer}
+s = 0
+procedures = [None] * 256
+
+
+def read():
+    sys.stdout.buffer.flush()
+    data = sys.stdin.buffer.read(1)
+    return data[0] if data else 0
+
+
+def write(byte):
+    sys.stdout.buffer.write(bytes((byte,)))
+
+
+def dump():
+    sys.stdout.buffer.flush()
+    start = MEMORY // 2
+    end = min(start + {DUMP_CELLS}, MEMORY)
+    print(f"pointer: {{p}}, cells {{start}}..{{end}}: {{list(m[start:end])}}", file=sys.stderr)
+
+
+def end():
+    sys.stdout.buffer.flush()
+    sys.exit(0)
+"#,
+            pointer = memory / 2,
+        );
+
+        for (id, body) in procedures.iter().enumerate() {
+            let _ = write!(source, "\n\ndef procedure_{id}():\n    global p, s\n{body}");
+        }
+        let _ = write!(
+            source,
+            "\n\ndef run():\n    global p, s\n{main}\n\nrun()\nsys.stdout.buffer.flush()\n"
+        );
+
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dialect::Dialect, token::Lexer};
+
+    #[test]
+    fn c_nests_loops_and_procedures() {
+        let lexer = Lexer::new("(,[.<]):".bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(lexer).unwrap();
+        let source = CTranspiler::new().memory(8).transpile(&tree);
+
+        assert!(source.contains("#define MEMORY 8\n"));
+        assert!(source.contains("typedef uint8_t cell;\n"));
+        assert!(source.contains(
+            "static void procedure_0(void) {\n    \
+                m[p] = input(m[p]);\n    \
+                while (m[p]) {\n        \
+                    putchar(m[p]);\n        \
+                    p = (p + 7) % MEMORY;\n    \
+                }\n\
+            }\n"
+        ));
+        assert!(source.contains(
+            "int main(void) {\n    \
+                procedures[m[p] & 255] = procedure_0;\n    \
+                if (procedures[m[p] & 255]) procedures[m[p] & 255]();\n    \
+                return 0;\n\
+            }\n"
+        ));
+    }
+
+    #[test]
+    fn c_keeps_wide_cells_unoptimized() {
+        let tree: SyntaxTree = ",[->--<]".parse().unwrap();
+
+        assert!(to_c(&tree).contains("m[(p + 1) % MEMORY] -= m[p] * 2;\n"));
+
+        let source = CTranspiler::new().cell_type(CellType::U32).transpile(&tree);
+        assert!(source.contains("typedef uint32_t cell;\n"));
+        assert!(source.contains(
+            "    while (m[p]) {\n        \
+                    m[p] -= 1;\n        \
+                    p = (p + 1) % MEMORY;\n        \
+                    m[p] -= 1;\n        \
+                    m[p] -= 1;\n        \
+                    p = (p + 32767) % MEMORY;\n    \
+                }\n"
+        ));
+    }
+
+    #[test]
+    fn rust_nests_loops_and_procedures() {
+        let lexer = Lexer::new("(,[.<]):".bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(lexer).unwrap();
+        let source = to_rust(&tree);
+
+        assert!(source.contains("const MEMORY: usize = 32768;\n"));
+        assert!(source.contains(
+            "fn run(bf: &mut Machine) {\n    \
+                bf.procedures[bf.m[bf.p] as usize] = Some(procedure_0);\n    \
+                if let Some(procedure) = bf.procedures[bf.m[bf.p] as usize] { procedure(bf); }\n\
+            }\n"
+        ));
+        assert!(source.contains(
+            "fn procedure_0(bf: &mut Machine) {\n    \
+                bf.m[bf.p] = bf.read(bf.m[bf.p]);\n    \
+                while bf.m[bf.p] != 0 {\n        \
+                    bf.write(bf.m[bf.p]);\n        \
+                    bf.p = (bf.p + 32767) % MEMORY;\n    \
+                }\n\
+            }\n"
+        ));
+    }
+
+    #[test]
+    fn rust_wraps_cells() {
+        let tree: SyntaxTree = ",[->++<]>.".parse().unwrap();
+        let source = to_rust(&tree);
+
+        assert!(source.contains(
+            "bf.m[(bf.p + 1) % MEMORY] = \
+                bf.m[(bf.p + 1) % MEMORY].wrapping_add(bf.m[bf.p].wrapping_mul(2));"
+        ));
+        assert!(source.contains("bf.m[bf.p] = 0;"));
+    }
+
+    #[test]
+    fn translations_follow_eof_behavior() {
+        let tree: SyntaxTree = ",.".parse().unwrap();
+        let cases = [
+            (EofBehavior::Zero, "return 0;", "_ => 0,"),
+            (EofBehavior::MinusOne, "return (cell)-1;", "_ => u8::MAX,"),
+            (EofBehavior::Unchanged, "return current;", "_ => current,"),
+            (EofBehavior::Error, "exit(1);", "std::process::exit(1)"),
+        ];
+
+        for (eof, c, rust) in cases {
+            let source = CTranspiler::new().eof(eof).transpile(&tree);
+            assert!(source.contains(c), "{eof:?}");
+            assert!(source.contains("m[p] = input(m[p]);"), "{eof:?}");
+
+            let source = RustTranspiler::new().eof(eof).transpile(&tree);
+            assert!(source.contains(rust), "{eof:?}");
+        }
+    }
+
+    #[test]
+    fn unicode_cells_go_through_utf8() {
+        let tree: SyntaxTree = ",.".parse().unwrap();
+
+        let source = CTranspiler::new()
+            .cell_type(CellType::U32)
+            .unicode(true)
+            .transpile(&tree);
+        assert!(source.contains("output(m[p]);"));
+        assert!(source.contains("code = code << 6 | (c & 0x3F);"));
+
+        let source = CTranspiler::new().unicode(true).transpile(&tree);
+        assert!(source.contains("putchar(m[p]);"));
+        assert!(!source.contains("void output(cell c)"));
+    }
+
+    #[test]
+    fn js_nests_loops_and_procedures() {
+        let lexer = Lexer::new("(,[.<]):".bytes()).dialect(Dialect::pbrain());
+        let tree = SyntaxTree::parse_spanned_tokens(lexer).unwrap();
+        let source = to_js(&tree);
+
+        assert!(source.contains("let p = 16384;\n"));
+        assert!(source.contains(
+            "function procedure_0() {\n    \
+                m[p] = read();\n    \
+                while (m[p]) {\n        \
+                    write(m[p]);\n        \
+                    p = (p + 32767) % MEMORY;\n    \
+                }\n\
+            }\n"
+        ));
+        assert!(source.contains(
+            "function run() {\n    \
+                procedures[m[p]] = procedure_0;\n    \
+                if (procedures[m[p]]) procedures[m[p]]();\n\
+            }\n"
+        ));
+    }
+
+    #[test]
+    fn python_indents_loops() {
+        let tree: SyntaxTree = ",[->+<]>[.[]]".parse().unwrap();
+        let source = to_python(&tree);
+
+        assert!(source.contains(
+            "def run():\n    \
+                global p, s\n    \
+                m[p] = read()\n    \
+                m[(p + 1) % MEMORY] = (m[(p + 1) % MEMORY] + m[p]) & 255\n    \
+                m[p] = 0\n    \
+                p = (p + 1) % MEMORY\n    \
+                while m[p]:\n        \
+                    write(m[p])\n        \
+                    while m[p]:\n            \
+                        pass\n\
+                \n\
+                \n\
+                run()\n"
+        ));
+    }
+}
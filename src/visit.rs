@@ -0,0 +1,239 @@
+//! Traversal of the expressions of a [`SyntaxTree`](crate::SyntaxTree)
+//!
+//! Every method of the traits has a default implementation that walks
+//! into the bodies of loops and procedures, so implementors only override the methods
+//! they are interested in. The `walk_*` functions hold those defaults,
+//! to keep walking from an overridden method.
+
+use crate::syntax::{Expression, SyntaxTree};
+
+/// Visits expressions by reference
+///
+/// # Example
+/// ```
+/// use brainfuck::{visit::{self, ExpressionVisitor}, Expression, SyntaxTree};
+///
+/// #[derive(Default)]
+/// struct LoopCounter(usize);
+///
+/// impl ExpressionVisitor for LoopCounter {
+///     fn visit_loop(&mut self, body: &SyntaxTree) {
+///         self.0 += 1;
+///         visit::walk_loop(self, body);
+///     }
+/// }
+///
+/// let tree: SyntaxTree = "+[>[-]<-]".parse().unwrap();
+/// let mut counter = LoopCounter::default();
+/// counter.visit_block(&tree);
+///
+/// assert_eq!(counter.0, 2);
+/// ```
+pub trait ExpressionVisitor {
+    /// Visits a sequence of expressions, like a whole tree
+    fn visit_block(&mut self, block: &[Expression]) {
+        walk_block(self, block)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr)
+    }
+
+    /// Visits the body of a loop
+    fn visit_loop(&mut self, body: &SyntaxTree) {
+        walk_loop(self, body)
+    }
+
+    /// Visits the body of a procedure
+    fn visit_procedure(&mut self, body: &SyntaxTree) {
+        walk_procedure(self, body)
+    }
+}
+
+pub fn walk_block<V: ExpressionVisitor + ?Sized>(visitor: &mut V, block: &[Expression]) {
+    for expr in block {
+        visitor.visit_expression(expr);
+    }
+}
+
+pub fn walk_expression<V: ExpressionVisitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+    match expr {
+        Expression::Loop(body) => visitor.visit_loop(body),
+        Expression::Procedure(body) => visitor.visit_procedure(body),
+        _ => (),
+    }
+}
+
+pub fn walk_loop<V: ExpressionVisitor + ?Sized>(visitor: &mut V, body: &SyntaxTree) {
+    visitor.visit_block(body)
+}
+
+pub fn walk_procedure<V: ExpressionVisitor + ?Sized>(visitor: &mut V, body: &SyntaxTree) {
+    visitor.visit_block(body)
+}
+
+/// Visits expressions by mutable reference, to rewrite them in place
+///
+/// Blocks are given as vectors, so expressions can be added or removed.
+/// Rewriting a [`SyntaxTree`](crate::SyntaxTree) may leave it without the
+/// location of its expressions.
+pub trait ExpressionVisitorMut {
+    /// Visits a sequence of expressions, like a whole tree
+    fn visit_block_mut(&mut self, block: &mut Vec<Expression>) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr)
+    }
+
+    /// Visits the body of a loop
+    fn visit_loop_mut(&mut self, body: &mut SyntaxTree) {
+        walk_loop_mut(self, body)
+    }
+
+    /// Visits the body of a procedure
+    fn visit_procedure_mut(&mut self, body: &mut SyntaxTree) {
+        walk_procedure_mut(self, body)
+    }
+}
+
+pub fn walk_block_mut<V>(visitor: &mut V, block: &mut Vec<Expression>)
+where
+    V: ExpressionVisitorMut + ?Sized,
+{
+    for expr in block {
+        visitor.visit_expression_mut(expr);
+    }
+}
+
+pub fn walk_expression_mut<V>(visitor: &mut V, expr: &mut Expression)
+where
+    V: ExpressionVisitorMut + ?Sized,
+{
+    match expr {
+        Expression::Loop(body) => visitor.visit_loop_mut(body),
+        Expression::Procedure(body) => visitor.visit_procedure_mut(body),
+        _ => (),
+    }
+}
+
+pub fn walk_loop_mut<V>(visitor: &mut V, body: &mut SyntaxTree)
+where
+    V: ExpressionVisitorMut + ?Sized,
+{
+    visitor.visit_block_mut(body)
+}
+
+pub fn walk_procedure_mut<V>(visitor: &mut V, body: &mut SyntaxTree)
+where
+    V: ExpressionVisitorMut + ?Sized,
+{
+    visitor.visit_block_mut(body)
+}
+
+/// Consumes expressions to build new ones
+///
+/// # Example
+/// ```
+/// use brainfuck::{visit::ExpressionFolder, Expression, SyntaxTree};
+///
+/// struct Mirror;
+///
+/// impl ExpressionFolder for Mirror {
+///     fn fold_expression(&mut self, expr: Expression) -> Expression {
+///         match expr {
+///             Expression::Forward => Expression::Backward,
+///             Expression::Backward => Expression::Forward,
+///             Expression::Loop(body) => self.fold_loop(body),
+///             expr => expr,
+///         }
+///     }
+/// }
+///
+/// let tree: SyntaxTree = "+[>-<]".parse().unwrap();
+/// let mirrored: SyntaxTree = "+[<->]".parse().unwrap();
+///
+/// assert_eq!(Mirror.fold_block(tree.to_vec()), *mirrored);
+/// ```
+pub trait ExpressionFolder {
+    /// Folds a sequence of expressions, like a whole tree
+    fn fold_block(&mut self, block: Vec<Expression>) -> Vec<Expression> {
+        block
+            .into_iter()
+            .map(|expr| self.fold_expression(expr))
+            .collect()
+    }
+
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        match expr {
+            Expression::Loop(body) => self.fold_loop(body),
+            Expression::Procedure(body) => self.fold_procedure(body),
+            expr => expr,
+        }
+    }
+
+    /// Folds the body of a loop into a new expression
+    fn fold_loop(&mut self, body: SyntaxTree) -> Expression {
+        Expression::Loop(self.fold_block(body.into_iter().collect()).into())
+    }
+
+    /// Folds the body of a procedure into a new expression
+    fn fold_procedure(&mut self, body: SyntaxTree) -> Expression {
+        Expression::Procedure(self.fold_block(body.into_iter().collect()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Depth {
+        current: usize,
+        max: usize,
+    }
+
+    impl ExpressionVisitor for Depth {
+        fn visit_loop(&mut self, body: &SyntaxTree) {
+            self.current += 1;
+            self.max = self.max.max(self.current);
+            walk_loop(self, body);
+            self.current -= 1;
+        }
+    }
+
+    struct DropInput;
+
+    impl ExpressionVisitorMut for DropInput {
+        fn visit_block_mut(&mut self, block: &mut Vec<Expression>) {
+            block.retain(|expr| *expr != Expression::Input);
+            walk_block_mut(self, block);
+        }
+    }
+
+    #[test]
+    fn visitor_walks_nested_loops() {
+        let tree: SyntaxTree = "[[]][[[-]]+]".parse().unwrap();
+        let mut depth = Depth { current: 0, max: 0 };
+        depth.visit_block(&tree);
+
+        assert_eq!(depth.max, 3);
+    }
+
+    #[test]
+    fn mutable_visitor_rewrites_in_place() {
+        let mut tree: SyntaxTree = ",+[,>[,.]]".parse().unwrap();
+        DropInput.visit_block_mut(&mut tree);
+
+        assert_eq!(tree.to_code(), "+[>[.]]");
+    }
+
+    #[test]
+    fn folder_keeps_unchanged_code() {
+        struct Identity;
+        impl ExpressionFolder for Identity {}
+
+        let tree: SyntaxTree = "+[>[-]<,]".parse().unwrap();
+        assert_eq!(Identity.fold_block(tree.to_vec()), *tree);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::bytecode::Op;
+
+/// Instruction run by `MemoryContext::run_program`
+///
+/// Common sequences of [`Op`]s are fused into a single instruction, and
+/// moves and multiplications proven to stay in the memory have their own
+/// instructions, so that most steps take a single dispatch without looking
+/// anything else up.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Inst {
+    Add(u8),
+    /// Move that can't take the pointer out of the memory
+    Move(isize),
+    /// Move that may wrap around the memory
+    MoveWrapping(isize),
+    Set(u8),
+    /// [`Inst::Move`] and then [`Inst::Add`] to the cell reached
+    MoveAdd {
+        offset: isize,
+        amount: u8,
+    },
+    /// Adds the current cell to the cell at `offset`, which is in the memory
+    AddTo(isize),
+    /// Multiplication to a cell known to be in the memory
+    Multiply {
+        offset: isize,
+        factor: u8,
+    },
+    /// Multiplication to a cell that may be past the edges of the memory
+    MultiplyWrapping {
+        offset: isize,
+        factor: u8,
+    },
+    /// A loop only moving the pointer by the offset, like `[>]`, until it
+    /// reaches a zero cell
+    Scan(isize),
+    /// [`Inst::Add`] and then [`Inst::JumpIfNotZero`], closing a loop
+    AddJumpIfNotZero {
+        amount: u8,
+        target: usize,
+    },
+    Input,
+    Output,
+    DebugDump,
+    JumpIfZero(usize),
+    JumpIfNotZero(usize),
+    Procedure(usize),
+    Return,
+    Call,
+    End,
+    Store,
+    Retrieve,
+    ShiftLeft,
+    ShiftRight,
+    Not,
+    Xor,
+    And,
+    Or,
+}
+
+/// Lowers `ops` into instructions, given which of them were proven to stay
+/// in the memory by [`bounds::in_bounds`](crate::bounds::in_bounds)
+///
+/// Jumps point at instructions instead of operations. Nothing jumps into
+/// the middle of a fused sequence, since jumps only land right after
+/// brackets, procedures and calls, which never start one.
+pub(crate) fn lower(ops: &[Op], proven: &[bool]) -> Vec<Inst> {
+    let mut insts = Vec::with_capacity(ops.len());
+    // Instruction starting at every operation, for operations starting one
+    let mut starts = vec![usize::MAX; ops.len() + 1];
+    let mut index = 0;
+
+    while let Some(&op) = ops.get(index) {
+        starts[index] = insts.len();
+
+        let (inst, fused) = match (op, ops.get(index + 1), ops.get(index + 2)) {
+            (Op::JumpIfZero(_), Some(&Op::Move(offset)), Some(Op::JumpIfNotZero(_))) => {
+                (Inst::Scan(offset), 3)
+            }
+            (Op::Move(offset), Some(&Op::Add(amount)), _) if proven[index] => {
+                (Inst::MoveAdd { offset, amount }, 2)
+            }
+            (Op::Add(amount), Some(&Op::JumpIfNotZero(target)), _) => {
+                (Inst::AddJumpIfNotZero { amount, target }, 2)
+            }
+            (op, ..) => (single(op, proven[index]), 1),
+        };
+
+        insts.push(inst);
+        index += fused;
+    }
+    starts[ops.len()] = insts.len();
+
+    for inst in &mut insts {
+        match inst {
+            Inst::JumpIfZero(target)
+            | Inst::JumpIfNotZero(target)
+            | Inst::Procedure(target)
+            | Inst::AddJumpIfNotZero { target, .. } => {
+                *target = starts[*target];
+                debug_assert_ne!(*target, usize::MAX, "jumps land on instructions");
+            }
+            _ => (),
+        }
+    }
+
+    insts
+}
+
+/// Instruction running `op` on its own
+fn single(op: Op, proven: bool) -> Inst {
+    match op {
+        Op::Add(amount) => Inst::Add(amount),
+        Op::Move(offset) if proven => Inst::Move(offset),
+        Op::Move(offset) => Inst::MoveWrapping(offset),
+        Op::Set(value) => Inst::Set(value),
+        Op::Multiply { offset, factor: 1 } if proven => Inst::AddTo(offset),
+        Op::Multiply { offset, factor } if proven => Inst::Multiply { offset, factor },
+        Op::Multiply { offset, factor } => Inst::MultiplyWrapping { offset, factor },
+        Op::Input => Inst::Input,
+        Op::Output => Inst::Output,
+        Op::DebugDump => Inst::DebugDump,
+        Op::JumpIfZero(target) => Inst::JumpIfZero(target),
+        Op::JumpIfNotZero(target) => Inst::JumpIfNotZero(target),
+        Op::Procedure(end) => Inst::Procedure(end),
+        Op::Return => Inst::Return,
+        Op::Call => Inst::Call,
+        Op::End => Inst::End,
+        Op::Store => Inst::Store,
+        Op::Retrieve => Inst::Retrieve,
+        Op::ShiftLeft => Inst::ShiftLeft,
+        Op::ShiftRight => Inst::ShiftRight,
+        Op::Not => Inst::Not,
+        Op::Xor => Inst::Xor,
+        Op::And => Inst::And,
+        Op::Or => Inst::Or,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bounds, bytecode::Program, syntax::SyntaxTree};
+
+    fn lowered(code: &str) -> Vec<Inst> {
+        let tree: SyntaxTree = code.parse().unwrap();
+        let program = Program::new(&tree);
+        let proven = bounds::in_bounds(program.ops(), 8, 16);
+
+        lower(program.ops(), &proven)
+    }
+
+    #[test]
+    fn lower_fuses_common_sequences() {
+        assert_eq!(
+            lowered("+[>]>+[-]"),
+            [
+                Inst::Add(1),
+                Inst::Scan(1),
+                Inst::MoveWrapping(1),
+                Inst::Add(1),
+                Inst::JumpIfZero(6),
+                Inst::AddJumpIfNotZero {
+                    amount: 255,
+                    target: 5
+                },
+            ]
+        );
+        assert_eq!(
+            lowered(">+<"),
+            [
+                Inst::MoveAdd {
+                    offset: 1,
+                    amount: 1
+                },
+                Inst::Move(-1)
+            ]
+        );
+    }
+
+    #[test]
+    fn lower_points_jumps_at_instructions() {
+        assert_eq!(
+            lowered("[>+<-]>[>]"),
+            [
+                Inst::JumpIfZero(4),
+                Inst::MoveAdd {
+                    offset: 1,
+                    amount: 1
+                },
+                Inst::MoveAdd {
+                    offset: -1,
+                    amount: 255
+                },
+                Inst::JumpIfNotZero(1),
+                Inst::Move(1),
+                Inst::Scan(1),
+            ]
+        );
+    }
+}
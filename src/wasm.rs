@@ -0,0 +1,422 @@
+use crate::{
+    bytecode::{Op, Program},
+    execution::DEFAULT_BRAINFUCK_STACK_SIZE,
+    optimizer::OptLevel,
+    syntax::SyntaxTree,
+};
+
+/// Size of a page of linear memory
+const PAGE: usize = 65536;
+
+/// Types of the functions of a module, by index
+const RUN_TYPE: u32 = 0;
+const READ_TYPE: u32 = 1;
+const WRITE_TYPE: u32 = 2;
+
+/// Globals of a module, by index
+const POINTER: u32 = 0;
+const STORAGE: u32 = 1;
+const HALTED: u32 = 2;
+
+/// Local of every function holding the address of the current cell
+const SCRATCH: u32 = 0;
+
+impl Program {
+    /// Encodes this program as a WebAssembly module running it on a memory
+    /// of `memory` cells
+    ///
+    /// The module exports its linear memory as `memory`, whose first
+    /// `memory` bytes are the cells, and a `run` function taking and
+    /// returning nothing. It imports from `env` the functions used by the
+    /// program:
+    /// - `read`, returning the next byte of the input, for `,`
+    /// - `write`, taking the byte to output, for `.`
+    /// - `dump`, taking the pointer, for `#`
+    pub fn to_wasm(&self, memory: usize) -> Vec<u8> {
+        Encoder::new(self.ops(), memory.max(1)).module()
+    }
+}
+
+impl SyntaxTree {
+    /// Encodes this tree as a WebAssembly module, fully optimized for a
+    /// memory of the default size
+    ///
+    /// See [`Program::to_wasm`] for what the module imports and exports.
+    ///
+    /// # Example
+    /// ```
+    /// # use brainfuck::SyntaxTree;
+    /// let tree: SyntaxTree = ",[.,]".parse().unwrap();
+    /// let module = tree.to_wasm();
+    ///
+    /// assert_eq!(module[..4], *b"\0asm");
+    /// ```
+    pub fn to_wasm(&self) -> Vec<u8> {
+        self.compile_with(OptLevel::Aggressive)
+            .to_wasm(DEFAULT_BRAINFUCK_STACK_SIZE)
+    }
+}
+
+/// Host functions a module imports, in the order they are imported
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Import {
+    Read,
+    Write,
+    Dump,
+}
+
+/// Writes the binary encoding of a module
+struct Encoder<'a> {
+    ops: &'a [Op],
+    memory: usize,
+    imports: Vec<Import>,
+    /// Ranges of the instructions of every procedure found so far
+    procedures: Vec<(usize, usize)>,
+}
+
+impl<'a> Encoder<'a> {
+    fn new(ops: &'a [Op], memory: usize) -> Self {
+        let mut imports = Vec::new();
+        for (op, import) in [
+            (Op::Input, Import::Read),
+            (Op::Output, Import::Write),
+            (Op::DebugDump, Import::Dump),
+        ] {
+            if ops.contains(&op) {
+                imports.push(import);
+            }
+        }
+
+        let mut procedures = Vec::new();
+        for (index, op) in ops.iter().enumerate() {
+            if let Op::Procedure(after) = op {
+                procedures.push((index + 1, after - 1));
+            }
+        }
+
+        Encoder {
+            ops,
+            memory,
+            imports,
+            procedures,
+        }
+    }
+
+    /// Address of the table holding the procedure defined for each value
+    fn procedure_table(&self) -> u32 {
+        self.memory as u32
+    }
+
+    fn function_index(&self, import: Import) -> u32 {
+        let position = self.imports.iter().position(|&i| i == import);
+        position.expect("used functions are imported") as u32
+    }
+
+    /// Index of the function running the procedure numbered `id`
+    fn procedure_index(&self, id: usize) -> u32 {
+        (self.imports.len() + 1 + id) as u32
+    }
+
+    fn module(&self) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut types = Vec::new();
+        leb_u(&mut types, 3);
+        types.extend([0x60, 0x00, 0x00]);
+        types.extend([0x60, 0x00, 0x01, 0x7f]);
+        types.extend([0x60, 0x01, 0x7f, 0x00]);
+        section(&mut module, 1, &types);
+
+        let mut imports = Vec::new();
+        leb_u(&mut imports, self.imports.len() as u64);
+        for import in &self.imports {
+            let (name, ty) = match import {
+                Import::Read => ("read", READ_TYPE),
+                Import::Write => ("write", WRITE_TYPE),
+                Import::Dump => ("dump", WRITE_TYPE),
+            };
+            name_of(&mut imports, "env");
+            name_of(&mut imports, name);
+            imports.push(0x00);
+            leb_u(&mut imports, ty as u64);
+        }
+        section(&mut module, 2, &imports);
+
+        let functions = 1 + self.procedures.len();
+        let mut declared = Vec::new();
+        leb_u(&mut declared, functions as u64);
+        for _ in 0..functions {
+            leb_u(&mut declared, RUN_TYPE as u64);
+        }
+        section(&mut module, 3, &declared);
+
+        if !self.procedures.is_empty() {
+            let mut table = vec![0x01, 0x70, 0x01];
+            leb_u(&mut table, self.procedures.len() as u64);
+            leb_u(&mut table, self.procedures.len() as u64);
+            section(&mut module, 4, &table);
+        }
+
+        let bytes = self.memory + 256 * 4;
+        let mut memory = vec![0x01, 0x00];
+        leb_u(&mut memory, bytes.div_ceil(PAGE) as u64);
+        section(&mut module, 5, &memory);
+
+        let mut globals = Vec::new();
+        leb_u(&mut globals, 3);
+        for value in [self.memory as i64 / 2, 0, 0] {
+            globals.extend([0x7f, 0x01, 0x41]);
+            leb_s(&mut globals, value);
+            globals.push(0x0b);
+        }
+        section(&mut module, 6, &globals);
+
+        let mut exports = Vec::new();
+        leb_u(&mut exports, 2);
+        name_of(&mut exports, "memory");
+        exports.extend([0x02, 0x00]);
+        name_of(&mut exports, "run");
+        exports.push(0x00);
+        leb_u(&mut exports, self.imports.len() as u64);
+        section(&mut module, 7, &exports);
+
+        if !self.procedures.is_empty() {
+            let mut elements = vec![0x01, 0x00, 0x41, 0x00, 0x0b];
+            leb_u(&mut elements, self.procedures.len() as u64);
+            for id in 0..self.procedures.len() {
+                leb_u(&mut elements, self.procedure_index(id) as u64);
+            }
+            section(&mut module, 9, &elements);
+        }
+
+        let mut code = Vec::new();
+        leb_u(&mut code, functions as u64);
+        function(&mut code, &self.body(0, self.ops.len()));
+        for &(start, end) in &self.procedures {
+            function(&mut code, &self.body(start, end));
+        }
+        section(&mut module, 10, &code);
+
+        module
+    }
+
+    /// Instructions running the ops from `start` to `end`, skipping the
+    /// bodies of procedures
+    fn body(&self, start: usize, end: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut index = start;
+
+        while index < end {
+            let op = self.ops[index];
+            index += 1;
+
+            match op {
+                Op::Add(amount) => {
+                    self.address(&mut out, 0);
+                    self.load(&mut out, 0);
+                    out.push(0x41);
+                    leb_s(&mut out, amount as i64);
+                    out.push(0x6a);
+                    store8(&mut out);
+                }
+                Op::Move(offset) => {
+                    self.address(&mut out, offset);
+                    out.extend([0x24, POINTER as u8]);
+                }
+                Op::Set(value) => {
+                    self.address(&mut out, 0);
+                    out.push(0x41);
+                    leb_s(&mut out, value as i64);
+                    store8(&mut out);
+                }
+                Op::Multiply { offset, factor } => {
+                    self.address(&mut out, offset);
+                    out.extend([0x22, SCRATCH as u8, 0x20, SCRATCH as u8]);
+                    load8(&mut out);
+                    self.load(&mut out, 0);
+                    out.push(0x41);
+                    leb_s(&mut out, factor as i64);
+                    out.extend([0x6c, 0x6a]);
+                    store8(&mut out);
+                }
+                Op::Input => {
+                    self.address(&mut out, 0);
+                    out.push(0x10);
+                    leb_u(&mut out, self.function_index(Import::Read) as u64);
+                    store8(&mut out);
+                }
+                Op::Output => {
+                    self.load(&mut out, 0);
+                    out.push(0x10);
+                    leb_u(&mut out, self.function_index(Import::Write) as u64);
+                }
+                Op::DebugDump => {
+                    out.extend([0x23, POINTER as u8, 0x10]);
+                    leb_u(&mut out, self.function_index(Import::Dump) as u64);
+                }
+                Op::JumpIfZero(_) => {
+                    out.extend([0x02, 0x40, 0x03, 0x40]);
+                    self.load(&mut out, 0);
+                    out.extend([0x45, 0x0d, 0x01]);
+                }
+                Op::JumpIfNotZero(_) => out.extend([0x0c, 0x00, 0x0b, 0x0b]),
+                Op::Procedure(after) => {
+                    let id = self.procedures.iter().position(|&(s, _)| s == index);
+                    let id = id.expect("procedures are found beforehand");
+                    index = after;
+
+                    self.table_entry(&mut out);
+                    out.push(0x41);
+                    leb_s(&mut out, id as i64 + 1);
+                    out.extend([0x36, 0x02]);
+                    leb_u(&mut out, self.procedure_table() as u64);
+                }
+                Op::Return => (),
+                Op::Call => {
+                    self.table_entry(&mut out);
+                    out.extend([0x28, 0x02]);
+                    leb_u(&mut out, self.procedure_table() as u64);
+                    out.extend([0x22, SCRATCH as u8, 0x04, 0x40]);
+                    out.extend([0x20, SCRATCH as u8, 0x41, 0x01, 0x6b]);
+                    out.extend([0x11, RUN_TYPE as u8, 0x00]);
+                    out.extend([0x23, HALTED as u8, 0x04, 0x40, 0x0f, 0x0b]);
+                    out.push(0x0b);
+                }
+                Op::End => out.extend([0x41, 0x01, 0x24, HALTED as u8, 0x0f]),
+                Op::Store => {
+                    self.load(&mut out, 0);
+                    out.extend([0x24, STORAGE as u8]);
+                }
+                Op::Retrieve => {
+                    self.address(&mut out, 0);
+                    out.extend([0x23, STORAGE as u8]);
+                    store8(&mut out);
+                }
+                Op::ShiftLeft | Op::ShiftRight | Op::Not | Op::Xor | Op::And | Op::Or => {
+                    self.address(&mut out, 0);
+                    self.load(&mut out, 0);
+                    match op {
+                        Op::ShiftLeft => out.extend([0x41, 0x01, 0x74]),
+                        Op::ShiftRight => out.extend([0x41, 0x01, 0x76]),
+                        Op::Not => out.extend([0x41, 0x7f, 0x73]),
+                        Op::Xor => out.extend([0x23, STORAGE as u8, 0x73]),
+                        Op::And => out.extend([0x23, STORAGE as u8, 0x71]),
+                        _ => out.extend([0x23, STORAGE as u8, 0x72]),
+                    }
+                    store8(&mut out);
+                }
+            }
+        }
+
+        out.push(0x0b);
+        out
+    }
+
+    /// Pushes the address of the cell `offset` cells away from the
+    /// pointer, wrapping around the memory
+    fn address(&self, out: &mut Vec<u8>, offset: isize) {
+        out.extend([0x23, POINTER as u8]);
+
+        let offset = offset.rem_euclid(self.memory as isize);
+        if offset != 0 {
+            out.push(0x41);
+            leb_s(out, offset as i64);
+            out.push(0x6a);
+            out.push(0x41);
+            leb_s(out, self.memory as i64);
+            out.push(0x70);
+        }
+    }
+
+    fn load(&self, out: &mut Vec<u8>, offset: isize) {
+        self.address(out, offset);
+        load8(out);
+    }
+
+    /// Pushes the address of the procedure table entry of the current cell
+    fn table_entry(&self, out: &mut Vec<u8>) {
+        self.load(out, 0);
+        out.extend([0x41, 0x02, 0x74]);
+    }
+}
+
+fn load8(out: &mut Vec<u8>) {
+    out.extend([0x2d, 0x00, 0x00]);
+}
+
+fn store8(out: &mut Vec<u8>) {
+    out.extend([0x3a, 0x00, 0x00]);
+}
+
+/// Appends a function body with one local, the scratch address
+fn function(out: &mut Vec<u8>, instructions: &[u8]) {
+    let mut body = vec![0x01, 0x01, 0x7f];
+    body.extend(instructions);
+    leb_u(out, body.len() as u64);
+    out.extend(body);
+}
+
+fn section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    leb_u(out, contents.len() as u64);
+    out.extend(contents);
+}
+
+fn name_of(out: &mut Vec<u8>, name: &str) {
+    leb_u(out, name.len() as u64);
+    out.extend(name.as_bytes());
+}
+
+/// Unsigned LEB128 encoding of `value`
+fn leb_u(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return out.push(byte);
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Signed LEB128 encoding of `value`
+fn leb_s(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            return out.push(byte);
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leb128_encodes_integers() {
+        let mut out = Vec::new();
+        leb_u(&mut out, 624485);
+        assert_eq!(out, [0xe5, 0x8e, 0x26]);
+
+        out.clear();
+        leb_s(&mut out, -123456);
+        assert_eq!(out, [0xc0, 0xbb, 0x78]);
+
+        out.clear();
+        leb_s(&mut out, 64);
+        assert_eq!(out, [0xc0, 0x00]);
+    }
+
+    #[test]
+    fn wasm_imports_only_used_functions() {
+        let tree: SyntaxTree = "+[-.]".parse().unwrap();
+        let module = tree.compile().to_wasm(8);
+
+        assert_eq!(module[..8], *b"\0asm\x01\0\0\0");
+        let imports = b"\x02\x0d\x01\x03env\x05write\x00\x02";
+        assert!(module.windows(imports.len()).any(|w| w == imports));
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+/// Runs `bf` with `args`, feeding it `stdin`
+fn bf(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bf"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("bf runs");
+
+    // Scripts may stop before reading everything
+    let _ = child.stdin.take().expect("stdin is piped").write_all(stdin);
+    child.wait_with_output().expect("bf finishes")
+}
+
+/// Path to `name` in a directory of its own for `test`
+fn temp_file(test: &str, name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bf-cli-{}-{test}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn status(output: &Output) -> i32 {
+    output.status.code().expect("bf exits on its own")
+}
+
+#[test]
+fn exit_statuses() {
+    let missing = temp_file("exit_statuses", "missing.bf");
+    let cases: [(&[&str], i32); 8] = [
+        (&["-e", "+"], 0),
+        (&["--no-such-option"], 2),
+        (&["compile", "--target", "rust", "--cell-size", "16"], 2),
+        (&["--max-steps", "10", "-e", "+[]"], 3),
+        (&["--timeout", "100ms", "-e", "+[]"], 4),
+        (&["-e", "["], 5),
+        (&[missing.to_str().unwrap()], 6),
+        (&["--eof", "error", "-e", ","], 7),
+    ];
+
+    for (args, expected) in cases {
+        assert_eq!(status(&bf(args, b"")), expected, "{args:?}");
+    }
+}
+
+#[test]
+fn eof_behaviors() {
+    let read = |eof, code| {
+        let output = bf(&["--output-mode", "decimal", "--eof", eof, "-e", code], b"");
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(read("zero", "+,."), "0 ");
+    assert_eq!(read("minus-one", ",.,."), "255 255 ");
+    assert_eq!(read("unchanged", "+++,."), "3 ");
+    assert_eq!(bf(&["--eof", "error", "-e", ",.,."], b"a").stdout, b"a");
+}
+
+#[test]
+fn max_steps() {
+    let output = bf(&["--max-steps", "8", "-e", "+++++++."], b"");
+    assert_eq!(status(&output), 0);
+    assert_eq!(output.stdout, [7]);
+
+    let output = bf(&["--max-steps", "5", "-e", "+++++++."], b"");
+    assert_eq!(status(&output), 3);
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("after running 5 steps"));
+}
+
+#[test]
+fn record_and_replay() {
+    let log = temp_file("record_and_replay", "input.log");
+    let log = log.to_str().unwrap();
+
+    let output = bf(&["--record", log, "-e", ",.,."], b"abc");
+    assert_eq!(output.stdout, b"ab");
+    assert_eq!(fs::read(log).unwrap(), b"ab");
+
+    let output = bf(&["--replay", log, "-e", ",.,.,."], b"xyz");
+    assert_eq!(status(&output), 0);
+    assert_eq!(output.stdout, b"ab\0");
+}
+
+#[test]
+fn pipe() {
+    let copy = temp_file("pipe", "copy.bf");
+    let next = temp_file("pipe", "next.bf");
+    fs::write(&copy, ",[.,]").unwrap();
+    fs::write(&next, ",[+.,]").unwrap();
+
+    let output = bf(
+        &["pipe", copy.to_str().unwrap(), next.to_str().unwrap()],
+        b"abc",
+    );
+    assert_eq!(status(&output), 0);
+    assert_eq!(output.stdout, b"bcd");
+
+    let broken = temp_file("pipe", "broken.bf");
+    fs::write(&broken, "[").unwrap();
+    let output = bf(
+        &["pipe", copy.to_str().unwrap(), broken.to_str().unwrap()],
+        b"",
+    );
+    assert_eq!(status(&output), 5);
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn fmt_check() {
+    let messy = temp_file("fmt_check", "messy.bf");
+    let tidy = temp_file("fmt_check", "tidy.bf");
+    fs::write(&messy, "+[->+<]").unwrap();
+    fs::write(&tidy, "+\n[\n    ->+<\n]\n").unwrap();
+
+    let output = bf(&["fmt", messy.to_str().unwrap()], b"");
+    assert_eq!(output.stdout, fs::read(&tidy).unwrap());
+
+    assert_eq!(
+        status(&bf(&["fmt", "--check", tidy.to_str().unwrap()], b"")),
+        0
+    );
+    let output = bf(&["fmt", "--check", messy.to_str().unwrap()], b"");
+    assert_eq!(status(&output), 1);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("messy.bf"));
+}
+
+#[test]
+fn translate() {
+    let output = bf(&["translate", "--to", "ook"], b"+.");
+    assert_eq!(status(&output), 0);
+    assert_eq!(output.stdout, b"Ook. Ook. Ook! Ook.");
+
+    let output = bf(&["translate", "--from", "ook"], &output.stdout);
+    assert_eq!(output.stdout, b"+.");
+}
+
+#[test]
+fn minify() {
+    let output = bf(&["minify"], b"+ add one\n[-] clear .");
+    assert_eq!(status(&output), 0);
+    assert_eq!(output.stdout, b"+[-].");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("21 bytes down to 5 bytes"));
+}